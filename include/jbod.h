@@ -0,0 +1,93 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+
+/*
+ * The C ABI of jbod-rs, built with the ffi feature:
+ *
+ *   cargo rustc --release --lib --features ffi --crate-type cdylib
+ *
+ * and linked with -ljbod. The listings return the JSON records of
+ * `jbod list -e -o json` and `jbod list -d -o json`, to free with
+ * jbod_free_string(). On failure a function returns NULL or a non zero
+ * code and jbod_last_error() tells why.
+ *
+ * The configuration is the one of JBOD_CONFIG or /etc/jbod/jbod.toml,
+ * loaded by the first call, a configuration that can't be used fails the
+ * calls with JBOD_INVALID_ARGUMENT. A panic of jbod fails the call with
+ * JBOD_FAILED instead of unwinding into the caller.
+ */
+
+#ifndef JBOD_H
+#define JBOD_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define JBOD_OK                 0
+#define JBOD_NOT_FOUND          1
+#define JBOD_PERMISSION_DENIED  2
+#define JBOD_TOOL_MISSING       3
+#define JBOD_INVALID_ARGUMENT   4
+#define JBOD_UNSUPPORTED        5
+#define JBOD_IO_ERROR           6
+#define JBOD_FAILED             7
+
+/* The enclosures as a JSON array, NULL on failure. */
+char *jbod_list_enclosures(void);
+
+/*
+ * The disks of an enclosure, a slot, a serial number or an alias, or of
+ * every enclosure when enclosure is NULL, as a JSON array, NULL on failure.
+ */
+char *jbod_list_disks(const char *enclosure);
+
+/*
+ * Turns the "locate" or the "fault" led of a disk on, or off when on is 0.
+ * The device is a serial number, a WWN, a device or <enclosure>/<slot>.
+ * Returns JBOD_OK or the code of the failure.
+ */
+int jbod_set_led(const char *device, const char *led, int on);
+
+/*
+ * Why the last failed call of this thread failed, NULL when none did. The
+ * string stays valid until the next failure of the thread.
+ */
+const char *jbod_last_error(void);
+
+/* Frees a string returned by jbod. */
+void jbod_free_string(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* JBOD_H */
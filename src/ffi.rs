@@ -0,0 +1,257 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+//! The C ABI of the `ffi` feature, for the agents linking against jbod-rs
+//! instead of parsing the output of the CLI, see `include/jbod.h`.
+//!
+//! The listings return JSON strings, the records of `jbod list -e -o json`
+//! and `jbod list -d -o json`, to free with `jbod_free_string`. On failure
+//! a function returns NULL or a non zero code and `jbod_last_error` tells
+//! why, a configuration that can't be used or a panic included.
+
+// The C callers hand raw pointers, every dereference says why it holds.
+#[allow(non_snake_case)]
+#[allow(unsafe_code)]
+pub mod Ffi {
+    use serde::Serialize;
+    use std::cell::RefCell;
+    use std::ffi::{CStr, CString};
+    use std::os::raw::{c_char, c_int};
+    use std::panic::{self, AssertUnwindSafe};
+    use std::ptr;
+
+    use crate::jbod::context::Context;
+    use crate::jbod::disks::DiskShelf;
+    use crate::jbod::element::Element::LedState;
+    use crate::utils::config::Config;
+    use crate::utils::helper::Util;
+    use crate::utils::output::Output::ErrorCode;
+
+    pub const JBOD_OK: c_int = 0;
+    pub const JBOD_NOT_FOUND: c_int = 1;
+    pub const JBOD_PERMISSION_DENIED: c_int = 2;
+    pub const JBOD_TOOL_MISSING: c_int = 3;
+    pub const JBOD_INVALID_ARGUMENT: c_int = 4;
+    pub const JBOD_UNSUPPORTED: c_int = 5;
+    pub const JBOD_IO_ERROR: c_int = 6;
+    pub const JBOD_FAILED: c_int = 7;
+
+    thread_local! {
+        static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+    }
+
+    /// Records the error of the last call of this thread, returns its code.
+    fn fail(code: ErrorCode, message: &str) -> c_int {
+        let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+        LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+        match code {
+            ErrorCode::NotFound => JBOD_NOT_FOUND,
+            ErrorCode::PermissionDenied => JBOD_PERMISSION_DENIED,
+            ErrorCode::ToolMissing => JBOD_TOOL_MISSING,
+            ErrorCode::InvalidArgument => JBOD_INVALID_ARGUMENT,
+            ErrorCode::Unsupported => JBOD_UNSUPPORTED,
+            ErrorCode::IoError => JBOD_IO_ERROR,
+            ErrorCode::Failed => JBOD_FAILED,
+        }
+    }
+
+    /// Returns what `f` returns, or `failed` when it panicked, a panic must
+    /// not unwind into the C caller.
+    fn guarded<T>(failed: T, f: impl FnOnce() -> T) -> T {
+        match panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(result) => result,
+            Err(e) => {
+                let reason = match (e.downcast_ref::<&str>(), e.downcast_ref::<String>()) {
+                    (Some(reason), _) => reason.to_string(),
+                    (_, Some(reason)) => reason.clone(),
+                    _ => "unknown reason".to_string(),
+                };
+                fail(ErrorCode::Failed, &format!("jbod panicked: {}", reason));
+                failed
+            }
+        }
+    }
+
+    /// Loads the configuration, the one of `JBOD_CONFIG` or the default
+    /// one, or returns the code of why it can't be used.
+    fn load_config() -> Result<(), c_int> {
+        Config::try_init(None)
+            .map(|_| ())
+            .map_err(|e| fail(ErrorCode::InvalidArgument, &e))
+    }
+
+    /// Returns the string of a C argument, None for NULL.
+    ///
+    /// # Safety
+    ///
+    /// `s` is NULL or a valid NUL terminated string.
+    unsafe fn argument(s: *const c_char, name: &str) -> Result<Option<String>, c_int> {
+        if s.is_null() {
+            return Ok(None);
+        }
+        // SAFETY: s isn't NULL and the caller passes a NUL terminated string.
+        match CStr::from_ptr(s).to_str() {
+            Ok(s) => Ok(Some(s.to_string())),
+            Err(_) => Err(fail(
+                ErrorCode::InvalidArgument,
+                &format!("{} is not valid UTF-8", name),
+            )),
+        }
+    }
+
+    /// Returns the records as a JSON string owned by the caller.
+    fn json<T: Serialize>(records: &[T]) -> *mut c_char {
+        match serde_json::to_string(records).map(CString::new) {
+            Ok(Ok(json)) => json.into_raw(),
+            Ok(Err(e)) => {
+                fail(ErrorCode::Failed, &e.to_string());
+                ptr::null_mut()
+            }
+            Err(e) => {
+                fail(ErrorCode::Failed, &e.to_string());
+                ptr::null_mut()
+            }
+        }
+    }
+
+    /// Returns the scan options of an enclosure, every enclosure for None,
+    /// the scans of an agent never draw a progress bar.
+    fn scan_options(enclosure: Option<String>) -> DiskShelf::ScanOptions {
+        Util::set_quiet(true);
+        DiskShelf::ScanOptions {
+            enclosure,
+            ..DiskShelf::ScanOptions::default()
+        }
+    }
+
+    /// Returns the enclosures as a JSON array, NULL on failure.
+    #[no_mangle]
+    pub extern "C" fn jbod_list_enclosures() -> *mut c_char {
+        guarded(ptr::null_mut(), || {
+            if load_config().is_err() {
+                return ptr::null_mut();
+            }
+            let run = Context::Run::new(scan_options(None));
+            json(&DiskShelf::jbod_enclosures(&run))
+        })
+    }
+
+    /// Returns the disks of an enclosure, or of every enclosure when
+    /// `enclosure` is NULL, as a JSON array, NULL on failure.
+    ///
+    /// # Safety
+    ///
+    /// `enclosure` is NULL or a valid NUL terminated string, a slot, a
+    /// serial number or an alias.
+    #[no_mangle]
+    pub unsafe extern "C" fn jbod_list_disks(enclosure: *const c_char) -> *mut c_char {
+        guarded(ptr::null_mut(), || {
+            // SAFETY: enclosure is NULL or a C string, see # Safety.
+            let enclosure = match argument(enclosure, "enclosure") {
+                Ok(enclosure) => enclosure,
+                Err(_) => return ptr::null_mut(),
+            };
+            if load_config().is_err() {
+                return ptr::null_mut();
+            }
+            let run = Context::Run::new(scan_options(enclosure.clone()));
+            if let Some(id) = &enclosure {
+                if DiskShelf::jbod_enclosures(&run).is_empty() {
+                    fail(ErrorCode::NotFound, &format!("enclosure {} not found", id));
+                    return ptr::null_mut();
+                }
+            }
+            let mut disks = DiskShelf::jbod_disk_map(&run);
+            DiskShelf::sort_disks(&mut disks);
+            json(&disks)
+        })
+    }
+
+    /// Turns the `locate` or the `fault` led of a disk on, or off when `on`
+    /// is 0, returns `JBOD_OK` or the code of the failure.
+    ///
+    /// # Safety
+    ///
+    /// `device` and `led` are valid NUL terminated strings, `device` being
+    /// a serial number, a WWN, a device or `<enclosure>/<slot>`.
+    #[no_mangle]
+    pub unsafe extern "C" fn jbod_set_led(
+        device: *const c_char,
+        led: *const c_char,
+        on: c_int,
+    ) -> c_int {
+        guarded(JBOD_FAILED, || {
+            // SAFETY: device and led are C strings, see # Safety.
+            let (device, led) = match (argument(device, "device"), argument(led, "led")) {
+                (Ok(Some(device)), Ok(Some(led))) => (device, led),
+                (Err(code), _) | (_, Err(code)) => return code,
+                _ => return fail(ErrorCode::InvalidArgument, "device and led can't be NULL"),
+            };
+            if let Err(code) = load_config() {
+                return code;
+            }
+            let (location, path) = match DiskShelf::disk_led(&device, &led) {
+                Ok(found) => found,
+                Err((code, message)) => return fail(code, &message),
+            };
+            let state = if on != 0 { LedState::On } else { LedState::Off };
+            match DiskShelf::try_write_led(&location.enclosure, &path, state) {
+                Ok(()) => JBOD_OK,
+                Err((code, message)) => fail(code, &message),
+            }
+        })
+    }
+
+    /// Returns why the last failed call of this thread failed, NULL when
+    /// none did. The string belongs to jbod and stays valid until the next
+    /// failure of the thread.
+    #[no_mangle]
+    pub extern "C" fn jbod_last_error() -> *const c_char {
+        guarded(ptr::null(), || {
+            LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+        })
+    }
+
+    /// Frees a string returned by jbod.
+    ///
+    /// # Safety
+    ///
+    /// `s` is NULL or a string returned by jbod, not freed yet.
+    #[no_mangle]
+    pub unsafe extern "C" fn jbod_free_string(s: *mut c_char) {
+        guarded((), || {
+            if !s.is_null() {
+                // SAFETY: s comes from CString::into_raw in json() and the
+                // caller frees it once.
+                drop(CString::from_raw(s));
+            }
+        })
+    }
+}
@@ -1,2 +1,36 @@
+pub mod apply;
+pub mod bench;
+pub mod burnin;
+pub mod cabling;
+pub mod cache;
+pub mod catalog;
+pub mod context;
 pub mod disks;
+pub mod diskstats;
+pub mod element;
 pub mod enclosure;
+pub mod events;
+pub mod exec;
+pub mod find;
+pub mod firmware;
+pub mod genmap;
+pub mod health;
+pub mod inventory;
+pub mod io_errors;
+pub mod map;
+pub mod modepage;
+pub mod plugin;
+pub mod quirks;
+pub mod resolver;
+pub mod sed;
+pub mod sgio;
+pub mod show;
+pub mod spares;
+pub mod standby;
+pub mod summary;
+pub mod topology;
+pub mod wear;
+pub mod write_cache;
+
+#[cfg(test)]
+mod sysfs_tests;
@@ -0,0 +1,417 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Apply {
+    use clap::ArgMatches;
+    use colored::*;
+    use serde::{Deserialize, Serialize};
+    use std::fs;
+    use std::path::Path;
+
+    use crate::jbod::context::Context;
+    use crate::jbod::disks::DiskShelf;
+    use crate::jbod::element::Element::LedState;
+    use crate::jbod::enclosure::BackPlane::Enclosure;
+    use crate::jbod::resolver::Resolver;
+    use crate::utils::audit::Audit;
+    use crate::utils::config::Config;
+    use crate::utils::config::Config::Role;
+    use crate::utils::helper::Util;
+    use crate::utils::output::Output;
+    use crate::utils::output::Output::ErrorCode;
+
+    extern crate prettytable;
+    use prettytable::{color, format, Attr, Cell, Row, Table};
+
+    /// The desired state of the JBOD.
+    ///
+    /// ```yaml
+    /// enclosures:
+    ///   - id: 5000ccab0405db00
+    ///     alias: shelf-a
+    /// slots:
+    ///   - id: 0:0:60:0/7
+    ///     locate: on
+    ///     fault: off
+    ///     role: spare
+    /// ```
+    #[derive(Debug, Default, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Manifest {
+        pub enclosures: Vec<EnclosureState>,
+        pub slots: Vec<SlotState>,
+    }
+
+    /// An enclosure, identified by its SCSI address, serial number or alias.
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct EnclosureState {
+        pub id: String,
+        pub alias: Option<String>,
+    }
+
+    /// A slot, identified by anything `jbod locate` accepts, the fields not
+    /// given are left untouched.
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct SlotState {
+        pub id: String,
+        pub locate: Option<LedState>,
+        pub fault: Option<LedState>,
+        /// spare, reserved or none
+        pub role: Option<String>,
+    }
+
+    /// What has to be done to reach the desired state.
+    #[derive(Debug, Clone, Serialize)]
+    pub struct Change {
+        /// The enclosure, or the enclosure and the slot
+        pub target: String,
+        /// alias, locate, fault or role
+        pub property: String,
+        pub current: String,
+        pub desired: String,
+        #[serde(skip)]
+        action: Action,
+    }
+
+    #[derive(Debug, Clone)]
+    enum Action {
+        Led {
+            enclosure: String,
+            path: String,
+            state: LedState,
+        },
+        Alias {
+            serial: String,
+            model: String,
+            alias: String,
+        },
+        Role {
+            serial: String,
+            model: String,
+            slot: u32,
+            role: Option<Role>,
+        },
+    }
+
+    /// Returns the role of a manifest, None for `none`.
+    fn parse_role(value: &str) -> Result<Option<Role>, String> {
+        match value.trim().to_lowercase().as_str() {
+            "spare" => Ok(Some(Role::Spare)),
+            "reserved" => Ok(Some(Role::Reserved)),
+            "none" => Ok(None),
+            other => Err(format!(
+                "unknown role {}, expected spare, reserved or none",
+                other
+            )),
+        }
+    }
+
+    /// Returns the text displayed for an optional value.
+    fn display<T: ToString>(value: Option<T>) -> String {
+        value
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| "none".to_string())
+    }
+
+    /// Returns the led changes of a slot.
+    fn led_change(
+        target: &str,
+        property: &str,
+        enclosure: &str,
+        path: &Option<String>,
+        desired: LedState,
+    ) -> Result<Option<Change>, String> {
+        let path = path
+            .as_ref()
+            .ok_or_else(|| format!("{} does not expose {} led", target, property))?;
+        let current =
+            LedState::read(Path::new(path)).map_err(|e| format!("cannot read {}: {}", path, e))?;
+        if current == desired {
+            return Ok(None);
+        }
+        Ok(Some(Change {
+            target: target.to_string(),
+            property: property.to_string(),
+            current: current.to_string(),
+            desired: desired.to_string(),
+            action: Action::Led {
+                enclosure: enclosure.to_string(),
+                path: path.clone(),
+                state: desired,
+            },
+        }))
+    }
+
+    /// Returns the changes needed to reach the manifest, or the reason why
+    /// it can't be reached, nothing is applied in the latter case.
+    ///
+    /// # Arguments
+    ///
+    /// * `manifest` - the desired state
+    /// * `enclosures` - the enclosures of the host
+    ///
+    pub fn diff(manifest: &Manifest, enclosures: &[Enclosure]) -> Result<Vec<Change>, String> {
+        let settings = Config::get();
+        let find = |id: &str| {
+            enclosures
+                .iter()
+                .find(|e| e.matches(id))
+                .ok_or_else(|| format!("enclosure {} not found", id))
+        };
+        let mut changes = Vec::new();
+
+        for wanted in &manifest.enclosures {
+            let enclosure = find(&wanted.id)?;
+            let alias = match &wanted.alias {
+                Some(alias) => alias,
+                None => continue,
+            };
+            let current = settings.enclosure_alias(&enclosure.serial, &enclosure.model);
+            if current != Some(alias.as_str()) {
+                changes.push(Change {
+                    target: enclosure.slot.clone(),
+                    property: "alias".to_string(),
+                    current: display(current),
+                    desired: alias.clone(),
+                    action: Action::Alias {
+                        serial: enclosure.serial.clone(),
+                        model: enclosure.model.clone(),
+                        alias: alias.clone(),
+                    },
+                });
+            }
+        }
+
+        for wanted in &manifest.slots {
+            let location = Resolver::locate(&wanted.id)
+                .ok_or_else(|| format!("slot {} not found", wanted.id))?;
+            let target = format!(
+                "{} {}{}",
+                location.enclosure,
+                location.slot,
+                DiskShelf::slot_label_suffix(&location.label)
+            );
+            if let Some(state) = wanted.locate {
+                changes.extend(led_change(
+                    &target,
+                    "locate",
+                    &location.enclosure,
+                    &location.led_locate_path,
+                    state,
+                )?);
+            }
+            if let Some(state) = wanted.fault {
+                changes.extend(led_change(
+                    &target,
+                    "fault",
+                    &location.enclosure,
+                    &location.led_fault_path,
+                    state,
+                )?);
+            }
+            if let Some(role) = &wanted.role {
+                let role = parse_role(role).map_err(|e| format!("{}: {}", wanted.id, e))?;
+                let enclosure = find(&location.enclosure)?;
+                let slot = Util::slot_number(&location.slot)
+                    .ok_or_else(|| format!("{}: no slot number", target))?;
+                let current =
+                    settings.disk_role(&enclosure.serial, &enclosure.model, &location.slot, "");
+                if current != role {
+                    changes.push(Change {
+                        target,
+                        property: "role".to_string(),
+                        current: display(current),
+                        desired: display(role),
+                        action: Action::Role {
+                            serial: enclosure.serial.clone(),
+                            model: enclosure.model.clone(),
+                            slot,
+                            role,
+                        },
+                    });
+                }
+            }
+        }
+
+        Ok(changes)
+    }
+
+    /// Applies a change, the aliases and the roles are written into the
+    /// configuration file.
+    fn apply(change: &Change) -> Result<(), String> {
+        match &change.action {
+            Action::Led {
+                enclosure,
+                path,
+                state,
+            } => DiskShelf::try_write_led(enclosure, path, *state).map_err(|(_, message)| message),
+            Action::Alias {
+                serial,
+                model,
+                alias,
+            } => {
+                let result = Config::update_chassis(serial, model, |entry| {
+                    entry.insert("alias".to_string(), toml::Value::String(alias.clone()));
+                });
+                audit(change, &result);
+                result
+            }
+            Action::Role {
+                serial,
+                model,
+                slot,
+                role,
+            } => {
+                let result = Config::update_chassis(serial, model, |entry| {
+                    let roles = entry
+                        .entry("roles")
+                        .or_insert_with(|| toml::Value::Table(toml::value::Table::new()));
+                    if let Some(roles) = roles.as_table_mut() {
+                        match role {
+                            Some(role) => {
+                                roles.insert(slot.to_string(), toml::Value::String(role.to_string()));
+                            }
+                            None => {
+                                roles.remove(&slot.to_string());
+                            }
+                        }
+                    }
+                });
+                audit(change, &result);
+                result
+            }
+        }
+    }
+
+    /// Records a configuration change in the audit log, the LED ones are
+    /// recorded by `DiskShelf::write_led` itself.
+    fn audit(change: &Change, result: &Result<(), String>) {
+        Audit::record(
+            &change.property,
+            &change.target,
+            Some(&change.current),
+            &change.desired,
+            result.as_ref().map(|_| ()).map_err(|e| e.as_str()),
+        );
+    }
+
+    /// Creates the pretty table for the changes.
+    fn create_apply_table() -> Table {
+        let mut apply_table = Table::new();
+        apply_table.set_format(*format::consts::FORMAT_NO_BORDER);
+        let header = ["TARGET", "PROPERTY", "CURRENT", "DESIRED"];
+        apply_table.add_row(Row::new(
+            header
+                .iter()
+                .map(|h| {
+                    Cell::new(h)
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(color::BLUE))
+                })
+                .collect(),
+        ));
+
+        apply_table
+    }
+
+    /// Returns an error if the manifest can't be read or reached.
+    ///
+    /// This function is used in the `apply` menu option, it compares the
+    /// manifest with the current state and applies the differences only,
+    /// or lists them with `--dry-run`.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `apply` subcommand
+    ///
+    pub fn jbod_apply(options: &ArgMatches) -> Result<(), ()> {
+        let path = options.value_of("manifest").unwrap_or_default();
+        let dry_run = options.is_present("dry-run");
+        let manifest: Manifest = match fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| serde_yaml::from_str(&content).map_err(|e| e.to_string()))
+        {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                Output::print_error_code(ErrorCode::InvalidArgument, &format!("{}: {}", path, e));
+                return Err(());
+            }
+        };
+
+        let run = Context::Run::new(DiskShelf::ScanOptions::from_matches(options));
+        let changes = match diff(&manifest, &DiskShelf::jbod_enclosures(&run)) {
+            Ok(changes) => changes,
+            Err(e) => {
+                Output::print_error_code(ErrorCode::NotFound, &format!("{}: {}", path, e));
+                Output::print_hint("nothing was applied");
+                return Err(());
+            }
+        };
+
+        let format = Output::get_format(options);
+        if format != Output::Format::Table {
+            Output::render(format, &changes).map_err(|e| Output::print_error(&e))?;
+        } else if changes.is_empty() {
+            println!("Nothing to change, the JBOD matches {}", path.green());
+        } else {
+            let mut apply_table = create_apply_table();
+            for change in &changes {
+                apply_table.add_row(Row::new(vec![
+                    Cell::new(&change.target),
+                    Cell::new(&change.property),
+                    Cell::new(&change.current),
+                    Cell::new(&change.desired).with_style(Attr::ForegroundColor(color::GREEN)),
+                ]));
+            }
+            Output::print_table(&apply_table);
+        }
+
+        if dry_run {
+            return Ok(());
+        }
+        let mut failed = false;
+        for change in &changes {
+            if let Err(e) = apply(change) {
+                Output::print_error(&format!("{} {}: {}", change.target, change.property, e));
+                failed = true;
+            }
+        }
+        if failed {
+            return Err(());
+        }
+        if format == Output::Format::Table && !changes.is_empty() {
+            println!("Applied {} change(s)", changes.len());
+        }
+        Ok(())
+    }
+}
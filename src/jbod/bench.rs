@@ -0,0 +1,388 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Bench {
+    use clap::ArgMatches;
+    use serde::Serialize;
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, Seek, SeekFrom};
+    use std::os::unix::fs::{FileExt, OpenOptionsExt};
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+    use crate::jbod::context::Context;
+    use crate::jbod::disks::DiskShelf;
+    use crate::jbod::disks::DiskShelf::Disk;
+    use crate::jbod::resolver::Resolver;
+    use crate::jbod::sgio::SgIo::AlignedBuffer;
+    use crate::utils::helper::Util;
+    use crate::utils::i18n::I18n;
+    use crate::utils::i18n::I18n::Message;
+    use crate::utils::output::Output;
+    use crate::utils::output::Output::ErrorCode;
+
+    use prettytable::{color, format, Attr, Cell, Row, Table};
+
+    /// Size of each sequential read.
+    const SEQUENTIAL_BLOCK: usize = 1 << 20;
+    /// Size of each random read.
+    const RANDOM_BLOCK: usize = 4096;
+    /// Alignment of the buffers and offsets required by O_DIRECT.
+    const ALIGNMENT: usize = 4096;
+    /// Default duration of each test on each disk.
+    const DEFAULT_SECONDS: u64 = 10;
+    /// A disk below this share of the median of its model is an outlier.
+    const OUTLIER_RATIO: f64 = 0.7;
+
+    /// The throughput of one slot.
+    #[derive(Debug, Serialize)]
+    pub struct SlotBench {
+        pub enclosure: String,
+        pub slot: String,
+        pub device_path: String,
+        pub device_map: String,
+        pub model: String,
+        pub serial: String,
+        /// Sequential read throughput in MB/s
+        pub sequential_mb_s: Option<f64>,
+        /// Random 4 KiB reads per second
+        pub random_iops: Option<f64>,
+        /// Slower than the other disks of the same model
+        pub outlier: bool,
+        /// Why the slot is an outlier or why it couldn't be tested
+        pub note: String,
+    }
+
+    /// A xorshift generator, the offsets only need to be spread, not secure.
+    struct Offsets(u64);
+
+    impl Offsets {
+        fn seeded() -> Self {
+            let seed = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or(0x2545_f491_4f6c_dd1d);
+            Offsets(seed | 1)
+        }
+
+        fn next(&mut self, blocks: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % blocks
+        }
+    }
+
+    /// Returns the block device opened for direct reads and its size in bytes.
+    fn open_direct(device: &str) -> io::Result<(File, u64)> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .custom_flags(nix::libc::O_DIRECT)
+            .open(device)?;
+        let size = file.seek(SeekFrom::End(0))?;
+        Ok((file, size))
+    }
+
+    /// Returns the sequential read throughput of a disk in MB/s.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - the block device opened with O_DIRECT
+    /// * `size` - the size of the device in bytes
+    /// * `duration` - how long the test runs
+    ///
+    fn sequential_read(file: &File, size: u64, duration: Duration) -> io::Result<f64> {
+        let mut buffer = AlignedBuffer::new(SEQUENTIAL_BLOCK, ALIGNMENT);
+        let started = Instant::now();
+        let mut offset = 0;
+        let mut bytes = 0u64;
+        while started.elapsed() < duration {
+            if offset + SEQUENTIAL_BLOCK as u64 > size {
+                offset = 0;
+            }
+            let read = file.read_at(buffer.as_mut_slice(), offset)?;
+            if read == 0 {
+                break;
+            }
+            offset += read as u64;
+            bytes += read as u64;
+        }
+        Ok(bytes as f64 / 1_000_000.0 / started.elapsed().as_secs_f64())
+    }
+
+    /// Returns the number of random reads per second of a disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - the block device opened with O_DIRECT
+    /// * `size` - the size of the device in bytes
+    /// * `duration` - how long the test runs
+    ///
+    fn random_read(file: &File, size: u64, duration: Duration) -> io::Result<f64> {
+        let mut buffer = AlignedBuffer::new(RANDOM_BLOCK, ALIGNMENT);
+        let blocks = size / RANDOM_BLOCK as u64;
+        if blocks == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "empty device"));
+        }
+        let mut offsets = Offsets::seeded();
+        let started = Instant::now();
+        let mut reads = 0u64;
+        while started.elapsed() < duration {
+            file.read_at(buffer.as_mut_slice(), offsets.next(blocks) * RANDOM_BLOCK as u64)?;
+            reads += 1;
+        }
+        Ok(reads as f64 / started.elapsed().as_secs_f64())
+    }
+
+    /// Returns the benchmark of a single disk.
+    fn bench_disk(disk: &Disk, sequential: bool, random: bool, duration: Duration) -> SlotBench {
+        let mut bench = SlotBench {
+            enclosure: disk.enclosure.clone(),
+            slot: disk.slot.clone(),
+            device_path: disk.device_path.clone(),
+            device_map: disk.device_map.clone(),
+            model: disk.model.clone(),
+            serial: disk.serial.clone(),
+            sequential_mb_s: None,
+            random_iops: None,
+            outlier: false,
+            note: String::new(),
+        };
+        let (file, size) = match open_direct(&disk.device_map) {
+            Ok(opened) => opened,
+            Err(e) => {
+                bench.note = I18n::tr(Message::CannotOpen, &[&disk.device_map, &e]);
+                return bench;
+            }
+        };
+        let mut failures = Vec::new();
+        if sequential {
+            match sequential_read(&file, size, duration) {
+                Ok(rate) => bench.sequential_mb_s = Some(rate),
+                Err(e) => failures.push(format!("sequential read failed: {}", e)),
+            }
+        }
+        if random {
+            match random_read(&file, size, duration) {
+                Ok(rate) => bench.random_iops = Some(rate),
+                Err(e) => failures.push(format!("random read failed: {}", e)),
+            }
+        }
+        bench.note = failures.join(", ");
+        bench
+    }
+
+    /// Returns the median of the values, None when there is none.
+    fn median(mut values: Vec<f64>) -> Option<f64> {
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(|a, b| a.total_cmp(b));
+        let middle = values.len() / 2;
+        if values.len().is_multiple_of(2) {
+            Some((values[middle - 1] + values[middle]) / 2.0)
+        } else {
+            Some(values[middle])
+        }
+    }
+
+    /// Flags the slots slower than the other disks of the same model.
+    ///
+    /// Disks of a model are expected to perform alike, a slow one usually
+    /// sits behind a degraded link, a bad cable or a failing expander port.
+    fn flag_outliers(benches: &mut [SlotBench]) {
+        let mut models: Vec<String> = benches.iter().map(|b| b.model.clone()).collect();
+        models.sort();
+        models.dedup();
+
+        for model in models {
+            // A model alone in the shelf has nothing to compare with.
+            if benches.iter().filter(|b| b.model == model).count() < 3 {
+                continue;
+            }
+            let sequential = median(
+                benches
+                    .iter()
+                    .filter(|b| b.model == model)
+                    .filter_map(|b| b.sequential_mb_s)
+                    .collect(),
+            );
+            let random = median(
+                benches
+                    .iter()
+                    .filter(|b| b.model == model)
+                    .filter_map(|b| b.random_iops)
+                    .collect(),
+            );
+            for bench in benches.iter_mut().filter(|b| b.model == model) {
+                let mut reasons = Vec::new();
+                if let (Some(rate), Some(median)) = (bench.sequential_mb_s, sequential) {
+                    if rate < median * OUTLIER_RATIO {
+                        reasons.push(format!("sequential {:.0} MB/s, median {:.0}", rate, median));
+                    }
+                }
+                if let (Some(rate), Some(median)) = (bench.random_iops, random) {
+                    if rate < median * OUTLIER_RATIO {
+                        reasons.push(format!("random {:.0} IOPS, median {:.0}", rate, median));
+                    }
+                }
+                if !reasons.is_empty() {
+                    bench.outlier = true;
+                    bench.note = format!("{}, check the link", reasons.join(", "));
+                }
+            }
+        }
+    }
+
+    /// Creates the pretty table for the benchmark.
+    fn create_bench_table() -> Table {
+        let mut bench_table = Table::new();
+        bench_table.set_format(*format::consts::FORMAT_NO_BORDER);
+        let header = ["DEVICE", "ENCLOSURE", "SLOT", "MODEL", "SEQ MB/s", "RANDOM IOPS", "NOTE"];
+        bench_table.add_row(Row::new(
+            header
+                .iter()
+                .map(|h| {
+                    Cell::new(h)
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(color::BLUE))
+                })
+                .collect(),
+        ));
+
+        bench_table
+    }
+
+    /// Returns the rate with no decimal, or a dash when it wasn't measured.
+    fn rate(value: Option<f64>) -> String {
+        value.map_or("-".to_string(), |v| format!("{:.0}", v))
+    }
+
+    /// Returns an empty Result or an error when a slot is an outlier or
+    /// couldn't be measured.
+    ///
+    /// The disks are read one at a time by default so they don't compete for
+    /// the bandwidth of the HBA and the expanders, `--parallel` trades the
+    /// accuracy for the duration on large shelves.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `bench` subcommand
+    ///
+    pub fn jbod_bench(options: &ArgMatches) -> Result<(), ()> {
+        let duration = Duration::from_secs(
+            options
+                .value_of("seconds")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(DEFAULT_SECONDS),
+        );
+        let parallel: usize = options
+            .value_of("parallel")
+            .and_then(|p| p.parse().ok())
+            .unwrap_or(1);
+        let (sequential, random) = match options.value_of("test") {
+            Some("sequential") => (true, false),
+            Some("random") => (false, true),
+            _ => (true, true),
+        };
+
+        let mut scan = DiskShelf::ScanOptions::from_matches(options);
+        scan.fields.temperature = false;
+        scan.fields.firmware = false;
+        let run = Context::Run::new(scan);
+        let disks = DiskShelf::jbod_disk_map(&run);
+        for error in run.errors() {
+            Output::print_warning(&error.to_string());
+        }
+        let mut disks: Vec<Disk> = match options.value_of("device") {
+            Some(device) => match Resolver::resolve(device, &disks) {
+                Some(disk) => vec![disk.clone()],
+                None => {
+                    Output::print_error_code(
+                        ErrorCode::NotFound,
+                        &I18n::tr(Message::NotADisk, &[&device]),
+                    );
+                    return Err(());
+                }
+            },
+            None => disks,
+        };
+        // Disks without a block device, like those of a split path, are skipped.
+        disks.retain(|d| d.device_map.starts_with("/dev/"));
+        DiskShelf::sort_disks(&mut disks);
+        if disks.is_empty() {
+            Output::print_error_code(ErrorCode::NotFound, "no disk to benchmark");
+            return Err(());
+        }
+
+        let spinner = Util::Spinner::start(format!(
+            "Reading {} disks, {} seconds per test",
+            disks.len(),
+            duration.as_secs()
+        ));
+        let mut benches =
+            Util::parallel_map(&disks, parallel, |d| bench_disk(d, sequential, random, duration));
+        drop(spinner);
+        flag_outliers(&mut benches);
+
+        let format = Output::get_format(options);
+        if format != Output::Format::Table {
+            Output::render(format, &benches).map_err(|e| Output::print_error(&e))?;
+        } else {
+            let mut bench_table = create_bench_table();
+            for bench in &benches {
+                let mut row = Row::new(vec![
+                    Cell::new(&bench.device_map),
+                    Cell::new(&bench.enclosure),
+                    Cell::new(&bench.slot),
+                    Cell::new(&bench.model),
+                    Cell::new(&rate(bench.sequential_mb_s)),
+                    Cell::new(&rate(bench.random_iops)),
+                    Cell::new(&bench.note),
+                ]);
+                if bench.outlier {
+                    row = Row::new(
+                        row.iter()
+                            .map(|c| c.clone().with_style(Attr::ForegroundColor(color::RED)))
+                            .collect(),
+                    );
+                }
+                bench_table.add_row(row);
+            }
+            Output::print_table(&bench_table);
+        }
+
+        if benches.iter().any(|b| !b.note.is_empty()) {
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,571 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Burnin {
+    use clap::ArgMatches;
+    use colored::*;
+    use serde::Serialize;
+    use std::fs;
+    use std::io;
+    use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+    use std::sync::Mutex;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use crate::jbod::context::Context;
+    use crate::jbod::disks::DiskShelf;
+    use crate::jbod::disks::DiskShelf::Disk;
+    use crate::jbod::sgio::SgIo;
+    use crate::utils::helper::Util;
+    use crate::utils::i18n::I18n;
+    use crate::utils::i18n::I18n::Message;
+    use crate::utils::output::Output;
+    use crate::utils::output::Output::ErrorCode;
+
+    use prettytable::{color, format, Attr, Cell, Row, Table};
+
+    /// Bytes checked by each VERIFY, the data isn't transferred.
+    const VERIFY_CHUNK: u64 = 32 << 20;
+    /// Bytes moved by each READ or WRITE, within what every HBA accepts.
+    const TRANSFER_CHUNK: u64 = 512 << 10;
+    /// How long a single command may take on a struggling disk.
+    const IO_TIMEOUT: Duration = Duration::from_secs(60);
+    /// Failed block ranges kept for the report, the count goes on.
+    const MAX_REPORTED_ERRORS: usize = 32;
+    /// How often the progress of every disk is printed.
+    const PROGRESS_INTERVAL: Duration = Duration::from_secs(60);
+    /// The patterns written by the destructive test, like badblocks.
+    const PATTERNS: [u8; 4] = [0xaa, 0x55, 0xff, 0x00];
+
+    /// How the surface is tested.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Mode {
+        /// VERIFY, the disk reads its own media, read only.
+        Verify,
+        /// READ of every block, read only.
+        Read,
+        /// Patterns written then read back, destroys the data.
+        Write,
+    }
+
+    /// The progress of the test of one disk, shared with the progress
+    /// printer.
+    struct Tracker {
+        bytes: AtomicU64,
+        errors: AtomicU64,
+        passes: AtomicU32,
+        done: AtomicBool,
+        failed_lbas: Mutex<Vec<u64>>,
+    }
+
+    impl Tracker {
+        fn new() -> Self {
+            Tracker {
+                bytes: AtomicU64::new(0),
+                errors: AtomicU64::new(0),
+                passes: AtomicU32::new(0),
+                done: AtomicBool::new(false),
+                failed_lbas: Mutex::new(Vec::new()),
+            }
+        }
+
+        /// Records a failed range of blocks.
+        fn failure(&self, lba: u64) {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+            let mut lbas = self.failed_lbas.lock().unwrap_or_else(|e| e.into_inner());
+            if lbas.len() < MAX_REPORTED_ERRORS {
+                lbas.push(lba);
+            }
+        }
+    }
+
+    /// The outcome of the test of one disk.
+    #[derive(Debug, Serialize)]
+    pub struct DiskReport {
+        pub enclosure: String,
+        pub slot: String,
+        pub device_path: String,
+        pub model: String,
+        pub serial: String,
+        pub mode: Mode,
+        /// Complete passes over the surface
+        pub passes: u32,
+        pub bytes_tested: u64,
+        pub errors: u64,
+        /// First block of the first failed ranges
+        pub failed_lbas: Vec<u64>,
+        pub elapsed_seconds: u64,
+        /// passed, failed, or why the test couldn't run
+        pub result: String,
+    }
+
+    /// Returns why a disk can't be overwritten, None when nothing uses it.
+    ///
+    /// A disk with partitions, holders (md, dm, zfs) or a mount is refused,
+    /// whatever the path of a dual-ported disk they go through.
+    pub fn in_use(disk: &Disk) -> Option<String> {
+        std::iter::once(&disk.device_path)
+            .chain(&disk.alternate_paths)
+            .find_map(|path| path_in_use(path))
+    }
+
+    /// Returns why the block device of a sg node can't be overwritten.
+    fn path_in_use(device_path: &str) -> Option<String> {
+        let sg = device_path.rsplit('/').next()?;
+        let block_dir = Util::sys_class_scsi_generic().join(sg).join("device/block");
+        let block = match fs::read_dir(&block_dir).ok().and_then(|mut d| d.next()) {
+            Some(Ok(entry)) => entry,
+            _ => return None,
+        };
+        let name = block.file_name().to_string_lossy().to_string();
+        let sysfs_block = Util::sysfs_path("block").join(&name);
+
+        let has_entries = |dir: &std::path::Path, prefix: &str| {
+            fs::read_dir(dir)
+                .map(|d| {
+                    d.flatten()
+                        .any(|e| e.file_name().to_string_lossy().starts_with(prefix))
+                })
+                .unwrap_or(false)
+        };
+        if has_entries(&sysfs_block, &name) {
+            return Some(format!("/dev/{} has partitions", name));
+        }
+        if has_entries(&sysfs_block.join("holders"), "") {
+            return Some(format!("/dev/{} is held by another device", name));
+        }
+        let mounts = fs::read_to_string("/proc/mounts").unwrap_or_default();
+        let node = format!("/dev/{}", name);
+        if mounts.lines().any(|l| l.split_whitespace().next() == Some(node.as_str())) {
+            return Some(format!("{} is mounted", node));
+        }
+        None
+    }
+
+    /// Returns false once the deadline is reached and at least one pass was
+    /// done, the test stops there.
+    fn keep_going(deadline: Instant, tracker: &Tracker) -> bool {
+        Instant::now() < deadline || tracker.passes.load(Ordering::Relaxed) == 0
+    }
+
+    /// Runs one pass of VERIFY or READ, false when interrupted by the deadline.
+    fn read_pass(
+        device: &SgIo::Device,
+        mode: Mode,
+        geometry: (u64, u32),
+        deadline: Instant,
+        tracker: &Tracker,
+    ) -> bool {
+        let (blocks, block_size) = geometry;
+        let chunk = if mode == Mode::Verify { VERIFY_CHUNK } else { TRANSFER_CHUNK };
+        let chunk_blocks = (chunk / block_size as u64).max(1);
+        let buffer_len = if mode == Mode::Read { chunk_blocks * block_size as u64 } else { 0 };
+        let mut buffer = vec![0u8; buffer_len as usize];
+
+        let mut lba = 0;
+        while lba < blocks {
+            if Instant::now() >= deadline && tracker.passes.load(Ordering::Relaxed) > 0 {
+                return false;
+            }
+            let count = chunk_blocks.min(blocks - lba) as u32;
+            let result = match mode {
+                Mode::Verify => device.verify(lba, count, IO_TIMEOUT),
+                _ => {
+                    let len = count as usize * block_size as usize;
+                    device.read_blocks(lba, count, &mut buffer[..len], IO_TIMEOUT)
+                }
+            };
+            if result.is_err() {
+                tracker.failure(lba);
+            }
+            tracker
+                .bytes
+                .fetch_add(count as u64 * block_size as u64, Ordering::Relaxed);
+            lba += count as u64;
+        }
+        true
+    }
+
+    /// Runs one pass of the destructive test: the pattern is written over
+    /// the whole surface then read back and compared.
+    fn write_pass(
+        device: &SgIo::Device,
+        pattern: u8,
+        geometry: (u64, u32),
+        deadline: Instant,
+        tracker: &Tracker,
+    ) -> bool {
+        let (blocks, block_size) = geometry;
+        let chunk_blocks = (TRANSFER_CHUNK / block_size as u64).max(1);
+        let written = vec![pattern; (chunk_blocks * block_size as u64) as usize];
+        let mut read = vec![0u8; written.len()];
+
+        for verify in [false, true] {
+            let mut lba = 0;
+            while lba < blocks {
+                if Instant::now() >= deadline && tracker.passes.load(Ordering::Relaxed) > 0 {
+                    return false;
+                }
+                let count = chunk_blocks.min(blocks - lba) as u32;
+                let len = count as usize * block_size as usize;
+                let result = if verify {
+                    device
+                        .read_blocks(lba, count, &mut read[..len], IO_TIMEOUT)
+                        .and_then(|_| {
+                            if read[..len] == written[..len] {
+                                Ok(())
+                            } else {
+                                Err(io::Error::new(io::ErrorKind::InvalidData, "pattern mismatch"))
+                            }
+                        })
+                } else {
+                    device.write_blocks(lba, count, &written[..len], IO_TIMEOUT)
+                };
+                if result.is_err() {
+                    tracker.failure(lba);
+                }
+                // Each block is written and read, both halves count.
+                tracker.bytes.fetch_add(len as u64 / 2, Ordering::Relaxed);
+                lba += count as u64;
+            }
+        }
+        true
+    }
+
+    /// Tests the surface of a disk until the deadline, at least one pass.
+    ///
+    /// # Arguments
+    ///
+    /// * `disk` - the disk to test
+    /// * `mode` - how the surface is tested
+    /// * `deadline` - when the test stops
+    /// * `tracker` - where the progress is recorded
+    ///
+    fn burnin_disk(
+        disk: &Disk,
+        mode: Mode,
+        deadline: Instant,
+        tracker: &Tracker,
+    ) -> Result<(), String> {
+        let open = if mode == Mode::Write { SgIo::Device::open_rw } else { SgIo::Device::open };
+        let device = open(&disk.device_path)
+            .map_err(|e| I18n::tr(Message::CannotOpen, &[&disk.device_path, &e]))?;
+        let geometry = device
+            .capacity()
+            .map_err(|e| format!("cannot read the capacity: {}", e))?;
+
+        let mut pass = 0;
+        while keep_going(deadline, tracker) {
+            let complete = match mode {
+                Mode::Write => write_pass(
+                    &device,
+                    PATTERNS[pass % PATTERNS.len()],
+                    geometry,
+                    deadline,
+                    tracker,
+                ),
+                _ => read_pass(&device, mode, geometry, deadline, tracker),
+            };
+            if !complete {
+                break;
+            }
+            pass += 1;
+            tracker.passes.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// Returns the disks selected by `--slots`, every disk without it.
+    fn selected_disks(disks: Vec<Disk>, slots: &Option<Vec<u32>>) -> Vec<Disk> {
+        match slots {
+            Some(slots) => disks
+                .into_iter()
+                .filter(|d| {
+                    Util::slot_number(&d.slot)
+                        .map(|n| slots.contains(&n))
+                        .unwrap_or(false)
+                })
+                .collect(),
+            None => disks,
+        }
+    }
+
+    /// Prints the progress of every disk.
+    fn print_progress(disks: &[Disk], trackers: &[Tracker], pass_bytes: &[u64]) {
+        for ((disk, tracker), total) in disks.iter().zip(trackers).zip(pass_bytes) {
+            if tracker.done.load(Ordering::Relaxed) {
+                continue;
+            }
+            let bytes = tracker.bytes.load(Ordering::Relaxed);
+            let passes = tracker.passes.load(Ordering::Relaxed);
+            let percent = match total {
+                0 => 0.0,
+                t => (bytes % t) as f64 * 100.0 / *t as f64,
+            };
+            eprintln!(
+                "{} {}: pass {} {:>5.1}%, {} errors",
+                disk.device_path.bold(),
+                disk.slot,
+                passes + 1,
+                percent,
+                tracker.errors.load(Ordering::Relaxed)
+            );
+        }
+    }
+
+    /// Creates the pretty table for the burn-in report.
+    fn create_report_table() -> Table {
+        let mut report_table = Table::new();
+        report_table.set_format(*format::consts::FORMAT_NO_BORDER);
+        let header = [
+            "DEVICE", "ENCLOSURE", "SLOT", "SERIAL", "PASSES", "TESTED", "ERRORS", "RESULT",
+        ];
+        report_table.add_row(Row::new(
+            header
+                .iter()
+                .map(|h| {
+                    Cell::new(h)
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(color::BLUE))
+                })
+                .collect(),
+        ));
+
+        report_table
+    }
+
+    /// Returns the capacity of a pass over a disk in bytes, 0 if unknown.
+    fn pass_bytes(disk: &Disk) -> u64 {
+        SgIo::Device::open(&disk.device_path)
+            .and_then(|d| d.capacity())
+            .map(|(blocks, size)| blocks * size as u64)
+            .unwrap_or(0)
+    }
+
+    /// Returns an empty Result or an error when a disk failed its test.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `burnin` subcommand
+    ///
+    pub fn jbod_burnin(options: &ArgMatches) -> Result<(), ()> {
+        let mode = match options.value_of("mode") {
+            Some("read") => Mode::Read,
+            Some("write") => Mode::Write,
+            _ => Mode::Verify,
+        };
+        if mode == Mode::Write && !options.is_present("destructive") {
+            Output::print_error_code(
+                ErrorCode::InvalidArgument,
+                "--mode write destroys the data of the disks, confirm with --destructive",
+            );
+            return Err(());
+        }
+        let slots = match options.value_of("slots").map(Util::parse_slots).transpose() {
+            Ok(slots) => slots,
+            Err(e) => {
+                Output::print_error_code(ErrorCode::InvalidArgument, &e);
+                return Err(());
+            }
+        };
+        let hours: f64 = options
+            .value_of("hours")
+            .and_then(|h| h.parse().ok())
+            .unwrap_or(0.0);
+
+        let mut scan = DiskShelf::ScanOptions::from_matches(options);
+        scan.fields.temperature = false;
+        scan.fields.firmware = false;
+        let run = Context::Run::new(scan);
+        let mut disks = selected_disks(DiskShelf::jbod_disk_map(&run), &slots);
+        DiskShelf::sort_disks(&mut disks);
+        for error in run.errors() {
+            Output::print_warning(&error.to_string());
+        }
+        if disks.is_empty() {
+            Output::print_error_code(ErrorCode::NotFound, "no disk selected");
+            return Err(());
+        }
+
+        let mut reports: Vec<DiskReport> = Vec::new();
+        let mut tested: Vec<Disk> = Vec::new();
+        for disk in disks {
+            let refused = if mode == Mode::Write { in_use(&disk) } else { None };
+            match refused {
+                Some(reason) => reports.push(report(&disk, mode, &Tracker::new(), 0, Err(reason))),
+                None => tested.push(disk),
+            }
+        }
+
+        let format = Output::get_format(options);
+        let started = Instant::now();
+        let deadline = started + Duration::from_secs_f64(hours.max(0.0) * 3600.0);
+        let trackers: Vec<Tracker> = tested.iter().map(|_| Tracker::new()).collect();
+        let totals: Vec<u64> = tested.iter().map(pass_bytes).collect();
+        let how = match mode {
+            Mode::Verify => "SCSI VERIFY",
+            Mode::Read => "reads",
+            Mode::Write => "written patterns",
+        };
+        if hours > 0.0 {
+            eprintln!("Testing {} disks with {} for {} hours", tested.len(), how, hours);
+        } else {
+            eprintln!("Testing {} disks with {}, a single pass", tested.len(), how);
+        }
+
+        let results: Vec<Result<(), String>> = thread::scope(|scope| {
+            let workers: Vec<_> = tested
+                .iter()
+                .zip(&trackers)
+                .map(|(disk, tracker)| {
+                    scope.spawn(move || {
+                        let result = burnin_disk(disk, mode, deadline, tracker);
+                        tracker.done.store(true, Ordering::Relaxed);
+                        result
+                    })
+                })
+                .collect();
+
+            // A timed test is followed by its duration, a single pass by the
+            // bytes tested, the periodic lines are only printed without bar.
+            let progress = match hours > 0.0 {
+                true => Util::Progress::start(
+                    format!("Testing {} disks", tested.len()),
+                    deadline.duration_since(started).as_secs(),
+                    false,
+                ),
+                false => Util::Progress::start(
+                    format!("Testing {} disks", tested.len()),
+                    totals.iter().sum(),
+                    true,
+                ),
+            };
+            let mut last_progress = Instant::now();
+            while !trackers.iter().all(|t| t.done.load(Ordering::Relaxed)) {
+                thread::sleep(Duration::from_millis(200));
+                if hours > 0.0 {
+                    progress.set(started.elapsed().as_secs());
+                } else {
+                    progress.set(trackers.iter().map(|t| t.bytes.load(Ordering::Relaxed)).sum());
+                }
+                if format == Output::Format::Table
+                    && !Util::show_progress()
+                    && last_progress.elapsed() >= PROGRESS_INTERVAL
+                {
+                    print_progress(&tested, &trackers, &totals);
+                    last_progress = Instant::now();
+                }
+            }
+            drop(progress);
+
+            workers
+                .into_iter()
+                .map(|w| w.join().unwrap_or_else(|_| Err("the test panicked".to_string())))
+                .collect()
+        });
+
+        let elapsed = started.elapsed().as_secs();
+        for ((disk, tracker), result) in tested.iter().zip(&trackers).zip(results) {
+            reports.push(report(disk, mode, tracker, elapsed, result));
+        }
+        reports.sort_by(|a, b| {
+            Util::natural_cmp(&a.enclosure, &b.enclosure)
+                .then_with(|| Util::natural_cmp(&a.slot, &b.slot))
+        });
+
+        if format != Output::Format::Table {
+            Output::render(format, &reports).map_err(|e| Output::print_error(&e))?;
+        } else {
+            let mut report_table = create_report_table();
+            for report in &reports {
+                let result = if report.result == "passed" {
+                    Cell::new(&report.result).with_style(Attr::ForegroundColor(color::GREEN))
+                } else {
+                    Cell::new(&report.result)
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(color::RED))
+                };
+                report_table.add_row(Row::new(vec![
+                    Cell::new(&report.device_path),
+                    Cell::new(&report.enclosure),
+                    Cell::new(&report.slot),
+                    Cell::new(&report.serial),
+                    Cell::new(&report.passes.to_string()),
+                    Cell::new(&format!("{} GB", report.bytes_tested / 1_000_000_000)),
+                    Cell::new(&report.errors.to_string()),
+                    result,
+                ]));
+            }
+            Output::print_table(&report_table);
+        }
+
+        if reports.iter().all(|r| r.result == "passed") {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+
+    /// Returns the DiskReport of a disk from its tracker.
+    fn report(
+        disk: &Disk,
+        mode: Mode,
+        tracker: &Tracker,
+        elapsed: u64,
+        result: Result<(), String>,
+    ) -> DiskReport {
+        let errors = tracker.errors.load(Ordering::Relaxed);
+        let result = match result {
+            Ok(()) if errors == 0 => "passed".to_string(),
+            Ok(()) => "failed".to_string(),
+            Err(e) => e,
+        };
+        DiskReport {
+            enclosure: disk.enclosure.clone(),
+            slot: disk.slot.clone(),
+            device_path: disk.device_path.clone(),
+            model: disk.model.clone(),
+            serial: disk.serial.clone(),
+            mode,
+            passes: tracker.passes.load(Ordering::Relaxed),
+            bytes_tested: tracker.bytes.load(Ordering::Relaxed),
+            errors,
+            failed_lbas: tracker
+                .failed_lbas
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone(),
+            elapsed_seconds: elapsed,
+            result,
+        }
+    }
+}
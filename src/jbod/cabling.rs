@@ -0,0 +1,226 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Cabling {
+    use clap::ArgMatches;
+    use colored::*;
+    use serde::Serialize;
+    use std::io::{self, BufRead, IsTerminal, Write};
+
+    use crate::jbod::context::Context;
+    use crate::jbod::disks::DiskShelf;
+    use crate::jbod::element::Element::LedState;
+    use crate::jbod::map::ChassisMap;
+    use crate::jbod::resolver::Resolver;
+    use crate::utils::i18n::I18n;
+    use crate::utils::i18n::I18n::Message;
+    use crate::utils::output::Output;
+    use crate::utils::output::Output::ErrorCode;
+
+    extern crate prettytable;
+    use prettytable::{color, format, Attr, Cell, Row, Table};
+
+    /// What the operator saw when a slot was blinking.
+    #[derive(Debug, Serialize)]
+    pub struct Check {
+        pub enclosure: String,
+        pub slot: String,
+        /// The bay that should blink, its label or its position from 1
+        pub expected: String,
+        /// The bay the operator saw blinking, None when it was the expected one
+        pub observed: Option<String>,
+    }
+
+    /// What the operator answered.
+    enum Answer {
+        Yes,
+        No(String),
+        Quit,
+    }
+
+    /// Returns the answer read from stdin, an empty line is a yes.
+    fn ask(prompt: &str) -> Answer {
+        let stdin = io::stdin();
+        let read = |prompt: &str| {
+            print!("{}", prompt);
+            let _ = io::stdout().flush();
+            let mut line = String::new();
+            match stdin.lock().read_line(&mut line) {
+                Ok(0) | Err(_) => None,
+                Ok(_) => Some(line.trim().to_string()),
+            }
+        };
+        loop {
+            match read(prompt).as_deref().map(str::to_lowercase).as_deref() {
+                None | Some("q") | Some("quit") => return Answer::Quit,
+                Some("") => return Answer::Yes,
+                Some(answer) if I18n::is_yes(answer) => return Answer::Yes,
+                Some(answer) if I18n::is_no(answer) => {
+                    return match read(&I18n::tr(Message::CablingWhichBay, &[])) {
+                        Some(bay) if !bay.is_empty() => Answer::No(bay),
+                        Some(_) => Answer::No("unknown".to_string()),
+                        None => Answer::Quit,
+                    }
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    /// Creates the pretty table for the mismatches.
+    fn create_cabling_table() -> Table {
+        let mut cabling_table = Table::new();
+        cabling_table.set_format(*format::consts::FORMAT_NO_BORDER);
+        let header = ["SLOT", "EXPECTED", "OBSERVED"];
+        cabling_table.add_row(Row::new(
+            header
+                .iter()
+                .map(|h| {
+                    Cell::new(h)
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(color::BLUE))
+                })
+                .collect(),
+        ));
+
+        cabling_table
+    }
+
+    /// Returns an error when a slot doesn't blink where it should.
+    ///
+    /// This function is used in the `verify-cabling` menu option, it turns
+    /// the locate led of each slot of an enclosure on in slot order and asks
+    /// the operator whether the expected bay is blinking, so swapped
+    /// backplane cables are caught during the commissioning.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `verify-cabling` subcommand
+    ///
+    pub fn jbod_verify_cabling(options: &ArgMatches) -> Result<(), ()> {
+        let id = options.value_of("enclosure").unwrap_or_default();
+        if !io::stdin().is_terminal() {
+            Output::print_error_code(
+                ErrorCode::InvalidArgument,
+                &I18n::tr(Message::CablingNoTerminal, &[]),
+            );
+            return Err(());
+        }
+        let mut scan = DiskShelf::ScanOptions::from_matches(options);
+        scan.fields = DiskShelf::Fields::minimal();
+        let run = Context::Run::new(scan);
+        let enclosure = match DiskShelf::jbod_enclosures(&run)
+            .into_iter()
+            .find(|e| e.matches(id))
+        {
+            Some(enclosure) => enclosure,
+            None => {
+                Output::print_error_code(
+                    ErrorCode::NotFound,
+                    &I18n::tr(Message::NoEnclosure, &[&id]),
+                );
+                return Err(());
+            }
+        };
+
+        let bays = ChassisMap::bays(&enclosure);
+        println!(
+            "{}",
+            I18n::tr(Message::CablingIntro, &[&enclosure.slot.bold(), &enclosure.model])
+        );
+        let mut checks = Vec::new();
+        for (index, bay) in bays.iter().enumerate() {
+            let expected = bay.label.clone().unwrap_or_else(|| (index + 1).to_string());
+            let led = Resolver::locate(&format!("{}/{}", enclosure.slot, bay.slot))
+                .and_then(|l| l.led_locate_path);
+            let led = match led {
+                Some(led) => led,
+                None => {
+                    Output::print_warning(&I18n::tr(Message::NoLocateLed, &[&bay.slot]));
+                    continue;
+                }
+            };
+
+            DiskShelf::write_led(&enclosure.slot, &led, LedState::On);
+            let answer = ask(&I18n::tr(
+                Message::CablingPrompt,
+                &[
+                    &(index + 1),
+                    &bays.len(),
+                    &bay.slot.yellow().bold(),
+                    &expected.green().bold(),
+                ],
+            ));
+            DiskShelf::write_led(&enclosure.slot, &led, LedState::Off);
+
+            let observed = match answer {
+                Answer::Yes => None,
+                Answer::No(bay) => Some(bay),
+                Answer::Quit => break,
+            };
+            checks.push(Check {
+                enclosure: enclosure.slot.clone(),
+                slot: bay.slot.clone(),
+                expected,
+                observed,
+            });
+        }
+
+        let mismatches: Vec<&Check> = checks.iter().filter(|c| c.observed.is_some()).collect();
+        let format = Output::get_format(options);
+        if format != Output::Format::Table {
+            Output::render(format, &checks).map_err(|e| Output::print_error(&e))?;
+        } else if mismatches.is_empty() {
+            println!(
+                "{}",
+                I18n::tr(Message::CablingMatches, &[&checks.len(), &bays.len()])
+            );
+        } else {
+            println!();
+            let mut cabling_table = create_cabling_table();
+            for check in &mismatches {
+                cabling_table.add_row(Row::new(vec![
+                    Cell::new(&check.slot),
+                    Cell::new(&check.expected),
+                    Cell::new(check.observed.as_deref().unwrap_or_default())
+                        .with_style(Attr::ForegroundColor(color::RED)),
+                ]));
+            }
+            Output::print_table(&cabling_table);
+            Output::print_hint(&I18n::tr(Message::CablingHint, &[]));
+        }
+
+        if !mismatches.is_empty() {
+            return Err(());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Cache {
+    use serde::{Deserialize, Serialize};
+    use std::collections::hash_map::DefaultHasher;
+    use std::fs;
+    use std::hash::{Hash, Hasher};
+    use std::path::Path;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use crate::jbod::disks::DiskShelf::{Disk, Fields};
+    use crate::jbod::enclosure::BackPlane::Enclosure;
+    use crate::utils::helper::Util;
+
+    pub const CACHE_DIR: &str = "/run/jbod";
+    pub const CACHE_FILE: &str = "/run/jbod/topology.json";
+
+    /// The discovered topology as stored on disk.
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct Topology {
+        /// Seconds since epoch when the topology was discovered.
+        pub created: u64,
+        /// Fingerprint of the devices present, see `hotplug_fingerprint()`.
+        pub fingerprint: u64,
+        /// The expensive fields collected for the disks.
+        pub fields: Fields,
+        pub enclosures: Vec<Enclosure>,
+        pub disks: Vec<Disk>,
+    }
+
+    /// Returns the current time in seconds since epoch.
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+
+    /// Returns the sorted entry names of a directory.
+    fn sorted_entries(path: &Path) -> Vec<String> {
+        let mut entries: Vec<String> = fs::read_dir(path)
+            .map(|d| {
+                d.flatten()
+                    .map(|e| e.file_name().to_string_lossy().to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        entries.sort();
+        entries
+    }
+
+    /// Returns a hash of the devices currently attached.
+    ///
+    /// Any hotplug event adds or removes a scsi_generic node or changes the
+    /// device linked to an enclosure slot, so the hash changes with it.
+    pub fn hotplug_fingerprint() -> u64 {
+        let mut hasher = DefaultHasher::new();
+
+        let sys_class_enclosure = Util::sys_class_enclosure();
+
+        sorted_entries(&Util::sys_class_scsi_generic()).hash(&mut hasher);
+        for enclosure in sorted_entries(&sys_class_enclosure) {
+            let enclosure_path = sys_class_enclosure.join(&enclosure);
+            enclosure.hash(&mut hasher);
+            for component in sorted_entries(&enclosure_path) {
+                let device = fs::read_link(enclosure_path.join(&component).join("device"))
+                    .map(|l| l.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                component.hash(&mut hasher);
+                device.hash(&mut hasher);
+            }
+        }
+
+        hasher.finish()
+    }
+
+    /// Returns true if `cached` collected at least the `wanted` fields.
+    fn covers(cached: &Fields, wanted: &Fields) -> bool {
+        (cached.temperature || !wanted.temperature)
+            && (cached.firmware || !wanted.firmware)
+            && (cached.serial || !wanted.serial)
+    }
+
+    /// Returns the cached Topology if it is younger than `ttl`, has the wanted
+    /// fields and no device was added or removed since, otherwise None.
+    ///
+    /// # Arguments
+    ///
+    /// * `ttl` - the maximum age of the cache
+    /// * `fields` - the expensive fields the caller needs
+    ///
+    pub fn load(ttl: Duration, fields: &Fields) -> Option<Topology> {
+        let content = fs::read_to_string(CACHE_FILE).ok()?;
+        let topology: Topology = serde_json::from_str(&content).ok()?;
+
+        if now().saturating_sub(topology.created) > ttl.as_secs()
+            || !covers(&topology.fields, fields)
+            || topology.fingerprint != hotplug_fingerprint()
+        {
+            return None;
+        }
+
+        Some(topology)
+    }
+
+    /// Writes the topology to the cache, failures are ignored since the
+    /// cache is only an optimization (an unprivileged user cannot write it).
+    ///
+    /// # Arguments
+    ///
+    /// * `fields` - the expensive fields collected for the disks
+    /// * `enclosures` - the enclosures discovered
+    /// * `disks` - the disks discovered
+    ///
+    pub fn store(fields: Fields, enclosures: Vec<Enclosure>, disks: Vec<Disk>) {
+        let topology = Topology {
+            created: now(),
+            fingerprint: hotplug_fingerprint(),
+            fields,
+            enclosures,
+            disks,
+        };
+        if fs::create_dir_all(CACHE_DIR).is_err() {
+            return;
+        }
+        if let Ok(content) = serde_json::to_string(&topology) {
+            let _ = fs::write(CACHE_FILE, content);
+        }
+    }
+
+    /// Removes the cache, used by `jbod cache clear`, a missing cache is
+    /// already cleared.
+    pub fn clear() -> std::io::Result<()> {
+        match fs::remove_file(CACHE_FILE) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,323 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Catalog {
+    use clap::ArgMatches;
+    use colored::*;
+    use serde::{Deserialize, Serialize};
+    use std::fmt;
+    use std::fs;
+
+    use crate::jbod::context::Context;
+    use crate::jbod::disks::DiskShelf;
+    use crate::jbod::disks::DiskShelf::Disk;
+    use crate::jbod::enclosure::BackPlane;
+    use crate::jbod::enclosure::BackPlane::Enclosure;
+    use crate::utils::output::Output;
+    use crate::utils::output::Output::ErrorCode;
+
+    extern crate prettytable;
+    use prettytable::{color, format, Attr, Cell, Row, Table};
+
+    /// The firmware revisions approved for a model.
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Approved {
+        /// The model, a trailing `*` matches every model starting with it
+        pub model: String,
+        pub firmware: Vec<String>,
+    }
+
+    /// The catalog of the approved firmware revisions, example:
+    ///
+    /// ```toml
+    /// [[disk]]
+    /// model = "HUH721212AL*"
+    /// firmware = ["A3D0", "A3Z0"]
+    ///
+    /// [[enclosure]]
+    /// model = "H4102-J"
+    /// firmware = ["2033"]
+    /// ```
+    #[derive(Debug, Default, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Catalog {
+        pub disk: Vec<Approved>,
+        pub enclosure: Vec<Approved>,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Compliance {
+        /// The firmware is approved
+        Approved,
+        /// The model is in the catalog but not its firmware
+        Outdated,
+        /// The model isn't in the catalog
+        Unlisted,
+        /// The firmware couldn't be read
+        Unknown,
+    }
+
+    impl fmt::Display for Compliance {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let name = match self {
+                Compliance::Approved => "approved",
+                Compliance::Outdated => "outdated",
+                Compliance::Unlisted => "unlisted",
+                Compliance::Unknown => "unknown",
+            };
+            write!(f, "{}", name)
+        }
+    }
+
+    /// The firmware of a disk or an enclosure controller checked against
+    /// the catalog.
+    #[derive(Debug, Serialize)]
+    pub struct Component {
+        /// disk or controller
+        pub kind: String,
+        /// The SCSI address of the enclosure, example: 15:0:1:0
+        pub enclosure: String,
+        /// The slot of the disk or the subenclosure id of the controller
+        pub location: String,
+        pub serial: String,
+        pub model: String,
+        pub firmware: String,
+        pub approved: Vec<String>,
+        pub compliance: Compliance,
+    }
+
+    /// Returns true when a model of the catalog matches a model.
+    fn model_matches(pattern: &str, model: &str) -> bool {
+        let (pattern, model) = (pattern.trim().to_uppercase(), model.trim().to_uppercase());
+        match pattern.strip_suffix('*') {
+            Some(prefix) => model.starts_with(prefix),
+            None => pattern == model,
+        }
+    }
+
+    /// Returns the compliance of a firmware and the revisions approved
+    /// for its model.
+    fn compliance(entries: &[Approved], model: &str, firmware: &str) -> (Compliance, Vec<String>) {
+        match entries.iter().find(|e| model_matches(&e.model, model)) {
+            Some(entry) if firmware.trim().is_empty() => {
+                (Compliance::Unknown, entry.firmware.clone())
+            }
+            Some(entry) => {
+                let approved = entry
+                    .firmware
+                    .iter()
+                    .any(|f| f.trim().eq_ignore_ascii_case(firmware.trim()));
+                let compliance = if approved {
+                    Compliance::Approved
+                } else {
+                    Compliance::Outdated
+                };
+                (compliance, entry.firmware.clone())
+            }
+            None => (Compliance::Unlisted, Vec::new()),
+        }
+    }
+
+    /// Returns the catalog read from a TOML file, or why it can't be used.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the catalog file
+    ///
+    pub fn load(path: &str) -> Result<Catalog, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        toml::from_str(&content).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    /// Returns every disk and enclosure controller checked against the
+    /// catalog, the controllers must have been read with
+    /// `BackPlane::read_controllers`.
+    ///
+    /// # Arguments
+    ///
+    /// * `catalog` - the approved revisions
+    /// * `enclosures` - the enclosures and their controllers
+    /// * `disks` - the disks, with their firmware
+    ///
+    pub fn check(catalog: &Catalog, enclosures: &[Enclosure], disks: &[Disk]) -> Vec<Component> {
+        let mut components = Vec::new();
+        for enclosure in enclosures {
+            for controller in &enclosure.controllers {
+                let (compliance, approved) = compliance(
+                    &catalog.enclosure,
+                    &controller.product,
+                    &controller.revision,
+                );
+                components.push(Component {
+                    kind: "controller".to_string(),
+                    enclosure: enclosure.slot.clone(),
+                    location: controller.id.clone(),
+                    serial: enclosure.serial.clone(),
+                    model: controller.product.clone(),
+                    firmware: controller.revision.clone(),
+                    approved,
+                    compliance,
+                });
+            }
+        }
+        for disk in disks {
+            let (compliance, approved) = compliance(&catalog.disk, &disk.model, &disk.fw_revision);
+            components.push(Component {
+                kind: "disk".to_string(),
+                enclosure: disk.enclosure.clone(),
+                location: disk.slot.clone(),
+                serial: disk.serial.clone(),
+                model: disk.model.clone(),
+                firmware: disk.fw_revision.clone(),
+                approved,
+                compliance,
+            });
+        }
+        components
+    }
+
+    /// Creates the pretty table for the components.
+    fn create_upgrade_table() -> Table {
+        let mut upgrade_table = Table::new();
+        upgrade_table.set_format(*format::consts::FORMAT_NO_BORDER);
+        let header = [
+            "KIND",
+            "ENCLOSURE",
+            "LOCATION",
+            "SERIAL",
+            "MODEL",
+            "FIRMWARE",
+            "APPROVED",
+            "COMPLIANCE",
+        ];
+        upgrade_table.add_row(Row::new(
+            header
+                .iter()
+                .map(|h| {
+                    Cell::new(h)
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(color::BLUE))
+                })
+                .collect(),
+        ));
+        upgrade_table
+    }
+
+    /// Returns an empty Result when every component of the catalog runs an
+    /// approved firmware. The out of date components and the ones whose
+    /// firmware couldn't be read are listed, every component with `--all`.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `upgrade-check` subcommand
+    ///
+    pub fn jbod_upgrade_check(options: &ArgMatches) -> Result<(), ()> {
+        let path = options.value_of("catalog").unwrap_or_default();
+        let catalog = load(path).map_err(|e| {
+            Output::print_error_code(
+                ErrorCode::InvalidArgument,
+                &format!("invalid catalog {}", e),
+            )
+        })?;
+
+        let mut scan = DiskShelf::ScanOptions::from_matches(options);
+        scan.fields.firmware = true;
+        let run = Context::Run::new(scan);
+        let mut disks = DiskShelf::jbod_disk_map(&run);
+        DiskShelf::sort_disks(&mut disks);
+        let mut enclosures = DiskShelf::jbod_enclosures(&run);
+        BackPlane::read_controllers(&mut enclosures, run.options.jobs);
+
+        let all = options.is_present("all");
+        let components: Vec<Component> = check(&catalog, &enclosures, &disks)
+            .into_iter()
+            .filter(|c| all || matches!(c.compliance, Compliance::Outdated | Compliance::Unknown))
+            .collect();
+        let outdated = components
+            .iter()
+            .filter(|c| c.compliance == Compliance::Outdated)
+            .count();
+
+        let format = Output::get_format(options);
+        if format != Output::Format::Table {
+            Output::render(format, &components).map_err(|e| Output::print_error(&e))?;
+        } else if components.is_empty() {
+            println!(
+                "Every component of the catalog runs an {} firmware",
+                "approved".green().bold()
+            );
+        } else {
+            let mut upgrade_table = create_upgrade_table();
+            for component in &components {
+                let compliance = Cell::new(&component.compliance.to_string());
+                let compliance = match component.compliance {
+                    Compliance::Approved => {
+                        compliance.with_style(Attr::ForegroundColor(color::GREEN))
+                    }
+                    Compliance::Outdated => compliance
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(color::RED)),
+                    Compliance::Unlisted | Compliance::Unknown => {
+                        compliance.with_style(Attr::ForegroundColor(color::YELLOW))
+                    }
+                };
+                upgrade_table.add_row(Row::new(vec![
+                    Cell::new(&component.kind),
+                    Cell::new(&component.enclosure),
+                    Cell::new(&component.location),
+                    Cell::new(&component.serial),
+                    Cell::new(&component.model),
+                    Cell::new(&component.firmware),
+                    Cell::new(&component.approved.join(", ")),
+                    compliance,
+                ]));
+            }
+            Output::print_table(&upgrade_table);
+        }
+
+        let errors = run.errors();
+        Output::render_errors(format, &errors);
+        if outdated > 0 {
+            Output::print_error(&format!(
+                "{} components run a firmware that is not approved",
+                outdated
+            ));
+            return Err(());
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+}
@@ -0,0 +1,122 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Context {
+    use serde::Serialize;
+    use std::collections::HashMap;
+    use std::fmt;
+    use std::sync::{Mutex, OnceLock};
+
+    use crate::jbod::disks::DiskShelf;
+    use crate::jbod::disks::DiskShelf::ScanOptions;
+    use crate::jbod::enclosure::BackPlane;
+    use crate::jbod::enclosure::BackPlane::Enclosure;
+
+    /// A failure that didn't stop the run, the other enclosures are still
+    /// listed.
+    #[derive(Debug, Clone, Serialize)]
+    pub struct ScanError {
+        /// The enclosure that couldn't be scanned, example: 15:0:1:0
+        pub enclosure: String,
+        /// What went wrong
+        pub error: String,
+    }
+
+    impl fmt::Display for ScanError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "enclosure {}: {}", self.enclosure, self.error)
+        }
+    }
+
+    /// The state of one invocation.
+    ///
+    /// The enclosure discovery and the sg map are queried the first time
+    /// they are needed and reused afterwards, so every external query
+    /// happens once per run whatever the number of callers.
+    #[derive(Debug)]
+    pub struct Run {
+        /// Options of the scan.
+        pub options: ScanOptions,
+        enclosures: OnceLock<Vec<Enclosure>>,
+        sg_map: OnceLock<HashMap<String, String>>,
+        errors: Mutex<Vec<ScanError>>,
+    }
+
+    impl Run {
+        /// Returns a new context, nothing is probed yet.
+        ///
+        /// # Arguments
+        ///
+        /// * `options` - the ScanOptions of the run
+        ///
+        pub fn new(options: ScanOptions) -> Self {
+            Run {
+                options,
+                enclosures: OnceLock::new(),
+                sg_map: OnceLock::new(),
+                errors: Mutex::new(Vec::new()),
+            }
+        }
+
+        /// Returns every enclosure found by `BackPlane::get_enclosure`.
+        pub fn enclosures(&self) -> &[Enclosure] {
+            self.enclosures
+                .get_or_init(|| BackPlane::get_enclosure(self.options.jobs))
+        }
+
+        /// Returns the sg device to block device map from `sg_map`.
+        pub fn sg_map(&self) -> &HashMap<String, String> {
+            self.sg_map.get_or_init(DiskShelf::get_disk_sd_map)
+        }
+
+        /// Records the failure of an enclosure, the scan goes on.
+        ///
+        /// # Arguments
+        ///
+        /// * `enclosure` - the enclosure identification
+        /// * `error` - what went wrong
+        ///
+        pub fn record_error(&self, enclosure: &str, error: String) {
+            self.errors
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(ScanError {
+                    enclosure: enclosure.to_string(),
+                    error,
+                });
+        }
+
+        /// Returns the failures recorded so far.
+        pub fn errors(&self) -> Vec<ScanError> {
+            self.errors.lock().unwrap_or_else(|e| e.into_inner()).clone()
+        }
+    }
+}
@@ -32,44 +32,244 @@
 pub mod DiskShelf {
     use clap::ArgMatches;
     use colored::*;
+    use nix::unistd::{access, AccessFlags};
     use std::collections::HashMap;
     use std::fs;
-    use std::io::{BufRead, BufReader};
-    use std::process::{exit, Command, Stdio};
+    use std::path::Path;
+    use std::process::exit;
+    use serde::{Deserialize, Serialize};
+    use std::time::Duration;
 
+    use crate::jbod::cache::Cache;
+    use crate::jbod::context::Context;
+    use crate::jbod::diskstats::DiskStats;
+    use crate::jbod::element::Element::{LedState, SlotStatus};
     use crate::jbod::enclosure::BackPlane;
+    use crate::jbod::io_errors::IoErrors;
+    use crate::jbod::quirks::Quirks;
+    use crate::jbod::resolver::Resolver;
+    use crate::jbod::sed::Sed;
+    use crate::jbod::sgio::SgIo;
+    use crate::jbod::standby::Standby;
+    use crate::jbod::topology::Topology;
+    use crate::utils::audit::Audit;
+    use crate::utils::config::Config;
     use crate::utils::helper::Util;
-    use crate::utils::helper::Util::{SCSI_TEMP, SGINFO, SG_MAP};
+    use crate::utils::helper::Util::{SCSI_TEMP, SGINFO, SG_MAP, SG_VPD};
+    use crate::utils::i18n::I18n;
+    use crate::utils::i18n::I18n::Message;
+    use crate::utils::output::Output;
+    use crate::utils::output::Output::ErrorCode;
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone, Serialize, Deserialize)]
     pub struct Disk {
         // Enclosure number identification, example: 15:0:1:0
         pub enclosure: String,
         // Disk slopt identification
         pub slot: String,
+        // Physical slot label printed on the chassis, from the configuration
+        pub label: Option<String>,
+        // Spare or reserved disk, from the configuration
+        #[serde(default)]
+        pub role: Option<Config::Role>,
         // Disk path, example: /dev/sg105
         pub device_path: String,
         // Disk map, example: /dev/sdcz
         pub device_map: String,
         // Disk temperature
-        pub temperature: String,
+        pub temperature: Temperature,
         // Disk vendor
         pub vendor: String,
         // Disk model
         pub model: String,
         // Disk serial number
         pub serial: String,
+        // Disk world wide identifier from sysfs, example: naa.5000cca26b6a1234
+        #[serde(default)]
+        pub wwn: String,
+        // Disk SAS address, empty off a SAS fabric, example: 0x5000cca26b6a1235
+        #[serde(default)]
+        pub sas_address: String,
+        // Other sg nodes of the same disk, seen through another path
+        #[serde(default)]
+        pub alternate_paths: Vec<String>,
         // Disk firmware revision
         pub fw_revision: String,
-        // Path to led control file
-        pub led_locate_path: String,
-        // Path to led control file
-        pub led_fault_path: String,
+        // SES status of the slot
+        pub status: SlotStatus,
+        // Path to led control file, None when the enclosure has no locate led
+        pub led_locate_path: Option<String>,
+        // Path to led control file, None when the enclosure has no fault led
+        pub led_fault_path: Option<String>,
+        // TCG state of the disk, only collected on request
+        #[serde(default)]
+        pub sed: Option<Sed::Status>,
+        // Write cache enabled, the WCE bit of the caching page, only
+        // collected on request
+        #[serde(default)]
+        pub write_cache: Option<bool>,
+        // Power condition of the disk, only collected on request
+        #[serde(default)]
+        pub power: Option<Standby::Power>,
+        // I/O error counters of the SCSI midlayer, only collected on request
+        #[serde(default)]
+        pub io_errors: Option<IoErrors::Counters>,
+        // Activity of the mapped block device, only collected on request
+        #[serde(default)]
+        pub io: Option<DiskStats::Utilization>,
+        // Percentage of the rated endurance used by a flash disk, only
+        // collected on request
+        #[serde(default)]
+        pub wear: Option<u8>,
+        // Failure predicted by the drive (SMART trip), only collected on
+        // request, example: asc 0x5d ascq 0x10
+        #[serde(default)]
+        pub predicted_failure: Option<String>,
     }
 
-    /// Returns a string with the temperature
+    /// The temperature of a disk.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(into = "TemperatureValue", from = "TemperatureValue")]
+    pub enum Temperature {
+        /// The current temperature.
+        Celsius(i32),
+        /// The drive doesn't report its temperature.
+        Unsupported,
+        /// The probe failed.
+        Unreadable,
+        /// The temperature was not requested.
+        NotCollected,
+    }
+
+    /// How a Temperature is written in the machine readable outputs and the
+    /// cache, a number of Celsius or the name of the state.
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum TemperatureValue {
+        Celsius(i32),
+        State(String),
+    }
+
+    impl From<Temperature> for TemperatureValue {
+        fn from(temperature: Temperature) -> Self {
+            match temperature {
+                Temperature::Celsius(t) => TemperatureValue::Celsius(t),
+                Temperature::Unsupported => TemperatureValue::State("unsupported".to_string()),
+                Temperature::Unreadable => TemperatureValue::State("unreadable".to_string()),
+                Temperature::NotCollected => TemperatureValue::State("not_collected".to_string()),
+            }
+        }
+    }
+
+    impl From<TemperatureValue> for Temperature {
+        fn from(value: TemperatureValue) -> Self {
+            match value {
+                TemperatureValue::Celsius(t) => Temperature::Celsius(t),
+                TemperatureValue::State(s) => match s.as_str() {
+                    "unsupported" => Temperature::Unsupported,
+                    "not_collected" => Temperature::NotCollected,
+                    _ => Temperature::Unreadable,
+                },
+            }
+        }
+    }
+
+    /// The expensive fields of a disk, each one costs a subprocess or a
+    /// sysfs read per disk, the others are always collected.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Fields {
+        pub temperature: bool,
+        pub firmware: bool,
+        pub serial: bool,
+    }
+
+    impl Fields {
+        /// Returns the Fields with everything collected.
+        pub fn all() -> Self {
+            Fields {
+                temperature: true,
+                firmware: true,
+                serial: true,
+            }
+        }
+
+        /// Returns the Fields with only the location of the disks collected.
+        pub fn minimal() -> Self {
+            Fields {
+                temperature: false,
+                firmware: false,
+                serial: false,
+            }
+        }
+    }
+
+    /// Options controlling how the disks are scanned.
+    #[derive(Debug, Clone)]
+    pub struct ScanOptions {
+        /// Maximum number of disks probed at the same time.
+        pub jobs: usize,
+        /// Fields collected for each disk, the others are left empty.
+        pub fields: Fields,
+        /// Maximum age of the on-disk topology cache, None disables it.
+        pub cache: Option<Duration>,
+        /// Only this enclosure is scanned, see `Enclosure::matches`.
+        pub enclosure: Option<String>,
+        /// Keep one entry per sg node instead of merging dual-ported disks.
+        pub raw_paths: bool,
+    }
+
+    impl Default for ScanOptions {
+        fn default() -> Self {
+            ScanOptions {
+                jobs: Config::get().jobs.unwrap_or(Util::DEFAULT_JOBS),
+                fields: Fields::all(),
+                cache: None,
+                enclosure: None,
+                raw_paths: false,
+            }
+        }
+    }
+
+    impl ScanOptions {
+        /// Returns the ScanOptions from the command line, falling back on
+        /// the configuration.
+        pub fn from_matches(options: &ArgMatches) -> Self {
+            let mut scan = ScanOptions::default();
+            if let Some(jobs) = options.value_of("jobs").and_then(|j| j.parse().ok()) {
+                scan.jobs = jobs;
+            }
+            // `--no-cache` only skips the cache, `jbod cache clear` removes it.
+            if options.is_present("no-cache") {
+                scan.cache = None;
+            } else if let Some(ttl) = options.value_of("cache").and_then(Util::parse_duration) {
+                scan.cache = Some(ttl);
+            }
+            scan.raw_paths = matches!(options.try_contains_id("raw-paths"), Ok(true));
+            if let Ok(Some(enclosure)) = options.try_get_one::<String>("enclosure") {
+                scan.enclosure = Some(enclosure.clone());
+            }
+            if let Ok(Some(fields)) = options.try_get_many::<String>("fields") {
+                let fields: Vec<&String> = fields.collect();
+                scan.fields = Fields {
+                    temperature: fields.iter().any(|f| *f == "temperature"),
+                    firmware: fields.iter().any(|f| *f == "firmware"),
+                    serial: fields.iter().any(|f| *f == "serial"),
+                };
+            }
+            // Without access to the sg nodes every SCSI query fails, only
+            // the sysfs attributes are listed.
+            if Util::unreadable_sg_node().is_some() {
+                scan.fields.temperature = false;
+                scan.fields.firmware = false;
+            }
+            scan
+        }
+    }
+
+    /// Returns the Temperature of a disk
     ///
-    /// This function is a wrapper over scsi_temperature script.
+    /// This function is a wrapper over scsi_temperature script, the third
+    /// line holds the current temperature or says it isn't available.
     ///
     /// # Argumets
     ///
@@ -80,16 +280,22 @@ pub mod DiskShelf {
     /// let temperature = get_disk_temperature("/dev/sg100");
     /// ```
     ///
-    fn get_disk_temperature(disk: String) -> String {
-        let scsi_temp_cmd = Command::new(SCSI_TEMP)
-            .args(&[disk])
-            .output()
-            .expect("Failed to scsi_temperature the device");
-        let scsi_temp_output = String::from_utf8_lossy(&scsi_temp_cmd.stdout);
+    fn get_disk_temperature(disk: String) -> Temperature {
+        let scsi_temp_output = Util::command_stdout(SCSI_TEMP, &[&disk]);
         let output_spl: Vec<&str> = scsi_temp_output.split('\n').collect();
-        let temperature: String = output_spl[2].chars().filter(|n| n.is_digit(10)).collect();
+        let line = match output_spl.get(2) {
+            Some(line) => line,
+            None => return Temperature::Unreadable,
+        };
+        if line.contains("not available") || line.contains("not supported") {
+            return Temperature::Unsupported;
+        }
+        let temperature: String = line.chars().filter(|n| n.is_ascii_digit()).collect();
 
-        temperature
+        match temperature.parse() {
+            Ok(t) => Temperature::Celsius(t),
+            Err(_) => Temperature::Unreadable,
+        }
     }
 
     /// Returns a string with the disk firmware version
@@ -107,11 +313,7 @@ pub mod DiskShelf {
     ///
     fn get_disk_firmware(disk: String) -> String {
         let mut fw_revision = String::new();
-        let sginfo_temp_cmd = Command::new(SGINFO)
-            .args(&[disk])
-            .output()
-            .expect("Failed to sginfo the device");
-        let sginfo_temp_output = String::from_utf8_lossy(&sginfo_temp_cmd.stdout);
+        let sginfo_temp_output = Util::command_stdout(SGINFO, &[&disk]);
 
         for fw_info in sginfo_temp_output.split('\n') {
             if fw_info.contains("Revision level") {
@@ -122,25 +324,58 @@ pub mod DiskShelf {
 
         fw_revision
     }
-    /// Returns a string with the disk serial number
+    /// Returns a string with the disk serial number or N/A
+    ///
+    /// The unit serial number page is often unreadable for SATA drives
+    /// behind a SAS expander, so the sources are tried in order: SG_IO VPD
+    /// page 0x80, the sysfs `vpd_pg80`, the serial of a t10 `wwid`,
+    /// `sg_vpd` and finally the ATA IDENTIFY data.
     ///
     /// # Arguments
     ///
-    /// * `disk` - a string with the device path
+    /// * `disk` - a string with the sysfs device path
+    /// * `device_path` - a string with the device path
+    /// * `sg` - the SG_IO device if it could be opened
     ///
     /// # Example
     /// ```
-    /// let serial = get_disk_serial("/dev/sg100");
+    /// let serial = get_disk_serial(generic_device, "/dev/sg100", None);
     /// ```
     ///
-    fn get_disk_serial(disk: String) -> String {
-        let res = fs::read(disk + "/vpd_pg80");
-        let content = match res {
-            Ok(c) => c.to_vec(),
-            Err(_err) => "N/A".as_bytes().to_vec(),
-        };
+    fn get_disk_serial(disk: String, device_path: &str, sg: Option<&SgIo::Device>) -> String {
+        if let Some(serial) = sg.and_then(|d| d.serial().ok()) {
+            return serial;
+        }
+
+        if let Ok(page) = fs::read(format!("{disk}/vpd_pg80")) {
+            let serial = Util::vpd_serial(&page);
+            if !serial.is_empty() {
+                return serial;
+            }
+        }
+
+        // SATA drives get a wwid like "t10.ATA     <model>     <serial>".
+        if let Ok(wwid) = fs::read_to_string(format!("{disk}/wwid")) {
+            if let Some(serial) = Util::t10_serial(&wwid) {
+                return serial.to_string();
+            }
+        }
+
+        if Util::path_exists(SG_VPD) {
+            let sg_vpd_output = Util::command_stdout(SG_VPD, &["-p", "sn", device_path]);
+            for line in sg_vpd_output.lines() {
+                if let Some(serial) = line.trim().strip_prefix("Unit serial number:") {
+                    if !serial.trim().is_empty() {
+                        return serial.trim().to_string();
+                    }
+                }
+            }
+        }
 
-        unsafe { String::from_utf8_unchecked(content.to_vec()).to_string() }
+        match sg.and_then(|d| d.ata_serial().ok()) {
+            Some(serial) => serial,
+            None => "N/A".to_string(),
+        }
     }
 
     /// Returns a string with the disk vendor
@@ -161,11 +396,7 @@ pub mod DiskShelf {
             Err(_err) => "N/A".as_bytes().to_vec(),
         };
 
-        unsafe {
-            let mut s = String::from_utf8_unchecked(content.to_vec()).to_string();
-            s.truncate(s.len() - 1);
-            s
-        }
+        String::from_utf8_lossy(&content).trim_end().to_string()
     }
 
     /// Returns a string with the disk model
@@ -186,42 +417,30 @@ pub mod DiskShelf {
             Err(_err) => "N/A".as_bytes().to_vec(),
         };
 
-        unsafe {
-            let mut s = String::from_utf8_unchecked(content.to_vec()).to_string();
-            s.truncate(s.len() - 1);
-            s
-        }
+        String::from_utf8_lossy(&content).trim_end().to_string()
     }
 
     /// Returns a HashMap with two strings, example: /dev/sg116 and /dev/sddk
     ///
     /// This function is a wraper over sg_map
     ///
-    fn get_disk_sd_map() -> HashMap<String, String> {
+    pub fn get_disk_sd_map() -> HashMap<String, String> {
         let mut disks: HashMap<String, String> = HashMap::new();
-        let mut sg_map_cmd = Command::new(SG_MAP).stdout(Stdio::piped()).spawn().unwrap();
-
-        {
-            let stdout = sg_map_cmd.stdout.as_mut().unwrap();
-            let stdout_reader = BufReader::new(stdout);
-            let stdout_lines = stdout_reader.lines();
-
-            for line in stdout_lines {
-                let line_unwrap = line.unwrap().to_owned();
-                let output_split: Vec<&str> = line_unwrap.split_whitespace().collect();
-                if output_split.len() > 1 {
-                    disks.insert(output_split[0].to_string(), output_split[1].to_string());
-                } else {
-                    disks.insert(output_split[0].to_string(), "NONE".to_string());
-                }
+        let sg_map_output = Util::command_stdout(SG_MAP, &[]);
+
+        for line in sg_map_output.lines() {
+            let output_split: Vec<&str> = line.split_whitespace().collect();
+            if output_split.len() > 1 {
+                disks.insert(output_split[0].to_string(), output_split[1].to_string());
+            } else if let Some(sg) = output_split.first() {
+                disks.insert(sg.to_string(), "NONE".to_string());
             }
         }
-        sg_map_cmd.wait().unwrap();
 
         disks
     }
 
-    /// Returns a string with the led file location or NONE
+    /// Returns a string with the led file location or None
     ///
     /// This function tries to localize if the enclosure provide led support via file
     ///
@@ -230,25 +449,24 @@ pub mod DiskShelf {
     /// * `enclosure_slot` - a string reference with the enclosure_slot identification
     /// * `disk_slot` - a string reference with the disk slot number identification
     ///
-    fn get_disk_led_locate_path(enclosure_slot: &str, disk_slot: &str) -> String {
-        let sys_class_enclosure: &str = "/sys/class/enclosure/";
+    fn get_disk_led_locate_path(enclosure_slot: &str, disk_slot: &str) -> Option<String> {
+        let sys_class_enclosure = Util::sys_class_enclosure();
 
-        Util::verify_sysclass_folder(sys_class_enclosure);
+        Util::verify_sysclass_folder(&sys_class_enclosure);
 
-        let led_locate_path = sys_class_enclosure.to_string()
-            + enclosure_slot
-            + &"/".to_string()
-            + &disk_slot.to_string()
-            + &"/locate".to_string();
+        let led_locate_path = sys_class_enclosure
+            .join(enclosure_slot)
+            .join(disk_slot)
+            .join("locate");
 
-        if Util::path_exists(&led_locate_path) {
-            return led_locate_path;
+        if led_locate_path.exists() {
+            Some(led_locate_path.to_string_lossy().to_string())
         } else {
-            return "NONE".to_string();
+            None
         }
     }
 
-    /// Returns a string with the led file location or NONE
+    /// Returns a string with the led file location or None
     ///
     /// This function tries to localize if the enclosure provide led support via file
     ///
@@ -257,186 +475,256 @@ pub mod DiskShelf {
     /// * `enclosure_slot` - a string reference with the enclosure_slot identification
     /// * `disk_slot` - a string reference with the disk slot number identification
     ///
-    fn get_disk_led_fault_path(enclosure_slot: &str, disk_slot: &str) -> String {
-        let sys_class_enclosure: &str = "/sys/class/enclosure/";
+    fn get_disk_led_fault_path(enclosure_slot: &str, disk_slot: &str) -> Option<String> {
+        let sys_class_enclosure = Util::sys_class_enclosure();
 
-        Util::verify_sysclass_folder(sys_class_enclosure);
+        Util::verify_sysclass_folder(&sys_class_enclosure);
 
-        let led_fault_path = sys_class_enclosure.to_string()
-            + enclosure_slot
-            + &"/".to_string()
-            + &disk_slot.to_string()
-            + &"/fault".to_string();
+        let led_fault_path = sys_class_enclosure
+            .join(enclosure_slot)
+            .join(disk_slot)
+            .join("fault");
 
-        if Util::path_exists(&led_fault_path) {
-            return led_fault_path;
+        if led_fault_path.exists() {
+            Some(led_fault_path.to_string_lossy().to_string())
         } else {
-            return "NONE".to_string();
+            None
         }
     }
 
-    /// Here we write 0 or 1 into the disk led file
-    fn set_disk_led_locate(disk: String, option: &str) {
-        if Util::path_exists(&disk) {
-            let jbod = jbod_disk_map();
-            let found_disk: Vec<Disk> = jbod
-                .into_iter()
-                .filter(|v| (v.device_path == disk) || (v.device_map == disk))
-                .collect();
-            if !found_disk.is_empty() {
-                if Util::path_exists(&found_disk[0].led_locate_path) {
-                    fs::write(&found_disk[0].led_locate_path, option.clone())
-                        .expect("Unable to write on locate led");
-                    match option {
-                        "0" => {
-                            println!(
-                                "Disk slot: {} {}",
-                                found_disk[0].slot.green().bold(),
-                                option
-                            );
-                        }
-                        "1" => {
-                            println!(
-                                "Disk slot: {} {}",
-                                found_disk[0].slot.yellow().blink().bold(),
-                                option
-                            );
-                        }
-                        _ => println!("Option not identified"),
-                    }
-                } else {
-                    println!(
-                        "{}: {} does not expose locate led",
-                        "Error".red().bold(),
-                        disk.yellow().bold()
-                    );
-                }
+    /// Returns " (label)" when the slot has a physical label, or an empty string.
+    pub fn slot_label_suffix(label: &Option<String>) -> String {
+        match label {
+            Some(label) => format!(" ({})", label),
+            None => String::new(),
+        }
+    }
+
+    /// Prints the slot of a disk colored by the state of its led.
+    fn print_led_state(found_disk: &Resolver::Location, state: LedState, on: ColoredString) {
+        let slot = match state {
+            LedState::Off => found_disk.slot.green().bold(),
+            LedState::On => on,
+        };
+        println!(
+            "Disk slot: {}{} {}",
+            slot,
+            slot_label_suffix(&found_disk.label),
+            state.as_sysfs()
+        );
+    }
+
+    /// Writes the LedState into a led file or returns the ErrorCode and the
+    /// reason.
+    ///
+    /// The permission is checked first, so an unprivileged user is told how
+    /// to get it instead of getting a bare EACCES. The write holds the
+    /// control lock of the enclosure, see `Util::lock_enclosure`. Every
+    /// attempt is recorded in the audit log.
+    ///
+    /// # Arguments
+    ///
+    /// * `enclosure` - the enclosure of the slot, example: 15:0:1:0
+    /// * `led` - the led file, example: /sys/class/enclosure/15:0:1:0/Slot 05/locate
+    /// * `state` - the state to write
+    ///
+    pub fn try_write_led(enclosure: &str, led: &str, state: LedState) -> Result<(), (ErrorCode, String)> {
+        let path = Path::new(led);
+        let action = format!(
+            "led_{}",
+            path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default()
+        );
+        let target = format!(
+            "{}/{}",
+            enclosure,
+            path.parent()
+                .and_then(|p| p.file_name())
+                .map(|n| n.to_string_lossy())
+                .unwrap_or_default()
+        );
+        let old = LedState::read(path).ok().map(|s| s.to_string());
+        let audit = |result| Audit::record(&action, &target, old.as_deref(), &state.to_string(), result);
+
+        if access(led, AccessFlags::W_OK).is_err() {
+            audit(Err("permission denied"));
+            return Err((
+                ErrorCode::PermissionDenied,
+                format!("{} is not writable by this user", led),
+            ));
+        }
+        let _lock = match Util::lock_enclosure(enclosure) {
+            Ok(lock) => Some(lock),
+            Err(e) => {
+                Output::print_warning(&format!("cannot lock enclosure {}: {}", enclosure, e));
+                None
             }
-        } else {
-            println!(
-                "{} device {} not found",
-                "Error:".red().bold(),
-                disk.yellow().bold(),
-            );
-            exit(1);
+        };
+        if let Err(e) = state.write(path) {
+            audit(Err(&e.to_string()));
+            return Err((ErrorCode::from_io(&e), format!("cannot write {}: {}", led, e)));
         }
+        audit(Ok(()));
+        Ok(())
     }
 
-    /// Here we write 0 or 1 into the disk led file
-    fn set_disk_led_fault(disk: String, option: &str) {
-        if Util::path_exists(&disk) {
-            let jbod = jbod_disk_map();
-            let found_disk: Vec<Disk> = jbod
-                .into_iter()
-                .filter(|v| (v.device_path == disk) || (v.device_map == disk))
-                .collect();
-            if !found_disk.is_empty() {
-                if Util::path_exists(&found_disk[0].led_fault_path) {
-                    fs::write(&found_disk[0].led_fault_path, option.clone())
-                        .expect("Unable to write on locate led");
-                    match option {
-                        "0" => {
-                            println!(
-                                "Disk slot: {} {}",
-                                found_disk[0].slot.green().bold(),
-                                option
-                            );
-                        }
-                        "1" => {
-                            println!(
-                                "Disk slot: {} {}",
-                                found_disk[0].slot.red().blink().bold(),
-                                option
-                            );
-                        }
-                        _ => println!("Option not identified"),
-                    }
-                } else {
-                    println!(
-                        "{}: {} does not expose fault led",
-                        "Error".red().bold(),
-                        disk.yellow().bold()
-                    );
-                }
+    /// Writes the LedState into a led file or exits with the reason, see
+    /// `try_write_led`.
+    pub fn write_led(enclosure: &str, led: &str, state: LedState) {
+        if let Err((code, message)) = try_write_led(enclosure, led, state) {
+            Output::print_error_code(code, &message);
+            if code == ErrorCode::PermissionDenied {
+                Output::print_hint(&Util::permission_guidance(led));
             }
-        } else {
-            println!(
-                "{} device {} not found",
-                "Error:".red().bold(),
-                disk.yellow().bold(),
-            );
             exit(1);
         }
     }
 
-    /// Returns strings with enclouse, slot, device path, temperature and the location of
-    /// the led's files
-    ///
-    /// This function gets all details of a disk.
+    /// Returns where a disk sits and its `locate` or `fault` led file, or
+    /// the ErrorCode and why the led can't be switched.
     ///
     /// # Arguments
     ///
-    /// * `device` - a string with the device path
-    /// * `enclosure_slot` - the enclosure slot number, example: 15:0:0:0
+    /// * `disk` - a serial number, a WWN, a device or `<enclosure>/<slot>`
+    /// * `led` - `locate` or `fault`
     ///
-    fn get_disk_details(
-        device: String,
-        enclosure_slot: String,
-    ) -> (
-        String,
+    pub fn disk_led(disk: &str, led: &str) -> Result<(Resolver::Location, String), (ErrorCode, String)> {
+        let location = Resolver::locate(disk)
+            .ok_or_else(|| (ErrorCode::NotFound, format!("device {} not found", disk)))?;
+        let (path, missing) = match led {
+            "locate" => (location.led_locate_path.clone(), Message::NoLocateLed),
+            "fault" => (location.led_fault_path.clone(), Message::NoFaultLed),
+            _ => {
+                return Err((
+                    ErrorCode::InvalidArgument,
+                    format!("invalid led {}, expected locate or fault", led),
+                ))
+            }
+        };
+        match path {
+            Some(path) => Ok((location, path)),
+            None => Err((ErrorCode::Unsupported, I18n::tr(missing, &[&disk]))),
+        }
+    }
+
+    /// Here we write the LedState into the `locate` or `fault` led file of
+    /// a disk, an error is printed when it has no such led.
+    fn set_disk_led(disk: &str, led: &str, state: LedState) -> Result<(), ()> {
+        let (found_disk, path) = disk_led(disk, led).map_err(|(code, e)| Output::print_error_code(code, &e))?;
+        write_led(&found_disk.enclosure, &path, state);
+        let on = match led {
+            "fault" => found_disk.slot.red().blink().bold(),
+            _ => found_disk.slot.yellow().blink().bold(),
+        };
+        print_led_state(&found_disk, state, on);
+        Ok(())
+    }
+
+    /// The enclosure, slot, device path, temperature, firmware revision, vendor,
+    /// model, serial, wwn, sas address, status and led files of a disk.
+    type DiskDetails = (
         String,
         String,
         String,
+        Temperature,
         String,
         String,
         String,
         String,
         String,
         String,
-    ) {
-        let sys_class_enclosure: &str = "/sys/class/enclosure/";
+        SlotStatus,
+        Option<String>,
+        Option<String>,
+    );
+
+    /// Returns strings with enclouse, slot, device path, temperature and the location of
+    /// the led's files
+    ///
+    /// This function gets all details of a disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - a string with the device path
+    /// * `enclosure_slot` - the enclosure slot number, example: 15:0:0:0
+    /// * `fields` - the expensive fields to collect
+    /// * `quirk` - the quirk of the enclosure family
+    ///
+    fn get_disk_details(
+        device: String,
+        enclosure_slot: String,
+        fields: &Fields,
+        quirk: &Quirks::Quirk,
+    ) -> DiskDetails {
+        let sys_class_enclosure = Util::sys_class_enclosure();
         let mut enclosure = String::new();
         let mut slot = String::new();
         let mut device_path = String::new();
-        let mut temperature = String::new();
+        let mut temperature = Temperature::NotCollected;
         let mut fw_revision = String::new();
         let mut vendor = String::new();
         let mut model = String::new();
         let mut serial = String::new();
-        let mut disk_locate_led = String::new();
-        let mut disk_fault_led = String::new();
+        let mut wwn = String::new();
+        let mut sas_address = String::new();
+        let mut status = SlotStatus::Unknown;
+        let mut disk_locate_led = None;
+        let mut disk_fault_led = None;
 
-        let path_tostr_spl: Vec<&str> = device.split('/').collect();
-        let _slot = path_tostr_spl[5];
-        let cmp_slot = _slot.to_lowercase();
+        let component = Path::new(&device)
+            .file_name()
+            .map(|c| c.to_string_lossy().to_string())
+            .unwrap_or_default();
 
-        Util::verify_sysclass_folder(sys_class_enclosure);
+        Util::verify_sysclass_folder(&sys_class_enclosure);
 
-        if cmp_slot.contains("slot")
-            || cmp_slot.contains("disk")
-            || cmp_slot.contains("array device")
-            || cmp_slot.bytes().all(|c| c.is_ascii_digit())
-        {
-            let generic_device = format!("{sys_class_enclosure}{enclosure_slot}/{_slot}/device");
+        if quirk.is_slot_component(&component) {
+            let component_path = sys_class_enclosure.join(&enclosure_slot).join(&component);
+            let generic_device = component_path.join("device").to_string_lossy().to_string();
             let physical_device = format!("{generic_device}/scsi_generic/");
 
-            if Util::path_exists(&physical_device) {
-                let physical_path = fs::read_dir(physical_device).unwrap();
-                for dev in physical_path {
-                    let _dev = dev.unwrap().path();
-                    let __dev = _dev.to_str().unwrap();
-                    let split_dev: Vec<&str> = __dev.split('/').collect();
-                    let __get_slot: Vec<&str> = split_dev[5].split(',').collect();
-                    enclosure = split_dev[4].to_string();
-                    slot = __get_slot[0].to_string();
-                    device_path = format!("/dev/{}", split_dev[8]);
-                    temperature = get_disk_temperature(device_path.clone());
-                    fw_revision = get_disk_firmware(device_path.clone());
+            if let Ok(physical_path) = fs::read_dir(physical_device) {
+                for dev in physical_path.flatten() {
+                    enclosure = enclosure_slot.clone();
+                    slot = component.split(',').next().unwrap_or(&component).to_string();
+                    device_path = format!("/dev/{}", dev.file_name().to_string_lossy());
+                    // A single fd answers every SCSI query, the helper
+                    // binaries and sysfs are only used when SG_IO fails.
+                    let sg = SgIo::Device::open(&device_path).ok();
+                    if fields.temperature {
+                        temperature = match sg.as_ref().map(|d| d.temperature()) {
+                            Some(Ok(Some(t))) => Temperature::Celsius(t),
+                            Some(Ok(None)) => Temperature::Unsupported,
+                            _ => get_disk_temperature(device_path.clone()),
+                        };
+                    }
+                    if fields.firmware {
+                        fw_revision = match sg.as_ref().and_then(|d| d.inquiry().ok()) {
+                            Some(inquiry) => inquiry.revision,
+                            None => get_disk_firmware(device_path.clone()),
+                        };
+                    }
                     vendor = get_disk_vendor(generic_device.clone().to_string());
                     model = get_disk_model(generic_device.clone().to_string());
-                    serial = get_disk_serial(generic_device.clone().to_string());
-                    disk_locate_led = get_disk_led_locate_path(&enclosure_slot, split_dev[5]);
-                    disk_fault_led = get_disk_led_fault_path(&enclosure_slot, split_dev[5]);
+                    if fields.serial {
+                        serial = get_disk_serial(generic_device.clone(), &device_path, sg.as_ref());
+                    }
+                    wwn = fs::read_to_string(format!("{generic_device}/wwid"))
+                        .map(|w| w.trim().to_string())
+                        .unwrap_or_default();
+                    // SATA disks get a t10 wwid, the SAT layer translates
+                    // their WWN into the NAA designator used by udev and ZFS.
+                    if !wwn.starts_with("naa.") {
+                        if let Some(naa) = sg.as_ref().and_then(|d| d.wwn().ok()) {
+                            wwn = naa;
+                        }
+                    }
+                    sas_address = fs::canonicalize(&generic_device)
+                        .ok()
+                        .and_then(|d| Topology::device_sas_address(&d))
+                        .unwrap_or_default();
+                    status = SlotStatus::read(&component_path);
+                    disk_locate_led = get_disk_led_locate_path(&enclosure_slot, &component);
+                    disk_fault_led = get_disk_led_fault_path(&enclosure_slot, &component);
                 }
             }
         }
@@ -449,6 +737,9 @@ pub mod DiskShelf {
             vendor,
             model,
             serial,
+            wwn,
+            sas_address,
+            status,
             disk_locate_led,
             disk_fault_led,
         )
@@ -456,68 +747,366 @@ pub mod DiskShelf {
 
     /// Returns a vector of disk structure
     ///
-    /// This function collects all information of a disk
+    /// This function collects all information of a disk, the slots of every
+    /// enclosure are probed concurrently and returned in the enclosure order.
     ///
     /// # Arguments
     ///
     /// * `enc_vec` - A vector including all enclosures we want to scan for disks.
+    /// * `run` - a reference of the run Context
     ///
-    fn get_disks_per_enclosure(enc_vec: Vec<BackPlane::Enclosure>) -> Vec<Disk> {
-        let mut disk: Vec<Disk> = Vec::new();
-        let sys_class_enclosure: &str = "/sys/class/enclosure/";
-        let sg_map = get_disk_sd_map(); // Get all sg_map once in a HashMap
-
-        Util::verify_sysclass_folder(sys_class_enclosure);
-
-        for enclosure in enc_vec {
-            let paths = fs::read_dir(sys_class_enclosure.to_string() + &enclosure.slot).unwrap();
-            for path in paths {
-                let _get_path = path.unwrap().path();
-
-                let path_tostr = _get_path.to_str().unwrap();
-                let (
-                    _enclosure,
-                    _slot,
-                    _device_path,
-                    _temperature,
-                    _fw_revision,
-                    _vendor,
-                    _model,
-                    _serial,
-                    _led_locate_path,
-                    _led_fault_path,
-                ) = get_disk_details(path_tostr.to_string(), enclosure.slot.to_string());
-
-                if !_device_path.is_empty() {
-                    disk.push(Disk {
-                        enclosure: _enclosure,
-                        slot: _slot,
-                        device_map: sg_map.get(&_device_path).unwrap().to_string(),
-                        device_path: _device_path,
-                        temperature: _temperature,
-                        fw_revision: _fw_revision,
-                        vendor: _vendor,
-                        model: _model,
-                        serial: _serial,
-                        led_locate_path: _led_locate_path,
-                        led_fault_path: _led_fault_path,
-                    });
+    fn get_disks_per_enclosure(enc_vec: Vec<BackPlane::Enclosure>, run: &Context::Run) -> Vec<Disk> {
+        let options = &run.options;
+        let sys_class_enclosure = Util::sys_class_enclosure();
+        let sg_map = run.sg_map();
+
+        Util::verify_sysclass_folder(&sys_class_enclosure);
+
+        let mut slots: Vec<(&BackPlane::Enclosure, String)> = Vec::new();
+        for enclosure in &enc_vec {
+            let enclosure_path = sys_class_enclosure.join(&enclosure.slot);
+            let paths = match fs::read_dir(&enclosure_path) {
+                Ok(paths) => paths,
+                Err(e) => {
+                    run.record_error(
+                        &enclosure.slot,
+                        format!("cannot read {}: {}", enclosure_path.display(), e),
+                    );
+                    continue;
                 }
+            };
+            let mut enclosure_slots: Vec<String> = paths
+                .flatten()
+                .map(|p| p.path().to_string_lossy().to_string())
+                .collect();
+            enclosure_slots.sort_by(|a, b| Util::natural_cmp(a, b));
+            slots.extend(enclosure_slots.into_iter().map(|p| (enclosure, p)));
+        }
+
+        let message = match enc_vec.as_slice() {
+            [enclosure] => format!("Scanning enclosure {}", enclosure.slot),
+            _ => format!("Scanning {} enclosures", enc_vec.len()),
+        };
+        let progress = Util::Progress::start(message, slots.len() as u64, false);
+        let details = Util::parallel_map(&slots, options.jobs, |(enclosure, path)| {
+            let quirk = Quirks::for_enclosure(&enclosure.vendor, &enclosure.model);
+            let detail =
+                get_disk_details(path.to_string(), enclosure.slot.to_string(), &options.fields, quirk);
+            progress.inc(1);
+            detail
+        });
+        drop(progress);
+
+        let mut disk: Vec<Disk> = Vec::new();
+        for ((enclosure, _), detail) in slots.iter().zip(details) {
+            let (
+                _enclosure,
+                _slot,
+                _device_path,
+                _temperature,
+                _fw_revision,
+                _vendor,
+                _model,
+                _serial,
+                _wwn,
+                _sas_address,
+                _status,
+                _led_locate_path,
+                _led_fault_path,
+            ) = detail;
+
+            if !_device_path.is_empty() {
+                let label =
+                    Quirks::slot_label(&enclosure.serial, &enclosure.vendor, &enclosure.model, &_slot);
+                let role =
+                    Config::get().disk_role(&enclosure.serial, &enclosure.model, &_slot, &_serial);
+                disk.push(Disk {
+                    enclosure: _enclosure,
+                    slot: _slot,
+                    label,
+                    role,
+                    device_map: sg_map
+                        .get(&_device_path)
+                        .cloned()
+                        .unwrap_or_else(|| "NONE".to_string()),
+                    device_path: _device_path,
+                    temperature: _temperature,
+                    fw_revision: _fw_revision,
+                    vendor: _vendor,
+                    model: _model,
+                    serial: _serial,
+                    wwn: _wwn,
+                    sas_address: _sas_address,
+                    alternate_paths: Vec::new(),
+                    status: _status,
+                    led_locate_path: _led_locate_path,
+                    led_fault_path: _led_fault_path,
+                    sed: None,
+                    write_cache: None,
+                    power: None,
+                    io_errors: None,
+                    io: None,
+                    wear: None,
+                    predicted_failure: None,
+                });
             }
         }
 
         disk
     }
 
+    /// Returns what identifies a physical disk, its wwn or else its serial,
+    /// None when neither is known.
+    fn disk_identity(disk: &Disk) -> Option<&str> {
+        [disk.wwn.as_str(), disk.serial.as_str()]
+            .into_iter()
+            .find(|id| !id.is_empty() && *id != "N/A")
+    }
+
+    /// Returns the disks not seen yet and records the paths of the others.
+    ///
+    /// A dual-ported SAS disk is found once per HBA with a different sg
+    /// node, the first entry is kept and the sg nodes of the next ones are
+    /// added to its `alternate_paths`.
+    ///
+    /// # Arguments
+    ///
+    /// * `known` - the disks merged so far, duplicates are recorded there
+    /// * `disks` - the disks to merge
+    ///
+    pub fn merge_paths(known: &mut Vec<Disk>, disks: Vec<Disk>) -> Vec<Disk> {
+        let mut added = Vec::new();
+        for disk in disks {
+            let duplicate = disk_identity(&disk).and_then(|id| {
+                known
+                    .iter_mut()
+                    .find(|k| disk_identity(k) == Some(id) && k.device_path != disk.device_path)
+            });
+            match duplicate {
+                Some(first) => first.alternate_paths.push(disk.device_path),
+                None => {
+                    known.push(disk.clone());
+                    added.push(disk);
+                }
+            }
+        }
+        added
+    }
+
+    /// Returns the disks with the dual-ported ones merged, see `merge_paths`.
+    fn dedupe_disks(disks: Vec<Disk>) -> Vec<Disk> {
+        let mut known = Vec::new();
+        merge_paths(&mut known, disks);
+        known
+    }
+
+    /// Sorts the disks by enclosure then slot in natural order, so
+    /// "Slot 2" comes before "Slot 10".
+    pub fn sort_disks(disks: &mut [Disk]) {
+        disks.sort_by(|a, b| {
+            Util::natural_cmp(&a.enclosure, &b.enclosure)
+                .then_with(|| Util::natural_cmp(&a.slot, &b.slot))
+        });
+    }
+
+    /// The keys the disks can be sorted by, see `sort_disks_by`.
+    pub const SORT_KEYS: &[&str] = &["slot", "temp", "serial", "device"];
+
+    /// Sorts the disks by one of `SORT_KEYS` in natural order, the disks
+    /// without a temperature come last, ties are kept in slot order.
+    ///
+    /// # Arguments
+    ///
+    /// * `disks` - the disks
+    /// * `key` - slot, temp, serial or device, anything else is slot
+    ///
+    pub fn sort_disks_by(disks: &mut [Disk], key: &str) {
+        sort_disks(disks);
+        match key {
+            "temp" => disks.sort_by_key(|d| match d.temperature {
+                Temperature::Celsius(t) => (false, t),
+                _ => (true, 0),
+            }),
+            "serial" => disks.sort_by(|a, b| Util::natural_cmp(&a.serial, &b.serial)),
+            "device" => disks.sort_by(|a, b| Util::natural_cmp(&a.device_path, &b.device_path)),
+            _ => {}
+        }
+    }
+
+    /// Returns a vector with the fields that could not be collected for a disk
+    ///
+    /// An empty vector means every field was read properly, it is used by
+    /// `--strict` to reject partial data. Fields that were not requested
+    /// are not errors.
+    ///
+    /// # Arguments
+    ///
+    /// * `disk` - a reference of Disk
+    /// * `fields` - the expensive fields that were requested
+    ///
+    pub fn collection_errors(disk: &Disk, fields: &Fields) -> Vec<String> {
+        let mut errors = Vec::new();
+        let missing = |v: &str| v.is_empty() || v == "N/A";
+
+        // A drive that doesn't report its temperature isn't a failure.
+        if fields.temperature && disk.temperature == Temperature::Unreadable {
+            errors.push("unreadable temperature".to_string());
+        }
+        if fields.serial && missing(&disk.serial) {
+            errors.push("missing serial".to_string());
+        }
+        if missing(&disk.vendor) {
+            errors.push("missing vendor".to_string());
+        }
+        if missing(&disk.model) {
+            errors.push("missing model".to_string());
+        }
+        if fields.firmware && missing(&disk.fw_revision) {
+            errors.push("missing firmware revision".to_string());
+        }
+
+        errors
+    }
+
     /// Returns a vector with disk structure
     ///
     /// This is the public function that returns all disks and its information.
     ///
-    pub fn jbod_disk_map() -> Vec<Disk> {
-        let enc = BackPlane::get_enclosure();
-        let disks = get_disks_per_enclosure(enc);
+    /// # Arguments
+    ///
+    /// * `run` - a reference of the run Context
+    ///
+    /// When the cache is enabled a fresh enough topology is returned without
+    /// probing anything, otherwise the scan result is cached.
+    ///
+    pub fn jbod_disk_map(run: &Context::Run) -> Vec<Disk> {
+        let options = &run.options;
+        let disks = scan_disk_map(run);
+        if options.raw_paths {
+            disks
+        } else {
+            dedupe_disks(disks)
+        }
+    }
 
-        disks
+    /// Returns the disks of `jbod_disk_map` without merging the paths.
+    fn scan_disk_map(run: &Context::Run) -> Vec<Disk> {
+        let options = &run.options;
+        if let Some(ttl) = options.cache {
+            if let Some(topology) = Cache::load(ttl, &options.fields) {
+                let enclosures = scoped_enclosures(topology.enclosures, options);
+                let mut disks = topology.disks;
+                disks.retain(|d| enclosures.iter().any(|e| e.slot == d.enclosure));
+                return disks;
+            }
+        }
+
+        let enc = run.enclosures().to_vec();
+        // A scoped scan is partial, it would hide the other shelves from
+        // the next cached run.
+        if options.cache.is_some() && options.enclosure.is_none() {
+            let disks = get_disks_per_enclosure(enc.clone(), run);
+            Cache::store(options.fields, enc, disks.clone());
+            return disks;
+        }
+
+        get_disks_per_enclosure(scoped_enclosures(enc, options), run)
+    }
+
+    /// Returns a vector with the disks of one enclosure.
+    ///
+    /// This function always probes the hardware, it lets the caller show
+    /// each enclosure as soon as it is scanned.
+    ///
+    /// # Arguments
+    ///
+    /// * `run` - a reference of the run Context
+    /// * `enclosure` - the enclosure to scan
+    ///
+    pub fn jbod_enclosure_disks(run: &Context::Run, enclosure: &BackPlane::Enclosure) -> Vec<Disk> {
+        get_disks_per_enclosure(vec![enclosure.clone()], run)
+    }
+
+    /// Returns the enclosures kept by the `enclosure` option of a scan.
+    ///
+    /// # Arguments
+    ///
+    /// * `enclosures` - all the enclosures found
+    /// * `options` - a reference of ScanOptions
+    ///
+    fn scoped_enclosures(
+        mut enclosures: Vec<BackPlane::Enclosure>,
+        options: &ScanOptions,
+    ) -> Vec<BackPlane::Enclosure> {
+        if let Some(id) = &options.enclosure {
+            enclosures.retain(|e| e.matches(id));
+        }
+        enclosures
+    }
+
+    /// Returns a vector with the Enclosure structure for each enclosure.
+    ///
+    /// This function uses the on-disk topology cache when it is enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `run` - a reference of the run Context
+    ///
+    pub fn jbod_enclosures(run: &Context::Run) -> Vec<BackPlane::Enclosure> {
+        let options = &run.options;
+        if let Some(ttl) = options.cache {
+            if let Some(mut topology) = Cache::load(ttl, &Fields::minimal()) {
+                // The location comes from the configuration, which may have
+                // changed since the topology was cached.
+                for enclosure in &mut topology.enclosures {
+                    enclosure.location =
+                        Config::get().enclosure_location(&enclosure.serial).cloned();
+                }
+                return scoped_enclosures(topology.enclosures, options);
+            }
+        }
+
+        scoped_enclosures(run.enclosures().to_vec(), options)
+    }
+
+    /// Returns the `<enclosure>/<slot>` of the bays given with `--slot`, the
+    /// enclosure is part of the slot or given with `--enclosure`.
+    ///
+    /// The bays are found from sysfs, so the led of a dead disk whose
+    /// device node is gone can still be switched.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `led` subcommand
+    ///
+    fn led_slots(options: &ArgMatches) -> Result<Vec<String>, (ErrorCode, String)> {
+        let enclosure = match options.value_of("enclosure") {
+            Some(id) if Util::sys_class_enclosure().join(id).exists() => Some(id.to_string()),
+            Some(id) => {
+                let run = Context::Run::new(ScanOptions::from_matches(options));
+                match jbod_enclosures(&run).first() {
+                    Some(enclosure) => Some(enclosure.slot.clone()),
+                    None => {
+                        return Err((ErrorCode::NotFound, I18n::tr(Message::NoEnclosure, &[&id])))
+                    }
+                }
+            }
+            None => None,
+        };
+
+        options
+            .values_of("slot")
+            .into_iter()
+            .flatten()
+            .map(|slot| match (Resolver::parse(slot), &enclosure) {
+                (Resolver::Identifier::Slot { .. }, _) => Ok(slot.to_string()),
+                (_, Some(enclosure)) => Ok(format!("{}/{}", enclosure, slot)),
+                (_, None) => Err((
+                    ErrorCode::InvalidArgument,
+                    format!("{} is not <enclosure>/<slot>, give its enclosure with --enclosure", slot),
+                )),
+            })
+            .collect()
     }
 
     /// [TODO] fix the return
@@ -544,32 +1133,162 @@ pub mod DiskShelf {
             exit(1);
         }
 
-        if is_locate {
-            let disk = options
-                .value_of("locate")
-                .unwrap_or(&"/dev/null".to_string())
-                .to_string();
-            if on {
-                set_disk_led_locate(disk.clone(), &"1".to_string());
+        let slots = led_slots(options).map_err(|(code, e)| Output::print_error_code(code, &e))?;
+        if !slots.is_empty() && !is_locate && !is_fault {
+            Output::print_error_code(ErrorCode::InvalidArgument, "--slot needs --locate or --fault");
+            return Err(());
+        }
+        // The disks given to --locate or --fault, or else the bays of --slot.
+        let targets = |led: &str| -> Result<Vec<String>, ()> {
+            let disks: Vec<String> = options.values_of(led).into_iter().flatten().map(String::from).collect();
+            match (disks.is_empty(), slots.is_empty()) {
+                (false, _) => Ok(disks),
+                (true, false) => Ok(slots.clone()),
+                (true, true) => {
+                    Output::print_error_code(
+                        ErrorCode::InvalidArgument,
+                        &format!("--{} needs a disk or --slot", led),
+                    );
+                    Err(())
+                }
+            }
+        };
+
+        // Every disk is switched, the command fails if one of them failed.
+        let mut switched = Ok(());
+        for (led, wanted) in [("locate", is_locate), ("fault", is_fault)] {
+            if !wanted {
+                continue;
             }
-            if off {
-                set_disk_led_locate(disk.clone(), &"0".to_string());
+            for disk in targets(led)? {
+                if on {
+                    switched = set_disk_led(&disk, led, LedState::On).and(switched);
+                }
+                if off {
+                    switched = set_disk_led(&disk, led, LedState::Off).and(switched);
+                }
             }
         }
 
-        if is_fault {
-            let disk = options
-                .value_of("fault")
-                .unwrap_or(&"/dev/null".to_string())
-                .to_string();
-            if on {
-                set_disk_led_fault(disk.clone(), &"1".to_string());
+        switched
+    }
+
+    /// Returns an error if the disk can't be found or located.
+    ///
+    /// This function is used in the `locate` menu option, it resolves a
+    /// disk by serial number, wwn, device or enclosure/slot, prints where it
+    /// is and turns its locate led on, or off with `--off`.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `locate` subcommand
+    ///
+    pub fn jbod_locate(options: &ArgMatches) -> Result<(), ()> {
+        let id = options.value_of("disk").unwrap_or_default();
+        let state = if options.is_present("off") {
+            LedState::Off
+        } else {
+            LedState::On
+        };
+
+        let found_disk = match Resolver::locate(id) {
+            Some(found_disk) => found_disk,
+            None => {
+                Output::print_error_code(ErrorCode::NotFound, &format!("device {} not found", id));
+                Output::print_hint("a serial number, a wwn, a device or <enclosure>/<slot> is expected");
+                return Err(());
             }
-            if off {
-                set_disk_led_fault(disk.clone(), &"0".to_string());
+        };
+        let device_map = get_disk_sd_map()
+            .get(&found_disk.device_path)
+            .cloned()
+            .unwrap_or_else(|| "NONE".to_string());
+        println!(
+            "Enclosure: {} Slot: {}{} Disk: {} Map: {}",
+            found_disk.enclosure.green(),
+            found_disk.slot.green(),
+            slot_label_suffix(&found_disk.label),
+            found_disk.device_path.green(),
+            device_map.green()
+        );
+
+        let led = match &found_disk.led_locate_path {
+            Some(led) => led,
+            None => {
+                Output::print_error_code(
+                    ErrorCode::Unsupported,
+                    &I18n::tr(Message::NoLocateLed, &[&id]),
+                );
+                return Err(());
             }
-        }
+        };
+        write_led(&found_disk.enclosure, led, state);
+        print_led_state(&found_disk, state, found_disk.slot.yellow().blink().bold());
 
         Ok(())
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn disk(slot: &str, serial: &str, device: &str, temperature: Temperature) -> Disk {
+            let mut disk: Disk = serde_json::from_value(serde_json::json!({
+                "enclosure": "0:0:60:0",
+                "slot": slot,
+                "device_path": device,
+                "device_map": "/dev/sd-missing",
+                "temperature": "not_collected",
+                "vendor": "HGST",
+                "model": "HUH721212AL5200",
+                "serial": serial,
+                "wwn": "",
+                "fw_revision": "A3D0",
+                "status": "ok",
+            }))
+            .expect("a disk record");
+            disk.temperature = temperature;
+            disk
+        }
+
+        fn slots(disks: &[Disk]) -> Vec<&str> {
+            disks.iter().map(|d| d.slot.as_str()).collect()
+        }
+
+        fn shelf() -> Vec<Disk> {
+            vec![
+                disk("Slot 10", "8HG6ABCF", "/dev/sg3", Temperature::Celsius(52)),
+                disk("Slot 2", "8HG6ABCE", "/dev/sg12", Temperature::Unreadable),
+                disk("Slot 1", "8HG6ABCD", "/dev/sg100", Temperature::Celsius(35)),
+                disk("Slot 3", "8HG6ABCA", "/dev/sg9", Temperature::Celsius(35)),
+            ]
+        }
+
+        #[test]
+        fn slots_sort_in_natural_order() {
+            let mut disks = shelf();
+            sort_disks_by(&mut disks, "slot");
+
+            assert_eq!(slots(&disks), ["Slot 1", "Slot 2", "Slot 3", "Slot 10"]);
+        }
+
+        #[test]
+        fn unknown_temperature_sorts_last() {
+            let mut disks = shelf();
+            sort_disks_by(&mut disks, "temp");
+
+            // Slot 1 and Slot 3 tie, they stay in slot order.
+            assert_eq!(slots(&disks), ["Slot 1", "Slot 3", "Slot 10", "Slot 2"]);
+        }
+
+        #[test]
+        fn devices_and_serials_sort_in_natural_order() {
+            let mut disks = shelf();
+            sort_disks_by(&mut disks, "device");
+            assert_eq!(slots(&disks), ["Slot 10", "Slot 3", "Slot 2", "Slot 1"]);
+
+            sort_disks_by(&mut disks, "serial");
+            assert_eq!(slots(&disks), ["Slot 3", "Slot 1", "Slot 2", "Slot 10"]);
+        }
+    }
 }
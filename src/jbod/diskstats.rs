@@ -0,0 +1,172 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod DiskStats {
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+    use std::fmt;
+    use std::fs;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use crate::jbod::disks::DiskShelf::Disk;
+    use crate::utils::output::Output;
+
+    pub const DISKSTATS: &str = "/proc/diskstats";
+    /// How long the activity is measured by default.
+    pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+    /// The kernel counts the sectors of the statistics in 512 bytes.
+    const SECTOR_SIZE: u64 = 512;
+
+    /// The counters of a block device in /proc/diskstats.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct Counters {
+        pub reads: u64,
+        pub sectors_read: u64,
+        pub writes: u64,
+        pub sectors_written: u64,
+        /// The requests sent to the device and not completed yet.
+        pub in_flight: u64,
+        /// The milliseconds the device had requests in flight.
+        pub io_ticks: u64,
+    }
+
+    /// The activity of a disk during the sampling interval.
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+    pub struct Utilization {
+        pub read_iops: f64,
+        pub write_iops: f64,
+        pub read_bytes_per_second: f64,
+        pub write_bytes_per_second: f64,
+        /// The requests in flight at the end of the interval.
+        pub in_flight: u64,
+        /// The share of the interval the disk was busy, from 0 to 100.
+        pub busy_percent: f64,
+    }
+
+    impl fmt::Display for Utilization {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(
+                f,
+                "r {:.0}/s {:.1}MB/s w {:.0}/s {:.1}MB/s inflight {} busy {:.0}%",
+                self.read_iops,
+                self.read_bytes_per_second / 1_000_000.0,
+                self.write_iops,
+                self.write_bytes_per_second / 1_000_000.0,
+                self.in_flight,
+                self.busy_percent
+            )
+        }
+    }
+
+    /// Returns the counters of the block devices by name, example: sdb.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - the content of /proc/diskstats
+    ///
+    pub fn parse(content: &str) -> HashMap<String, Counters> {
+        let mut devices = HashMap::new();
+        for line in content.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 14 {
+                continue;
+            }
+            let value = |i: usize| fields[i].parse::<u64>().unwrap_or(0);
+            devices.insert(
+                fields[2].to_string(),
+                Counters {
+                    reads: value(3),
+                    sectors_read: value(5),
+                    writes: value(7),
+                    sectors_written: value(9),
+                    in_flight: value(11),
+                    io_ticks: value(12),
+                },
+            );
+        }
+        devices
+    }
+
+    /// Returns the counters of the block devices now.
+    pub fn read() -> Result<HashMap<String, Counters>, String> {
+        fs::read_to_string(DISKSTATS)
+            .map(|content| parse(&content))
+            .map_err(|e| format!("cannot read {}: {}", DISKSTATS, e))
+    }
+
+    /// Returns the activity between two readings `elapsed` apart.
+    pub fn utilization(before: &Counters, after: &Counters, elapsed: Duration) -> Utilization {
+        let seconds = elapsed.as_secs_f64().max(0.001);
+        let rate = |b: u64, a: u64| a.saturating_sub(b) as f64 / seconds;
+        let busy = after.io_ticks.saturating_sub(before.io_ticks) as f64 / (seconds * 1000.0);
+        Utilization {
+            read_iops: rate(before.reads, after.reads),
+            write_iops: rate(before.writes, after.writes),
+            read_bytes_per_second: rate(before.sectors_read, after.sectors_read)
+                * SECTOR_SIZE as f64,
+            write_bytes_per_second: rate(before.sectors_written, after.sectors_written)
+                * SECTOR_SIZE as f64,
+            in_flight: after.in_flight,
+            busy_percent: (busy * 100.0).min(100.0),
+        }
+    }
+
+    /// Fills the `io` field of the disks with their activity during the
+    /// `interval`, the unmapped ones keep None.
+    ///
+    /// # Arguments
+    ///
+    /// * `disks` - the disks to sample
+    /// * `interval` - how long the activity is measured
+    ///
+    pub fn annotate(disks: &mut [Disk], interval: Duration) {
+        let sample = || read().map(|counters| (Instant::now(), counters));
+        let (before, after) = match sample().and_then(|before| {
+            thread::sleep(interval);
+            sample().map(|after| (before, after))
+        }) {
+            Ok(samples) => samples,
+            Err(e) => {
+                Output::print_warning(&e);
+                return;
+            }
+        };
+
+        let elapsed = after.0.duration_since(before.0);
+        for disk in disks.iter_mut() {
+            let name = disk.device_map.trim_start_matches("/dev/");
+            if let (Some(b), Some(a)) = (before.1.get(name), after.1.get(name)) {
+                disk.io = Some(utilization(b, a, elapsed));
+            }
+        }
+    }
+}
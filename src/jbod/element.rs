@@ -0,0 +1,190 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Element {
+    use serde::{Deserialize, Serialize};
+    use std::fmt;
+    use std::fs;
+    use std::io;
+    use std::path::Path;
+
+    /// The state of a slot LED, the only values the kernel accepts in the
+    /// `locate` and `fault` files of an enclosure component.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum LedState {
+        Off,
+        On,
+    }
+
+    impl LedState {
+        /// Returns the value written to sysfs.
+        pub fn as_sysfs(&self) -> &'static str {
+            match self {
+                LedState::Off => "0",
+                LedState::On => "1",
+            }
+        }
+
+        /// Returns the LedState read from sysfs or None for unknown content.
+        pub fn from_sysfs(value: &str) -> Option<Self> {
+            match value.trim() {
+                "0" => Some(LedState::Off),
+                "1" => Some(LedState::On),
+                _ => None,
+            }
+        }
+
+        /// Returns the LedState of a LED file.
+        ///
+        /// # Arguments
+        ///
+        /// * `path` - the `locate` or `fault` file of a component
+        ///
+        pub fn read(path: &Path) -> io::Result<Self> {
+            let content = fs::read_to_string(path)?;
+            LedState::from_sysfs(&content).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unexpected led state: {}", content.trim()),
+                )
+            })
+        }
+
+        /// Writes the LedState into a LED file.
+        ///
+        /// # Arguments
+        ///
+        /// * `path` - the `locate` or `fault` file of a component
+        ///
+        pub fn write(&self, path: &Path) -> io::Result<()> {
+            fs::write(path, self.as_sysfs())
+        }
+    }
+
+    impl fmt::Display for LedState {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                LedState::Off => write!(f, "off"),
+                LedState::On => write!(f, "on"),
+            }
+        }
+    }
+
+    /// The SES element status of a slot as shown in the `status` file of
+    /// an enclosure component, it is also used for the other elements of
+    /// the enclosure like fans and power supplies.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum SlotStatus {
+        Unsupported,
+        Ok,
+        Critical,
+        Noncritical,
+        Unrecoverable,
+        NotInstalled,
+        Unknown,
+        NotAvailable,
+        NoAccess,
+    }
+
+    impl SlotStatus {
+        /// Returns the SlotStatus from the kernel names, Unknown when it
+        /// isn't one of them.
+        pub fn from_sysfs(value: &str) -> Self {
+            match value.trim() {
+                "unsupported" => SlotStatus::Unsupported,
+                "OK" => SlotStatus::Ok,
+                "critical" => SlotStatus::Critical,
+                "non-critical" => SlotStatus::Noncritical,
+                "unrecoverable" => SlotStatus::Unrecoverable,
+                "not installed" => SlotStatus::NotInstalled,
+                "unavailable" => SlotStatus::NotAvailable,
+                "no access" => SlotStatus::NoAccess,
+                _ => SlotStatus::Unknown,
+            }
+        }
+
+        /// Returns the SlotStatus from the names printed by `sg_ses`,
+        /// Unknown when it isn't one of them.
+        pub fn from_ses(value: &str) -> Self {
+            match value.trim() {
+                "Unsupported" => SlotStatus::Unsupported,
+                "OK" => SlotStatus::Ok,
+                "Critical" => SlotStatus::Critical,
+                "Noncritical" => SlotStatus::Noncritical,
+                "Unrecoverable" => SlotStatus::Unrecoverable,
+                "Not installed" => SlotStatus::NotInstalled,
+                "Not available" => SlotStatus::NotAvailable,
+                "No access allowed" => SlotStatus::NoAccess,
+                _ => SlotStatus::Unknown,
+            }
+        }
+
+        /// Returns the SlotStatus of a component, Unknown when it can't be
+        /// read.
+        ///
+        /// # Arguments
+        ///
+        /// * `component` - the component path, example: /sys/class/enclosure/15:0:1:0/Slot 05
+        ///
+        pub fn read(component: &Path) -> Self {
+            fs::read_to_string(component.join("status"))
+                .map(|s| SlotStatus::from_sysfs(&s))
+                .unwrap_or(SlotStatus::Unknown)
+        }
+
+        /// Returns true if SES reports a problem with the slot.
+        pub fn is_failed(&self) -> bool {
+            matches!(
+                self,
+                SlotStatus::Critical | SlotStatus::Noncritical | SlotStatus::Unrecoverable
+            )
+        }
+    }
+
+    impl fmt::Display for SlotStatus {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let name = match self {
+                SlotStatus::Unsupported => "Unsupported",
+                SlotStatus::Ok => "OK",
+                SlotStatus::Critical => "Critical",
+                SlotStatus::Noncritical => "Non-critical",
+                SlotStatus::Unrecoverable => "Unrecoverable",
+                SlotStatus::NotInstalled => "Not installed",
+                SlotStatus::Unknown => "Unknown",
+                SlotStatus::NotAvailable => "Unavailable",
+                SlotStatus::NoAccess => "No access",
+            };
+            write!(f, "{}", name)
+        }
+    }
+}
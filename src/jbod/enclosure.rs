@@ -31,17 +31,24 @@
 #[allow(non_snake_case)]
 pub mod BackPlane {
     use std::fmt;
-    use std::io::{BufRead, BufReader};
-    use std::process::{Command};
+    use std::fs;
+    use std::time::Duration;
     use regex::Regex;
+    use serde::{Deserialize, Serialize};
 
+    use crate::jbod::element::Element::SlotStatus;
+    use crate::jbod::quirks::Quirks;
+    use crate::jbod::topology::Topology;
+    use crate::utils::config::Config;
+    use crate::utils::config::Config::RackLocation;
+    use crate::utils::helper::Util;
     use crate::utils::helper::Util::{LSSCSI, SG_INQ, SG_SES};
+    use crate::utils::output::Output;
 
     extern crate prettytable;
-    extern crate subprocess;
     use prettytable::{color, format, Attr, Cell, Row, Table};
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone, Serialize, Deserialize)]
     pub struct Enclosure {
         pub slot: String,
         pub device_path: String,
@@ -49,9 +56,65 @@ pub mod BackPlane {
         pub model: String,
         pub revision: String,
         pub serial: String,
+        /// SAS address of the SES device, empty off a SAS fabric
+        #[serde(default)]
+        pub sas_address: String,
+        /// SAS address of the expander holding the SES device
+        #[serde(default)]
+        pub expander_sas_address: String,
+        /// Rack and unit from the configuration
+        #[serde(default)]
+        pub location: Option<RackLocation>,
+        /// Attached over USB, UAS or mass storage, its commands get more time
+        #[serde(default)]
+        pub usb: bool,
+        /// The controllers of the enclosure, its ESMs or IOMs, only read by
+        /// `read_controllers`
+        #[serde(default)]
+        pub controllers: Vec<Controller>,
     }
 
-    #[derive(Debug)]
+    /// A subenclosure of the SES configuration page, usually an ESM.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Controller {
+        /// The SES subenclosure identifier
+        pub id: String,
+        pub primary: bool,
+        pub vendor: String,
+        pub product: String,
+        /// The firmware revision of the controller
+        pub revision: String,
+        /// From the download microcode status page, example: No download
+        /// microcode operation in progress
+        pub microcode_status: Option<String>,
+    }
+
+    /// How many times the command timeout an enclosure attached over USB
+    /// gets, the bridges turn the SES pages around much slower than SAS.
+    pub const USB_TIMEOUT_FACTOR: u32 = 4;
+
+    /// Returns the timeout of the commands sent to an enclosure.
+    ///
+    /// # Arguments
+    ///
+    /// * `usb` - the enclosure is attached over USB
+    ///
+    fn enclosure_timeout(usb: bool) -> Duration {
+        if usb {
+            Util::command_timeout() * USB_TIMEOUT_FACTOR
+        } else {
+            Util::command_timeout()
+        }
+    }
+
+    impl Enclosure {
+        /// Returns the timeout of the commands sent to this enclosure.
+        pub fn command_timeout(&self) -> Duration {
+            enclosure_timeout(self.usb)
+        }
+    }
+
+    #[derive(Debug, Serialize)]
     pub struct EnclosureFan {
         /// The slot number provided by the JBOD
         pub slot: String,
@@ -63,13 +126,165 @@ pub mod BackPlane {
         pub index: String,
         /// The RPM speed of the FAN.
         pub speed: i64,
+        /// The element status reported by SES, example: OK, Critical.
+        pub status: SlotStatus,
+        /// The state of the fan from its status and its Fail and Off bits.
+        pub state: FanState,
         /// The JBOD can provide extra information about the FAN speed
         /// and it can be used to create alerts in the future.
         pub comment: String,
     }
 
-    /// Creates the pretty table for the enclosure.
-    fn create_enclosure_table() -> Table {
+    /// The state of a cooling element, a stopped or failed fan can still
+    /// report a speed, so the Fail and Off bits win over the status.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum FanState {
+        Ok,
+        Warning,
+        Critical,
+        Failed,
+        Off,
+        Unknown,
+    }
+
+    impl FanState {
+        /// Returns the FanState of a cooling element.
+        ///
+        /// # Arguments
+        ///
+        /// * `status` - the element status
+        /// * `fail` - the element Fail bit
+        /// * `off` - the element Off bit
+        ///
+        pub fn new(status: SlotStatus, fail: bool, off: bool) -> Self {
+            match status {
+                _ if off => FanState::Off,
+                _ if fail => FanState::Failed,
+                SlotStatus::Unrecoverable => FanState::Failed,
+                SlotStatus::Critical => FanState::Critical,
+                SlotStatus::Noncritical => FanState::Warning,
+                SlotStatus::Ok => FanState::Ok,
+                _ => FanState::Unknown,
+            }
+        }
+
+        /// Returns the value exported by the `jbod_fan_status` metric.
+        pub fn code(&self) -> i64 {
+            match self {
+                FanState::Ok => 0,
+                FanState::Warning => 1,
+                FanState::Critical => 2,
+                FanState::Failed => 3,
+                FanState::Off => 4,
+                FanState::Unknown => 5,
+            }
+        }
+    }
+
+    impl Enclosure {
+        /// Returns true if the enclosure is identified by `id`, which can be
+        /// its SCSI address, its serial number or its configured alias.
+        pub fn matches(&self, id: &str) -> bool {
+            self.slot == id
+                || self.serial == id
+                || Config::get().enclosure_alias(&self.serial, &self.model) == Some(id)
+        }
+    }
+
+    /// The overall status of an enclosure from the summary bits of the SES
+    /// enclosure status page.
+    #[derive(Debug, Serialize)]
+    pub struct EnclosureHealth {
+        /// The slot number provided by the JBOD
+        pub slot: String,
+        /// The device serial number
+        pub serial: String,
+        /// The worst summary bit set: Unrecoverable, Critical, Noncritical,
+        /// else OK, Unknown when the page can't be read.
+        pub status: SlotStatus,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct EnclosureElement {
+        /// The slot number provided by the JBOD
+        pub slot: String,
+        /// The device serial number
+        pub serial: String,
+        /// The SES element type, example: Power supply, Temperature sensor.
+        pub element_type: String,
+        /// The name of the component provided by the JBOD.
+        pub description: String,
+        /// The slot position used by `sg_ses`.
+        pub index: String,
+        /// The element status reported by SES.
+        pub status: SlotStatus,
+        /// The measure of a sensor, example: Temperature=31 C.
+        pub reading: String,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct EnclosurePower {
+        /// The slot number provided by the JBOD
+        pub slot: String,
+        /// The device serial number
+        pub serial: String,
+        /// The name of the current sensor, usually naming its power supply.
+        pub sensor: String,
+        /// The voltage of the rail the current is measured on.
+        pub volts: Option<f64>,
+        pub amps: f64,
+        /// The power drawn, None without a voltage to multiply with.
+        pub watts: Option<f64>,
+    }
+
+    /// Returns a table cell with the status colored by its severity.
+    pub fn status_cell(status: SlotStatus) -> Cell {
+        let cell = Cell::new(&status.to_string());
+        match status {
+            SlotStatus::Ok => cell.with_style(Attr::ForegroundColor(color::GREEN)),
+            SlotStatus::Noncritical => cell
+                .with_style(Attr::Bold)
+                .with_style(Attr::ForegroundColor(color::YELLOW)),
+            SlotStatus::Critical | SlotStatus::Unrecoverable => cell
+                .with_style(Attr::Bold)
+                .with_style(Attr::Blink)
+                .with_style(Attr::ForegroundColor(color::RED)),
+            _ => cell,
+        }
+    }
+
+    /// Creates the pretty table for the SES elements.
+    pub fn create_element_table() -> Table {
+        let mut element_table = Table::new();
+        element_table.set_format(*format::consts::FORMAT_NO_BORDER);
+        element_table.add_row(Row::new(vec![
+            Cell::new("SLOT")
+                .with_style(Attr::Bold)
+                .with_style(Attr::ForegroundColor(color::BLUE)),
+            Cell::new("IDENT")
+                .with_style(Attr::Bold)
+                .with_style(Attr::ForegroundColor(color::BLUE)),
+            Cell::new("TYPE")
+                .with_style(Attr::Bold)
+                .with_style(Attr::ForegroundColor(color::BLUE)),
+            Cell::new("DESCRIPTION")
+                .with_style(Attr::Bold)
+                .with_style(Attr::ForegroundColor(color::BLUE)),
+            Cell::new("STATUS")
+                .with_style(Attr::Bold)
+                .with_style(Attr::ForegroundColor(color::BLUE)),
+            Cell::new("READING")
+                .with_style(Attr::Bold)
+                .with_style(Attr::ForegroundColor(color::BLUE)),
+        ]));
+
+        element_table
+    }
+
+    /// Creates the pretty table for the enclosure, with the firmware of its
+    /// controllers when they were read.
+    fn create_enclosure_table(controllers: bool) -> Table {
         let mut enclosure_table = Table::new();
         enclosure_table.set_format(*format::consts::FORMAT_NO_BORDER);
         enclosure_table.add_row(Row::new(vec![
@@ -91,7 +306,22 @@ pub mod BackPlane {
             Cell::new("SERIAL")
                 .with_style(Attr::Bold)
                 .with_style(Attr::ForegroundColor(color::BLUE)),
+            Cell::new("SAS ADDRESS")
+                .with_style(Attr::Bold)
+                .with_style(Attr::ForegroundColor(color::BLUE)),
+            Cell::new("LOCATION")
+                .with_style(Attr::Bold)
+                .with_style(Attr::ForegroundColor(color::BLUE)),
         ]));
+        if controllers {
+            if let Some(header) = enclosure_table.get_mut_row(0) {
+                header.add_cell(
+                    Cell::new("CONTROLLERS")
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(color::BLUE)),
+                );
+            }
+        }
 
         enclosure_table
     }
@@ -113,6 +343,9 @@ pub mod BackPlane {
             Cell::new("STATUS")
                 .with_style(Attr::Bold)
                 .with_style(Attr::ForegroundColor(color::BLUE)),
+            Cell::new("COMMENT")
+                .with_style(Attr::Bold)
+                .with_style(Attr::ForegroundColor(color::BLUE)),
             Cell::new("RPM")
                 .with_style(Attr::Bold)
                 .with_style(Attr::ForegroundColor(color::BLUE)),
@@ -123,18 +356,25 @@ pub mod BackPlane {
 
     /// Implementation to print the enclosure table without deal with the table.
     impl fmt::Display for Enclosure {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            let mut enclosure_table = create_enclosure_table();
-            enclosure_table.add_row(Row::new(vec![
+        fn fmt(&self, _f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let controllers = !self.controllers.is_empty();
+            let mut enclosure_table = create_enclosure_table(controllers);
+            let mut row = Row::new(vec![
                 Cell::new(&self.slot),
                 Cell::new(&self.device_path),
                 Cell::new(&self.vendor),
                 Cell::new(&self.model),
                 Cell::new(&self.revision),
                 Cell::new(&self.serial),
-            ]));
+                Cell::new(&self.sas_address),
+                Cell::new(&self.location.as_ref().map(|l| l.to_string()).unwrap_or_default()),
+            ]);
+            if controllers {
+                row.add_cell(Cell::new(&controller_revisions(&self.controllers)));
+            }
+            enclosure_table.add_row(row);
 
-            enclosure_table.printstd();
+            Output::print_table(&enclosure_table);
             Ok(())
         }
     }
@@ -146,22 +386,19 @@ pub mod BackPlane {
     /// # Arguments
     ///
     /// * `device` - a string with the device path of the enclosure
+    /// * `timeout` - how long sg_inq may take, longer over USB
     ///
     /// # Example
     /// ```
-    /// let (vendor, ident, rev, serial) = get_enclosure_details("/dev/sg9".to_string());
+    /// let (vendor, ident, rev, serial) = get_enclosure_details("/dev/sg9".to_string(), Util::command_timeout());
     /// ```
     ///
-    fn get_enclosure_details(device: String) -> (String, String, String, String) {
+    fn get_enclosure_details(device: String, timeout: Duration) -> (String, String, String, String) {
         let mut vendor = "NONE".to_string();
         let mut ident = "NONE".to_string();
         let mut rev = "NONE".to_string();
         let mut serial = "NONE".to_string();
-        let sginq_cmd = Command::new(SG_INQ)
-            .args(&[device])
-            .output()
-            .expect("Failed to sg_inq the device");
-        let sginq_output = String::from_utf8_lossy(&sginq_cmd.stdout);
+        let sginq_output = Util::command_stdout_timeout(SG_INQ, &[&device], timeout);
 
         for output in sginq_output.split('\n') {
             if output.contains("Vendor") {
@@ -187,126 +424,885 @@ pub mod BackPlane {
             }
         }
 
-        return (vendor, ident, rev, serial);
+        (vendor, ident, rev, serial)
+    }
+
+    /// Returns the fan speed(RPM) found in a sg_ses status line, example:
+    /// "Off=0, Actual speed=8830 rpm, Fan at fourth lowest speed".
+    fn parse_fan_speed(line: &str) -> i64 {
+        line.split(',')
+            .find(|f| f.contains("speed="))
+            .and_then(|f| {
+                let digits: String = f
+                    .chars()
+                    .skip_while(|c| !c.is_ascii_digit())
+                    .take_while(|c| c.is_ascii_digit())
+                    .collect();
+                digits.parse().ok()
+            })
+            .unwrap_or(0)
+    }
+
+    /// An element of the SES status page as printed by `sg_ses --join`.
+    #[derive(Debug)]
+    struct SesElement {
+        element_type: String,
+        description: String,
+        index: String,
+        status: SlotStatus,
+        /// The status lines following the element header.
+        lines: Vec<String>,
     }
 
-    /// Returns the fan speed(RPM) and a message provided by the jbod with extra information
-    /// about the FAN setup.
+    /// Returns a vector with every element found in the output of
+    /// `sg_ses --join`.
+    ///
+    /// Each element starts with a header line like "Fan 1 [0,0]  Element type: Cooling"
+    /// followed by its status lines, the status is decoded from the
+    /// "status:" field.
     ///
     /// # Arguments
     ///
-    /// * `device_path` - The enclosure device
-    /// * `fan_index` - The fan slot on the JBOD
-    ///
-    fn get_enclosure_fan_speed(device_path: &str, fan_index: &str) -> (i64, String) {
-        let mut speed: i64 = 0;
-        let mut comment: String = String::new();
-
-        let index = format!("--index={}", &fan_index);
-        let sg_ses_cmd = Command::new(SG_SES)
-            .arg(index)
-            .arg(&device_path)
-            .output()
-            .expect("Failed to get fan speed");
-        let sg_ses_output = String::from_utf8_lossy(&sg_ses_cmd.stdout);
-        let output_spl: Vec<&str> = sg_ses_output.split("\n").collect();
-        for output in output_spl {
-            if output.contains("speed") {
-                let output_speed: Vec<&str> = output.split(",").collect();
-                let _speed = output_speed[1]
-                    .trim()
-                    .chars()
-                    .skip_while(|c| !c.is_digit(10))
-                    .take_while(|c| c.is_digit(10))
-                    .fold(None, |acc, c| {
-                        c.to_digit(10).map(|b| acc.unwrap_or(0) * 10 + b)
-                    });
-                speed = _speed.unwrap().into();
-                comment = output_speed[2].trim().to_string();
+    /// * `output` - the output of `sg_ses -j`
+    ///
+    fn parse_elements(output: &str) -> Vec<SesElement> {
+        let header = Regex::new(
+            r"^\s*(?P<desc>.*?)\s*\[(?P<id>-?\d+,-?\d+)\]\s+Element type:\s*(?P<type>.*)$",
+        )
+        .unwrap();
+        let mut elements = Vec::new();
+        let mut current: Option<SesElement> = None;
+
+        for line in output.lines() {
+            if let Some(m) = header.captures(line) {
+                elements.extend(current.take());
+                current = Some(SesElement {
+                    element_type: m["type"].trim().to_string(),
+                    description: m["desc"].to_string(),
+                    index: m["id"].to_string(),
+                    status: SlotStatus::Unknown,
+                    lines: Vec::new(),
+                });
+                continue;
             }
+
+            if let Some(element) = current.as_mut() {
+                if let Some(pos) = line.find("status:") {
+                    element.status = SlotStatus::from_ses(&line[pos + "status:".len()..]);
+                }
+                element.lines.push(line.trim().to_string());
+            }
+        }
+        elements.extend(current);
+
+        elements
+    }
+
+    /// Returns true when a bit of an element is set, example: "Fail=1".
+    fn element_flag(element: &SesElement, flag: &str) -> bool {
+        let set = format!("{}=1", flag);
+        element
+            .lines
+            .iter()
+            .flat_map(|l| l.split(','))
+            .any(|f| f.trim() == set)
+    }
+
+    /// A cooling element: description, index, speed, status, state and comment.
+    type CoolingElement = (String, String, i64, SlotStatus, FanState, String);
+
+    /// Returns a vector with the description, index, speed, status, state and
+    /// comment of every cooling element found in the output of `sg_ses --join`.
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - the output of `sg_ses -j`
+    ///
+    fn parse_cooling_elements(output: &str) -> Vec<CoolingElement> {
+        parse_elements(output)
+            .into_iter()
+            .filter(|e| e.element_type.contains("Cooling"))
+            .map(|e| {
+                let mut speed = 0;
+                let mut comment = String::new();
+                if let Some(line) = e.lines.iter().find(|l| l.contains("speed=")) {
+                    speed = parse_fan_speed(line);
+                    let fields: Vec<&str> = line.split(',').collect();
+                    if let Some(p) = fields.iter().position(|f| f.contains("speed=")) {
+                        comment = fields[p + 1..].join(",").trim().to_string();
+                    }
+                }
+                let state = FanState::new(
+                    e.status,
+                    element_flag(&e, "Fail"),
+                    element_flag(&e, "Off"),
+                );
+                (e.description, e.index, speed, e.status, state, comment)
+            })
+            .collect()
+    }
+
+    /// Returns the measure of a sensor element, example: "Temperature=31 C".
+    fn element_reading(element: &SesElement) -> String {
+        const READINGS: &[&str] = &["Temperature=", "Voltage:", "Current:", "Actual speed="];
+        element
+            .lines
+            .iter()
+            .flat_map(|l| l.split(','))
+            .map(str::trim)
+            .find(|f| READINGS.iter().any(|r| f.starts_with(r)))
+            .unwrap_or("")
+            .to_string()
+    }
+
+    /// Returns the number following a sensor field, example: 12.10 for
+    /// "Voltage: 12.10 volts".
+    fn sensor_value(element: &SesElement, field: &str) -> Option<f64> {
+        element
+            .lines
+            .iter()
+            .flat_map(|l| l.split(','))
+            .map(str::trim)
+            .find_map(|f| f.strip_prefix(field))
+            .and_then(|v| v.split_whitespace().next())
+            .and_then(|v| v.parse().ok())
+    }
+
+    /// Returns the name, voltage, current and power of every current sensor
+    /// found in the output of `sg_ses --join`.
+    ///
+    /// SES has no power element, the power is the current of a sensor times
+    /// the voltage of its rail. The voltage sensors are listed in the same
+    /// order as the current sensors on the shelves measuring both, a single
+    /// voltage sensor is the rail of every current sensor, any other count
+    /// can't be paired.
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - the output of `sg_ses -j`
+    ///
+    fn parse_power(output: &str) -> Vec<(String, Option<f64>, f64, Option<f64>)> {
+        let elements = parse_elements(output);
+        let sensors = |kind: &str, field: &str| -> Vec<(String, f64)> {
+            elements
+                .iter()
+                .filter(|e| e.element_type.contains(kind) && !e.index.ends_with(",-1"))
+                .filter_map(|e| Some((e.description.clone(), sensor_value(e, field)?)))
+                .collect()
+        };
+        let voltages = sensors("Voltage sensor", "Voltage:");
+        let currents = sensors("Current sensor", "Current:");
+
+        currents
+            .iter()
+            .enumerate()
+            .map(|(i, (sensor, amps))| {
+                let volts = if voltages.len() == currents.len() {
+                    Some(voltages[i].1)
+                } else if voltages.len() == 1 {
+                    Some(voltages[0].1)
+                } else {
+                    None
+                };
+                (sensor.clone(), volts, *amps, volts.map(|v| v * amps))
+            })
+            .collect()
+    }
+
+    /// Returns the sg_ses status page of every enclosure, up to `jobs`
+    /// enclosures are read at the same time.
+    fn get_enclosure_status(enclosures: &[Enclosure], jobs: usize) -> Vec<String> {
+        Util::parallel_map(enclosures, jobs, |enclosure| {
+            Util::command_stdout_timeout(SG_SES, &["-j", "-ff", &enclosure.device_path], enclosure.command_timeout())
+        })
+    }
+
+    /// Returns the overall status from the summary line of `sg_ses -p 2`,
+    /// Unknown when it isn't found.
+    ///
+    /// ```text
+    ///   INVOP=0, INFO=0, NON-CRIT=0, CRIT=1, UNRECOV=0
+    /// ```
+    ///
+    fn parse_summary_status(output: &str) -> SlotStatus {
+        let line = match output.lines().find(|l| l.contains("INVOP=")) {
+            Some(line) => line,
+            None => return SlotStatus::Unknown,
+        };
+        let set = |bit: &str| {
+            line.split(',')
+                .map(str::trim)
+                .any(|f| f == format!("{}=1", bit))
+        };
+        if set("UNRECOV") {
+            SlotStatus::Unrecoverable
+        } else if set("CRIT") {
+            SlotStatus::Critical
+        } else if set("NON-CRIT") {
+            SlotStatus::Noncritical
+        } else {
+            SlotStatus::Ok
         }
-        return (speed, comment);
+    }
+
+    /// Returns the revision of each controller, example: "0:2033 1:2033".
+    pub fn controller_revisions(controllers: &[Controller]) -> String {
+        controllers
+            .iter()
+            .map(|c| format!("{}:{}", c.id, c.revision))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    /// Returns the subenclosures of the SES configuration page, as printed
+    /// by `sg_ses -p cf`.
+    ///
+    /// ```text
+    ///     Subenclosure identifier: 0 [primary]
+    ///       enclosure vendor: HGST      product: H4102-J           rev: 2033
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - the output of `sg_ses -p cf`
+    ///
+    fn parse_configuration(output: &str) -> Vec<Controller> {
+        let id_re = Regex::new(r"(?i)^\s*subenclosure identifier:\s*(?P<id>\d+)(?P<primary>\s*\[primary\])?").unwrap();
+        let vendor_re =
+            Regex::new(r"enclosure vendor:\s*(?P<vendor>.*?)\s+product:\s*(?P<product>.*?)\s+rev:\s*(?P<rev>\S*)").unwrap();
+        let mut controllers: Vec<Controller> = Vec::new();
+        for line in output.lines() {
+            if let Some(m) = id_re.captures(line) {
+                controllers.push(Controller {
+                    id: m["id"].to_string(),
+                    primary: m.name("primary").is_some(),
+                    vendor: String::new(),
+                    product: String::new(),
+                    revision: String::new(),
+                    microcode_status: None,
+                });
+            } else if let (Some(m), Some(controller)) = (vendor_re.captures(line), controllers.last_mut()) {
+                controller.vendor = m["vendor"].trim().to_string();
+                controller.product = m["product"].trim().to_string();
+                controller.revision = m["rev"].to_string();
+            }
+        }
+        controllers
+    }
+
+    /// Returns the download microcode status of each subenclosure, as
+    /// printed by `sg_ses -p dm`.
+    ///
+    /// ```text
+    ///   subenclosure identifier: 0 [primary]
+    ///     download microcode status: No download microcode operation in progress [0x0]
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - the output of `sg_ses -p dm`
+    ///
+    fn parse_microcode_status(output: &str) -> Vec<(String, String)> {
+        let id_re = Regex::new(r"(?i)^\s*subenclosure identifier:\s*(?P<id>\d+)").unwrap();
+        let status_re = Regex::new(r"download microcode status:\s*(?P<status>.*?)\s*(\[0x[0-9a-fA-F]+\])?\s*$").unwrap();
+        let mut statuses = Vec::new();
+        let mut id: Option<String> = None;
+        for line in output.lines() {
+            if let Some(m) = id_re.captures(line) {
+                id = Some(m["id"].to_string());
+            } else if let (Some(m), Some(id)) = (status_re.captures(line), id.take()) {
+                statuses.push((id, m["status"].to_string()));
+            }
+        }
+        statuses
+    }
+
+    /// Reads the controllers of every enclosure and their firmware from the
+    /// SES configuration and download microcode status pages, up to `jobs`
+    /// enclosures are read at the same time.
+    ///
+    /// The primary controller falls back on the INQUIRY of the enclosure
+    /// when the configuration page can't be read.
+    ///
+    /// # Arguments
+    ///
+    /// * `enclosures` - the enclosures to complete
+    /// * `jobs` - maximum number of enclosures probed at the same time
+    ///
+    pub fn read_controllers(enclosures: &mut [Enclosure], jobs: usize) {
+        let pages = Util::parallel_map(enclosures, jobs, |enclosure| {
+            let page = |name: &str| {
+                Util::run_command_timeout(SG_SES, &["-p", name, &enclosure.device_path], enclosure.command_timeout())
+                    .ok()
+                    .filter(|o| o.status.success())
+                    .map(|o| String::from_utf8_lossy(&o.stdout).to_string())
+                    .unwrap_or_default()
+            };
+            (page("cf"), page("dm"))
+        });
+        for (enclosure, (configuration, microcode)) in enclosures.iter_mut().zip(pages) {
+            let mut controllers = parse_configuration(&configuration);
+            if controllers.is_empty() {
+                controllers.push(Controller {
+                    id: "0".to_string(),
+                    primary: true,
+                    vendor: enclosure.vendor.clone(),
+                    product: enclosure.model.clone(),
+                    revision: enclosure.revision.clone(),
+                    microcode_status: None,
+                });
+            }
+            for (id, status) in parse_microcode_status(&microcode) {
+                if let Some(controller) = controllers.iter_mut().find(|c| c.id == id) {
+                    controller.microcode_status = Some(status);
+                }
+            }
+            enclosure.controllers = controllers;
+        }
+    }
+
+    /// Returns the overall status of every enclosure, up to `jobs`
+    /// enclosures are read at the same time.
+    ///
+    /// # Arguments
+    ///
+    /// * `enclosures` - the enclosures to read, usually from `get_enclosure()`
+    /// * `jobs` - maximum number of enclosures probed at the same time
+    ///
+    pub fn get_enclosure_health(enclosures: &[Enclosure], jobs: usize) -> Vec<EnclosureHealth> {
+        let statuses = Util::parallel_map(enclosures, jobs, |enclosure| {
+            Util::command_stdout_timeout(SG_SES, &["-p", "2", &enclosure.device_path], enclosure.command_timeout())
+        });
+        enclosures
+            .iter()
+            .zip(statuses)
+            .map(|(enclosure, output)| EnclosureHealth {
+                slot: enclosure.slot.clone(),
+                serial: enclosure.serial.clone(),
+                status: parse_summary_status(&output),
+            })
+            .collect()
+    }
+
+    /// Returns a vector with the EnclosureElement structure for every SES
+    /// element of the enclosures, like power supplies and sensors.
+    ///
+    /// # Arguments
+    ///
+    /// * `enclosures` - the enclosures to read, usually from `get_enclosure()`
+    /// * `jobs` - maximum number of enclosures probed at the same time
+    ///
+    pub fn get_enclosure_elements(enclosures: &[Enclosure], jobs: usize) -> Vec<EnclosureElement> {
+        let mut enclosure_elements: Vec<EnclosureElement> = Vec::new();
+
+        let statuses = get_enclosure_status(enclosures, jobs);
+        for (enclosure, sg_ses_output) in enclosures.iter().zip(statuses) {
+            for element in parse_elements(&sg_ses_output) {
+                enclosure_elements.push(EnclosureElement {
+                    slot: enclosure.slot.clone(),
+                    serial: enclosure.serial.clone(),
+                    reading: element_reading(&element),
+                    element_type: element.element_type,
+                    description: element.description,
+                    index: element.index,
+                    status: element.status,
+                });
+            }
+        }
+        enclosure_elements
+    }
+
+    /// Returns a vector with the EnclosurePower structure for every current
+    /// sensor of the enclosures, empty when SES measures no current.
+    ///
+    /// # Arguments
+    ///
+    /// * `enclosures` - the enclosures to read, usually from `get_enclosure()`
+    /// * `jobs` - maximum number of enclosures probed at the same time
+    ///
+    pub fn get_enclosure_power(enclosures: &[Enclosure], jobs: usize) -> Vec<EnclosurePower> {
+        let mut enclosure_power: Vec<EnclosurePower> = Vec::new();
+
+        let statuses = get_enclosure_status(enclosures, jobs);
+        for (enclosure, sg_ses_output) in enclosures.iter().zip(statuses) {
+            for (sensor, volts, amps, watts) in parse_power(&sg_ses_output) {
+                enclosure_power.push(EnclosurePower {
+                    slot: enclosure.slot.clone(),
+                    serial: enclosure.serial.clone(),
+                    sensor,
+                    volts,
+                    amps,
+                    watts,
+                });
+            }
+        }
+        enclosure_power
+    }
+
+    /// Creates the pretty table for the power readings.
+    pub fn create_power_table() -> Table {
+        let mut power_table = Table::new();
+        power_table.set_format(*format::consts::FORMAT_NO_BORDER);
+        let header = ["SLOT", "SERIAL", "SENSOR", "VOLTS", "AMPS", "WATTS"];
+        power_table.add_row(Row::new(
+            header
+                .iter()
+                .map(|h| {
+                    Cell::new(h)
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(color::BLUE))
+                })
+                .collect(),
+        ));
+
+        power_table
+    }
+
+    /// A device line of `lsscsi -g`.
+    #[derive(Debug, PartialEq, Eq)]
+    pub struct ScsiDevice {
+        /// The SCSI address, example: 15:0:1:0
+        pub hctl: String,
+        /// The peripheral type, example: disk, enclosu
+        pub device_type: String,
+        pub vendor: String,
+        pub model: String,
+        pub revision: String,
+        /// The block device, None for "-"
+        pub device_node: Option<String>,
+        /// The generic SCSI device, None for "-"
+        pub sg_node: Option<String>,
+    }
+
+    /// Returns the text of a column or an empty string, trimmed.
+    fn column(line: &str, start: usize, width: usize) -> String {
+        line.get(start..(start + width).min(line.len()))
+            .unwrap_or("")
+            .trim()
+            .to_string()
+    }
+
+    /// Returns a vector with the ScsiDevice of every line of `lsscsi -g`.
+    ///
+    /// lsscsi prints the type, vendor, model and revision in columns of 8, 8,
+    /// 16 and 4 characters separated by a space, so vendor and model strings
+    /// may contain spaces. The columns are read from where the type starts,
+    /// a long SCSI address shifts the whole line. The device nodes are the
+    /// last two words. Lines that don't look like a device are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - the output of `lsscsi -g`
+    ///
+    /// # Example
+    /// ```
+    /// let devices = parse_lsscsi("[15:0:1:0]   enclosu HGST     H4102-J          2033  -  /dev/sg9");
+    /// assert_eq!(devices[0].sg_node, Some("/dev/sg9".to_string()));
+    /// ```
+    ///
+    fn parse_lsscsi(output: &str) -> Vec<ScsiDevice> {
+        let line_re = Regex::new(
+            r"^\[(?P<hctl>[^\]]+)\]\s*(?P<type>\S+)(?P<middle>.*?)\s+(?P<dev>/dev/\S+|-)\s+(?P<sg>/dev/\S+|-)\s*$",
+        )
+        .unwrap();
+        let node = |n: &str| if n == "-" { None } else { Some(n.to_string()) };
+        let mut devices = Vec::new();
+
+        for line in output.lines() {
+            let m = match line_re.captures(line) {
+                Some(m) => m,
+                None => continue,
+            };
+            let type_start = m.name("type").unwrap().start();
+            // Everything up to the block device belongs to the columns.
+            let columns = &line[..m.name("middle").unwrap().end()];
+            let (vendor, model, revision) = if columns.len() > type_start + 8 {
+                (
+                    column(columns, type_start + 8, 8),
+                    column(columns, type_start + 17, 16),
+                    column(columns, type_start + 34, 8),
+                )
+            } else {
+                (String::new(), String::new(), String::new())
+            };
+
+            devices.push(ScsiDevice {
+                hctl: m["hctl"].to_string(),
+                device_type: m["type"].to_string(),
+                vendor,
+                model,
+                revision,
+                device_node: node(&m["dev"]),
+                sg_node: node(&m["sg"]),
+            });
+        }
+
+        devices
     }
 
     /// Returns a vector with the EnclosureFan structure for each FAN.
     ///
     /// This function parses the output of sg_ses and collects information from
-    /// each FAN.
+    /// each FAN, a single status page read is done per enclosure and up to
+    /// `jobs` enclosures are read at the same time.
+    ///
+    /// # Arguments
+    ///
+    /// * `enclosures` - the enclosures to read, usually from `get_enclosure()`
+    /// * `jobs` - maximum number of enclosures probed at the same time
     ///
-    pub fn get_enclosure_fan() -> Vec<EnclosureFan> {
+    pub fn get_enclosure_fan(enclosures: &[Enclosure], jobs: usize) -> Vec<EnclosureFan> {
         let mut enclosure_fan: Vec<EnclosureFan> = Vec::new();
 
-        let enclosures = get_enclosure();
-        for enclosure in enclosures.iter() {
-            let cmd = format!("{} -j -ff {} | grep Cooling", SG_SES, enclosure.device_path);
-            let cmd_run = subprocess::Exec::shell(cmd.to_string())
-                .stream_stdout()
-                .unwrap();
-            let enc_fan = BufReader::new(cmd_run);
-
-            // Build regex
-            let re = Regex::new("(?P<desc>.*?)\\[(?P<id>-?\\d+,-?\\d+)\\].*Cooling").unwrap();
-
-            enc_fan.lines()
-                .filter_map(|l| l.ok())
-                .filter(|l| re.is_match(l.as_str()))
-                .for_each(|x| {
-                    let m = re.captures(x.as_str()).unwrap();
-                    if m.name("id").is_some() {
-                        let _idx = m.name("id").unwrap().as_str();
-                        let _desc = m.name("desc").unwrap().as_str(); // Empty string if no match
-                        let is_present =
-                            enclosure_fan.iter().any(|c| c.index == _idx && c.serial == enclosure.serial);
-                        if is_present == false {
-                            let (speed, comment): (i64, String) =
-                                get_enclosure_fan_speed(&enclosure.device_path, _idx);
-                            enclosure_fan.push(EnclosureFan {
-                                slot: enclosure.slot.clone(),
-                                serial: enclosure.serial.clone(),
-                                description: _desc.to_string(),
-                                index: _idx.to_string(),
-                                speed: speed,
-                                comment: comment,
-                            });
-                        }
-                    }
-                });
+        let statuses = get_enclosure_status(enclosures, jobs);
+        for (enclosure, sg_ses_output) in enclosures.iter().zip(statuses) {
+            let quirk = Quirks::for_enclosure(&enclosure.vendor, &enclosure.model);
+            for (desc, idx, speed, status, state, comment) in
+                parse_cooling_elements(&sg_ses_output)
+            {
+                let is_present = enclosure_fan
+                    .iter()
+                    .any(|c| c.index == idx && c.serial == enclosure.serial);
+                if !is_present {
+                    enclosure_fan.push(EnclosureFan {
+                        slot: enclosure.slot.clone(),
+                        serial: enclosure.serial.clone(),
+                        description: desc,
+                        index: idx,
+                        speed,
+                        status,
+                        state,
+                        comment: quirk.fan_comment(&comment),
+                    });
+                }
+            }
         }
         enclosure_fan
     }
 
+    /// Returns true when a SCSI device is an SES enclosure. lsscsi tags them
+    /// `enclosu`, but the USB bridges often report another type for the SES
+    /// device, those are recognized by their SCSI peripheral type, 0x0d, or
+    /// by the ses driver binding them in class/enclosure.
+    fn is_ses_device(device: &ScsiDevice) -> bool {
+        device.device_type.starts_with("enclosu")
+            || Util::sys_class_enclosure().join(&device.hctl).exists()
+            || fs::read_to_string(Util::sysfs_path(&format!("bus/scsi/devices/{}/type", device.hctl)))
+                .map(|t| t.trim() == "13")
+                .unwrap_or(false)
+    }
+
+    /// Returns true when a SCSI device hangs off USB, its sysfs path goes
+    /// through a USB port.
+    fn is_usb_attached(hctl: &str) -> bool {
+        fs::canonicalize(Util::sysfs_path(&format!("bus/scsi/devices/{}", hctl)))
+            .or_else(|_| fs::canonicalize(Util::sys_class_enclosure().join(hctl).join("device")))
+            .map(|p| {
+                p.components()
+                    .any(|c| c.as_os_str().to_string_lossy().starts_with("usb"))
+            })
+            .unwrap_or(false)
+    }
+
     /// Returns a vector with the Enclosure structure for each enclosure.
     ///
     /// This function parses `lsscsi` and calls `get_enclosure_details` to full
-    /// fill the Enclosure structure.
-    ///
-    pub fn get_enclosure() -> Vec<Enclosure> {
-        let lsscsi_cmd = Command::new(LSSCSI)
-            .args(&["-g"])
-            .output()
-            .expect("Failed to run get_enclosure()");
-        let lsscsi_output = String::from_utf8_lossy(&lsscsi_cmd.stdout);
-        let mut enclosure: Vec<Enclosure> = Vec::new();
-
-        for p_output in lsscsi_output.split('\n') {
-            if p_output.contains("enclosu") {
-                let mut s_output: Vec<&str> = p_output.split(' ').collect();
-                s_output.retain(|&content| !content.is_empty());
-
-                let device_index = s_output.iter().position(|&r| r.contains("/dev/")).unwrap();
-                let (_vendor, _ident, _rev, _serial) =
-                    get_enclosure_details(s_output[device_index].to_string());
-                enclosure.push(Enclosure {
-                    slot: s_output[0].to_string().replace(&['[', ']'][..], ""),
-                    device_path: s_output[device_index].to_string(),
+    /// fill the Enclosure structure, up to `jobs` enclosures are inquired at
+    /// the same time.
+    ///
+    /// # Arguments
+    ///
+    /// * `jobs` - maximum number of enclosures probed at the same time
+    ///
+    pub fn get_enclosure(jobs: usize) -> Vec<Enclosure> {
+        let lsscsi_output = Util::command_stdout(LSSCSI, &["-g"]);
+        let devices: Vec<(String, String, bool)> = parse_lsscsi(&lsscsi_output)
+            .into_iter()
+            .filter(is_ses_device)
+            .filter_map(|d| {
+                let usb = is_usb_attached(&d.hctl);
+                Some((d.hctl, d.sg_node?, usb))
+            })
+            .collect();
+
+        let details = Util::parallel_map(&devices, jobs, |(_, device_path, usb)| {
+            get_enclosure_details(device_path.to_string(), enclosure_timeout(*usb))
+        });
+
+        devices
+            .into_iter()
+            .zip(details)
+            .map(|((slot, device_path, usb), (_vendor, _ident, _rev, _serial))| {
+                let dir = Topology::device_dir(&device_path);
+                let sas_address = dir
+                    .as_deref()
+                    .and_then(Topology::device_sas_address)
+                    .unwrap_or_default();
+                let expander_sas_address = dir
+                    .map(|d| Topology::chain(&d))
+                    .and_then(|c| c.expanders.last().and_then(|(e, _)| Topology::sas_address(e)))
+                    .unwrap_or_default();
+                let location = Config::get().enclosure_location(&_serial).cloned();
+                Enclosure {
+                    slot,
+                    device_path,
                     vendor: _vendor,
                     model: _ident,
                     revision: _rev,
                     serial: _serial,
-                });
-            }
+                    sas_address,
+                    expander_sas_address,
+                    location,
+                    usb,
+                    controllers: Vec::new(),
+                }
+            })
+            .collect()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        // Captured with `lsscsi -g` on shelves of different vendors.
+        const LSSCSI_HGST: &str = "\
+[0:0:0:0]    disk    ATA      INTEL SSDSC2KB24 0110  /dev/sda   /dev/sg0 
+[15:0:0:0]   disk    HGST     HUH721212AL5200  A3D0  /dev/sdb   /dev/sg8 
+[15:0:1:0]   enclosu HGST     H4102-J          2033  -          /dev/sg9 
+";
+
+        const LSSCSI_SUPERMICRO: &str = "\
+[1:0:0:0]    disk    SEAGATE  ST8000NM0075     E004  /dev/sdc   /dev/sg2 
+[1:0:12:0]   enclosu LSI      SAS3x40          0601  -          /dev/sg3 
+[1:0:13:0]   enclosu SMC      SC846-P          0601  -          /dev/sg4 
+";
+
+        const LSSCSI_DELL: &str = "\
+[10:0:24:0]  enclosu DELL     MD1400           1.07  -          /dev/sg26
+[10:0:25:0]  disk    TOSHIBA  AL14SEB030N      DE09  /dev/sdaa  /dev/sg27
+";
+
+        const LSSCSI_SPACES: &str = "\
+[2:0:0:0]    enclosu HP       D3700 Enclosure  0190  -          /dev/sg5 
+[2:0:1:0]    enclosu NETAPP   DS424IOM6 A      0212  -          /dev/sg6 
+[123:0:100:0]enclosu Quanta   JB4602 SAS EXP   1.0   -          /dev/sg100
+[3:0:0:0]    cd/dvd  HL-DT-ST DVD+-RW GHB0N    A1C0  /dev/sr0   /dev/sg7 
+";
+
+        // Captured with `sg_ses -j -ff` on an HGST H4060-J, one element of
+        // each type, one fan stopped and one power supply failed.
+        const SES_STATUS_HGST: &str = "\
+  HGST      H4060-J           2033
+    Primary enclosure logical identifier (hex): 5000cca0500a1b3f
+Slot 10 [0,9]  Element type: Array device slot
+  Enclosure Status:
+    Predicted failure=0, Disabled=0, Swap=0, status: OK
+    OK=0, Reserved device=0, Hot spare=0, Cons check=0
+    Ready to insert=0, RMV=0, Ident=0, Report=0
+    App client bypass B=0, Fault sensed=0, Fault reqstd=0, Device off=0
+PSU 0 [1,0]  Element type: Power supply
+  Enclosure Status:
+    Predicted failure=0, Disabled=0, Swap=0, status: Critical
+    Ident=0, Do not remove=0, DC overvoltage=0, DC undervoltage=0
+    DC overcurrent=0, Hot swap=1, Fail=1, Requested on=1, Off=0
+    Overtmp fail=0, Temperature warn=0, AC fail=1, DC fail=0
+Fan 0 [2,-1]  Element type: Cooling
+  Enclosure Status:
+    Predicted failure=0, Disabled=0, Swap=0, status: Unsupported
+Fan 0 [2,0]  Element type: Cooling
+  Enclosure Status:
+    Predicted failure=0, Disabled=0, Swap=0, status: OK
+    Ident=0, Do not remove=0, Hot swap=0, Fail=0, Requested on=1
+    Off=0, Actual speed=8830 rpm, Fan at fourth lowest speed
+Fan 1 [2,1]  Element type: Cooling
+  Enclosure Status:
+    Predicted failure=0, Disabled=0, Swap=0, status: Noncritical
+    Ident=0, Do not remove=0, Hot swap=0, Fail=0, Requested on=0
+    Off=1, Actual speed=0 rpm, Fan stopped
+Ambient [3,0]  Element type: Temperature sensor
+  Enclosure Status:
+    Predicted failure=0, Disabled=0, Swap=0, status: OK
+    Ident=0, Fail=0, OT failure=0, OT warning=0, UT failure=0
+    UT warning=0
+    Temperature=31 C
+12V [4,0]  Element type: Voltage sensor
+  Enclosure Status:
+    Predicted failure=0, Disabled=0, Swap=0, status: OK
+    Ident=0, Do not remove=0, Fail=0, Warn Over=0, Warn Under=0, Crit Over=0
+    Crit Under=0
+    Voltage: 12.10 volts
+PSU 0 current [5,0]  Element type: Current sensor
+  Enclosure Status:
+    Predicted failure=0, Disabled=0, Swap=0, status: OK
+    Ident=0, Do not remove=0, Fail=0, Warn Over=0, Crit Over=0
+    Current: 3.41 amps
+PSU 1 current [5,1]  Element type: Current sensor
+  Enclosure Status:
+    Predicted failure=0, Disabled=0, Swap=0, status: OK
+    Ident=0, Do not remove=0, Fail=0, Warn Over=0, Crit Over=0
+    Current: 3.50 amps
+";
+
+        // Captured with `sg_ses -p 2` on the same enclosure.
+        const SES_SUMMARY_HGST: &str = "\
+  HGST      H4060-J           2033
+    Primary enclosure logical identifier (hex): 5000cca0500a1b3f
+Enclosure Status diagnostic page:
+  INVOP=0, INFO=0, NON-CRIT=1, CRIT=1, UNRECOV=0
+  generation code: 0x1
+";
+
+        #[test]
+        fn lsscsi_hgst() {
+            let devices = parse_lsscsi(LSSCSI_HGST);
+            assert_eq!(devices.len(), 3);
+            assert_eq!(
+                devices[0],
+                ScsiDevice {
+                    hctl: "0:0:0:0".to_string(),
+                    device_type: "disk".to_string(),
+                    vendor: "ATA".to_string(),
+                    model: "INTEL SSDSC2KB24".to_string(),
+                    revision: "0110".to_string(),
+                    device_node: Some("/dev/sda".to_string()),
+                    sg_node: Some("/dev/sg0".to_string()),
+                }
+            );
+            assert_eq!(
+                devices[2],
+                ScsiDevice {
+                    hctl: "15:0:1:0".to_string(),
+                    device_type: "enclosu".to_string(),
+                    vendor: "HGST".to_string(),
+                    model: "H4102-J".to_string(),
+                    revision: "2033".to_string(),
+                    device_node: None,
+                    sg_node: Some("/dev/sg9".to_string()),
+                }
+            );
+        }
+
+        #[test]
+        fn lsscsi_supermicro() {
+            let devices = parse_lsscsi(LSSCSI_SUPERMICRO);
+            let enclosures: Vec<(&str, &str, &str)> = devices
+                .iter()
+                .filter(|d| d.device_type == "enclosu")
+                .map(|d| (d.hctl.as_str(), d.model.as_str(), d.sg_node.as_deref().unwrap()))
+                .collect();
+            assert_eq!(
+                enclosures,
+                vec![("1:0:12:0", "SAS3x40", "/dev/sg3"), ("1:0:13:0", "SC846-P", "/dev/sg4")]
+            );
+        }
+
+        #[test]
+        fn lsscsi_dell_without_trailing_space() {
+            let devices = parse_lsscsi(LSSCSI_DELL);
+            assert_eq!(devices.len(), 2);
+            assert_eq!(devices[0].vendor, "DELL");
+            assert_eq!(devices[0].revision, "1.07");
+            assert_eq!(devices[0].sg_node.as_deref(), Some("/dev/sg26"));
+            assert_eq!(devices[1].device_node.as_deref(), Some("/dev/sdaa"));
+        }
+
+        #[test]
+        fn lsscsi_values_with_spaces() {
+            let devices = parse_lsscsi(LSSCSI_SPACES);
+            assert_eq!(devices.len(), 4);
+            assert_eq!(devices[0].model, "D3700 Enclosure");
+            assert_eq!(devices[0].sg_node.as_deref(), Some("/dev/sg5"));
+            assert_eq!(devices[1].vendor, "NETAPP");
+            assert_eq!(devices[1].model, "DS424IOM6 A");
+            assert_eq!(devices[1].revision, "0212");
+            assert_eq!(devices[3].vendor, "HL-DT-ST");
+            assert_eq!(devices[3].model, "DVD+-RW GHB0N");
+            assert_eq!(devices[3].device_node.as_deref(), Some("/dev/sr0"));
+        }
+
+        #[test]
+        fn lsscsi_long_address_shifts_columns() {
+            let devices = parse_lsscsi(LSSCSI_SPACES);
+            assert_eq!(devices[2].hctl, "123:0:100:0");
+            assert_eq!(devices[2].vendor, "Quanta");
+            assert_eq!(devices[2].model, "JB4602 SAS EXP");
+            assert_eq!(devices[2].revision, "1.0");
+        }
+
+        #[test]
+        fn lsscsi_skips_noise() {
+            let devices = parse_lsscsi("\nlsscsi: error\n[1:0:0:0]    disk\n");
+            assert!(devices.is_empty());
+        }
+
+        #[test]
+        fn ses_elements_and_their_status() {
+            let elements = parse_elements(SES_STATUS_HGST);
+            let decoded: Vec<(&str, &str, &str, SlotStatus)> = elements
+                .iter()
+                .map(|e| (e.description.as_str(), e.index.as_str(), e.element_type.as_str(), e.status))
+                .collect();
+            assert_eq!(
+                decoded,
+                vec![
+                    ("Slot 10", "0,9", "Array device slot", SlotStatus::Ok),
+                    ("PSU 0", "1,0", "Power supply", SlotStatus::Critical),
+                    ("Fan 0", "2,-1", "Cooling", SlotStatus::Unsupported),
+                    ("Fan 0", "2,0", "Cooling", SlotStatus::Ok),
+                    ("Fan 1", "2,1", "Cooling", SlotStatus::Noncritical),
+                    ("Ambient", "3,0", "Temperature sensor", SlotStatus::Ok),
+                    ("12V", "4,0", "Voltage sensor", SlotStatus::Ok),
+                    ("PSU 0 current", "5,0", "Current sensor", SlotStatus::Ok),
+                    ("PSU 1 current", "5,1", "Current sensor", SlotStatus::Ok),
+                ]
+            );
+            assert!(element_flag(&elements[1], "Fail"));
+            assert!(!element_flag(&elements[1], "Off"));
+            assert_eq!(element_reading(&elements[0]), "");
+            assert_eq!(element_reading(&elements[5]), "Temperature=31 C");
+            assert_eq!(element_reading(&elements[6]), "Voltage: 12.10 volts");
+        }
+
+        #[test]
+        fn ses_cooling_elements() {
+            let fans = parse_cooling_elements(SES_STATUS_HGST);
+            assert_eq!(fans.len(), 3);
+            assert_eq!(
+                fans[1],
+                (
+                    "Fan 0".to_string(),
+                    "2,0".to_string(),
+                    8830,
+                    SlotStatus::Ok,
+                    FanState::Ok,
+                    "Fan at fourth lowest speed".to_string(),
+                )
+            );
+            // The Off bit wins over the Noncritical status.
+            assert_eq!(fans[2].2, 0);
+            assert_eq!(fans[2].4, FanState::Off);
+            assert_eq!(fans[2].5, "Fan stopped");
+        }
+
+        #[test]
+        fn ses_power_from_a_single_voltage_sensor() {
+            let power = parse_power(SES_STATUS_HGST);
+            assert_eq!(power.len(), 2);
+            assert_eq!(power[0].0, "PSU 0 current");
+            assert_eq!(power[0].1, Some(12.10));
+            assert_eq!(power[0].2, 3.41);
+            assert_eq!(power[1].1, Some(12.10));
+            assert!((power[1].3.unwrap() - 42.35).abs() < 1e-9);
         }
 
-        enclosure
+        #[test]
+        fn ses_summary_status() {
+            assert_eq!(parse_summary_status(SES_SUMMARY_HGST), SlotStatus::Critical);
+            assert_eq!(
+                parse_summary_status("  INVOP=0, INFO=1, NON-CRIT=0, CRIT=0, UNRECOV=0\n"),
+                SlotStatus::Ok
+            );
+            assert_eq!(parse_summary_status(""), SlotStatus::Unknown);
+        }
     }
 }
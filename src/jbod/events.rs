@@ -0,0 +1,431 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Events {
+    use clap::ArgMatches;
+    use colored::*;
+    use nix::sys::socket::{bind, recv, socket, AddressFamily, MsgFlags, NetlinkAddr};
+    use nix::sys::socket::{SockFlag, SockProtocol, SockType};
+    use nix::unistd::close;
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+    use std::fmt;
+    use std::fs;
+    use std::path::Path;
+    use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+    use std::thread;
+    use std::time::{Duration, SystemTime};
+
+    use crate::jbod::context::Context;
+    use crate::jbod::disks::DiskShelf;
+    use crate::jbod::element::Element::SlotStatus;
+    use crate::jbod::enclosure::BackPlane;
+    use crate::utils::audit::Audit;
+    use crate::utils::helper::Util;
+    use crate::utils::output::Output;
+    use crate::utils::output::Output::ErrorCode;
+
+    /// How often the slots and the SES elements are read again.
+    const DEFAULT_INTERVAL: Duration = Duration::from_secs(10);
+    /// The kernel multicast group of the uevents.
+    const UEVENT_GROUP: u32 = 1;
+    /// The subsystems whose uevents concern the enclosures and their disks.
+    const SUBSYSTEMS: &[&str] = &["enclosure", "scsi_generic", "block"];
+
+    /// What happened.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Kind {
+        /// The state of a slot or an element when the stream starts.
+        State,
+        /// A disk showed up in a slot.
+        DiskAdded,
+        /// A disk left its slot.
+        DiskRemoved,
+        /// The SES status of a slot changed.
+        SlotStatus,
+        /// The SES status of a fan, a power supply, a sensor... changed.
+        ElementStatus,
+        /// The kernel added, removed or changed a device, from its uevent.
+        DeviceAdded,
+        DeviceRemoved,
+        DeviceChanged,
+    }
+
+    impl fmt::Display for Kind {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let name = match self {
+                Kind::State => "state",
+                Kind::DiskAdded => "disk added",
+                Kind::DiskRemoved => "disk removed",
+                Kind::SlotStatus => "slot status",
+                Kind::ElementStatus => "element status",
+                Kind::DeviceAdded => "device added",
+                Kind::DeviceRemoved => "device removed",
+                Kind::DeviceChanged => "device changed",
+            };
+            write!(f, "{}", name)
+        }
+    }
+
+    #[derive(Debug, Clone, Serialize)]
+    pub struct Event {
+        /// When the event was seen, RFC 3339 UTC.
+        pub time: String,
+        pub event: Kind,
+        /// The slot number of the enclosure provided by the JBOD, empty for
+        /// a uevent.
+        pub enclosure: String,
+        /// The slot, example: Slot 05, the element, example: Fan 2 [0,1],
+        /// or the kernel path of a uevent.
+        pub component: String,
+        /// The device in the slot or of the uevent, example: /dev/sg7.
+        pub device: Option<String>,
+        /// The status before and after a change, the status of a state.
+        pub old: Option<String>,
+        pub new: Option<String>,
+    }
+
+    impl Event {
+        fn new(event: Kind, enclosure: &str, component: &str) -> Self {
+            Event {
+                time: Audit::rfc3339(SystemTime::now()),
+                event,
+                enclosure: enclosure.to_string(),
+                component: component.to_string(),
+                device: None,
+                old: None,
+                new: None,
+            }
+        }
+    }
+
+    impl fmt::Display for Event {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let kind = match self.event {
+                Kind::DiskRemoved | Kind::DeviceRemoved => self.event.to_string().red().bold(),
+                Kind::State => self.event.to_string().normal(),
+                _ => self.event.to_string().green().bold(),
+            };
+            write!(f, "{} {}", self.time, kind)?;
+            if !self.enclosure.is_empty() {
+                write!(f, " {}", self.enclosure.bold())?;
+            }
+            write!(f, " {}", self.component)?;
+            if let Some(device) = &self.device {
+                write!(f, " {}", device.bold())?;
+            }
+            match (&self.old, &self.new) {
+                (Some(old), Some(new)) => write!(f, " {} -> {}", old.yellow(), new.yellow().bold()),
+                (None, Some(new)) => write!(f, " {}", new.yellow()),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    /// The state of a slot or an element, compared between two reads.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct State {
+        /// The device in a slot, None for an empty slot or an element.
+        device: Option<String>,
+        status: SlotStatus,
+        slot: bool,
+    }
+
+    /// The state of every slot and element, by enclosure and component.
+    type Snapshot = BTreeMap<(String, String), State>;
+
+    /// Returns the device of an enclosure slot, its sg node when it has
+    /// one, None when the slot is empty.
+    ///
+    /// # Arguments
+    ///
+    /// * `component` - the component path, example: /sys/class/enclosure/15:0:1:0/Slot 05
+    ///
+    fn slot_device(component: &Path) -> Option<String> {
+        let hctl = fs::read_link(component.join("device")).ok()?;
+        let sg = fs::read_dir(component.join("device/scsi_generic"))
+            .ok()
+            .and_then(|mut entries| entries.next())
+            .and_then(|entry| entry.ok())
+            .map(|entry| format!("/dev/{}", entry.file_name().to_string_lossy()));
+        Some(sg.unwrap_or_else(|| {
+            hctl.file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string()
+        }))
+    }
+
+    /// Returns the state of the slots read from sysfs and of the elements
+    /// read with `sg_ses`.
+    ///
+    /// # Arguments
+    ///
+    /// * `enclosures` - the enclosures watched
+    /// * `jobs` - maximum number of enclosures probed at the same time
+    ///
+    fn snapshot(enclosures: &[BackPlane::Enclosure], jobs: usize) -> Snapshot {
+        let mut snapshot = Snapshot::new();
+        let sys_class_enclosure = Util::sys_class_enclosure();
+
+        for enclosure in enclosures {
+            let enclosure_path = sys_class_enclosure.join(&enclosure.slot);
+            let components = fs::read_dir(&enclosure_path)
+                .into_iter()
+                .flatten()
+                .flatten();
+            for component in components.filter(|c| c.path().join("status").is_file()) {
+                let name = component.file_name().to_string_lossy().to_string();
+                let state = State {
+                    device: slot_device(&component.path()),
+                    status: SlotStatus::read(&component.path()),
+                    slot: true,
+                };
+                snapshot.insert((enclosure.slot.clone(), name), state);
+            }
+        }
+
+        for element in BackPlane::get_enclosure_elements(enclosures, jobs) {
+            let state = State {
+                device: None,
+                status: element.status,
+                slot: false,
+            };
+            let name = format!("{} [{}]", element.description, element.index);
+            snapshot.insert((element.slot, name), state);
+        }
+        snapshot
+    }
+
+    /// Returns a state event for every slot and element.
+    fn state_events(snapshot: &Snapshot) -> Vec<Event> {
+        let mut components: Vec<_> = snapshot.iter().collect();
+        components.sort_by(|((a, x), _), ((b, y), _)| {
+            Util::natural_cmp(a, b).then_with(|| Util::natural_cmp(x, y))
+        });
+        components
+            .into_iter()
+            .map(|((enclosure, component), state)| {
+                let mut event = Event::new(Kind::State, enclosure, component);
+                event.device = state.device.clone();
+                event.new = Some(state.status.to_string());
+                event
+            })
+            .collect()
+    }
+
+    /// Returns the events turning the `before` snapshot into `after`.
+    ///
+    /// A slot whose disk was swapped between the two reads gets a removal
+    /// and an addition, the components gone with their enclosure are only
+    /// reported by the uevents.
+    fn changes(before: &Snapshot, after: &Snapshot) -> Vec<Event> {
+        let mut events = Vec::new();
+        for ((enclosure, component), new) in after {
+            let old = match before.get(&(enclosure.clone(), component.clone())) {
+                Some(old) => old,
+                None => continue,
+            };
+            if old.device != new.device {
+                if let Some(device) = &old.device {
+                    let mut event = Event::new(Kind::DiskRemoved, enclosure, component);
+                    event.device = Some(device.clone());
+                    events.push(event);
+                }
+                if let Some(device) = &new.device {
+                    let mut event = Event::new(Kind::DiskAdded, enclosure, component);
+                    event.device = Some(device.clone());
+                    events.push(event);
+                }
+            }
+            if old.status != new.status {
+                let kind = if new.slot {
+                    Kind::SlotStatus
+                } else {
+                    Kind::ElementStatus
+                };
+                let mut event = Event::new(kind, enclosure, component);
+                event.device = new.device.clone();
+                event.old = Some(old.status.to_string());
+                event.new = Some(new.status.to_string());
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// Returns the event of a kernel uevent, None when its subsystem or its
+    /// action doesn't concern the enclosures.
+    ///
+    /// A uevent is `ACTION@DEVPATH` followed by the `KEY=VALUE` properties,
+    /// separated by NUL bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - the uevent received from the kernel
+    ///
+    fn parse_uevent(message: &[u8]) -> Option<Event> {
+        let message = String::from_utf8_lossy(message);
+        let mut fields = message.split('\0');
+        fields.next().filter(|header| header.contains('@'))?;
+        let properties: BTreeMap<&str, &str> = fields.filter_map(|f| f.split_once('=')).collect();
+
+        let subsystem = properties.get("SUBSYSTEM")?;
+        if !SUBSYSTEMS.contains(subsystem) || properties.get("DEVTYPE") == Some(&"partition") {
+            return None;
+        }
+        let kind = match *properties.get("ACTION")? {
+            "add" => Kind::DeviceAdded,
+            "remove" => Kind::DeviceRemoved,
+            "change" => Kind::DeviceChanged,
+            _ => return None,
+        };
+        let mut event = Event::new(kind, "", properties.get("DEVPATH")?);
+        event.device = properties
+            .get("DEVNAME")
+            .map(|name| match name.starts_with('/') {
+                true => name.to_string(),
+                false => format!("/dev/{}", name),
+            });
+        Some(event)
+    }
+
+    /// Starts a thread sending the uevents of the enclosures and of their
+    /// disks, or returns why the kernel can't be listened to.
+    fn listen_uevents(sender: Sender<Event>) -> nix::Result<()> {
+        let fd = socket(
+            AddressFamily::Netlink,
+            SockType::Datagram,
+            SockFlag::SOCK_CLOEXEC,
+            SockProtocol::NetlinkKObjectUEvent,
+        )?;
+        if let Err(e) = bind(fd, &NetlinkAddr::new(0, UEVENT_GROUP)) {
+            let _ = close(fd);
+            return Err(e);
+        }
+
+        thread::spawn(move || {
+            let mut buffer = vec![0u8; 8192];
+            while let Ok(len) = recv(fd, &mut buffer, MsgFlags::empty()) {
+                if let Some(event) = parse_uevent(&buffer[..len]) {
+                    if sender.send(event).is_err() {
+                        break;
+                    }
+                }
+            }
+            let _ = close(fd);
+        });
+        Ok(())
+    }
+
+    /// Prints events in the selected format, JSON is written one object
+    /// per line so the stream can be piped to `jq`.
+    fn print_events(format: Output::Format, events: &[Event]) -> Result<(), ()> {
+        match format {
+            Output::Format::Table => {
+                for event in events {
+                    println!("{}", event);
+                }
+                Ok(())
+            }
+            Output::Format::Json | Output::Format::Jsonl => {
+                Output::render(Output::Format::Jsonl, events).map_err(|e| Output::print_error(&e))
+            }
+            _ => Output::render(format, events).map_err(|e| Output::print_error(&e)),
+        }
+    }
+
+    /// Returns an empty Result or an error when the enclosures can't be
+    /// watched.
+    ///
+    /// This function prints the state of the slots and of the elements, with
+    /// `--follow` it then streams their changes and the uevents of the
+    /// enclosures and of their disks until interrupted.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `events` subcommand
+    ///
+    pub fn jbod_events(options: &ArgMatches) -> Result<(), ()> {
+        let format = Output::get_format(options);
+        let tabular = matches!(format, Output::Format::Csv | Output::Format::Tsv);
+        if options.is_present("follow") && tabular {
+            Output::print_error_code(
+                ErrorCode::InvalidArgument,
+                "a stream of events has no CSV output, use -o jsonl",
+            );
+            return Err(());
+        }
+        let interval = options
+            .value_of("interval")
+            .and_then(Util::parse_duration)
+            .unwrap_or(DEFAULT_INTERVAL);
+
+        let run = Context::Run::new(DiskShelf::ScanOptions::from_matches(options));
+        let enclosures = DiskShelf::jbod_enclosures(&run);
+        let jobs = run.options.jobs;
+        if enclosures.is_empty() {
+            Output::print_error_code(ErrorCode::NotFound, "no enclosure to watch");
+            return Err(());
+        }
+
+        let mut last = snapshot(&enclosures, jobs);
+        print_events(format, &state_events(&last))?;
+        if !options.is_present("follow") {
+            return Ok(());
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        if let Err(e) = listen_uevents(sender) {
+            Output::print_warning(&format!(
+                "cannot listen to the kernel uevents: {}, the changes are read every {}s",
+                e,
+                interval.as_secs()
+            ));
+        }
+        loop {
+            match receiver.recv_timeout(interval) {
+                Ok(event) => {
+                    let mut events = vec![event];
+                    events.extend(receiver.try_iter());
+                    print_events(format, &events)?;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                // Without uevents the slots are only read every interval.
+                Err(RecvTimeoutError::Disconnected) => thread::sleep(interval),
+            }
+            let current = snapshot(&enclosures, jobs);
+            print_events(format, &changes(&last, &current))?;
+            last = current;
+        }
+    }
+}
@@ -0,0 +1,316 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Exec {
+    use clap::ArgMatches;
+    use colored::*;
+    use regex::Regex;
+    use serde::Serialize;
+    use std::process::Command;
+    use std::sync::Mutex;
+
+    use crate::jbod::context::Context;
+    use crate::jbod::disks::DiskShelf;
+    use crate::jbod::disks::DiskShelf::Disk;
+    use crate::utils::helper::Util;
+    use crate::utils::output::Output;
+    use crate::utils::output::Output::ErrorCode;
+
+    /// The disk fields usable in a filter and as a `{placeholder}`.
+    pub const FIELDS: &[&str] = &[
+        "device",
+        "sg",
+        "enclosure",
+        "slot",
+        "label",
+        "vendor",
+        "model",
+        "serial",
+        "wwn",
+        "firmware",
+        "temp",
+        "status",
+        "role",
+    ];
+
+    /// Returns the value of a disk field, None when the disk has none.
+    ///
+    /// # Arguments
+    ///
+    /// * `disk` - the disk
+    /// * `field` - one of `FIELDS`
+    ///
+    pub fn field(disk: &Disk, field: &str) -> Option<String> {
+        let value = match field {
+            "device" => disk.device_map.clone(),
+            "sg" => disk.device_path.clone(),
+            "enclosure" => disk.enclosure.clone(),
+            "slot" => disk.slot.clone(),
+            "label" => disk.label.clone()?,
+            "vendor" => disk.vendor.clone(),
+            "model" => disk.model.clone(),
+            "serial" => disk.serial.clone(),
+            "wwn" => disk.wwn.clone(),
+            "firmware" => disk.fw_revision.clone(),
+            "temp" => match disk.temperature {
+                DiskShelf::Temperature::Celsius(t) => t.to_string(),
+                _ => return None,
+            },
+            "status" => serde_json::to_value(disk.status)
+                .ok()?
+                .as_str()?
+                .to_string(),
+            "role" => disk.role?.to_string(),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// A condition on a disk field, example: `temp>50` or `model~HUH72`.
+    #[derive(Debug, Clone)]
+    pub struct Filter {
+        pub field: String,
+        pub operator: String,
+        pub value: String,
+    }
+
+    impl Filter {
+        /// Returns the Filter of an expression or why it is invalid.
+        ///
+        /// The operators are `=`, `!=`, `~` (contains), and `<`, `<=`, `>`,
+        /// `>=` which compare numbers.
+        pub fn parse(expression: &str) -> Result<Self, String> {
+            let re = Regex::new(r"^\s*(\w+)\s*(>=|<=|!=|=|>|<|~)\s*(.*?)\s*$").unwrap();
+            let caps = re.captures(expression).ok_or_else(|| {
+                format!("invalid filter {}, expected <field><op><value>", expression)
+            })?;
+            let filter = Filter {
+                field: caps[1].to_lowercase(),
+                operator: caps[2].to_string(),
+                value: caps[3].to_string(),
+            };
+            if !FIELDS.contains(&filter.field.as_str()) {
+                return Err(format!(
+                    "unknown field {}, expected one of {}",
+                    filter.field,
+                    FIELDS.join(", ")
+                ));
+            }
+            if ["<", "<=", ">", ">="].contains(&filter.operator.as_str())
+                && filter.value.parse::<f64>().is_err()
+            {
+                return Err(format!(
+                    "{} compares numbers, {} isn't one",
+                    filter.operator, filter.value
+                ));
+            }
+            Ok(filter)
+        }
+
+        /// Returns true when the disk matches, a disk without the field
+        /// never matches. The strings are compared ignoring the case.
+        pub fn matches(&self, disk: &Disk) -> bool {
+            let value = match field(disk, &self.field) {
+                Some(value) => value,
+                None => return false,
+            };
+            let number = || value.parse::<f64>().ok();
+            let wanted = self.value.parse::<f64>().unwrap_or_default();
+            match self.operator.as_str() {
+                "=" => value.eq_ignore_ascii_case(&self.value),
+                "!=" => !value.eq_ignore_ascii_case(&self.value),
+                "~" => value.to_lowercase().contains(&self.value.to_lowercase()),
+                "<" => number().is_some_and(|n| n < wanted),
+                "<=" => number().is_some_and(|n| n <= wanted),
+                ">" => number().is_some_and(|n| n > wanted),
+                ">=" => number().is_some_and(|n| n >= wanted),
+                _ => false,
+            }
+        }
+    }
+
+    /// Returns the argument with the `{field}` placeholders replaced by the
+    /// values of the disk, an unknown placeholder is left as is.
+    fn expand(argument: &str, disk: &Disk) -> String {
+        let mut expanded = argument.to_string();
+        for name in FIELDS {
+            let placeholder = format!("{{{}}}", name);
+            if expanded.contains(&placeholder) {
+                expanded = expanded.replace(&placeholder, &field(disk, name).unwrap_or_default());
+            }
+        }
+        expanded
+    }
+
+    /// The result of the command run for a disk.
+    #[derive(Debug, Serialize)]
+    pub struct Execution {
+        pub enclosure: String,
+        pub slot: String,
+        pub device: String,
+        pub serial: String,
+        pub command: Vec<String>,
+        /// The exit code, None when the command couldn't run or was killed
+        pub status: Option<i32>,
+        pub stdout: String,
+        pub stderr: String,
+    }
+
+    impl Execution {
+        fn succeeded(&self) -> bool {
+            self.status == Some(0)
+        }
+    }
+
+    /// Returns the Execution of the command for a disk.
+    fn run_for(disk: &Disk, command: &[String]) -> Execution {
+        let command: Vec<String> = command.iter().map(|a| expand(a, disk)).collect();
+        let (status, stdout, stderr) = match Command::new(&command[0]).args(&command[1..]).output()
+        {
+            Ok(output) => (
+                output.status.code(),
+                String::from_utf8_lossy(&output.stdout).to_string(),
+                String::from_utf8_lossy(&output.stderr).to_string(),
+            ),
+            Err(e) => (
+                None,
+                String::new(),
+                format!("cannot run {}: {}\n", command[0], e),
+            ),
+        };
+        Execution {
+            enclosure: disk.enclosure.clone(),
+            slot: disk.slot.clone(),
+            device: disk.device_map.clone(),
+            serial: disk.serial.clone(),
+            command,
+            status,
+            stdout,
+            stderr,
+        }
+    }
+
+    /// Prints the output of a command under a header naming the disk.
+    fn print_execution(execution: &Execution) {
+        let status = match execution.status {
+            Some(0) => "exit 0".green(),
+            Some(code) => format!("exit {}", code).red().bold(),
+            None => "failed".red().bold(),
+        };
+        println!(
+            "{} {} {} {} {}",
+            "==>".blue().bold(),
+            execution.device.bold(),
+            execution.slot,
+            status,
+            "<==".blue().bold()
+        );
+        print!("{}", execution.stdout);
+        eprint!("{}", execution.stderr);
+    }
+
+    /// Returns an error when a command failed on a disk.
+    ///
+    /// This function is used in the `exec` menu option, it runs a command
+    /// for every disk matching all the filters, up to `--jobs` at once. The
+    /// output of each command is printed once it ends.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `exec` subcommand
+    ///
+    pub fn jbod_exec(options: &ArgMatches) -> Result<(), ()> {
+        let command: Vec<String> = options
+            .values_of("command")
+            .map(|v| v.map(str::to_string).collect())
+            .unwrap_or_default();
+        let filters: Vec<Filter> = match options
+            .values_of("filter")
+            .map(|v| v.map(Filter::parse).collect())
+            .unwrap_or_else(|| Ok(Vec::new()))
+        {
+            Ok(filters) => filters,
+            Err(e) => {
+                Output::print_error_code(ErrorCode::InvalidArgument, &e);
+                return Err(());
+            }
+        };
+
+        // The expensive fields are collected only when needed.
+        let uses = |name: &str| {
+            filters.iter().any(|f| f.field == name)
+                || command.iter().any(|a| a.contains(&format!("{{{}}}", name)))
+        };
+        let mut scan = DiskShelf::ScanOptions::from_matches(options);
+        scan.fields.temperature = uses("temp");
+        scan.fields.firmware = uses("firmware");
+        let jobs = scan.jobs;
+        let run = Context::Run::new(scan);
+        let mut disks = DiskShelf::jbod_disk_map(&run);
+        DiskShelf::sort_disks(&mut disks);
+        disks.retain(|d| filters.iter().all(|f| f.matches(d)));
+
+        let format = Output::get_format(options);
+        let streamed = format == Output::Format::Table || format == Output::Format::Jsonl;
+        let printing = Mutex::new(());
+        let executions = Util::parallel_map(&disks, jobs, |disk| {
+            let execution = run_for(disk, &command);
+            if streamed {
+                let _printing = printing.lock().unwrap();
+                if format == Output::Format::Table {
+                    print_execution(&execution);
+                } else if let Err(e) = Output::render(format, std::slice::from_ref(&execution)) {
+                    Output::print_error(&e);
+                }
+            }
+            execution
+        });
+        if !streamed {
+            Output::render(format, &executions).map_err(|e| Output::print_error(&e))?;
+        }
+        Output::render_errors(format, &run.errors());
+
+        let failed = executions.iter().filter(|e| !e.succeeded()).count();
+        if disks.is_empty() {
+            Output::print_warning("no disk matches the filters");
+        } else if failed > 0 {
+            Output::print_error(&format!(
+                "the command failed on {} of {} disks",
+                failed,
+                executions.len()
+            ));
+        }
+        if failed > 0 || !run.errors().is_empty() {
+            return Err(());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Find {
+    use clap::ArgMatches;
+
+    use crate::jbod::context::Context;
+    use crate::jbod::disks::DiskShelf;
+    use crate::jbod::disks::DiskShelf::Disk;
+    use crate::utils::output::Output;
+    use crate::utils::output::Output::ErrorCode;
+
+    extern crate prettytable;
+    use prettytable::{color, format, Attr, Cell, Row, Table};
+
+    /// Returns the name of the fields of a disk containing `text`, the
+    /// comparison ignores the case.
+    ///
+    /// # Arguments
+    ///
+    /// * `disk` - the disk
+    /// * `text` - the text looked for, lowercase
+    ///
+    fn matching_fields(disk: &Disk, text: &str) -> Vec<&'static str> {
+        let label = disk.label.clone().unwrap_or_default();
+        let fields = [
+            ("serial", disk.serial.as_str()),
+            ("model", disk.model.as_str()),
+            ("vendor", disk.vendor.as_str()),
+            ("device", disk.device_path.as_str()),
+            ("map", disk.device_map.as_str()),
+            ("enclosure", disk.enclosure.as_str()),
+            ("slot", disk.slot.as_str()),
+            ("label", label.as_str()),
+            ("wwn", disk.wwn.as_str()),
+            ("sas", disk.sas_address.as_str()),
+        ];
+        fields
+            .iter()
+            .filter(|(_, value)| value.to_lowercase().contains(text))
+            .map(|(name, _)| *name)
+            .collect()
+    }
+
+    /// Creates the pretty table for the disks found.
+    fn create_find_table() -> Table {
+        let mut find_table = Table::new();
+        find_table.set_format(*format::consts::FORMAT_NO_BORDER);
+        let header = [
+            "DEVICE",
+            "MAP",
+            "ENCLOSURE",
+            "SLOT",
+            "VENDOR",
+            "MODEL",
+            "SERIAL",
+            "WWN",
+            "MATCHED",
+        ];
+        find_table.add_row(Row::new(
+            header
+                .iter()
+                .map(|h| {
+                    Cell::new(h)
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(color::BLUE))
+                })
+                .collect(),
+        ));
+
+        find_table
+    }
+
+    /// Returns an empty Result or an error when no disk matches.
+    ///
+    /// This function is used in the `find` menu option, the text is looked
+    /// for in the serial, model, vendor, devices, enclosure, slot, label,
+    /// wwn and SAS address of every disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `find` subcommand
+    ///
+    pub fn jbod_find(options: &ArgMatches) -> Result<(), ()> {
+        let text = options.value_of("text").unwrap_or_default().to_lowercase();
+        let mut scan = DiskShelf::ScanOptions::from_matches(options);
+        scan.fields.temperature = false;
+        scan.fields.firmware = false;
+        let run = Context::Run::new(scan);
+        let mut disks = DiskShelf::jbod_disk_map(&run);
+        DiskShelf::sort_disks(&mut disks);
+
+        let found: Vec<(Disk, Vec<&str>)> = disks
+            .into_iter()
+            .filter_map(|disk| {
+                let fields = matching_fields(&disk, &text);
+                if fields.is_empty() {
+                    None
+                } else {
+                    Some((disk, fields))
+                }
+            })
+            .collect();
+
+        let format = Output::get_format(options);
+        if format != Output::Format::Table {
+            let disks: Vec<&Disk> = found.iter().map(|(disk, _)| disk).collect();
+            Output::render(format, &disks).map_err(|e| Output::print_error(&e))?;
+        } else if !found.is_empty() {
+            let mut find_table = create_find_table();
+            for (disk, fields) in &found {
+                find_table.add_row(Row::new(vec![
+                    Cell::new(&disk.device_path),
+                    Cell::new(&disk.device_map),
+                    Cell::new(&disk.enclosure),
+                    Cell::new(&format!(
+                        "{}{}",
+                        disk.slot,
+                        DiskShelf::slot_label_suffix(&disk.label)
+                    )),
+                    Cell::new(&disk.vendor),
+                    Cell::new(&disk.model),
+                    Cell::new(&disk.serial),
+                    Cell::new(&disk.wwn),
+                    Cell::new(&fields.join(",")),
+                ]));
+            }
+            Output::print_table(&find_table);
+        }
+
+        Output::render_errors(format, &run.errors());
+        if found.is_empty() {
+            if format == Output::Format::Table {
+                Output::print_error_code(ErrorCode::NotFound, &format!("no disk matches {}", text));
+            }
+            return Err(());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,442 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Firmware {
+    use clap::ArgMatches;
+    use colored::*;
+    use sha2::{Digest, Sha256};
+    use std::fs;
+    use std::io;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use crate::jbod::burnin::Burnin;
+    use crate::jbod::context::Context;
+    use crate::jbod::disks::DiskShelf;
+    use crate::jbod::enclosure::BackPlane;
+    use crate::jbod::sgio::SgIo;
+    use crate::utils::audit::Audit;
+    use crate::utils::helper::Util;
+    use crate::utils::i18n::I18n;
+    use crate::utils::i18n::I18n::Message;
+    use crate::utils::output::Output;
+    use crate::utils::output::Output::ErrorCode;
+
+    /// Default size of the chunks sent by WRITE BUFFER.
+    pub const DEFAULT_CHUNK: usize = 4096;
+
+    /// How long a device may take to store a chunk or to activate the
+    /// microcode, far longer than any other command.
+    const MICROCODE_TIMEOUT: Duration = Duration::from_secs(120);
+
+    /// How long a device may take to answer again after the activation.
+    const RESET_TIMEOUT: Duration = Duration::from_secs(300);
+
+    /// Returns the SHA-256 of the data in lowercase hexadecimal.
+    pub fn sha256_hex(data: &[u8]) -> String {
+        Sha256::digest(data)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// Returns the content of a firmware image, or why it can't be used.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - the path of the image
+    /// * `sha256` - the checksum the image must have, if known
+    ///
+    pub fn read_image(file: &str, sha256: Option<&str>) -> Result<Vec<u8>, String> {
+        let image = fs::read(file).map_err(|e| format!("cannot read {}: {}", file, e))?;
+        if image.is_empty() {
+            return Err(format!("{} is empty", file));
+        }
+
+        let checksum = sha256_hex(&image);
+        println!("Image {}: {} bytes, sha256 {}", file.bold(), image.len(), checksum);
+        if let Some(expected) = sha256 {
+            if !checksum.eq_ignore_ascii_case(expected.trim()) {
+                return Err(format!("{} doesn't match the sha256 {}", file, expected));
+            }
+            println!("Checksum {}", "verified".green().bold());
+        }
+
+        Ok(image)
+    }
+
+    /// Sends a microcode image with WRITE BUFFER, in chunks.
+    ///
+    /// With `MODE_DOWNLOAD_DEFER` the device only switches to the image once
+    /// it is whole and activated here, with `MODE_DOWNLOAD_SAVE` the device
+    /// activates it by itself after the last chunk.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - the device opened read write
+    /// * `image` - the microcode image
+    /// * `chunk` - the size of each WRITE BUFFER
+    /// * `mode` - the WRITE BUFFER download mode
+    /// * `show_progress` - draw a progress bar on stderr, see `Util::show_progress`
+    ///
+    pub fn download_microcode(
+        device: &SgIo::Device,
+        image: &[u8],
+        chunk: usize,
+        mode: u8,
+        show_progress: bool,
+    ) -> io::Result<()> {
+        let chunk = chunk.max(1);
+        let progress = show_progress
+            .then(|| Util::Progress::start("Downloading".to_string(), image.len() as u64, true));
+        for (i, data) in image.chunks(chunk).enumerate() {
+            let offset = i * chunk;
+            device
+                .write_buffer(mode, 0, offset as u32, data, MICROCODE_TIMEOUT)
+                .map_err(|e| io::Error::new(e.kind(), format!("at offset {}: {}", offset, e)))?;
+            if let Some(progress) = progress.as_ref() {
+                progress.set((offset + data.len()) as u64);
+            }
+        }
+
+        if mode != SgIo::MODE_DOWNLOAD_DEFER {
+            return Ok(());
+        }
+        device
+            .write_buffer(SgIo::MODE_ACTIVATE_DEFERRED, 0, 0, &[], MICROCODE_TIMEOUT)
+            .map_err(|e| io::Error::new(e.kind(), format!("activation failed: {}", e)))
+    }
+
+    /// Returns the INQUIRY of a device once it answers again after a reset.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the sg node of the device
+    ///
+    fn wait_ready(path: &str) -> io::Result<SgIo::Inquiry> {
+        let deadline = Instant::now() + RESET_TIMEOUT;
+        loop {
+            let ready = SgIo::Device::open(path).and_then(|d| {
+                d.test_unit_ready()?;
+                d.inquiry()
+            });
+            match ready {
+                Ok(inquiry) => return Ok(inquiry),
+                Err(e) if Instant::now() >= deadline => return Err(e),
+                Err(_) => thread::sleep(Duration::from_secs(2)),
+            }
+        }
+    }
+
+    /// Returns the chunk size given with `--chunk` or the default.
+    fn chunk_size(options: &ArgMatches) -> usize {
+        options
+            .value_of("chunk")
+            .and_then(|c| c.parse().ok())
+            .unwrap_or(DEFAULT_CHUNK)
+    }
+
+    /// Updates the microcode of an enclosure and checks its status after.
+    fn update_enclosure(options: &ArgMatches) -> Result<(), ()> {
+        let id = options.value_of("enclosure").unwrap_or_default();
+        let image = read_image(
+            options.value_of("file").unwrap_or_default(),
+            options.value_of("sha256"),
+        )
+        .map_err(|e| Output::print_error(&e))?;
+
+        let run = Context::Run::new(DiskShelf::ScanOptions::from_matches(options));
+        let enclosure = match DiskShelf::jbod_enclosures(&run)
+            .into_iter()
+            .find(|e| e.matches(id) || e.device_path == id)
+        {
+            Some(enclosure) => enclosure,
+            None => {
+                Output::print_error_code(
+                    ErrorCode::NotFound,
+                    &I18n::tr(Message::NoEnclosure, &[&id]),
+                );
+                return Err(());
+            }
+        };
+
+        let _lock = match Util::lock_enclosure(&enclosure.slot) {
+            Ok(lock) => Some(lock),
+            Err(e) => {
+                Output::print_warning(&format!("cannot lock enclosure {}: {}", enclosure.slot, e));
+                None
+            }
+        };
+
+        let device = SgIo::Device::open_rw(&enclosure.device_path).map_err(|e| {
+            Output::print_error(&I18n::tr(Message::CannotOpen, &[&enclosure.device_path, &e]))
+        })?;
+        let before = device.inquiry().map_err(|e| {
+            Output::print_error(&format!("{}: {}", enclosure.device_path, e))
+        })?;
+        println!(
+            "Enclosure {} {} {} revision {} on {}",
+            enclosure.slot.bold(),
+            before.vendor,
+            before.model,
+            before.revision.yellow().bold(),
+            enclosure.device_path
+        );
+
+        let failed_before = failed_elements(&enclosure);
+        let after = download_microcode(&device, &image, chunk_size(options), SgIo::MODE_DOWNLOAD_DEFER, true)
+            .map_err(|e| format!("{}: {}", enclosure.device_path, e))
+            .and_then(|_| {
+                drop(device);
+                println!("Waiting for the enclosure to restart...");
+                wait_ready(&enclosure.device_path).map_err(|e| {
+                    format!("{} didn't answer after the activation: {}", enclosure.device_path, e)
+                })
+            });
+        let file = options.value_of("file").unwrap_or_default();
+        Audit::record(
+            "firmware",
+            &format!("enclosure {} {}", enclosure.slot, enclosure.serial),
+            Some(&before.revision),
+            after.as_ref().map(|a| a.revision.as_str()).unwrap_or(file),
+            after.as_ref().map(|_| ()).map_err(|e| e.as_str()),
+        );
+        let after = after.map_err(|e| Output::print_error(&e))?;
+        println!(
+            "Firmware revision: {} -> {}",
+            before.revision.yellow().bold(),
+            after.revision.green().bold()
+        );
+        if after.revision == before.revision {
+            Output::print_warning("the revision didn't change, the enclosure may need a power cycle");
+        }
+
+        // Only the elements failing since the update make it fail, a fan
+        // already dead before isn't the firmware's fault.
+        let new_failures: Vec<BackPlane::EnclosureElement> = failed_elements(&enclosure)
+            .into_iter()
+            .filter(|e| !failed_before.iter().any(|b| b.index == e.index))
+            .collect();
+        if new_failures.is_empty() {
+            println!("Enclosure status: {}", "OK".green().bold());
+            return Ok(());
+        }
+        for element in &new_failures {
+            Output::print_error(&format!(
+                "{} {} ({}) is {} since the update",
+                element.element_type, element.description, element.index, element.status
+            ));
+        }
+        Err(())
+    }
+
+    /// Returns the SES elements of an enclosure reporting a problem.
+    fn failed_elements(enclosure: &BackPlane::Enclosure) -> Vec<BackPlane::EnclosureElement> {
+        BackPlane::get_enclosure_elements(std::slice::from_ref(enclosure), 1)
+            .into_iter()
+            .filter(|e| e.status.is_failed())
+            .collect()
+    }
+
+    /// Returns the WRITE BUFFER mode given with `--mode`, 7 by default.
+    fn download_mode(options: &ArgMatches) -> u8 {
+        match options.value_of("mode") {
+            Some("e") | Some("0e") | Some("0x0e") => SgIo::MODE_DOWNLOAD_DEFER,
+            _ => SgIo::MODE_DOWNLOAD_SAVE,
+        }
+    }
+
+    /// Returns the revision of a disk after a firmware download, or why
+    /// the download failed.
+    ///
+    /// # Arguments
+    ///
+    /// * `disk` - the disk to update
+    /// * `image` - the firmware image
+    /// * `chunk` - the size of each WRITE BUFFER
+    /// * `mode` - the WRITE BUFFER download mode
+    ///
+    fn update_disk(
+        disk: &DiskShelf::Disk,
+        image: &[u8],
+        chunk: usize,
+        mode: u8,
+    ) -> Result<String, String> {
+        let device = SgIo::Device::open_rw(&disk.device_path)
+            .map_err(|e| I18n::tr(Message::CannotOpen, &[&disk.device_path, &e]))?;
+        download_microcode(&device, image, chunk, mode, false).map_err(|e| e.to_string())?;
+        drop(device);
+
+        let after = wait_ready(&disk.device_path)
+            .map_err(|e| format!("didn't answer after the download: {}", e))?;
+        if after.revision == disk.fw_revision {
+            return Err(format!("revision still {}", after.revision));
+        }
+        Ok(after.revision)
+    }
+
+    /// Updates the firmware of every disk of a model.
+    ///
+    /// The disks of one enclosure are updated one after the other, a shelf
+    /// never has two disks resetting at once, up to `jobs` enclosures are
+    /// updated at the same time.
+    fn update_disks(options: &ArgMatches) -> Result<(), ()> {
+        let model = options.value_of("model").unwrap_or_default();
+        let run = Context::Run::new(DiskShelf::ScanOptions::from_matches(options));
+        let mut disks: Vec<DiskShelf::Disk> = DiskShelf::jbod_disk_map(&run)
+            .into_iter()
+            .filter(|d| d.model.starts_with(model))
+            .collect();
+        DiskShelf::sort_disks(&mut disks);
+        for error in run.errors() {
+            Output::print_warning(&error.to_string());
+        }
+
+        if disks.is_empty() {
+            Output::print_error_code(ErrorCode::NotFound, &format!("no disk model matches {}", model));
+            return Err(());
+        }
+        for disk in &disks {
+            println!(
+                "{} {} {}{} {} {} revision {}",
+                disk.device_path.bold(),
+                disk.enclosure,
+                disk.slot,
+                DiskShelf::slot_label_suffix(&disk.label),
+                disk.model,
+                disk.serial,
+                disk.fw_revision.yellow().bold()
+            );
+        }
+        if disks.len() > 1 && !options.is_present("all-matching") {
+            Output::print_error(&format!(
+                "{} disks match {}, use --all-matching to update all of them",
+                disks.len(),
+                model
+            ));
+            return Err(());
+        }
+        // Mode 7 resets the drive, under a live pool or a mount it drops
+        // out in the middle of its I/O.
+        if !options.is_present("force") {
+            let busy: Vec<(&DiskShelf::Disk, String)> = disks
+                .iter()
+                .filter_map(|d| Some((d, Burnin::in_use(d)?)))
+                .collect();
+            for (disk, reason) in &busy {
+                Output::print_error_code(
+                    ErrorCode::Failed,
+                    &format!("{}: {}, use --force to update it anyway", disk.device_path, reason),
+                );
+            }
+            if !busy.is_empty() {
+                return Err(());
+            }
+        }
+        if options.is_present("dry-run") {
+            return Ok(());
+        }
+
+        let image = read_image(
+            options.value_of("file").unwrap_or_default(),
+            options.value_of("sha256"),
+        )
+        .map_err(|e| Output::print_error(&e))?;
+        let chunk = chunk_size(options);
+        let mode = download_mode(options);
+
+        let mut enclosures: Vec<Vec<&DiskShelf::Disk>> = Vec::new();
+        for disk in &disks {
+            match enclosures.iter_mut().find(|e| e[0].enclosure == disk.enclosure) {
+                Some(enclosure) => enclosure.push(disk),
+                None => enclosures.push(vec![disk]),
+            }
+        }
+
+        let progress =
+            Util::Progress::start(format!("Updating {} disks", disks.len()), disks.len() as u64, false);
+        let results = Util::parallel_map(&enclosures, run.options.jobs, |enclosure_disks| {
+            enclosure_disks
+                .iter()
+                .map(|disk| {
+                    let result = update_disk(disk, &image, chunk, mode);
+                    progress.inc(1);
+                    Audit::record(
+                        "firmware",
+                        &format!("disk {} {}", disk.device_path, disk.serial),
+                        Some(&disk.fw_revision),
+                        result.as_deref().unwrap_or(options.value_of("file").unwrap_or_default()),
+                        result.as_ref().map(|_| ()).map_err(|e| e.as_str()),
+                    );
+                    progress.suspend(|| match &result {
+                        Ok(revision) => println!(
+                            "{} {} -> {}",
+                            disk.device_path.bold(),
+                            disk.fw_revision.yellow().bold(),
+                            revision.green().bold()
+                        ),
+                        Err(e) => Output::print_error(&format!("{}: {}", disk.device_path, e)),
+                    });
+                    result.is_ok()
+                })
+                .collect::<Vec<bool>>()
+        });
+        drop(progress);
+
+        let failed = results.iter().flatten().filter(|ok| !**ok).count();
+        if failed > 0 {
+            Output::print_error(&format!("{} of {} disks were not updated", failed, disks.len()));
+            return Err(());
+        }
+        println!("{} disks updated", disks.len());
+        Ok(())
+    }
+
+    /// Returns an empty Result or an error when the update failed.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `firmware` subcommand
+    ///
+    pub fn jbod_firmware(options: &ArgMatches) -> Result<(), ()> {
+        match options.subcommand() {
+            Some(("enclosure", m)) => update_enclosure(m),
+            Some(("disk", m)) => update_disks(m),
+            _ => {
+                Output::print_error_code(
+                    ErrorCode::InvalidArgument,
+                    "missing the device to update, see jbod firmware --help",
+                );
+                Err(())
+            }
+        }
+    }
+}
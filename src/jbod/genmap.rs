@@ -0,0 +1,237 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod GenMap {
+    use clap::ArgMatches;
+    use std::fs;
+    use std::path::Path;
+
+    use crate::jbod::context::Context;
+    use crate::jbod::disks::DiskShelf;
+    use crate::jbod::disks::DiskShelf::Disk;
+    use crate::jbod::enclosure::BackPlane::Enclosure;
+    use crate::utils::config::Config;
+    use crate::utils::helper::Util;
+    use crate::utils::output::Output;
+    use crate::utils::output::Output::ErrorCode;
+
+    const DEV_BY_PATH: &str = "/dev/disk/by-path/";
+    const DEV_BY_ID: &str = "/dev/disk/by-id/";
+
+    /// The physical position of a disk and the link udev keeps for it.
+    #[derive(Debug)]
+    pub struct Mapping {
+        /// The stable name, example: jbod1-05
+        pub alias: String,
+        /// The by-path or by-id link of the disk
+        pub link: String,
+        /// True when the link is tied to the slot, a WWN follows the disk
+        pub by_slot: bool,
+    }
+
+    /// Returns the name with only the characters udev and ZFS accept.
+    fn sanitize(name: &str) -> String {
+        name.trim()
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the name of an enclosure: its alias from the configuration,
+    /// or its rank among the enclosures sorted by serial.
+    fn enclosure_name(enclosure: &Enclosure, rank: usize) -> String {
+        match Config::get().enclosure_alias(&enclosure.serial, &enclosure.model) {
+            Some(alias) => sanitize(alias),
+            None => format!("enc{}", rank),
+        }
+    }
+
+    /// Returns the name of a slot: its label from the configuration, or its
+    /// number on two digits.
+    fn slot_name(disk: &Disk) -> String {
+        match &disk.label {
+            Some(label) => sanitize(label),
+            None => match Util::slot_number(&disk.slot) {
+                Some(number) => format!("{:02}", number),
+                None => sanitize(&disk.slot),
+            },
+        }
+    }
+
+    /// Returns the by-path link of a block device, None without one.
+    ///
+    /// Behind an expander the link names the expander and its phy, it stays
+    /// with the slot when the disk is replaced.
+    fn by_path_link(device_map: &str) -> Option<String> {
+        fs::read_dir(DEV_BY_PATH)
+            .ok()?
+            .flatten()
+            .filter(|e| !e.file_name().to_string_lossy().contains("-part"))
+            .find(|e| {
+                fs::canonicalize(e.path())
+                    .map(|p| p == Path::new(device_map))
+                    .unwrap_or(false)
+            })
+            .map(|e| e.path().to_string_lossy().to_string())
+    }
+
+    /// Returns the by-id link of the WWN of a disk, None for a WWN udev
+    /// doesn't link, like the t10 names of SATA disks.
+    fn by_wwn_link(wwn: &str) -> Option<String> {
+        let naa = wwn.strip_prefix("naa.")?;
+        Some(format!("{}wwn-0x{}", DEV_BY_ID, naa.to_lowercase()))
+    }
+
+    /// Returns the Mapping of every disk, in enclosure and slot order.
+    ///
+    /// The two SES processors of a dual ported shelf report the same
+    /// serial, they get a single name.
+    ///
+    /// # Arguments
+    ///
+    /// * `run` - a reference of the run Context
+    ///
+    pub fn mappings(run: &Context::Run) -> Vec<Mapping> {
+        let mut disks = DiskShelf::jbod_disk_map(run);
+        DiskShelf::sort_disks(&mut disks);
+        let mut enclosures = DiskShelf::jbod_enclosures(run);
+        enclosures.sort_by(|a, b| Util::natural_cmp(&a.serial, &b.serial));
+        let mut serials: Vec<&str> = enclosures.iter().map(|e| e.serial.as_str()).collect();
+        serials.dedup();
+
+        let mut mappings = Vec::new();
+        for disk in &disks {
+            let enclosure = match enclosures.iter().find(|e| e.slot == disk.enclosure) {
+                Some(enclosure) => enclosure,
+                None => continue,
+            };
+            let rank = serials.iter().position(|s| *s == enclosure.serial).unwrap_or(0);
+            let alias = format!(
+                "{}-{}",
+                enclosure_name(enclosure, rank),
+                slot_name(disk)
+            );
+            let (link, by_slot) = match by_path_link(&disk.device_map) {
+                Some(link) => (link, true),
+                None => match by_wwn_link(&disk.wwn) {
+                    Some(link) => (link, false),
+                    None => {
+                        Output::print_warning(&format!(
+                            "{} {} has neither a by-path link nor a WWN, it is not mapped",
+                            disk.enclosure, disk.slot
+                        ));
+                        continue;
+                    }
+                },
+            };
+            mappings.push(Mapping {
+                alias,
+                link,
+                by_slot,
+            });
+        }
+
+        mappings
+    }
+
+    /// Returns the vdev_id.conf for the mappings.
+    pub fn vdev_id_conf(mappings: &[Mapping]) -> String {
+        let mut conf = String::from(
+            "# Generated by jbod genmap, the aliases name the enclosure and the slot.\n\
+             # Install as /etc/zfs/vdev_id.conf then run: udevadm trigger\n",
+        );
+        for mapping in mappings {
+            if !mapping.by_slot {
+                conf.push_str("# by WWN, this alias follows the disk, not the slot\n");
+            }
+            conf.push_str(&format!("alias {} {}\n", mapping.alias, mapping.link));
+        }
+        conf
+    }
+
+    /// Returns the udev rules for the mappings, the disks and their
+    /// partitions get a link in /dev/jbod.
+    pub fn udev_rules(mappings: &[Mapping]) -> String {
+        let mut rules = String::from(
+            "# Generated by jbod genmap, the links name the enclosure and the slot.\n\
+             # Install as /etc/udev/rules.d/60-jbod.rules then run: udevadm trigger\n",
+        );
+        for mapping in mappings {
+            let name = mapping.link.rsplit('/').next().unwrap_or(&mapping.link);
+            let key = match name.strip_prefix("wwn-") {
+                Some(wwn) => format!("ENV{{ID_WWN_WITH_EXTENSION}}==\"{}\"", wwn),
+                None => format!("ENV{{ID_PATH}}==\"{}\"", name),
+            };
+            rules.push_str(&format!(
+                "SUBSYSTEM==\"block\", ENV{{DEVTYPE}}==\"disk\", {}, SYMLINK+=\"jbod/{}\"\n",
+                key, mapping.alias
+            ));
+            rules.push_str(&format!(
+                "SUBSYSTEM==\"block\", ENV{{DEVTYPE}}==\"partition\", {}, SYMLINK+=\"jbod/{}-part%n\"\n",
+                key, mapping.alias
+            ));
+        }
+        rules
+    }
+
+    /// Returns an empty Result or an error when no disk could be mapped.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `genmap` subcommand
+    ///
+    pub fn jbod_genmap(options: &ArgMatches) -> Result<(), ()> {
+        let mut scan = DiskShelf::ScanOptions::from_matches(options);
+        scan.fields.temperature = false;
+        scan.fields.firmware = false;
+        let run = Context::Run::new(scan);
+        let mappings = mappings(&run);
+        for error in run.errors() {
+            Output::print_warning(&error.to_string());
+        }
+        if mappings.is_empty() {
+            Output::print_error_code(ErrorCode::NotFound, "no disk to map");
+            return Err(());
+        }
+
+        match options.value_of("format") {
+            Some("udev") => print!("{}", udev_rules(&mappings)),
+            _ => print!("{}", vdev_id_conf(&mappings)),
+        }
+        Ok(())
+    }
+}
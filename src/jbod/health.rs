@@ -0,0 +1,575 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Health {
+    use clap::ArgMatches;
+    use colored::*;
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+    use std::fmt;
+
+    use crate::jbod::context::Context;
+    use crate::jbod::disks::DiskShelf;
+    use crate::jbod::element::Element::SlotStatus;
+    use crate::jbod::enclosure::BackPlane;
+    use crate::jbod::enclosure::BackPlane::FanState;
+    use crate::jbod::map::ChassisMap;
+    use crate::jbod::plugin::Plugin;
+    use crate::jbod::sgio::SgIo;
+    use crate::jbod::spares::Spares;
+    use crate::jbod::spares::Spares::SpareState;
+    use crate::jbod::wear::Wear;
+    use crate::utils::config::Config;
+    use crate::utils::helper::Util;
+    use crate::utils::i18n::I18n;
+    use crate::utils::i18n::I18n::Message;
+    use crate::utils::output::Output;
+    use crate::utils::output::Output::ErrorCode;
+
+    extern crate prettytable;
+    use prettytable::{color, format, Attr, Cell, Row, Table};
+
+    /// Celsius above which a disk is warm, shown in yellow.
+    pub const TEMPERATURE_WARM: i32 = 45;
+    /// Celsius above which a disk is overheating, shown in red.
+    pub const TEMPERATURE_HOT: i32 = 50;
+
+    /// Exit status of `jbod health` with a warning.
+    pub const EXIT_WARNING: i32 = 1;
+    /// Exit status of `jbod health` with a critical finding.
+    pub const EXIT_CRITICAL: i32 = 2;
+    /// Exit status of `jbod health` when an enclosure couldn't be read.
+    pub const EXIT_UNKNOWN: i32 = 3;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Severity {
+        Critical,
+        Warning,
+    }
+
+    impl fmt::Display for Severity {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                Severity::Critical => write!(f, "critical"),
+                Severity::Warning => write!(f, "warning"),
+            }
+        }
+    }
+
+    /// Something wrong with the JBOD.
+    #[derive(Debug, Clone, Serialize)]
+    pub struct Finding {
+        pub severity: Severity,
+        /// What is wrong, example: overtemp, failed, missing, fan
+        pub kind: &'static str,
+        /// The enclosure, the slot or the spare concerned
+        pub target: String,
+        pub message: String,
+    }
+
+    /// The health of the JBOD.
+    #[derive(Debug, Serialize)]
+    pub struct Report {
+        pub enclosures: usize,
+        pub disks: usize,
+        pub findings: Vec<Finding>,
+    }
+
+    impl Report {
+        /// Returns the number of findings of a severity per kind.
+        fn kinds(&self, severity: Severity) -> BTreeMap<&'static str, usize> {
+            let mut kinds = BTreeMap::new();
+            for finding in self.findings.iter().filter(|f| f.severity == severity) {
+                *kinds.entry(finding.kind).or_default() += 1;
+            }
+            kinds
+        }
+
+        /// Returns the exit status of `jbod health`: 2 with a critical
+        /// finding, 3 when an enclosure couldn't be read, 1 with a warning
+        /// and 0 when all is fine, like the monitoring plugins.
+        pub fn exit_code(&self) -> i32 {
+            let severities: Vec<Severity> = self.findings.iter().map(|f| f.severity).collect();
+            if severities.contains(&Severity::Critical) {
+                EXIT_CRITICAL
+            } else if self.findings.iter().any(|f| f.kind == "unreadable") {
+                EXIT_UNKNOWN
+            } else if severities.contains(&Severity::Warning) {
+                EXIT_WARNING
+            } else {
+                0
+            }
+        }
+
+        /// Returns the report in one line, example: "3 enclosures, 306
+        /// disks, 2 warnings (1 overtemp, 1 missing)".
+        pub fn oneline(&self) -> String {
+            let plural = |n: usize, word: &str| {
+                if n == 1 {
+                    format!("{} {}", n, word)
+                } else {
+                    format!("{} {}s", n, word)
+                }
+            };
+            let mut parts = vec![
+                plural(self.enclosures, "enclosure"),
+                plural(self.disks, "disk"),
+            ];
+            for (severity, word) in [
+                (Severity::Critical, "critical"),
+                (Severity::Warning, "warning"),
+            ] {
+                let kinds = self.kinds(severity);
+                if kinds.is_empty() {
+                    continue;
+                }
+                let count = kinds.values().sum();
+                let detail: Vec<String> = kinds
+                    .iter()
+                    .map(|(kind, n)| format!("{} {}", n, kind))
+                    .collect();
+                let counted = if severity == Severity::Critical {
+                    format!("{} {}", count, word)
+                } else {
+                    plural(count, word)
+                };
+                parts.push(format!("{} ({})", counted, detail.join(", ")));
+            }
+            if self.findings.is_empty() {
+                parts.push("all ok".to_string());
+            }
+            parts.join(", ")
+        }
+    }
+
+    /// Returns the failure predicted by a drive, None when there is none
+    /// or the drive doesn't report it.
+    ///
+    /// # Arguments
+    ///
+    /// * `device_path` - the sg node of the disk, example: /dev/sg12
+    ///
+    pub fn predicted_failure(device_path: &str) -> Result<Option<String>, String> {
+        let device = SgIo::Device::open(device_path)
+            .map_err(|e| I18n::tr(Message::CannotOpen, &[&device_path, &e]))?;
+        let exception = device
+            .informational_exception()
+            .map_err(|e| format!("cannot read the informational exceptions: {}", e))?;
+        Ok(exception.map(|(asc, ascq)| format!("asc 0x{:02x} ascq 0x{:02x}", asc, ascq)))
+    }
+
+    /// Fills the `predicted_failure` field of the disks, the ones that
+    /// couldn't be queried, like most SATA drives, keep None, like every
+    /// disk without the `smart` feature.
+    ///
+    /// # Arguments
+    ///
+    /// * `disks` - the disks to query
+    /// * `jobs` - the disks queried at the same time
+    ///
+    pub fn annotate_predicted_failures(disks: &mut [DiskShelf::Disk], jobs: usize) {
+        if !cfg!(feature = "smart") {
+            return;
+        }
+        let failures = Util::parallel_map(disks, jobs, |d| predicted_failure(&d.device_path));
+        for (disk, failure) in disks.iter_mut().zip(failures) {
+            disk.predicted_failure = failure.ok().flatten();
+        }
+    }
+
+    /// Returns the problems of a disk: a degraded SES slot status, a
+    /// failure predicted by the drive, an overheating or worn disk and a
+    /// missing block device map.
+    ///
+    /// The predicted failure and the wear are only known once annotated,
+    /// see `annotate_predicted_failures()` and `Wear::annotate()`.
+    pub fn disk_findings(disk: &DiskShelf::Disk) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        let target = format!(
+            "{} {}{}",
+            disk.enclosure,
+            disk.slot,
+            DiskShelf::slot_label_suffix(&disk.label)
+        );
+        let mut found = |severity, kind, message: String| {
+            findings.push(Finding {
+                severity,
+                kind,
+                target: target.clone(),
+                message,
+            })
+        };
+
+        if disk.status.is_failed() {
+            found(
+                Severity::Critical,
+                "failed",
+                format!("{} {} is {:?}", disk.device_path, disk.serial, disk.status),
+            );
+        }
+        if let Some(failure) = &disk.predicted_failure {
+            found(
+                Severity::Critical,
+                "smart",
+                format!("{} predicts a failure, {}", disk.device_path, failure),
+            );
+        }
+        if let DiskShelf::Temperature::Celsius(t) = disk.temperature {
+            if t > TEMPERATURE_HOT {
+                found(
+                    Severity::Warning,
+                    "overtemp",
+                    format!("{} is at {} Celsius", disk.device_path, t),
+                );
+            }
+        }
+        if let Some(wear) = disk.wear.filter(|w| Wear::worn(*w)) {
+            let severity = if wear >= 100 {
+                Severity::Critical
+            } else {
+                Severity::Warning
+            };
+            found(
+                severity,
+                "wear",
+                format!("{} used {}% of its rated endurance", disk.device_path, wear),
+            );
+        }
+        if disk.device_map == "NONE" {
+            found(
+                Severity::Warning,
+                "unmapped",
+                format!("{} has no block device", disk.device_path),
+            );
+        }
+
+        findings
+    }
+
+    /// Returns true if a fan reads 0 rpm, whatever its status. The comment
+    /// is read along the speed, a fan without it reports no speed at all.
+    fn stopped(fan: &BackPlane::EnclosureFan) -> bool {
+        fan.speed == 0 && !fan.comment.is_empty()
+    }
+
+    /// Returns the health of the enclosures and disks of a run: the scan
+    /// failures, the problems of the disks, see `disk_findings()`, the
+    /// missing disks and spares, the fans, the SES status of the enclosures and the alerts of
+    /// the collectors.
+    ///
+    /// # Arguments
+    ///
+    /// * `run` - the run Context, the disks need their temperature and serial
+    ///
+    pub fn evaluate(run: &Context::Run) -> Report {
+        let enclosures = DiskShelf::jbod_enclosures(run);
+        let mut disks = DiskShelf::jbod_disk_map(run);
+        DiskShelf::sort_disks(&mut disks);
+        evaluate_scanned(run, &enclosures, &mut disks)
+    }
+
+    /// Returns the health like `evaluate()` for the enclosures and disks
+    /// already scanned, the disks get their wear and predicted failure.
+    ///
+    /// # Arguments
+    ///
+    /// * `run` - the run Context of the scan
+    /// * `enclosures` - the enclosures of the run
+    /// * `disks` - the disks of the run
+    ///
+    pub fn evaluate_scanned(
+        run: &Context::Run,
+        enclosures: &[BackPlane::Enclosure],
+        disks: &mut [DiskShelf::Disk],
+    ) -> Report {
+        let settings = Config::get();
+        let jobs = run.options.jobs;
+        Wear::annotate(disks, jobs, true);
+        annotate_predicted_failures(disks, jobs);
+        // Collected first so the collectors failing are reported below.
+        let readings = Plugin::collect(run);
+        let mut findings = Vec::new();
+        let mut found = |severity, kind, target: String, message: String| {
+            findings.push(Finding {
+                severity,
+                kind,
+                target,
+                message,
+            })
+        };
+
+        for error in run.errors() {
+            found(
+                Severity::Warning,
+                "unreadable",
+                error.enclosure.clone(),
+                error.error.clone(),
+            );
+        }
+        for finding in disks.iter().flat_map(disk_findings) {
+            found(finding.severity, finding.kind, finding.target, finding.message);
+        }
+        for enclosure in enclosures {
+            for bay in ChassisMap::bays(enclosure) {
+                let expected =
+                    settings.slot_expected(&enclosure.serial, &enclosure.model, &bay.slot);
+                if expected == Some(true) && bay.device_path.is_none() {
+                    found(
+                        Severity::Warning,
+                        "missing",
+                        format!("{} {}", enclosure.slot, bay.slot),
+                        "the slot should hold a disk".to_string(),
+                    );
+                }
+            }
+        }
+        if Spares::configured() {
+            for spare in Spares::spares(run) {
+                let kind = match spare.state {
+                    SpareState::Missing => "missing",
+                    SpareState::InUse => "spare",
+                    SpareState::Standby => continue,
+                };
+                found(
+                    Severity::Warning,
+                    kind,
+                    spare.designation.clone(),
+                    format!("spare {}: {}", spare.state, spare.reason),
+                );
+            }
+        }
+        for fan in BackPlane::get_enclosure_fan(enclosures, jobs) {
+            let severity = match fan.state {
+                FanState::Failed | FanState::Critical => Severity::Critical,
+                FanState::Warning | FanState::Off => Severity::Warning,
+                FanState::Ok | FanState::Unknown if stopped(&fan) => Severity::Warning,
+                FanState::Ok | FanState::Unknown => continue,
+            };
+            found(
+                severity,
+                "fan",
+                format!("{} {}", fan.slot, fan.description),
+                format!("{:?} at {} rpm", fan.state, fan.speed),
+            );
+        }
+        for health in BackPlane::get_enclosure_health(enclosures, jobs) {
+            let severity = match health.status {
+                SlotStatus::Critical | SlotStatus::Unrecoverable => Severity::Critical,
+                SlotStatus::Noncritical => Severity::Warning,
+                _ => continue,
+            };
+            found(
+                severity,
+                "enclosure",
+                health.slot.clone(),
+                format!("SES reports {:?}", health.status),
+            );
+        }
+        for reading in readings {
+            if let Some(severity) = reading.sample.severity {
+                found(
+                    severity,
+                    "collector",
+                    format!("{} {}", reading.enclosure, reading.collector),
+                    format!("{}: {}", reading.sample.name, reading.sample.message),
+                );
+            }
+        }
+
+        findings.sort_by_key(|f| f.severity);
+        Report {
+            enclosures: enclosures.len(),
+            disks: disks.len(),
+            findings,
+        }
+    }
+
+    /// Prints the findings as a table, the critical ones in red.
+    pub fn print_findings(findings: &[Finding]) {
+        let mut status_table = create_status_table();
+        for finding in findings {
+            let severity = match finding.severity {
+                Severity::Critical => color::RED,
+                Severity::Warning => color::YELLOW,
+            };
+            status_table.add_row(Row::new(vec![
+                Cell::new(&finding.severity.to_string())
+                    .with_style(Attr::ForegroundColor(severity)),
+                Cell::new(finding.kind),
+                Cell::new(&finding.target),
+                Cell::new(&finding.message),
+            ]));
+        }
+        Output::print_table(&status_table);
+    }
+
+    /// Creates the pretty table for the findings.
+    fn create_status_table() -> Table {
+        let mut status_table = Table::new();
+        status_table.set_format(*format::consts::FORMAT_NO_BORDER);
+        let header = ["SEVERITY", "KIND", "TARGET", "MESSAGE"];
+        status_table.add_row(Row::new(
+            header
+                .iter()
+                .map(|h| {
+                    Cell::new(h)
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(color::BLUE))
+                })
+                .collect(),
+        ));
+
+        status_table
+    }
+
+    /// Returns an empty Result once the report is printed.
+    ///
+    /// This function is used in the `status` menu option, it lists what is
+    /// wrong with the JBOD, or sums it up in one line with `--oneline` for
+    /// the login banners.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `status` subcommand
+    ///
+    pub fn jbod_status(options: &ArgMatches) -> Result<(), ()> {
+        let report = scan_report(options);
+        print_report(options, &report)
+    }
+
+    /// Returns the health of the JBOD scanned with the options of the
+    /// subcommand, the firmware isn't needed.
+    fn scan_report(options: &ArgMatches) -> Report {
+        let mut scan = DiskShelf::ScanOptions::from_matches(options);
+        scan.fields.firmware = false;
+        let run = Context::Run::new(scan);
+        evaluate(&run)
+    }
+
+    /// Prints the report in the format asked, or in one line with `--oneline`.
+    fn print_report(options: &ArgMatches, report: &Report) -> Result<(), ()> {
+        let format = Output::get_format(options);
+        if format != Output::Format::Table {
+            return Output::render_document(format, report).map_err(|e| Output::print_error(&e));
+        }
+        if options.is_present("oneline") {
+            println!("{}", report.oneline());
+            return Ok(());
+        }
+
+        println!("{}", report.oneline().bold());
+        if !report.findings.is_empty() {
+            println!();
+            print_findings(&report.findings);
+        }
+        Ok(())
+    }
+
+    /// Returns the exit status of the health of the JBOD once reported.
+    ///
+    /// This function is used in the `health` menu option, it reports like
+    /// `status` and returns 0 when all is fine, `EXIT_WARNING`,
+    /// `EXIT_CRITICAL` or `EXIT_UNKNOWN` otherwise, see `Report::exit_code()`,
+    /// so cron jobs and monitoring wrappers don't parse the report. A report
+    /// that can't be printed doesn't hide the status of the JBOD.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `health` subcommand
+    ///
+    pub fn jbod_health(options: &ArgMatches) -> i32 {
+        if matches!(Output::get_format(options), Output::Format::Csv | Output::Format::Tsv) {
+            Output::print_error_code(ErrorCode::InvalidArgument, "health has no csv or tsv output");
+            return EXIT_UNKNOWN;
+        }
+
+        let report = scan_report(options);
+        let code = report.exit_code();
+        if !options.is_present("silent") && print_report(options, &report).is_err() && code == 0 {
+            return EXIT_UNKNOWN;
+        }
+        code
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn finding(severity: Severity, kind: &'static str) -> Finding {
+            Finding {
+                severity,
+                kind,
+                target: "0:0:60:0 Slot 10".to_string(),
+                message: String::new(),
+            }
+        }
+
+        fn report(findings: Vec<Finding>) -> Report {
+            Report {
+                enclosures: 1,
+                disks: 3,
+                findings,
+            }
+        }
+
+        #[test]
+        fn critical_wins_over_unreadable() {
+            let findings = vec![
+                finding(Severity::Warning, "unreadable"),
+                finding(Severity::Critical, "failed"),
+            ];
+
+            assert_eq!(report(findings).exit_code(), EXIT_CRITICAL);
+        }
+
+        #[test]
+        fn unreadable_wins_over_warnings() {
+            let findings = vec![
+                finding(Severity::Warning, "overtemp"),
+                finding(Severity::Warning, "unreadable"),
+            ];
+
+            assert_eq!(report(findings).exit_code(), EXIT_UNKNOWN);
+        }
+
+        #[test]
+        fn warnings_only() {
+            let findings = vec![
+                finding(Severity::Warning, "overtemp"),
+                finding(Severity::Warning, "missing"),
+            ];
+
+            assert_eq!(report(findings).exit_code(), EXIT_WARNING);
+            assert_eq!(report(Vec::new()).exit_code(), 0);
+        }
+    }
+}
@@ -0,0 +1,256 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Inventory {
+    use clap::ArgMatches;
+    use serde::Serialize;
+    use std::fs;
+
+    use crate::jbod::context::Context;
+    use crate::jbod::disks::DiskShelf;
+    use crate::jbod::disks::DiskShelf::{Disk, Fields};
+    use crate::jbod::enclosure::BackPlane::{self, Enclosure};
+    use crate::jbod::quirks::Quirks;
+    use crate::utils::config::Config;
+    use crate::utils::helper::Util;
+    use crate::utils::output::Output;
+
+    use prettytable::{color, format, Attr, Cell, Row, Table};
+
+    /// One slot of an enclosure and the disk in it.
+    ///
+    /// Only what identifies the hardware is kept, the SCSI addresses and the
+    /// device nodes change with reboots and would make two dumps differ.
+    #[derive(Debug, Serialize)]
+    pub struct Record {
+        pub enclosure_serial: String,
+        pub enclosure_alias: Option<String>,
+        pub enclosure_vendor: String,
+        pub enclosure_model: String,
+        pub enclosure_firmware: String,
+        /// The firmware of each controller, example: "0:2033 1:2033"
+        pub enclosure_controllers: String,
+        pub slot: String,
+        // Physical slot label printed on the chassis, from the configuration
+        pub label: Option<String>,
+        pub disk_vendor: Option<String>,
+        pub disk_model: Option<String>,
+        pub disk_serial: Option<String>,
+        pub disk_wwn: Option<String>,
+        pub disk_firmware: Option<String>,
+        pub capacity_bytes: Option<u64>,
+    }
+
+    /// Returns the capacity of a disk from the size of its block device.
+    ///
+    /// # Arguments
+    ///
+    /// * `device_path` - the sg node of the disk, example: /dev/sg12
+    ///
+    fn capacity_bytes(device_path: &str) -> Option<u64> {
+        let sg = device_path.rsplit('/').next()?;
+        let block = Util::sys_class_scsi_generic().join(sg).join("device/block");
+        let device = fs::read_dir(block).ok()?.flatten().next()?;
+        // sysfs counts 512 bytes sectors whatever the logical block size.
+        let sectors: u64 = fs::read_to_string(device.path().join("size"))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()?;
+        Some(sectors * 512)
+    }
+
+    /// Returns the slot components of an enclosure in natural order.
+    fn slots(enclosure: &Enclosure) -> Vec<String> {
+        let quirk = Quirks::for_enclosure(&enclosure.vendor, &enclosure.model);
+        let mut slots: Vec<String> = fs::read_dir(Util::sys_class_enclosure().join(&enclosure.slot))
+            .map(|d| {
+                d.flatten()
+                    .map(|e| e.file_name().to_string_lossy().to_string())
+                    .filter(|n| quirk.is_slot_component(n))
+                    .map(|n| n.split(',').next().unwrap_or(&n).to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        slots.sort_by(|a, b| Util::natural_cmp(a, b));
+        slots
+    }
+
+    /// Returns the non empty value or None.
+    fn known(value: &str) -> Option<String> {
+        (!value.is_empty() && value != "N/A").then(|| value.to_string())
+    }
+
+    /// Returns a Record for every slot of every enclosure, empty slots
+    /// included.
+    ///
+    /// The two SES processors of a dual ported shelf report the same
+    /// serial, such a shelf is listed once.
+    ///
+    /// # Arguments
+    ///
+    /// * `run` - a reference of the run Context
+    ///
+    pub fn records(run: &Context::Run) -> Vec<Record> {
+        let disks: Vec<Disk> = DiskShelf::jbod_disk_map(run);
+        let mut enclosures: Vec<Enclosure> = Vec::new();
+        for enclosure in DiskShelf::jbod_enclosures(run) {
+            if enclosure.serial.is_empty()
+                || !enclosures.iter().any(|e| e.serial == enclosure.serial)
+            {
+                enclosures.push(enclosure);
+            }
+        }
+        enclosures.sort_by(|a, b| Util::natural_cmp(&a.serial, &b.serial));
+        BackPlane::read_controllers(&mut enclosures, run.options.jobs);
+
+        let mut records: Vec<Record> = Vec::new();
+        for enclosure in &enclosures {
+            let alias = Config::get()
+                .enclosure_alias(&enclosure.serial, &enclosure.model)
+                .map(str::to_string);
+            for slot in slots(enclosure) {
+                let disk = disks
+                    .iter()
+                    .find(|d| d.enclosure == enclosure.slot && d.slot == slot);
+                records.push(Record {
+                    enclosure_serial: enclosure.serial.clone(),
+                    enclosure_alias: alias.clone(),
+                    enclosure_vendor: enclosure.vendor.clone(),
+                    enclosure_model: enclosure.model.clone(),
+                    enclosure_firmware: enclosure.revision.clone(),
+                    enclosure_controllers: BackPlane::controller_revisions(&enclosure.controllers),
+                    label: Quirks::slot_label(&enclosure.serial, &enclosure.vendor, &enclosure.model, &slot),
+                    slot,
+                    disk_vendor: disk.and_then(|d| known(&d.vendor)),
+                    disk_model: disk.and_then(|d| known(&d.model)),
+                    disk_serial: disk.and_then(|d| known(&d.serial)),
+                    disk_wwn: disk.and_then(|d| known(&d.wwn)),
+                    disk_firmware: disk.and_then(|d| known(&d.fw_revision)),
+                    capacity_bytes: disk.and_then(|d| capacity_bytes(&d.device_path)),
+                });
+            }
+        }
+
+        records
+    }
+
+    /// Creates the pretty table for the inventory.
+    fn create_inventory_table() -> Table {
+        let mut inventory_table = Table::new();
+        inventory_table.set_format(*format::consts::FORMAT_NO_BORDER);
+        let header = [
+            "ENCLOSURE", "SLOT", "VENDOR", "MODEL", "SERIAL", "WWN", "FIRMWARE", "CAPACITY",
+        ];
+        inventory_table.add_row(Row::new(
+            header
+                .iter()
+                .map(|h| {
+                    Cell::new(h)
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(color::BLUE))
+                })
+                .collect(),
+        ));
+
+        inventory_table
+    }
+
+    /// Returns the capacity in decimal units, like the disk labels.
+    fn human_capacity(bytes: u64) -> String {
+        let units = ["B", "KB", "MB", "GB", "TB", "PB"];
+        let mut value = bytes as f64;
+        let mut unit = 0;
+        while value >= 1000.0 && unit < units.len() - 1 {
+            value /= 1000.0;
+            unit += 1;
+        }
+        format!("{:.1} {}", value, units[unit])
+    }
+
+    /// Returns an empty Result or an error when the report can't be written.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `inventory` subcommand
+    ///
+    pub fn jbod_inventory(options: &ArgMatches) -> Result<(), ()> {
+        let mut scan = DiskShelf::ScanOptions::from_matches(options);
+        scan.fields = Fields {
+            temperature: false,
+            firmware: scan.fields.firmware,
+            serial: scan.fields.serial,
+        };
+        // Every path is kept so each slot finds its disk, whatever the
+        // enclosure the disk was merged under.
+        scan.raw_paths = true;
+        let run = Context::Run::new(scan);
+        let records = records(&run);
+
+        let format = Output::get_format(options);
+        if format != Output::Format::Table {
+            Output::render(format, &records).map_err(|e| Output::print_error(&e))?;
+        } else {
+            let mut inventory_table = create_inventory_table();
+            for record in &records {
+                let enclosure = record
+                    .enclosure_alias
+                    .clone()
+                    .unwrap_or_else(|| record.enclosure_serial.clone());
+                let slot = format!(
+                    "{}{}",
+                    record.slot,
+                    DiskShelf::slot_label_suffix(&record.label)
+                );
+                let cells = [
+                    enclosure,
+                    slot,
+                    record.disk_vendor.clone().unwrap_or_default(),
+                    record.disk_model.clone().unwrap_or_default(),
+                    record.disk_serial.clone().unwrap_or_default(),
+                    record.disk_wwn.clone().unwrap_or_default(),
+                    record.disk_firmware.clone().unwrap_or_default(),
+                    record.capacity_bytes.map(human_capacity).unwrap_or_default(),
+                ];
+                inventory_table.add_row(Row::new(cells.iter().map(|c| Cell::new(c)).collect()));
+            }
+            Output::print_table(&inventory_table);
+        }
+
+        let errors = run.errors();
+        Output::render_errors(format, &errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+}
@@ -0,0 +1,177 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod IoErrors {
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+    use std::fmt;
+    use std::fs;
+
+    use crate::jbod::cache::Cache::CACHE_DIR;
+    use crate::jbod::disks::DiskShelf::Disk;
+    use crate::utils::helper::Util;
+    use crate::utils::output::Output;
+
+    /// The error counters seen by the last listing, the next one shows the
+    /// errors since.
+    pub const STATE_FILE: &str = "/run/jbod/io-counters.json";
+
+    /// The I/O counters the SCSI midlayer keeps for a device, they are
+    /// reset when the device is attached again.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Counters {
+        /// The commands completed with an error.
+        pub ioerr: u64,
+        /// The commands completed.
+        pub iodone: u64,
+        /// The commands sent.
+        pub iorequest: u64,
+        /// The errors since the previous listing, None on the first one.
+        #[serde(default)]
+        pub ioerr_delta: Option<u64>,
+    }
+
+    impl fmt::Display for Counters {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{}/{}", self.ioerr, self.iodone)?;
+            if let Some(delta) = self.ioerr_delta {
+                write!(f, " (+{})", delta)?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Returns the value of a counter, the kernel prints them in hex.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - the content of the counter file, example: 0x1a
+    ///
+    pub fn parse_counter(content: &str) -> Option<u64> {
+        let content = content.trim();
+        match content.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok(),
+            None => content.parse().ok(),
+        }
+    }
+
+    /// Returns the counters of a disk from its sysfs device.
+    ///
+    /// # Arguments
+    ///
+    /// * `device_path` - the sg node of the disk, example: /dev/sg12
+    ///
+    pub fn read(device_path: &str) -> Result<Counters, String> {
+        let sg = device_path.rsplit('/').next().unwrap_or(device_path);
+        let device = Util::sys_class_scsi_generic().join(sg).join("device");
+        let counter = |name: &str| {
+            let path = device.join(name);
+            let content = fs::read_to_string(&path)
+                .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+            parse_counter(&content).ok_or_else(|| format!("invalid counter in {}", path.display()))
+        };
+        Ok(Counters {
+            ioerr: counter("ioerr_cnt")?,
+            iodone: counter("iodone_cnt")?,
+            iorequest: counter("iorequest_cnt")?,
+            ioerr_delta: None,
+        })
+    }
+
+    /// Returns the errors since the `previous` reading, a counter lower
+    /// than before was reset so all its errors are new.
+    ///
+    /// # Arguments
+    ///
+    /// * `previous` - the errors of the previous reading, if any
+    /// * `current` - the errors now
+    ///
+    pub fn delta(previous: Option<u64>, current: u64) -> Option<u64> {
+        previous.map(|p| if current >= p { current - p } else { current })
+    }
+
+    /// Returns the key a disk is remembered by across listings, the sg
+    /// node of a disk may change on reboot but not its identifiers.
+    pub fn key(disk: &Disk) -> String {
+        if !disk.wwn.is_empty() {
+            disk.wwn.clone()
+        } else if !disk.serial.is_empty() {
+            disk.serial.clone()
+        } else {
+            disk.device_path.clone()
+        }
+    }
+
+    /// Returns the error counters of the previous listing.
+    fn load() -> HashMap<String, u64> {
+        fs::read_to_string(STATE_FILE)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// Writes the error counters, failures are ignored like for the
+    /// cache, the next listing has no delta then.
+    fn store(counters: &HashMap<String, u64>) {
+        if fs::create_dir_all(CACHE_DIR).is_err() {
+            return;
+        }
+        if let Ok(content) = serde_json::to_string(counters) {
+            let _ = fs::write(STATE_FILE, content);
+        }
+    }
+
+    /// Fills the `io_errors` field of the disks with their counters and
+    /// the errors since the previous listing, the ones that couldn't be
+    /// read keep None.
+    ///
+    /// # Arguments
+    ///
+    /// * `disks` - the disks to read
+    /// * `jobs` - the disks read at the same time
+    ///
+    pub fn annotate(disks: &mut [Disk], jobs: usize) {
+        let readings = Util::parallel_map(disks, jobs, |d| read(&d.device_path));
+        let mut state = load();
+        for (disk, reading) in disks.iter_mut().zip(readings) {
+            match reading {
+                Ok(mut counters) => {
+                    let key = key(disk);
+                    counters.ioerr_delta = delta(state.get(&key).copied(), counters.ioerr);
+                    state.insert(key, counters.ioerr);
+                    disk.io_errors = Some(counters);
+                }
+                Err(e) => Output::print_warning(&format!("{}: {}", disk.device_path, e)),
+            }
+        }
+        store(&state);
+    }
+}
@@ -0,0 +1,309 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod ChassisMap {
+    use clap::ArgMatches;
+    use colored::*;
+    use serde::Serialize;
+    use std::fs;
+
+    use crate::jbod::context::Context;
+    use crate::jbod::disks::DiskShelf;
+    use crate::jbod::element::Element::{LedState, SlotStatus};
+    use crate::jbod::enclosure::BackPlane::Enclosure;
+    use crate::jbod::quirks::Quirks;
+    use crate::utils::config::Config;
+    use crate::utils::config::Config::Layout;
+    use crate::utils::helper::Util;
+    use crate::utils::i18n::I18n;
+    use crate::utils::i18n::I18n::Message;
+    use crate::utils::output::Output;
+    use crate::utils::output::Output::ErrorCode;
+
+    /// Bays per row when the chassis has no layout in the configuration.
+    const DEFAULT_COLUMNS: usize = 12;
+
+    /// What a bay shows, by order of precedence.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum BayState {
+        /// The fault led is on or SES reports a failure
+        Fault,
+        /// The locate led is on
+        Locate,
+        /// No disk in the bay
+        Empty,
+        Ok,
+    }
+
+    /// A bay of the chassis and where it is drawn.
+    #[derive(Debug, Clone, Serialize)]
+    pub struct Bay {
+        pub slot: String,
+        // Physical slot label printed on the chassis, from the configuration
+        pub label: Option<String>,
+        pub state: BayState,
+        /// The sg node of the disk, None for an empty bay
+        pub device_path: Option<String>,
+        /// front or rear, None when the layout doesn't place the bay
+        pub side: Option<&'static str>,
+        /// Row from the top, starting at 0
+        pub row: Option<usize>,
+        /// Column from the left, starting at 0
+        pub column: Option<usize>,
+    }
+
+    /// Returns the bays of an enclosure read from sysfs, in natural order.
+    ///
+    /// # Arguments
+    ///
+    /// * `enclosure` - the enclosure to draw
+    ///
+    pub fn bays(enclosure: &Enclosure) -> Vec<Bay> {
+        let quirk = Quirks::for_enclosure(&enclosure.vendor, &enclosure.model);
+        let enclosure_path = Util::sys_class_enclosure().join(&enclosure.slot);
+        let mut components: Vec<String> = fs::read_dir(&enclosure_path)
+            .map(|d| {
+                d.flatten()
+                    .map(|e| e.file_name().to_string_lossy().to_string())
+                    .filter(|n| quirk.is_slot_component(n))
+                    .collect()
+            })
+            .unwrap_or_default();
+        components.sort_by(|a, b| Util::natural_cmp(a, b));
+
+        components
+            .into_iter()
+            .map(|component| {
+                let path = enclosure_path.join(&component);
+                let slot = component
+                    .split(',')
+                    .next()
+                    .unwrap_or(&component)
+                    .to_string();
+                let device_path = fs::read_dir(path.join("device/scsi_generic"))
+                    .ok()
+                    .and_then(|mut d| d.next())
+                    .and_then(|e| e.ok())
+                    .map(|e| format!("/dev/{}", e.file_name().to_string_lossy()));
+                let status = SlotStatus::read(&path);
+                let led = |name: &str| LedState::read(&path.join(name)).ok() == Some(LedState::On);
+                let state = if led("fault") || status.is_failed() {
+                    BayState::Fault
+                } else if led("locate") {
+                    BayState::Locate
+                } else if device_path.is_none() || status == SlotStatus::NotInstalled {
+                    BayState::Empty
+                } else {
+                    BayState::Ok
+                };
+                Bay {
+                    label: Quirks::slot_label(&enclosure.serial, &enclosure.vendor, &enclosure.model, &slot),
+                    slot,
+                    state,
+                    device_path,
+                    side: None,
+                    row: None,
+                    column: None,
+                }
+            })
+            .collect()
+    }
+
+    /// Places the bays on the layout, a bay listed twice keeps its first
+    /// position.
+    fn place(bays: &mut [Bay], layout: &Layout) {
+        for (side, grid) in [("front", &layout.front), ("rear", &layout.rear)] {
+            for (row, numbers) in grid.iter().enumerate() {
+                for (column, number) in numbers.iter().enumerate() {
+                    let found = bays.iter_mut().find(|b| {
+                        b.side.is_none()
+                            && Util::slot_number(&b.slot).map(i64::from) == Some(*number)
+                    });
+                    if let Some(bay) = found {
+                        bay.side = Some(side);
+                        bay.row = Some(row);
+                        bay.column = Some(column);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the text of a bay, its label or its slot number.
+    fn bay_name(bay: &Bay) -> String {
+        match (&bay.label, Util::slot_number(&bay.slot)) {
+            (Some(label), _) => label.clone(),
+            (None, Some(number)) => format!("{:02}", number),
+            (None, None) => bay.slot.clone(),
+        }
+    }
+
+    /// Returns a bay drawn in its color, padded to `width`.
+    fn draw_bay(bay: &Bay, width: usize) -> String {
+        let text = format!("[{:^width$}]", bay_name(bay), width = width);
+        match bay.state {
+            BayState::Fault => text.red().bold().to_string(),
+            BayState::Locate => text.blue().bold().blink().to_string(),
+            BayState::Empty => text.dimmed().to_string(),
+            BayState::Ok => text.green().to_string(),
+        }
+    }
+
+    /// Prints a side of the chassis, a row per line.
+    fn print_grid(title: &str, grid: &[Vec<Option<&Bay>>], width: usize) {
+        println!("{}", title.bold());
+        for row in grid {
+            let cells: Vec<String> = row
+                .iter()
+                .map(|bay| match bay {
+                    Some(bay) => draw_bay(bay, width),
+                    None => " ".repeat(width + 2),
+                })
+                .collect();
+            println!("  {}", cells.join(" "));
+        }
+        println!();
+    }
+
+    /// Returns the grid of one side of the chassis.
+    fn side_grid<'a>(bays: &'a [Bay], side: &str) -> Vec<Vec<Option<&'a Bay>>> {
+        let rows = bays
+            .iter()
+            .filter(|b| b.side == Some(side))
+            .filter_map(|b| b.row)
+            .max()
+            .map_or(0, |r| r + 1);
+        let columns = bays
+            .iter()
+            .filter(|b| b.side == Some(side))
+            .filter_map(|b| b.column)
+            .max()
+            .map_or(0, |c| c + 1);
+        let mut grid = vec![vec![None; columns]; rows];
+        for bay in bays.iter().filter(|b| b.side == Some(side)) {
+            if let (Some(row), Some(column)) = (bay.row, bay.column) {
+                grid[row][column] = Some(bay);
+            }
+        }
+        grid
+    }
+
+    /// Returns an empty Result or an error when the enclosure isn't found.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `map` subcommand
+    ///
+    pub fn jbod_map(options: &ArgMatches) -> Result<(), ()> {
+        let id = options.value_of("enclosure").unwrap_or_default();
+        let mut scan = DiskShelf::ScanOptions::from_matches(options);
+        scan.fields = DiskShelf::Fields::minimal();
+        let run = Context::Run::new(scan);
+        let enclosure = match DiskShelf::jbod_enclosures(&run)
+            .into_iter()
+            .find(|e| e.matches(id))
+        {
+            Some(enclosure) => enclosure,
+            None => {
+                Output::print_error_code(
+                    ErrorCode::NotFound,
+                    &I18n::tr(Message::NoEnclosure, &[&id]),
+                );
+                return Err(());
+            }
+        };
+
+        let mut bays = bays(&enclosure);
+        let layout = Config::get()
+            .chassis_layout(&enclosure.serial, &enclosure.model)
+            .cloned();
+        match &layout {
+            Some(layout) => place(&mut bays, layout),
+            None => {
+                for (index, bay) in bays.iter_mut().enumerate() {
+                    bay.side = Some("front");
+                    bay.row = Some(index / DEFAULT_COLUMNS);
+                    bay.column = Some(index % DEFAULT_COLUMNS);
+                }
+            }
+        }
+
+        let format = Output::get_format(options);
+        if format != Output::Format::Table {
+            return Output::render(format, &bays).map_err(|e| Output::print_error(&e));
+        }
+
+        println!(
+            "{} {} {} {}\n",
+            enclosure.slot.bold(),
+            enclosure.vendor,
+            enclosure.model,
+            enclosure.serial
+        );
+        let width = bays.iter().map(|b| bay_name(b).len()).max().unwrap_or(2);
+        let front = side_grid(&bays, "front");
+        let rear = side_grid(&bays, "rear");
+        if !front.is_empty() {
+            print_grid("Front", &front, width);
+        }
+        if !rear.is_empty() {
+            print_grid("Rear", &rear, width);
+        }
+        let unplaced: Vec<String> = bays
+            .iter()
+            .filter(|b| b.side.is_none())
+            .map(bay_name)
+            .collect();
+        if !unplaced.is_empty() {
+            Output::print_warning(&format!(
+                "bays missing from the layout: {}",
+                unplaced.join(", ")
+            ));
+        }
+        println!(
+            "{} {} {} {}",
+            "[ok]".green(),
+            "[empty]".dimmed(),
+            "[fault]".red().bold(),
+            "[locate]".blue().bold()
+        );
+        if layout.is_none() {
+            eprintln!(
+                "{} the bays are drawn in slot order, add a [chassis.layout] for model {} to the configuration",
+                "Hint:".yellow().bold(),
+                enclosure.model
+            );
+        }
+
+        Ok(())
+    }
+}
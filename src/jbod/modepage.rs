@@ -0,0 +1,426 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod ModePage {
+    use clap::ArgMatches;
+    use serde::Serialize;
+    use std::path::Path;
+
+    use crate::jbod::context::Context;
+    use crate::jbod::disks::DiskShelf;
+    use crate::jbod::resolver::Resolver;
+    use crate::jbod::resolver::Resolver::Identifier;
+    use crate::jbod::sgio::SgIo;
+    use crate::utils::i18n::I18n;
+    use crate::utils::i18n::I18n::Message;
+    use crate::utils::output::Output;
+    use crate::utils::output::Output::ErrorCode;
+
+    use prettytable::{color, format, Attr, Cell, Row, Table};
+
+    const PAGE_CONTROL: u8 = 0x0a;
+    const PAGE_INFORMATIONAL_EXCEPTIONS: u8 = 0x1c;
+
+    /// The pages shown without `--page`.
+    pub const DEFAULT_PAGES: &[u8] = &[
+        SgIo::PAGE_CACHING,
+        PAGE_CONTROL,
+        PAGE_INFORMATIONAL_EXCEPTIONS,
+    ];
+
+    /// A field of a mode page: its byte, its lowest bit, its width in bits,
+    /// its acronym and what it means.
+    type Layout = (usize, u8, u8, &'static str, &'static str);
+
+    const CACHING: &[Layout] = &[
+        (2, 0, 1, "RCD", "Read cache disable"),
+        (2, 1, 1, "MF", "Multiplication factor"),
+        (2, 2, 1, "WCE", "Write cache enable"),
+        (2, 3, 1, "SIZE", "Size enable"),
+        (2, 4, 1, "DISC", "Discontinuity"),
+        (2, 6, 1, "ABPF", "Abort pre-fetch"),
+        (2, 7, 1, "IC", "Initiator control"),
+        (3, 0, 4, "WRP", "Write retention priority"),
+        (3, 4, 4, "DRRP", "Demand read retention priority"),
+        (4, 0, 16, "DPTL", "Disable pre-fetch transfer length"),
+        (6, 0, 16, "MIPF", "Minimum pre-fetch"),
+        (8, 0, 16, "MAPF", "Maximum pre-fetch"),
+        (10, 0, 16, "MAPFC", "Maximum pre-fetch ceiling"),
+        (12, 0, 1, "NV_DIS", "Non-volatile cache disable"),
+        (12, 5, 1, "DRA", "Disable read-ahead"),
+        (12, 7, 1, "FSW", "Force sequential write"),
+        (13, 0, 8, "NCS", "Number of cache segments"),
+        (14, 0, 16, "CSS", "Cache segment size"),
+    ];
+
+    const CONTROL: &[Layout] = &[
+        (2, 0, 1, "RLEC", "Report log exception condition"),
+        (2, 1, 1, "GLTSD", "Global logging target save disable"),
+        (2, 2, 1, "D_SENSE", "Descriptor format sense data"),
+        (2, 4, 1, "TMF_ONLY", "Task management functions only"),
+        (2, 5, 3, "TST", "Task set type"),
+        (3, 1, 2, "QERR", "Queue error management"),
+        (3, 4, 4, "QAM", "Queue algorithm modifier"),
+        (4, 3, 1, "SWP", "Software write protect"),
+        (4, 4, 2, "UA_INTLCK", "Unit attention interlocks control"),
+        (4, 6, 1, "RAC", "Report a check"),
+        (5, 0, 3, "AUTOLOAD", "Autoload mode"),
+        (5, 6, 1, "TAS", "Task aborted status"),
+        (5, 7, 1, "ATO", "Application tag owner"),
+        (8, 0, 16, "BTP", "Busy timeout period, 100 ms"),
+        (
+            10,
+            0,
+            16,
+            "ESTCT",
+            "Extended self-test completion time, seconds",
+        ),
+    ];
+
+    const INFORMATIONAL_EXCEPTIONS: &[Layout] = &[
+        (2, 0, 1, "LOGERR", "Log errors"),
+        (2, 1, 1, "EBACKERR", "Enable background error"),
+        (2, 2, 1, "TEST", "Test device failure notifications"),
+        (2, 3, 1, "DEXCPT", "Disable exceptions"),
+        (2, 4, 1, "EWASC", "Enable warning"),
+        (2, 5, 1, "EBF", "Enable background function"),
+        (2, 7, 1, "PERF", "Performance"),
+        (
+            3,
+            0,
+            4,
+            "MRIE",
+            "Method of reporting informational exceptions",
+        ),
+        (4, 0, 32, "INTT", "Interval timer, 100 ms"),
+        (8, 0, 32, "REPC", "Report count"),
+    ];
+
+    const POWER_CONDITION: &[Layout] = &[
+        (2, 0, 1, "STANDBY_Y", "Standby_y timer enabled"),
+        (3, 0, 1, "STANDBY_Z", "Standby_z timer enabled"),
+        (3, 1, 1, "IDLE_A", "Idle_a timer enabled"),
+        (3, 2, 1, "IDLE_B", "Idle_b timer enabled"),
+        (3, 3, 1, "IDLE_C", "Idle_c timer enabled"),
+        (4, 0, 32, "IACT", "Idle_a condition timer, 100 ms"),
+        (8, 0, 32, "SZCT", "Standby_z condition timer, 100 ms"),
+        (12, 0, 32, "IBCT", "Idle_b condition timer, 100 ms"),
+        (16, 0, 32, "ICCT", "Idle_c condition timer, 100 ms"),
+        (20, 0, 32, "SYCT", "Standby_y condition timer, 100 ms"),
+    ];
+
+    /// A decoded field of a mode page.
+    #[derive(Debug, Clone, Serialize)]
+    pub struct Field {
+        /// The page code, example: 0x08
+        pub page: String,
+        /// The name of the page, example: Caching
+        pub page_name: String,
+        /// The acronym of the field from SPC and SBC, example: WCE
+        pub name: String,
+        /// The value of the field, the hexadecimal bytes for a page that
+        /// isn't decoded
+        pub value: String,
+        /// What the field means
+        pub description: String,
+    }
+
+    /// Returns the name and the layout of a mode page, None for a page
+    /// that isn't decoded.
+    fn layout(page: u8) -> Option<(&'static str, &'static [Layout])> {
+        match page {
+            SgIo::PAGE_CACHING => Some(("Caching", CACHING)),
+            PAGE_CONTROL => Some(("Control", CONTROL)),
+            SgIo::PAGE_POWER_CONDITION => Some(("Power condition", POWER_CONDITION)),
+            PAGE_INFORMATIONAL_EXCEPTIONS => {
+                Some(("Informational exceptions", INFORMATIONAL_EXCEPTIONS))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a field, None when the page is too short.
+    fn extract(data: &[u8], byte: usize, shift: u8, bits: u8) -> Option<u32> {
+        if bits >= 8 {
+            let bytes = data.get(byte..byte + bits as usize / 8)?;
+            Some(bytes.iter().fold(0u32, |v, b| (v << 8) | *b as u32))
+        } else {
+            Some((*data.get(byte)? as u32 >> shift) & ((1 << bits) - 1))
+        }
+    }
+
+    /// Returns the fields of a mode page as returned by MODE SENSE.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - the mode page, starting with its code and its length
+    ///
+    pub fn decode(data: &[u8]) -> Vec<Field> {
+        let code = data.first().map(|c| c & 0x3f).unwrap_or_default();
+        let page = format!("0x{:02x}", code);
+        let field = |page_name: &str, name: &str, value: String, description: &str| Field {
+            page: page.clone(),
+            page_name: page_name.to_string(),
+            name: name.to_string(),
+            value,
+            description: description.to_string(),
+        };
+
+        let saveable = data.first().map(|c| c & 0x80 != 0).unwrap_or_default();
+        match layout(code) {
+            Some((page_name, fields)) => {
+                let mut decoded = vec![field(
+                    page_name,
+                    "PS",
+                    (saveable as u8).to_string(),
+                    "Parameters saveable",
+                )];
+                decoded.extend(fields.iter().filter_map(
+                    |(byte, shift, bits, name, description)| {
+                        extract(data, *byte, *shift, *bits)
+                            .map(|v| field(page_name, name, v.to_string(), description))
+                    },
+                ));
+                decoded
+            }
+            None => {
+                let raw: Vec<String> = data.iter().skip(2).map(|b| format!("{:02x}", b)).collect();
+                vec![
+                    field(
+                        "",
+                        "PS",
+                        (saveable as u8).to_string(),
+                        "Parameters saveable",
+                    ),
+                    field("", "RAW", raw.join(" "), "Parameters, not decoded"),
+                ]
+            }
+        }
+    }
+
+    /// Returns the page code given by the user, in hexadecimal with or
+    /// without 0x, example: 0x08
+    pub fn parse_page(page: &str) -> Result<u8, String> {
+        let hex = page.trim_start_matches("0x").trim_start_matches("0X");
+        match u8::from_str_radix(hex, 16) {
+            Ok(code) if code < 0x3f => Ok(code),
+            _ => Err(format!("{} is not a mode page, example: 0x08", page)),
+        }
+    }
+
+    /// Returns the page control of the `--values` option.
+    fn page_control(options: &ArgMatches) -> u8 {
+        match options.value_of("values") {
+            Some("changeable") => 1,
+            Some("default") => 2,
+            Some("saved") => 3,
+            _ => 0,
+        }
+    }
+
+    /// Returns the node to query: a device node as is, anything else is
+    /// resolved among the disks of the enclosures.
+    fn device_path(options: &ArgMatches, identifier: &str) -> Result<String, ()> {
+        if let Identifier::Path(path) = Resolver::parse(identifier) {
+            if Path::new(&path).exists() {
+                return Ok(path);
+            }
+        }
+
+        let mut scan = DiskShelf::ScanOptions::from_matches(options);
+        scan.fields.temperature = false;
+        scan.fields.firmware = false;
+        let run = Context::Run::new(scan);
+        let disks = DiskShelf::jbod_disk_map(&run);
+        match Resolver::resolve(identifier, &disks) {
+            Some(disk) => Ok(disk.device_path.clone()),
+            None => {
+                Output::print_error_code(
+                    ErrorCode::NotFound,
+                    &I18n::tr(Message::NotADisk, &[&identifier]),
+                );
+                Err(())
+            }
+        }
+    }
+
+    /// Creates the pretty table for the mode pages.
+    fn create_modepage_table() -> Table {
+        let mut modepage_table = Table::new();
+        modepage_table.set_format(*format::consts::FORMAT_NO_BORDER);
+        let header = ["PAGE", "NAME", "FIELD", "VALUE", "DESCRIPTION"];
+        modepage_table.add_row(Row::new(
+            header
+                .iter()
+                .map(|h| {
+                    Cell::new(h)
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(color::BLUE))
+                })
+                .collect(),
+        ));
+
+        modepage_table
+    }
+
+    /// Returns an empty Result or an error when a page couldn't be read.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `modepage` subcommand
+    ///
+    pub fn jbod_modepage(options: &ArgMatches) -> Result<(), ()> {
+        let pages = match options.value_of("page") {
+            Some(page) => vec![parse_page(page)
+                .map_err(|e| Output::print_error_code(ErrorCode::InvalidArgument, &e))?],
+            None => DEFAULT_PAGES.to_vec(),
+        };
+        let path = device_path(options, options.value_of("device").unwrap_or_default())?;
+        let device = SgIo::Device::open(&path).map_err(|e| {
+            Output::print_error_code(
+                ErrorCode::from_io(&e),
+                &I18n::tr(Message::CannotOpen, &[&path, &e]),
+            )
+        })?;
+
+        let control = page_control(options);
+        let mut fields = Vec::new();
+        let mut failed = false;
+        for page in pages {
+            match device.mode_sense(page, control) {
+                Ok(data) => fields.extend(decode(&data)),
+                Err(e) => {
+                    failed = true;
+                    Output::print_warning(&format!("{}: mode page 0x{:02x}: {}", path, page, e));
+                }
+            }
+        }
+
+        let format = Output::get_format(options);
+        if format != Output::Format::Table {
+            Output::render(format, &fields).map_err(|e| Output::print_error(&e))?;
+        } else {
+            let mut modepage_table = create_modepage_table();
+            for field in &fields {
+                modepage_table.add_row(Row::new(vec![
+                    Cell::new(&field.page),
+                    Cell::new(&field.page_name),
+                    Cell::new(&field.name).with_style(Attr::Bold),
+                    Cell::new(&field.value),
+                    Cell::new(&field.description),
+                ]));
+            }
+            Output::print_table(&modepage_table);
+        }
+
+        if failed {
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        // Captured with `sg_modes -p 0x08` and `sg_modes -p 0x1c` on an HGST
+        // HUH721212AL5200, the page code and length first.
+        const CACHING_HGST: &[u8] = &[
+            0x88, 0x12, 0x04, 0x00, 0xff, 0xff, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0x80, 0x14,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        const INFORMATIONAL_EXCEPTIONS_HGST: &[u8] = &[
+            0x9c, 0x0a, 0x10, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+        ];
+
+        fn value<'a>(fields: &'a [Field], name: &str) -> Option<&'a str> {
+            fields
+                .iter()
+                .find(|f| f.name == name)
+                .map(|f| f.value.as_str())
+        }
+
+        #[test]
+        fn caching_page() {
+            let fields = decode(CACHING_HGST);
+            assert_eq!(fields.len(), CACHING.len() + 1);
+            assert!(fields
+                .iter()
+                .all(|f| f.page == "0x08" && f.page_name == "Caching"));
+            assert_eq!(value(&fields, "PS"), Some("1"));
+            assert_eq!(value(&fields, "WCE"), Some("1"));
+            assert_eq!(value(&fields, "RCD"), Some("0"));
+            assert_eq!(value(&fields, "DPTL"), Some("65535"));
+            assert_eq!(value(&fields, "MIPF"), Some("0"));
+            assert_eq!(value(&fields, "MAPFC"), Some("65535"));
+            assert_eq!(value(&fields, "FSW"), Some("1"));
+            assert_eq!(value(&fields, "DRA"), Some("0"));
+            assert_eq!(value(&fields, "NCS"), Some("20"));
+        }
+
+        #[test]
+        fn informational_exceptions_page() {
+            let fields = decode(INFORMATIONAL_EXCEPTIONS_HGST);
+            assert_eq!(value(&fields, "EWASC"), Some("1"));
+            assert_eq!(value(&fields, "DEXCPT"), Some("0"));
+            assert_eq!(value(&fields, "MRIE"), Some("6"));
+            assert_eq!(value(&fields, "INTT"), Some("0"));
+            assert_eq!(value(&fields, "REPC"), Some("1"));
+        }
+
+        #[test]
+        fn truncated_page_drops_the_missing_fields() {
+            let fields = decode(&CACHING_HGST[..6]);
+            assert_eq!(value(&fields, "WCE"), Some("1"));
+            assert_eq!(value(&fields, "DPTL"), Some("65535"));
+            assert_eq!(value(&fields, "MIPF"), None);
+            assert_eq!(value(&fields, "CSS"), None);
+        }
+
+        #[test]
+        fn unknown_page_is_dumped() {
+            let fields = decode(&[0x19, 0x06, 0x06, 0x00, 0x07, 0xd0, 0x00, 0x00]);
+            assert_eq!(fields.len(), 2);
+            assert_eq!(fields[0].page, "0x19");
+            assert_eq!(value(&fields, "PS"), Some("0"));
+            assert_eq!(value(&fields, "RAW"), Some("06 00 07 d0 00 00"));
+        }
+
+        #[test]
+        fn page_codes() {
+            assert_eq!(parse_page("0x08"), Ok(0x08));
+            assert_eq!(parse_page("1C"), Ok(0x1c));
+            assert!(parse_page("0x3f").is_err());
+            assert!(parse_page("caching").is_err());
+        }
+    }
+}
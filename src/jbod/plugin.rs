@@ -0,0 +1,323 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Plugin {
+    use prettytable::{color, format, Attr, Cell, Row, Table};
+    use serde::Serialize;
+    use std::sync::{Arc, RwLock};
+
+    use crate::jbod::context::Context;
+    use crate::jbod::disks::DiskShelf;
+    use crate::jbod::enclosure::BackPlane;
+    use crate::jbod::health::Health::Severity;
+    use crate::utils::config::Config;
+    use crate::utils::helper::Util;
+
+    /// What a collector measured or checked on an enclosure.
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct Sample {
+        /// example: psu_efficiency, the metric label in the exporter
+        pub name: String,
+        /// A measure, exported as `jbod_collector_value`.
+        pub value: Option<f64>,
+        /// A problem reported by `jbod status`, None when all is fine.
+        pub severity: Option<Severity>,
+        pub message: String,
+    }
+
+    /// A sample with the collector and the enclosure it comes from.
+    #[derive(Debug, Clone, Serialize)]
+    pub struct Reading {
+        pub collector: String,
+        /// The slot number of the enclosure provided by the JBOD
+        pub enclosure: String,
+        /// The enclosure serial number
+        pub serial: String,
+        #[serde(flatten)]
+        pub sample: Sample,
+    }
+
+    /// A source of samples, run on every enclosure by `list --collectors`,
+    /// `status` and the exporter.
+    ///
+    /// Vendor-specific SES pages or site-specific checks are added with
+    /// `register` by a program built on the jbod crate, or without code as
+    /// a `[[collectors]]` command of the configuration.
+    ///
+    /// # Example:
+    /// ```
+    /// struct Uptime;
+    ///
+    /// impl Plugin::Collector for Uptime {
+    ///     fn name(&self) -> &str {
+    ///         "esm_uptime"
+    ///     }
+    ///
+    ///     fn collect(&self, enclosure: &BackPlane::Enclosure) -> Result<Vec<Plugin::Sample>, String> {
+    ///         let hours = read_uptime(&enclosure.device_path)?;
+    ///         Ok(vec![Plugin::Sample::value("hours", hours)])
+    ///     }
+    /// }
+    ///
+    /// Plugin::register(Box::new(Uptime));
+    /// ```
+    pub trait Collector: Send + Sync {
+        /// The name of the collector, unique among the collectors.
+        fn name(&self) -> &str;
+
+        /// Returns the samples of an enclosure or why it can't be read.
+        fn collect(&self, enclosure: &BackPlane::Enclosure) -> Result<Vec<Sample>, String>;
+    }
+
+    impl Sample {
+        /// Returns a sample measuring `value`.
+        pub fn value(name: &str, value: f64) -> Self {
+            Sample {
+                name: name.to_string(),
+                value: Some(value),
+                severity: None,
+                message: String::new(),
+            }
+        }
+
+        /// Returns a sample reporting a problem.
+        pub fn alert(name: &str, severity: Severity, message: &str) -> Self {
+            Sample {
+                name: name.to_string(),
+                value: None,
+                severity: Some(severity),
+                message: message.to_string(),
+            }
+        }
+    }
+
+    static REGISTERED: RwLock<Vec<Arc<dyn Collector>>> = RwLock::new(Vec::new());
+
+    /// Adds a collector, it runs after the ones registered before it and
+    /// before the `[[collectors]]` of the configuration.
+    pub fn register(collector: Box<dyn Collector>) {
+        REGISTERED
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(Arc::from(collector));
+    }
+
+    /// Returns the registered collectors followed by the ones of the
+    /// configuration.
+    pub fn collectors() -> Vec<Arc<dyn Collector>> {
+        let mut collectors = REGISTERED.read().unwrap_or_else(|e| e.into_inner()).clone();
+        for entry in &Config::get().collectors {
+            collectors.push(Arc::new(CommandCollector(entry.clone())));
+        }
+        collectors
+    }
+
+    /// A collector running a command per enclosure, from a `[[collectors]]`
+    /// entry of the configuration.
+    ///
+    /// Each line the command prints is a sample, `<name> <value>` for a
+    /// measure, `<name> <value|-> <ok|warning|critical> [message]` for a
+    /// check, the empty lines and the ones starting with `#` are skipped.
+    /// The command fails the enclosure when it exits with an error.
+    ///
+    /// ```text
+    /// psu_efficiency 0.92
+    /// psu_vendor - warning PSU 2 is not an approved part
+    /// ```
+    pub struct CommandCollector(pub Config::CommandCollector);
+
+    impl Collector for CommandCollector {
+        fn name(&self) -> &str {
+            &self.0.name
+        }
+
+        fn collect(&self, enclosure: &BackPlane::Enclosure) -> Result<Vec<Sample>, String> {
+            let args: Vec<String> = self
+                .0
+                .args
+                .iter()
+                .map(|arg| {
+                    arg.replace("{device}", &enclosure.device_path)
+                        .replace("{slot}", &enclosure.slot)
+                        .replace("{serial}", &enclosure.serial)
+                        .replace("{vendor}", &enclosure.vendor)
+                        .replace("{model}", &enclosure.model)
+                })
+                .collect();
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            let output =
+                Util::run_command_timeout(&self.0.command, &args, enclosure.command_timeout())
+                    .map_err(|e| format!("{}: {}", self.0.command, e))?;
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                return Err(match stderr.trim() {
+                    "" => format!("{} exited with {}", self.0.command, output.status),
+                    e => format!("{} exited with {}: {}", self.0.command, output.status, e),
+                });
+            }
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(parse_sample)
+                .collect()
+        }
+    }
+
+    /// Returns the sample of a line printed by a `CommandCollector`.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - example: psu_vendor - warning PSU 2 is not an approved part
+    ///
+    pub fn parse_sample(line: &str) -> Result<Sample, String> {
+        let mut rest = line.trim();
+        let mut field = || {
+            let (field, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            rest = tail.trim_start();
+            Some(field).filter(|f| !f.is_empty())
+        };
+        let name = field().unwrap_or_default();
+        let value = match field() {
+            None | Some("-") => None,
+            Some(v) => Some(
+                v.parse::<f64>()
+                    .map_err(|_| format!("invalid value in {:?}", line))?,
+            ),
+        };
+        let severity = match field() {
+            None | Some("ok") => None,
+            Some("warning") => Some(Severity::Warning),
+            Some("critical") => Some(Severity::Critical),
+            Some(s) => return Err(format!("invalid severity {} in {:?}", s, line)),
+        };
+        Ok(Sample {
+            name: name.to_string(),
+            value,
+            severity,
+            message: rest.to_string(),
+        })
+    }
+
+    /// Returns the readings of every collector on the enclosures of a run,
+    /// the failures are recorded in the run.
+    ///
+    /// # Arguments
+    ///
+    /// * `run` - a reference of the run Context
+    ///
+    pub fn collect(run: &Context::Run) -> Vec<Reading> {
+        let collectors = collectors();
+        if collectors.is_empty() {
+            return Vec::new();
+        }
+        let enclosures = DiskShelf::jbod_enclosures(run);
+        let mut pairs = Vec::new();
+        for enclosure in &enclosures {
+            for collector in &collectors {
+                pairs.push((enclosure, collector));
+            }
+        }
+
+        let results = Util::parallel_map(&pairs, run.options.jobs, |(enclosure, collector)| {
+            collector.collect(enclosure)
+        });
+        let mut readings = Vec::new();
+        for ((enclosure, collector), result) in pairs.iter().zip(results) {
+            match result {
+                Ok(samples) => readings.extend(samples.into_iter().map(|sample| Reading {
+                    collector: collector.name().to_string(),
+                    enclosure: enclosure.slot.clone(),
+                    serial: enclosure.serial.clone(),
+                    sample,
+                })),
+                Err(e) => run.record_error(
+                    &enclosure.slot,
+                    format!("collector {}: {}", collector.name(), e),
+                ),
+            }
+        }
+        readings
+    }
+
+    /// Creates the pretty table for the readings of the collectors.
+    pub fn create_collector_table() -> Table {
+        let mut collector_table = Table::new();
+        collector_table.set_format(*format::consts::FORMAT_NO_BORDER);
+        let header = [
+            "ENCLOSURE",
+            "COLLECTOR",
+            "NAME",
+            "VALUE",
+            "SEVERITY",
+            "MESSAGE",
+        ];
+        collector_table.add_row(Row::new(
+            header
+                .iter()
+                .map(|h| {
+                    Cell::new(h)
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(color::BLUE))
+                })
+                .collect(),
+        ));
+
+        collector_table
+    }
+
+    /// Returns the row of a reading for `create_collector_table`.
+    pub fn collector_row(reading: &Reading) -> Row {
+        let severity = match reading.sample.severity {
+            Some(Severity::Critical) => Cell::new("critical")
+                .with_style(Attr::Bold)
+                .with_style(Attr::ForegroundColor(color::RED)),
+            Some(Severity::Warning) => {
+                Cell::new("warning").with_style(Attr::ForegroundColor(color::YELLOW))
+            }
+            None => Cell::new("ok").with_style(Attr::ForegroundColor(color::GREEN)),
+        };
+        Row::new(vec![
+            Cell::new(&reading.enclosure),
+            Cell::new(&reading.collector),
+            Cell::new(&reading.sample.name),
+            Cell::new(
+                &reading
+                    .sample
+                    .value
+                    .map(|v| v.to_string())
+                    .unwrap_or_default(),
+            ),
+            severity,
+            Cell::new(&reading.sample.message),
+        ])
+    }
+}
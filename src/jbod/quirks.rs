@@ -0,0 +1,242 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Quirks {
+    use serde::Serialize;
+    use std::fs;
+    use std::path::Path;
+
+    use crate::utils::config::Config;
+    use crate::utils::helper::Util;
+
+    /// How the comment following the speed of a fan is shown, example:
+    /// "Fan at fourth lowest speed".
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum FanComment {
+        /// As printed by sg_ses
+        Keep,
+        /// The SES speed code, the firmware sets the code but not the rpm
+        SpeedCode,
+        /// Dropped, the firmware reports the same code whatever the speed
+        Drop,
+    }
+
+    /// What an enclosure family does differently from the SES standard.
+    #[derive(Debug, Serialize)]
+    pub struct Quirk {
+        pub name: &'static str,
+        /// Matched against the start of the SES vendor, case insensitive
+        #[serde(skip)]
+        vendors: &'static [&'static str],
+        /// Matched against the start of the SES model, any model when empty
+        #[serde(skip)]
+        models: &'static [&'static str],
+        /// Added to the SES slot number to get the bay printed on the chassis,
+        /// a `[[chassis]]` entry of the configuration wins over it
+        pub slot_offset: i64,
+        /// Words of the slot descriptors besides slot, disk and array device
+        pub slot_words: &'static [&'static str],
+        /// The descriptors number the drawer first, the slot is the last number
+        pub slot_last_number: bool,
+        pub fan_comment: FanComment,
+        pub description: &'static str,
+    }
+
+    /// The enclosures following the standard.
+    const GENERIC: Quirk = Quirk {
+        name: "generic",
+        vendors: &[],
+        models: &[],
+        slot_offset: 0,
+        slot_words: &[],
+        slot_last_number: false,
+        fan_comment: FanComment::Keep,
+        description: "standard SES descriptors",
+    };
+
+    const QUIRKS: &[Quirk] = &[
+        Quirk {
+            name: "supermicro",
+            vendors: &["SMC", "Supermicro"],
+            models: &[],
+            slot_offset: 0,
+            slot_words: &[],
+            slot_last_number: false,
+            fan_comment: FanComment::Drop,
+            description: "the BMC drives the fans, the SES speed code never changes",
+        },
+        Quirk {
+            name: "dell-md-me",
+            vendors: &["DELL"],
+            models: &["MD", "ME"],
+            slot_offset: 0,
+            slot_words: &["drive"],
+            slot_last_number: false,
+            fan_comment: FanComment::SpeedCode,
+            description: "Drive descriptors, the fans report a speed code",
+        },
+        Quirk {
+            name: "hpe-d-series",
+            vendors: &["HP", "HPE"],
+            models: &["D2", "D3", "D6", "D8"],
+            slot_offset: 1,
+            slot_words: &["bay"],
+            slot_last_number: false,
+            fan_comment: FanComment::Keep,
+            description: "Bay descriptors, the bays are numbered from 1",
+        },
+        Quirk {
+            name: "seagate-xyratex",
+            vendors: &["XYRATEX", "SEAGATE"],
+            models: &[],
+            slot_offset: 0,
+            slot_words: &["drive"],
+            slot_last_number: true,
+            fan_comment: FanComment::SpeedCode,
+            description: "drawer and slot descriptors, the fans report a speed code",
+        },
+    ];
+
+    /// The speed codes of SES, from the comments of sg_ses.
+    const SPEED_CODES: &[(&str, u8)] = &[
+        ("stopped", 0),
+        ("lowest speed", 1),
+        ("second lowest speed", 2),
+        ("third lowest speed", 3),
+        ("fourth lowest speed", 4),
+        ("intermediate speed", 4),
+        ("third highest speed", 5),
+        ("second highest speed", 6),
+        ("highest speed", 7),
+    ];
+
+    impl Quirk {
+        /// Returns true when a component of the enclosure in sysfs is a slot.
+        pub fn is_slot_component(&self, name: &str) -> bool {
+            let name_lower = name.to_lowercase();
+            Util::is_slot_component(name) || self.slot_words.iter().any(|w| name_lower.contains(w))
+        }
+
+        /// Returns the number of a slot name, example: 7 for "Drawer 1 Slot 07"
+        /// when the slot is the last number.
+        pub fn slot_number(&self, slot: &str) -> Option<u32> {
+            if !self.slot_last_number {
+                return Util::slot_number(slot);
+            }
+            slot.split(|c: char| !c.is_ascii_digit())
+                .rfind(|n| !n.is_empty())?
+                .parse()
+                .ok()
+        }
+
+        /// Returns the bay printed on the chassis for a slot name, None when
+        /// it is the SES slot number.
+        pub fn label(&self, slot: &str) -> Option<String> {
+            if self.slot_offset == 0 && !self.slot_last_number {
+                return None;
+            }
+            let number = self.slot_number(slot)? as i64 + self.slot_offset;
+            Some(number.to_string())
+        }
+
+        /// Returns the comment of a fan as this enclosure family should show it.
+        pub fn fan_comment(&self, comment: &str) -> String {
+            match self.fan_comment {
+                FanComment::Keep => comment.to_string(),
+                FanComment::Drop => String::new(),
+                FanComment::SpeedCode => {
+                    let lower = comment.to_lowercase();
+                    SPEED_CODES
+                        .iter()
+                        .filter(|(text, _)| lower.ends_with(text))
+                        .max_by_key(|(text, _)| text.len())
+                        .map(|(_, code)| format!("speed code {}/7", code))
+                        .unwrap_or_else(|| comment.to_string())
+                }
+            }
+        }
+    }
+
+    /// Returns the quirk of an enclosure family, the generic one when it
+    /// follows the standard.
+    ///
+    /// # Arguments
+    ///
+    /// * `vendor` - the SES vendor, example: HGST
+    /// * `model` - the SES model, example: H4102-J
+    ///
+    pub fn for_enclosure(vendor: &str, model: &str) -> &'static Quirk {
+        let starts = |value: &str, prefix: &str| {
+            value
+                .trim()
+                .to_uppercase()
+                .starts_with(&prefix.to_uppercase())
+        };
+        QUIRKS
+            .iter()
+            .find(|q| {
+                q.vendors.iter().any(|v| starts(vendor, v))
+                    && (q.models.is_empty() || q.models.iter().any(|m| starts(model, m)))
+            })
+            .unwrap_or(&GENERIC)
+    }
+
+    /// Returns the quirk of an enclosure of class/enclosure in sysfs, from
+    /// the vendor and the model of its SES device.
+    ///
+    /// # Arguments
+    ///
+    /// * `enclosure_dir` - example: /sys/class/enclosure/15:0:1:0
+    ///
+    pub fn for_sysfs(enclosure_dir: &Path) -> &'static Quirk {
+        let read = |name: &str| {
+            fs::read_to_string(enclosure_dir.join("device").join(name)).unwrap_or_default()
+        };
+        for_enclosure(&read("vendor"), &read("model"))
+    }
+
+    /// Returns the physical label of a slot, the one of the configuration
+    /// or else the bay numbering of the enclosure family.
+    ///
+    /// # Arguments
+    ///
+    /// * `serial` - the enclosure serial number
+    /// * `vendor` - the enclosure vendor
+    /// * `model` - the enclosure model
+    /// * `slot` - the slot name reported by SES
+    ///
+    pub fn slot_label(serial: &str, vendor: &str, model: &str, slot: &str) -> Option<String> {
+        Config::get()
+            .slot_label(serial, model, slot)
+            .or_else(|| for_enclosure(vendor, model).label(slot))
+    }
+}
@@ -0,0 +1,407 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Resolver {
+    use lazy_static::lazy_static;
+    use regex::Regex;
+    use serde::Serialize;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use crate::jbod::disks::DiskShelf::Disk;
+    use crate::jbod::quirks::Quirks;
+    use crate::utils::helper::Util;
+
+    const DEV_BY_ID: &str = "/dev/disk/by-id/";
+
+    lazy_static! {
+        static ref SLOT_RE: Regex = Regex::new(r"^(?P<enc>\d+:\d+:\d+:\d+)[:/](?P<slot>.+)$").unwrap();
+        static ref WWN_RE: Regex =
+            Regex::new(r"^(?i)(wwn-)?(0x|naa\.)?(?P<wwn>[0-9a-f]{16}([0-9a-f]{16})?)$").unwrap();
+    }
+
+    /// Where a disk sits in the JBOD, found from sysfs without probing the disk.
+    #[derive(Debug, Clone, Serialize)]
+    pub struct Location {
+        // Enclosure number identification, example: 15:0:1:0
+        pub enclosure: String,
+        // Disk slot identification
+        pub slot: String,
+        // Physical slot label printed on the chassis, from the configuration
+        pub label: Option<String>,
+        // Disk path, example: /dev/sg105, empty when the slot has no device
+        pub device_path: String,
+        // Path to led control file, None when the slot has no locate led
+        pub led_locate_path: Option<String>,
+        // Path to led control file, None when the slot has no fault led
+        pub led_fault_path: Option<String>,
+    }
+
+    /// The different ways a human can name a disk.
+    #[derive(Debug, PartialEq, Eq)]
+    pub enum Identifier {
+        /// A device node, already canonicalized, example: /dev/sdcz or /dev/sg105
+        Path(String),
+        /// A World Wide Name without prefix, lowercase, example: 5000c500a1b2c3d4
+        Wwn(String),
+        /// The enclosure identification and the slot, example: 15:0:1:0/23
+        Slot { enclosure: String, slot: String },
+        /// Anything else is considered a serial number
+        Serial(String),
+    }
+
+    /// Returns the Identifier for a given string.
+    ///
+    /// It accepts `/dev/sdX`, `/dev/sgN`, `/dev/disk/by-id/...`, bare `sdX`
+    /// or `sgN`, a WWN (`0x...`, `naa....` or `wwn-0x...`), `enclosure:slot`
+    /// or `enclosure/slot`, and finally a serial number.
+    ///
+    /// # Arguments
+    ///
+    /// * `identifier` - a string reference with what the user typed
+    ///
+    /// # Example
+    /// ```
+    /// let id = parse("15:0:1:0/23");
+    /// ```
+    ///
+    pub fn parse(identifier: &str) -> Identifier {
+        let identifier = identifier.trim();
+
+        if identifier.starts_with('/') {
+            return Identifier::Path(canonicalize(identifier));
+        }
+
+        let bare = format!("/dev/{}", identifier);
+        if (identifier.starts_with("sd") || identifier.starts_with("sg")) && Path::new(&bare).exists()
+        {
+            return Identifier::Path(canonicalize(&bare));
+        }
+
+        if let Some(m) = SLOT_RE.captures(identifier) {
+            return Identifier::Slot {
+                enclosure: m["enc"].to_string(),
+                slot: m["slot"].to_string(),
+            };
+        }
+
+        // A serial can look like a WWN, `resolve()` and `locate()` try it as
+        // a serial when no disk has this WWN.
+        if let Some(m) = WWN_RE.captures(identifier) {
+            return Identifier::Wwn(m["wwn"].to_lowercase());
+        }
+
+        Identifier::Serial(identifier.to_string())
+    }
+
+    /// Returns the path with symlinks resolved, or the path itself if it
+    /// cannot be resolved (the node may be gone).
+    fn canonicalize(path: &str) -> String {
+        match fs::canonicalize(path) {
+            Ok(p) => p.to_string_lossy().to_string(),
+            Err(_) => path.to_string(),
+        }
+    }
+
+    /// Returns the block device behind a WWN using the udev by-id links.
+    fn wwn_to_path(wwn: &str) -> Option<String> {
+        let link = format!("{}wwn-0x{}", DEV_BY_ID, wwn);
+        if Path::new(&link).exists() {
+            Some(canonicalize(&link))
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if both slot names designate the same slot, it compares
+    /// numerically when both contain digits, "Slot 05" and "5" are the same slot.
+    fn same_slot(disk_slot: &str, slot: &str) -> bool {
+        if disk_slot.trim().eq_ignore_ascii_case(slot.trim()) {
+            return true;
+        }
+        match (Util::slot_number(disk_slot), Util::slot_number(slot)) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    /// Returns true if the serial number of the disk matches.
+    fn same_serial(disk_serial: &str, serial: &str) -> bool {
+        let cleaned: String = disk_serial.chars().filter(|c| c.is_ascii_graphic()).collect();
+        !serial.is_empty() && cleaned.eq_ignore_ascii_case(serial)
+    }
+
+    /// Returns true if the sysfs `wwid` of a disk is the WWN, example:
+    /// "naa.5000c500a1b2c3d4" for 5000c500a1b2c3d4.
+    fn same_wwn(wwid: &str, wwn: &str) -> bool {
+        let wwid = wwid.trim().to_lowercase();
+        let bare = wwid
+            .strip_prefix("naa.")
+            .or_else(|| wwid.strip_prefix("0x"))
+            .unwrap_or(&wwid);
+        !wwn.is_empty() && bare == wwn
+    }
+
+    /// Returns the disk designated by the identifier or None.
+    ///
+    /// This is the single place where every subcommand resolves a user
+    /// provided identifier into a disk from the JBOD.
+    ///
+    /// # Arguments
+    ///
+    /// * `identifier` - a string reference with what the user typed
+    /// * `disks` - the disks to search into, usually from `jbod_disk_map()`
+    ///
+    pub fn resolve<'a>(identifier: &str, disks: &'a [Disk]) -> Option<&'a Disk> {
+        let by_serial = |serial: &str| disks.iter().find(|d| same_serial(&d.serial, serial));
+        match parse(identifier) {
+            Identifier::Path(path) => disks
+                .iter()
+                .find(|d| d.device_path == path || d.device_map == path),
+            Identifier::Wwn(wwn) => disks
+                .iter()
+                .find(|d| same_wwn(&d.wwn, &wwn))
+                .or_else(|| {
+                    let path = wwn_to_path(&wwn)?;
+                    disks
+                        .iter()
+                        .find(|d| d.device_path == path || d.device_map == path)
+                })
+                .or_else(|| by_serial(identifier.trim())),
+            Identifier::Slot { enclosure, slot } => disks
+                .iter()
+                .find(|d| d.enclosure == enclosure && same_slot(&d.slot, &slot)),
+            Identifier::Serial(serial) => by_serial(&serial),
+        }
+    }
+
+    /// Returns a string with the file path if it exists or None.
+    fn led_path(component: &Path, led: &str) -> Option<String> {
+        let path = component.join(led);
+        if path.exists() {
+            Some(path.to_string_lossy().to_string())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the content of a sysfs attribute trimmed or an empty string.
+    fn read_attribute(path: &Path) -> String {
+        fs::read_to_string(path)
+            .map(|c| c.trim().to_string())
+            .unwrap_or_default()
+    }
+
+    /// Returns the Location for a slot component of an enclosure in sysfs.
+    ///
+    /// # Arguments
+    ///
+    /// * `component` - the component path, example: /sys/class/enclosure/15:0:1:0/Slot 05
+    ///
+    fn location_from_component(component: &Path) -> Option<Location> {
+        let enclosure_dir = component.parent()?;
+        let enclosure = enclosure_dir.file_name()?.to_string_lossy().to_string();
+        let name = component.file_name()?.to_string_lossy().to_string();
+        let slot = name.split(',').next().unwrap_or(&name).to_string();
+
+        let device_path = fs::read_dir(component.join("device/scsi_generic"))
+            .ok()
+            .and_then(|mut d| d.next())
+            .and_then(|d| d.ok())
+            .map(|d| format!("/dev/{}", d.file_name().to_string_lossy()))
+            .unwrap_or_default();
+
+        let enclosure_serial = fs::read(enclosure_dir.join("device/vpd_pg80"))
+            .map(|p| Util::vpd_serial(&p))
+            .unwrap_or_default();
+        let enclosure_vendor = read_attribute(&enclosure_dir.join("device/vendor"));
+        let enclosure_model = read_attribute(&enclosure_dir.join("device/model"));
+        let label = Quirks::slot_label(&enclosure_serial, &enclosure_vendor, &enclosure_model, &slot);
+
+        Some(Location {
+            enclosure,
+            slot,
+            label,
+            device_path,
+            led_locate_path: led_path(component, "locate"),
+            led_fault_path: led_path(component, "fault"),
+        })
+    }
+
+    /// Returns the enclosure component of a device node using the kernel
+    /// `enclosure_device:` link of the scsi device.
+    fn component_of_device(path: &str) -> Option<PathBuf> {
+        let name = Path::new(path).file_name()?.to_string_lossy().to_string();
+        let device_dir = if name.starts_with("sg") {
+            Util::sys_class_scsi_generic().join(&name).join("device")
+        } else {
+            Util::sysfs_path("block").join(&name).join("device")
+        };
+
+        fs::read_dir(device_dir)
+            .ok()?
+            .flatten()
+            .find(|e| e.file_name().to_string_lossy().starts_with("enclosure_device:"))
+            .and_then(|e| fs::canonicalize(e.path()).ok())
+    }
+
+    /// Returns every slot component of every enclosure, or of one enclosure.
+    fn components(enclosure: Option<&str>) -> Vec<PathBuf> {
+        let enclosures: Vec<PathBuf> = match enclosure {
+            Some(e) => vec![Util::sys_class_enclosure().join(e)],
+            None => fs::read_dir(Util::sys_class_enclosure())
+                .map(|d| d.flatten().map(|e| e.path()).collect())
+                .unwrap_or_default(),
+        };
+
+        let mut components: Vec<PathBuf> = enclosures
+            .iter()
+            .flat_map(|e| {
+                let quirk = Quirks::for_sysfs(e);
+                fs::read_dir(e)
+                    .into_iter()
+                    .flat_map(|d| d.flatten().map(|c| c.path()))
+                    .filter(move |p| {
+                        p.file_name()
+                            .map(|n| quirk.is_slot_component(&n.to_string_lossy()))
+                            .unwrap_or(false)
+                    })
+            })
+            .collect();
+        components.sort_by(|a, b| {
+            Util::natural_cmp(&a.to_string_lossy(), &b.to_string_lossy())
+        });
+        components
+    }
+
+    /// Returns the Location of the disk with this serial number, read from
+    /// its VPD page 0x80 in sysfs or, for the SATA drives without one, from
+    /// the end of their t10 `wwid` like `list` does.
+    fn locate_serial(serial: &str) -> Option<Location> {
+        components(None)
+            .iter()
+            .find(|c| {
+                let vpd = fs::read(c.join("device/vpd_pg80"))
+                    .map(|p| same_serial(&Util::vpd_serial(&p), serial))
+                    .unwrap_or(false);
+                vpd || Util::t10_serial(&read_attribute(&c.join("device/wwid")))
+                    .map(|s| same_serial(s, serial))
+                    .unwrap_or(false)
+            })
+            .and_then(|c| location_from_component(c))
+    }
+
+    /// Returns the Location designated by the identifier or None.
+    ///
+    /// Unlike `resolve()` it doesn't need a full scan of the JBOD, it walks
+    /// sysfs only, so a slot is found even when its disk is dead.
+    ///
+    /// # Arguments
+    ///
+    /// * `identifier` - a string reference with what the user typed
+    ///
+    pub fn locate(identifier: &str) -> Option<Location> {
+        match parse(identifier) {
+            Identifier::Path(path) => location_from_component(&component_of_device(&path)?),
+            // Without its udev link the WWN is looked for in the disks of the slots.
+            Identifier::Wwn(wwn) => match wwn_to_path(&wwn).and_then(|p| component_of_device(&p)) {
+                Some(component) => location_from_component(&component),
+                None => components(None)
+                    .iter()
+                    .find(|c| same_wwn(&read_attribute(&c.join("device/wwid")), &wwn))
+                    .and_then(|c| location_from_component(c))
+                    .or_else(|| locate_serial(identifier.trim())),
+            },
+            Identifier::Slot { enclosure, slot } => components(Some(&enclosure))
+                .iter()
+                .filter_map(|c| location_from_component(c))
+                .find(|l| same_slot(&l.slot, &slot)),
+            Identifier::Serial(serial) => locate_serial(&serial),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn disk(slot: &str, serial: &str, wwn: &str) -> Disk {
+            serde_json::from_value(serde_json::json!({
+                "enclosure": "0:0:60:0",
+                "slot": slot,
+                "device_path": "/dev/sg-missing",
+                "device_map": "/dev/sd-missing",
+                "temperature": 35,
+                "vendor": "HGST",
+                "model": "HUH721212AL5200",
+                "serial": serial,
+                "wwn": wwn,
+                "fw_revision": "A3D0",
+                "status": "ok",
+            }))
+            .expect("a disk record")
+        }
+
+        #[test]
+        fn parse_tells_wwn_slot_and_serial_apart() {
+            let wwn = Identifier::Wwn("5000cca2510a1b4c".to_string());
+
+            assert_eq!(parse("5000CCA2510A1B4C"), wwn);
+            assert_eq!(parse("0x5000cca2510a1b4c"), wwn);
+            assert_eq!(parse("naa.5000cca2510a1b4c"), wwn);
+            assert_eq!(parse("wwn-0x5000cca2510a1b4c"), wwn);
+            assert_eq!(
+                parse("15:0:1:0/23"),
+                Identifier::Slot {
+                    enclosure: "15:0:1:0".to_string(),
+                    slot: "23".to_string(),
+                }
+            );
+            assert_eq!(parse(" 8HG6ABCF "), Identifier::Serial("8HG6ABCF".to_string()));
+        }
+
+        #[test]
+        fn hexadecimal_serial_falls_back_from_wwn() {
+            let disks = [
+                disk("Slot 00", "8HG6ABCD", "naa.5000cca2510a1b2c"),
+                disk("Slot 01", "1918A1B2C3D4E5F6", "naa.5000cca2510a1b3c"),
+            ];
+
+            // Parsed as a WWN that no disk has, it is then a serial.
+            assert!(matches!(parse("1918A1B2C3D4E5F6"), Identifier::Wwn(_)));
+            let by_serial = resolve("1918A1B2C3D4E5F6", &disks).expect("found by serial");
+            let by_wwn = resolve("0x5000CCA2510A1B2C", &disks).expect("found by wwn");
+
+            assert_eq!(by_serial.slot, "Slot 01");
+            assert_eq!(by_wwn.slot, "Slot 00");
+            assert!(resolve("0123456789abcdef", &disks).is_none());
+        }
+    }
+}
@@ -0,0 +1,342 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Sed {
+    use clap::ArgMatches;
+    use serde::{Deserialize, Serialize};
+    use std::fmt;
+
+    use crate::jbod::context::Context;
+    use crate::jbod::disks::DiskShelf;
+    use crate::jbod::disks::DiskShelf::Disk;
+    use crate::jbod::sgio::SgIo;
+    use crate::utils::helper::Util;
+    use crate::utils::i18n::I18n;
+    use crate::utils::i18n::I18n::Message;
+    use crate::utils::output::Output;
+    use crate::utils::output::Output::ErrorCode;
+
+    use prettytable::{color, format, Attr, Cell, Row, Table};
+
+    /// Security protocol listing the protocols supported by the disk.
+    const PROTOCOL_LIST: u8 = 0x00;
+    /// Security protocol of the TCG storage specifications.
+    const PROTOCOL_TCG: u8 = 0x01;
+    /// ComID answering the Level 0 Discovery.
+    const COMID_DISCOVERY: u16 = 0x0001;
+    /// Size of the Level 0 Discovery response, about ten features fit.
+    const DISCOVERY_LEN: u32 = 2048;
+    /// Size of the header preceding the feature descriptors.
+    const DISCOVERY_HEADER: usize = 48;
+    const FEATURE_LOCKING: u16 = 0x0002;
+
+    /// The TCG state of a disk, from its Level 0 Discovery.
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Status {
+        /// The security subsystem class, None for a disk without TCG
+        /// support, example: Opal 2.0, Enterprise
+        pub ssc: Option<String>,
+        /// The disk encrypts its media
+        pub media_encryption: bool,
+        /// The locking SP is activated, the disk was taken ownership of
+        pub locking_enabled: bool,
+        /// A locking range is locked, its data can't be read
+        pub locked: bool,
+        /// The shadow MBR is enabled and not yet done, the disk only shows
+        /// its pre-boot image
+        pub mbr_shadowed: bool,
+    }
+
+    impl Status {
+        /// Returns the Status of a disk without TCG support.
+        fn unsupported() -> Self {
+            Status {
+                ssc: None,
+                media_encryption: false,
+                locking_enabled: false,
+                locked: false,
+                mbr_shadowed: false,
+            }
+        }
+
+        /// Returns true for a self-encrypting drive.
+        pub fn is_sed(&self) -> bool {
+            self.media_encryption
+                && matches!(self.ssc.as_deref(), Some(ssc) if !ssc.starts_with("Pyrite"))
+        }
+
+        /// Returns the ownership of the disk as shown to a human.
+        pub fn ownership(&self) -> &'static str {
+            if self.locking_enabled {
+                "owned"
+            } else {
+                "factory"
+            }
+        }
+    }
+
+    impl fmt::Display for Status {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match &self.ssc {
+                None => write!(f, "no"),
+                Some(ssc) => {
+                    write!(f, "{}", ssc)?;
+                    if !self.is_sed() {
+                        write!(f, " (no encryption)")?;
+                    }
+                    write!(f, ", {}", self.ownership())?;
+                    if self.locked {
+                        write!(f, ", locked")?;
+                    } else if self.locking_enabled {
+                        write!(f, ", unlocked")?;
+                    }
+                    if self.mbr_shadowed {
+                        write!(f, ", shadow MBR")?;
+                    }
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// Returns the name of a security subsystem class feature or None.
+    fn ssc_name(code: u16) -> Option<&'static str> {
+        match code {
+            0x0100 => Some("Enterprise"),
+            0x0200 => Some("Opal 1.0"),
+            0x0203 => Some("Opal 2.0"),
+            0x0301 => Some("Opalite"),
+            0x0302 => Some("Pyrite 1.0"),
+            0x0303 => Some("Pyrite 2.0"),
+            0x0304 => Some("Ruby"),
+            _ => None,
+        }
+    }
+
+    /// Returns the Status from a Level 0 Discovery response.
+    ///
+    /// The response is a 48 bytes header then feature descriptors, each one
+    /// a 2 bytes code, a version byte, the length of the data and the data.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - the response to SECURITY PROTOCOL IN 0x01, ComID 0x0001
+    ///
+    pub fn parse_discovery(data: &[u8]) -> Status {
+        let mut status = Status::unsupported();
+        if data.len() < DISCOVERY_HEADER {
+            return status;
+        }
+        // The length field doesn't count itself.
+        let total = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize + 4;
+        let end = total.min(data.len());
+
+        let mut offset = DISCOVERY_HEADER;
+        while offset + 4 <= end {
+            let code = u16::from_be_bytes([data[offset], data[offset + 1]]);
+            let len = data[offset + 3] as usize;
+            if code == FEATURE_LOCKING && offset + 4 < end {
+                let flags = data[offset + 4];
+                status.locking_enabled = flags & 0x02 != 0;
+                status.locked = flags & 0x04 != 0;
+                status.media_encryption = flags & 0x08 != 0;
+                status.mbr_shadowed = flags & 0x10 != 0 && flags & 0x20 == 0;
+            }
+            // A disk may report several classes, the first is the main one.
+            if let (None, Some(name)) = (&status.ssc, ssc_name(code)) {
+                status.ssc = Some(name.to_string());
+            }
+            offset += 4 + len;
+        }
+
+        status
+    }
+
+    /// Returns the TCG Status of a disk.
+    ///
+    /// A disk that doesn't list the TCG protocol, or rejects the security
+    /// commands like most disks without security support, isn't a SED.
+    ///
+    /// # Arguments
+    ///
+    /// * `device_path` - the sg node of the disk, example: /dev/sg12
+    ///
+    pub fn status(device_path: &str) -> Result<Status, String> {
+        let device = SgIo::Device::open(device_path)
+            .map_err(|e| I18n::tr(Message::CannotOpen, &[&device_path, &e]))?;
+        let protocols = match device.security_protocol_in(PROTOCOL_LIST, 0, 512) {
+            Ok(protocols) => protocols,
+            Err(_) => return Ok(Status::unsupported()),
+        };
+        // A 2 bytes count at offset 6 then one byte per protocol.
+        let count = match protocols.get(6..8) {
+            Some(count) => u16::from_be_bytes([count[0], count[1]]) as usize,
+            None => return Ok(Status::unsupported()),
+        };
+        let listed = protocols.iter().skip(8).take(count);
+        if !listed.into_iter().any(|p| *p == PROTOCOL_TCG) {
+            return Ok(Status::unsupported());
+        }
+
+        device
+            .security_protocol_in(PROTOCOL_TCG, COMID_DISCOVERY, DISCOVERY_LEN)
+            .map(|data| parse_discovery(&data))
+            .map_err(|e| format!("level 0 discovery failed: {}", e))
+    }
+
+    /// Fills the `sed` field of the disks, the ones that couldn't be
+    /// queried keep None.
+    ///
+    /// # Arguments
+    ///
+    /// * `disks` - the disks to query
+    /// * `jobs` - the disks queried at the same time
+    ///
+    pub fn annotate(disks: &mut [Disk], jobs: usize) {
+        let statuses = Util::parallel_map(disks, jobs, |d| status(&d.device_path));
+        for (disk, status) in disks.iter_mut().zip(statuses) {
+            match status {
+                Ok(status) => disk.sed = Some(status),
+                Err(e) => Output::print_warning(&format!("{}: {}", disk.device_path, e)),
+            }
+        }
+    }
+
+    /// Creates the pretty table for the SED status.
+    fn create_sed_table() -> Table {
+        let mut sed_table = Table::new();
+        sed_table.set_format(*format::consts::FORMAT_NO_BORDER);
+        let header = [
+            "DEVICE", "ENCLOSURE", "SLOT", "SERIAL", "SSC", "ENCRYPTION", "OWNERSHIP", "LOCKED",
+        ];
+        sed_table.add_row(Row::new(
+            header
+                .iter()
+                .map(|h| {
+                    Cell::new(h)
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(color::BLUE))
+                })
+                .collect(),
+        ));
+
+        sed_table
+    }
+
+    /// Returns yes or no.
+    fn yes_no(value: bool) -> &'static str {
+        if value {
+            "yes"
+        } else {
+            "no"
+        }
+    }
+
+    /// Returns an empty Result or an error when a disk couldn't be queried.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `sed status` subcommand
+    ///
+    fn sed_status(options: &ArgMatches) -> Result<(), ()> {
+        let mut scan = DiskShelf::ScanOptions::from_matches(options);
+        scan.fields.temperature = false;
+        scan.fields.firmware = false;
+        let jobs = scan.jobs;
+        let run = Context::Run::new(scan);
+        let mut disks = DiskShelf::jbod_disk_map(&run);
+        DiskShelf::sort_disks(&mut disks);
+        annotate(&mut disks, jobs);
+        let failed = disks.iter().any(|d| d.sed.is_none()) || !run.errors().is_empty();
+
+        let format = Output::get_format(options);
+        if format != Output::Format::Table {
+            Output::render(format, &disks).map_err(|e| Output::print_error(&e))?;
+        } else {
+            let mut sed_table = create_sed_table();
+            for disk in &disks {
+                let (ssc, encryption, ownership, locked) = match &disk.sed {
+                    Some(sed) if sed.ssc.is_some() => (
+                        sed.ssc.clone().unwrap_or_default(),
+                        yes_no(sed.is_sed()),
+                        sed.ownership(),
+                        yes_no(sed.locked),
+                    ),
+                    Some(_) => ("none".to_string(), "no", "-", "-"),
+                    None => ("ERR".to_string(), "-", "-", "-"),
+                };
+                let locked = if locked == "yes" {
+                    Cell::new(locked)
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(color::RED))
+                } else {
+                    Cell::new(locked)
+                };
+                sed_table.add_row(Row::new(vec![
+                    Cell::new(&disk.device_path),
+                    Cell::new(&disk.enclosure),
+                    Cell::new(&disk.slot),
+                    Cell::new(&disk.serial),
+                    Cell::new(&ssc),
+                    Cell::new(encryption),
+                    Cell::new(ownership),
+                    locked,
+                ]));
+            }
+            Output::print_table(&sed_table);
+        }
+
+        let scanned = run.errors();
+        Output::render_errors(format, &scanned);
+        if failed {
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns an empty Result or an error, it dispatches the `sed`
+    /// subcommands.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `sed` subcommand
+    ///
+    pub fn jbod_sed(options: &ArgMatches) -> Result<(), ()> {
+        match options.subcommand() {
+            Some(("status", m)) => sed_status(m),
+            _ => {
+                Output::print_error_code(ErrorCode::InvalidArgument, "missing the view, see jbod sed --help");
+                Err(())
+            }
+        }
+    }
+}
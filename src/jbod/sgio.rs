@@ -0,0 +1,810 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+// The SG_IO ioctl and the buffers of O_DIRECT need unsafe code, each
+// block tells why it holds.
+#[allow(non_snake_case)]
+#[allow(unsafe_code)]
+pub mod SgIo {
+    use nix::fcntl::OFlag;
+    use std::alloc::{alloc_zeroed, dealloc, Layout};
+    use std::fs::{File, OpenOptions};
+    use std::io;
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::io::AsRawFd;
+    use std::time::Duration;
+
+    use crate::utils::helper::Util;
+
+    const SG_INTERFACE_ID: i32 = 'S' as i32;
+    const SG_DXFER_NONE: i32 = -1;
+    const SG_DXFER_TO_DEV: i32 = -2;
+    const SG_DXFER_FROM_DEV: i32 = -3;
+    const SENSE_LEN: usize = 32;
+
+    const TEST_UNIT_READY: u8 = 0x00;
+    const INQUIRY: u8 = 0x12;
+    const WRITE_BUFFER: u8 = 0x3b;
+    const READ_16: u8 = 0x88;
+    const WRITE_16: u8 = 0x8a;
+    const VERIFY_16: u8 = 0x8f;
+    const SERVICE_ACTION_IN_16: u8 = 0x9e;
+    const READ_CAPACITY_16: u8 = 0x10;
+    const ATA_PASS_THROUGH_16: u8 = 0x85;
+    const ATA_IDENTIFY_DEVICE: u8 = 0xec;
+    const ATA_READ_LOG_EXT: u8 = 0x2f;
+    /// The device statistics log and its solid state device page.
+    const ATA_LOG_DEVICE_STATISTICS: u8 = 0x04;
+    const ATA_STATISTICS_SSD: u8 = 0x07;
+    const LOG_SENSE: u8 = 0x4d;
+    const VPD_SERIAL: u8 = 0x80;
+    const VPD_DEVICE_IDENTIFICATION: u8 = 0x83;
+    const LOG_TEMPERATURE: u8 = 0x0d;
+    const LOG_SOLID_STATE_MEDIA: u8 = 0x11;
+    const LOG_INFORMATIONAL_EXCEPTIONS: u8 = 0x2f;
+    const SECURITY_PROTOCOL_IN: u8 = 0xa2;
+    const MODE_SENSE_10: u8 = 0x5a;
+    const MODE_SELECT_10: u8 = 0x55;
+    const REQUEST_SENSE: u8 = 0x03;
+    const START_STOP_UNIT: u8 = 0x1b;
+    /// Size of the MODE SENSE(10) and MODE SELECT(10) parameter header.
+    const MODE_HEADER_LEN: usize = 8;
+
+    /// WRITE BUFFER mode downloading microcode with offsets and saving it,
+    /// the device activates it after the last chunk.
+    pub const MODE_DOWNLOAD_SAVE: u8 = 0x07;
+    /// WRITE BUFFER mode downloading microcode with offsets, saving it and
+    /// deferring its activation.
+    pub const MODE_DOWNLOAD_DEFER: u8 = 0x0e;
+    /// WRITE BUFFER mode activating the deferred microcode.
+    pub const MODE_ACTIVATE_DEFERRED: u8 = 0x0f;
+
+    /// Mode page of the caching parameters, holding WCE.
+    pub const PAGE_CACHING: u8 = 0x08;
+    /// Mode page of the power condition timers.
+    pub const PAGE_POWER_CONDITION: u8 = 0x1a;
+    /// START STOP UNIT power condition moving the disk to standby, it
+    /// becomes active again on the next media access.
+    pub const POWER_STANDBY: u8 = 0x03;
+
+    /// The `sg_io_hdr_t` structure from <scsi/sg.h>.
+    #[repr(C)]
+    struct SgIoHdr {
+        interface_id: i32,
+        dxfer_direction: i32,
+        cmd_len: u8,
+        mx_sb_len: u8,
+        iovec_count: u16,
+        dxfer_len: u32,
+        dxferp: *mut u8,
+        cmdp: *const u8,
+        sbp: *mut u8,
+        timeout: u32,
+        flags: u32,
+        pack_id: i32,
+        usr_ptr: *mut u8,
+        status: u8,
+        masked_status: u8,
+        msg_status: u8,
+        sb_len_wr: u8,
+        host_status: u16,
+        driver_status: u16,
+        resid: i32,
+        duration: u32,
+        info: u32,
+    }
+
+    nix::ioctl_readwrite_bad!(sg_io, 0x2285, SgIoHdr);
+
+    /// A buffer aligned for O_DIRECT, a Vec gives no alignment guarantee.
+    pub struct AlignedBuffer {
+        ptr: *mut u8,
+        layout: Layout,
+    }
+
+    impl AlignedBuffer {
+        /// Returns a zeroed buffer of `len` bytes aligned on `align`.
+        pub fn new(len: usize, align: usize) -> Self {
+            let layout = Layout::from_size_align(len, align).expect("valid buffer layout");
+            assert!(layout.size() > 0, "empty aligned buffer");
+            // SAFETY: the layout has a non zero size, checked above.
+            let ptr = unsafe { alloc_zeroed(layout) };
+            if ptr.is_null() {
+                std::alloc::handle_alloc_error(layout);
+            }
+            AlignedBuffer { ptr, layout }
+        }
+
+        pub fn as_mut_slice(&mut self) -> &mut [u8] {
+            // SAFETY: ptr holds layout.size() initialized bytes owned by the
+            // buffer, the &mut self borrow makes the slice unique.
+            unsafe { std::slice::from_raw_parts_mut(self.ptr, self.layout.size()) }
+        }
+    }
+
+    impl Drop for AlignedBuffer {
+        fn drop(&mut self) {
+            // SAFETY: ptr was allocated with this layout and is freed once.
+            unsafe { dealloc(self.ptr, self.layout) }
+        }
+    }
+
+    /// The identification returned by a standard INQUIRY.
+    #[derive(Debug)]
+    pub struct Inquiry {
+        pub vendor: String,
+        pub model: String,
+        pub revision: String,
+    }
+
+    /// A SCSI device opened once to send several commands through SG_IO.
+    pub struct Device {
+        file: File,
+    }
+
+    /// Returns the ASCII field of a SCSI response trimmed.
+    fn ascii(data: &[u8]) -> String {
+        String::from_utf8_lossy(data)
+            .trim_matches(|c: char| c.is_whitespace() || c == '\0')
+            .to_string()
+    }
+
+    /// Returns the sense key, ASC and ASCQ of sense data, or an empty
+    /// string when there is none.
+    fn sense_summary(sense: &[u8]) -> String {
+        match sense_codes(sense) {
+            Some((key, asc, ascq)) => {
+                format!(", sense key 0x{:x} asc 0x{:02x} ascq 0x{:02x}", key, asc, ascq)
+            }
+            None => String::new(),
+        }
+    }
+
+    /// Returns the sense key, the additional sense code and its qualifier
+    /// of fixed or descriptor sense data, None when there is none.
+    pub fn sense_codes(sense: &[u8]) -> Option<(u8, u8, u8)> {
+        match sense.first().map(|b| b & 0x7f) {
+            Some(0x70) | Some(0x71) if sense.len() >= 14 => Some((sense[2] & 0x0f, sense[12], sense[13])),
+            Some(0x72) | Some(0x73) if sense.len() >= 4 => Some((sense[1] & 0x0f, sense[2], sense[3])),
+            _ => None,
+        }
+    }
+
+    impl Device {
+        /// Returns the Device opened read only, example: /dev/sg105.
+        ///
+        /// It is opened non blocking, the sg driver would otherwise wait on
+        /// a busy device.
+        pub fn open(path: &str) -> io::Result<Device> {
+            let file = OpenOptions::new()
+                .read(true)
+                .custom_flags(OFlag::O_NONBLOCK.bits())
+                .open(path)?;
+            Ok(Device { file })
+        }
+
+        /// Returns the Device opened read write, commands changing the
+        /// device like WRITE BUFFER are refused on a read only node.
+        pub fn open_rw(path: &str) -> io::Result<Device> {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .custom_flags(OFlag::O_NONBLOCK.bits())
+                .open(path)?;
+            Ok(Device { file })
+        }
+
+        /// Returns the number of bytes received for a command reading data
+        /// from the device.
+        ///
+        /// # Arguments
+        ///
+        /// * `cdb` - the SCSI command descriptor block
+        /// * `data` - the buffer receiving the response
+        ///
+        fn read_command(&self, cdb: &[u8], data: &mut [u8]) -> io::Result<usize> {
+            self.read_command_timeout(cdb, data, Util::command_timeout())
+        }
+
+        /// Returns the number of bytes received, like `read_command`, for a
+        /// command that may take longer than the usual timeout.
+        fn read_command_timeout(
+            &self,
+            cdb: &[u8],
+            data: &mut [u8],
+            timeout: Duration,
+        ) -> io::Result<usize> {
+            let len = data.len();
+            let resid = self.command(cdb, SG_DXFER_FROM_DEV, data.as_mut_ptr(), len, timeout)?;
+            Ok(len.saturating_sub(resid))
+        }
+
+        /// Returns an empty Result once a command sending data to the device
+        /// completed.
+        ///
+        /// # Arguments
+        ///
+        /// * `cdb` - the SCSI command descriptor block
+        /// * `data` - the data sent, empty for a command without data
+        /// * `timeout` - how long the device may take
+        ///
+        fn write_command(&self, cdb: &[u8], data: &[u8], timeout: Duration) -> io::Result<()> {
+            let direction = if data.is_empty() { SG_DXFER_NONE } else { SG_DXFER_TO_DEV };
+            // The sg driver only reads from the buffer of a data-out command.
+            self.command(cdb, direction, data.as_ptr() as *mut u8, data.len(), timeout)?;
+            Ok(())
+        }
+
+        /// Returns the residual count of a command sent through SG_IO.
+        fn command(
+            &self,
+            cdb: &[u8],
+            direction: i32,
+            data: *mut u8,
+            len: usize,
+            timeout: Duration,
+        ) -> io::Result<usize> {
+            let mut sense = [0u8; SENSE_LEN];
+            let mut hdr = SgIoHdr {
+                interface_id: SG_INTERFACE_ID,
+                dxfer_direction: direction,
+                cmd_len: cdb.len() as u8,
+                mx_sb_len: SENSE_LEN as u8,
+                iovec_count: 0,
+                dxfer_len: len as u32,
+                dxferp: data,
+                cmdp: cdb.as_ptr(),
+                sbp: sense.as_mut_ptr(),
+                timeout: timeout.as_millis().min(u32::MAX as u128) as u32,
+                flags: 0,
+                pack_id: 0,
+                usr_ptr: std::ptr::null_mut(),
+                status: 0,
+                masked_status: 0,
+                msg_status: 0,
+                sb_len_wr: 0,
+                host_status: 0,
+                driver_status: 0,
+                resid: 0,
+                duration: 0,
+                info: 0,
+            };
+
+            // SAFETY: the fd stays open with self.file, hdr is a complete
+            // sg_io_hdr whose cmdp, dxferp and sbp point to buffers of the
+            // lengths it gives, alive until the ioctl returns.
+            unsafe { sg_io(self.file.as_raw_fd(), &mut hdr) }.map_err(io::Error::from)?;
+
+            if hdr.status != 0 || hdr.host_status != 0 || (hdr.driver_status & 0x07) != 0 {
+                return Err(io::Error::other(format!(
+                    "SCSI command 0x{:02x} failed, status 0x{:02x} host 0x{:02x} driver 0x{:02x}{}",
+                    cdb[0],
+                    hdr.status,
+                    hdr.host_status,
+                    hdr.driver_status,
+                    sense_summary(&sense[..(hdr.sb_len_wr as usize).min(SENSE_LEN)])
+                )));
+            }
+
+            Ok(hdr.resid.max(0) as usize)
+        }
+
+        /// Returns an empty Result once the device is ready, it fails while
+        /// the device is resetting, like after a microcode activation.
+        pub fn test_unit_ready(&self) -> io::Result<()> {
+            let cdb = [TEST_UNIT_READY, 0, 0, 0, 0, 0];
+            self.write_command(&cdb, &[], Util::command_timeout())
+        }
+
+        /// Returns the sense data of REQUEST SENSE, it reports the power
+        /// condition without waking the device up.
+        pub fn request_sense(&self) -> io::Result<Vec<u8>> {
+            let mut data = vec![0u8; SENSE_LEN];
+            let cdb = [REQUEST_SENSE, 0, 0, 0, data.len() as u8, 0];
+            let len = self.read_command(&cdb, &mut data)?;
+            data.truncate(len);
+            Ok(data)
+        }
+
+        /// Returns an empty Result once START STOP UNIT completed.
+        ///
+        /// # Arguments
+        ///
+        /// * `power_condition` - the power condition, 0 to start or stop
+        ///   the device with `start`
+        /// * `start` - the START bit
+        /// * `timeout` - how long the device may take, spinning down may
+        ///   take tens of seconds
+        ///
+        pub fn start_stop_unit(
+            &self,
+            power_condition: u8,
+            start: bool,
+            timeout: Duration,
+        ) -> io::Result<()> {
+            let cdb = [START_STOP_UNIT, 0, 0, 0, (power_condition << 4) | start as u8, 0];
+            self.write_command(&cdb, &[], timeout)
+        }
+
+        /// Returns the number of logical blocks and the logical block size
+        /// from READ CAPACITY(16).
+        pub fn capacity(&self) -> io::Result<(u64, u32)> {
+            let mut data = [0u8; 32];
+            let mut cdb = [0u8; 16];
+            cdb[0] = SERVICE_ACTION_IN_16;
+            cdb[1] = READ_CAPACITY_16;
+            cdb[13] = data.len() as u8;
+            let len = self.read_command(&cdb, &mut data)?;
+            if len < 12 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "short READ CAPACITY response"));
+            }
+
+            let last_lba = u64::from_be_bytes(data[0..8].try_into().unwrap_or_default());
+            let block_size = u32::from_be_bytes(data[8..12].try_into().unwrap_or_default());
+            Ok((last_lba + 1, block_size))
+        }
+
+        /// Returns a 16 bytes CDB addressing `blocks` logical blocks at `lba`.
+        fn cdb16(opcode: u8, lba: u64, blocks: u32) -> [u8; 16] {
+            let mut cdb = [0u8; 16];
+            cdb[0] = opcode;
+            cdb[2..10].copy_from_slice(&lba.to_be_bytes());
+            cdb[10..14].copy_from_slice(&blocks.to_be_bytes());
+            cdb
+        }
+
+        /// Asks the device to check that blocks can be read, no data is
+        /// transferred, with VERIFY(16).
+        ///
+        /// # Arguments
+        ///
+        /// * `lba` - the first logical block
+        /// * `blocks` - the number of logical blocks
+        /// * `timeout` - how long the device may take
+        ///
+        pub fn verify(&self, lba: u64, blocks: u32, timeout: Duration) -> io::Result<()> {
+            self.write_command(&Self::cdb16(VERIFY_16, lba, blocks), &[], timeout)
+        }
+
+        /// Reads logical blocks with READ(16), `data` must hold them all.
+        ///
+        /// # Arguments
+        ///
+        /// * `lba` - the first logical block
+        /// * `blocks` - the number of logical blocks
+        /// * `data` - the buffer receiving the blocks
+        /// * `timeout` - how long the device may take
+        ///
+        pub fn read_blocks(
+            &self,
+            lba: u64,
+            blocks: u32,
+            data: &mut [u8],
+            timeout: Duration,
+        ) -> io::Result<()> {
+            let len = self.read_command_timeout(&Self::cdb16(READ_16, lba, blocks), data, timeout)?;
+            if len < data.len() {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "short READ response"));
+            }
+            Ok(())
+        }
+
+        /// Writes logical blocks with WRITE(16), it destroys their content.
+        ///
+        /// # Arguments
+        ///
+        /// * `lba` - the first logical block
+        /// * `blocks` - the number of logical blocks
+        /// * `data` - the content of the blocks
+        /// * `timeout` - how long the device may take
+        ///
+        pub fn write_blocks(
+            &self,
+            lba: u64,
+            blocks: u32,
+            data: &[u8],
+            timeout: Duration,
+        ) -> io::Result<()> {
+            self.write_command(&Self::cdb16(WRITE_16, lba, blocks), data, timeout)
+        }
+
+        /// Sends a WRITE BUFFER(10).
+        ///
+        /// # Arguments
+        ///
+        /// * `mode` - the mode, example: `MODE_DOWNLOAD_DEFER`
+        /// * `buffer_id` - the buffer, 0 for the microcode of most devices
+        /// * `offset` - the offset of `data` in the buffer
+        /// * `data` - the chunk sent, at most 16 MiB
+        /// * `timeout` - how long the device may take to store the chunk
+        ///
+        pub fn write_buffer(
+            &self,
+            mode: u8,
+            buffer_id: u8,
+            offset: u32,
+            data: &[u8],
+            timeout: Duration,
+        ) -> io::Result<()> {
+            if offset > 0xff_ffff || data.len() > 0xff_ffff {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "WRITE BUFFER offset and length are limited to 24 bits",
+                ));
+            }
+            let offset = offset.to_be_bytes();
+            let len = (data.len() as u32).to_be_bytes();
+            let cdb = [
+                WRITE_BUFFER,
+                mode & 0x1f,
+                buffer_id,
+                offset[1],
+                offset[2],
+                offset[3],
+                len[1],
+                len[2],
+                len[3],
+                0,
+            ];
+            self.write_command(&cdb, data, timeout)
+        }
+
+        /// Returns the standard INQUIRY identification.
+        pub fn inquiry(&self) -> io::Result<Inquiry> {
+            let mut data = [0u8; 96];
+            let cdb = [INQUIRY, 0, 0, 0, data.len() as u8, 0];
+            let len = self.read_command(&cdb, &mut data)?;
+            if len < 36 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "short INQUIRY response"));
+            }
+
+            Ok(Inquiry {
+                vendor: ascii(&data[8..16]),
+                model: ascii(&data[16..32]),
+                revision: ascii(&data[32..36]),
+            })
+        }
+
+        /// Returns the raw content of a VPD page.
+        ///
+        /// # Arguments
+        ///
+        /// * `page` - the VPD page code, example: 0x80
+        ///
+        pub fn vpd_page(&self, page: u8) -> io::Result<Vec<u8>> {
+            let mut data = vec![0u8; 252];
+            let cdb = [INQUIRY, 0x01, page, 0, data.len() as u8, 0];
+            let len = self.read_command(&cdb, &mut data)?;
+            data.truncate(len);
+            Ok(data)
+        }
+
+        /// Returns the unit serial number from the VPD page 0x80.
+        pub fn serial(&self) -> io::Result<String> {
+            let serial = Util::vpd_serial(&self.vpd_page(VPD_SERIAL)?);
+            if serial.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "empty serial number"));
+            }
+            Ok(serial)
+        }
+
+        /// Returns the NAA World Wide Name of the logical unit from the VPD
+        /// page 0x83, example: naa.5000cca26b6a1234
+        pub fn wwn(&self) -> io::Result<String> {
+            let page = self.vpd_page(VPD_DEVICE_IDENTIFICATION)?;
+            // Designators follow the 4 bytes header: code set, association
+            // and type, reserved, length then the identifier.
+            let mut offset = 4;
+            while offset + 4 <= page.len() {
+                let association = (page[offset + 1] >> 4) & 0x03;
+                let designator_type = page[offset + 1] & 0x0f;
+                let len = page[offset + 3] as usize;
+                let end = (offset + 4 + len).min(page.len());
+                if association == 0 && designator_type == 3 && len > 0 {
+                    let naa: String = page[offset + 4..end]
+                        .iter()
+                        .map(|b| format!("{:02x}", b))
+                        .collect();
+                    return Ok(format!("naa.{}", naa));
+                }
+                offset += 4 + len;
+            }
+            Err(io::Error::new(io::ErrorKind::NotFound, "no NAA designator"))
+        }
+
+        /// Returns the serial number from the ATA IDENTIFY DEVICE data, sent
+        /// through an ATA PASS-THROUGH(16) to reach SATA drives behind a SAS
+        /// expander.
+        pub fn ata_serial(&self) -> io::Result<String> {
+            let mut data = [0u8; 512];
+            // PIO data-in, the transfer length is the sector count of 1.
+            let cdb = [
+                ATA_PASS_THROUGH_16, 4 << 1, 0x0e, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0,
+                ATA_IDENTIFY_DEVICE, 0,
+            ];
+            let len = self.read_command(&cdb, &mut data)?;
+            if len < 40 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "short IDENTIFY response"));
+            }
+
+            // Words 10 to 19 hold the serial, each word is big endian.
+            let serial: Vec<u8> = data[20..40].chunks(2).flat_map(|w| [w[1], w[0]]).collect();
+            let serial = ascii(&serial);
+            if serial.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "empty serial number"));
+            }
+            Ok(serial)
+        }
+
+        /// Returns the current temperature in Celsius from the LOG SENSE
+        /// temperature page 0x0d, None when the drive doesn't report it.
+        pub fn temperature(&self) -> io::Result<Option<i32>> {
+            let mut data = [0u8; 64];
+            let cdb = [LOG_SENSE, 0, 0x40 | LOG_TEMPERATURE, 0, 0, 0, 0, 0, data.len() as u8, 0];
+            let len = self.read_command(&cdb, &mut data)?;
+
+            // Parameters follow the 4 bytes header, parameter 0x0000 is the
+            // current temperature, 0xff means it isn't available.
+            let mut offset = 4;
+            while offset + 4 <= len {
+                let code = u16::from_be_bytes([data[offset], data[offset + 1]]);
+                let param_len = data[offset + 3] as usize;
+                if code == 0 && param_len >= 2 && offset + 5 < len {
+                    return match data[offset + 5] {
+                        0xff => Ok(None),
+                        t => Ok(Some(t as i32)),
+                    };
+                }
+                offset += 4 + param_len;
+            }
+
+            Ok(None)
+        }
+
+        /// Returns the additional sense code and its qualifier of the
+        /// failure predicted by the drive (SMART), from the LOG SENSE
+        /// informational exceptions page 0x2f, None when there is none.
+        pub fn informational_exception(&self) -> io::Result<Option<(u8, u8)>> {
+            let mut data = [0u8; 64];
+            let cdb = [
+                LOG_SENSE, 0, 0x40 | LOG_INFORMATIONAL_EXCEPTIONS, 0, 0, 0, 0, 0, data.len() as u8, 0,
+            ];
+            let len = self.read_command(&cdb, &mut data)?;
+
+            // Parameter 0x0000 holds the ASC and ASCQ of the last exception,
+            // an ASC of 0 means no failure is predicted.
+            let mut offset = 4;
+            while offset + 4 <= len {
+                let code = u16::from_be_bytes([data[offset], data[offset + 1]]);
+                let param_len = data[offset + 3] as usize;
+                if code == 0 && param_len >= 2 && offset + 5 < len {
+                    return match (data[offset + 4], data[offset + 5]) {
+                        (0, _) => Ok(None),
+                        codes => Ok(Some(codes)),
+                    };
+                }
+                offset += 4 + param_len;
+            }
+
+            Ok(None)
+        }
+
+        /// Returns the percentage of the rated endurance used from the LOG
+        /// SENSE solid state media page 0x11, None when the drive doesn't
+        /// report it. It goes past 100 once the endurance is exceeded.
+        pub fn percentage_used(&self) -> io::Result<Option<u8>> {
+            let mut data = [0u8; 64];
+            let cdb = [
+                LOG_SENSE, 0, 0x40 | LOG_SOLID_STATE_MEDIA, 0, 0, 0, 0, 0, data.len() as u8, 0,
+            ];
+            let len = self.read_command(&cdb, &mut data)?;
+
+            // Parameter 0x0001 is the percentage used endurance indicator,
+            // its value is the last byte after 3 reserved ones.
+            let mut offset = 4;
+            while offset + 4 <= len {
+                let code = u16::from_be_bytes([data[offset], data[offset + 1]]);
+                let param_len = data[offset + 3] as usize;
+                if code == 1 && param_len >= 4 && offset + 7 < len {
+                    return Ok(Some(data[offset + 7]));
+                }
+                offset += 4 + param_len;
+            }
+
+            Ok(None)
+        }
+
+        /// Returns the percentage used endurance indicator of a SATA SSD from
+        /// the device statistics log, read with READ LOG EXT through an ATA
+        /// PASS-THROUGH(16), None when the drive doesn't report it.
+        pub fn ata_percentage_used(&self) -> io::Result<Option<u8>> {
+            let mut data = [0u8; 512];
+            // 48-bit PIO data-in of one page, the log address goes in the LBA
+            // low and the page number in the LBA mid.
+            let cdb = [
+                ATA_PASS_THROUGH_16, (4 << 1) | 1, 0x0e, 0, 0, 0, 1, 0,
+                ATA_LOG_DEVICE_STATISTICS, 0, ATA_STATISTICS_SSD, 0, 0, 0,
+                ATA_READ_LOG_EXT, 0,
+            ];
+            let len = self.read_command(&cdb, &mut data)?;
+            if len < 16 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "short device statistics page"));
+            }
+
+            // The statistic is the qword at offset 8, its top byte flags it
+            // supported (bit 7) and valid (bit 6).
+            let flags = data[15];
+            if flags & 0xc0 != 0xc0 {
+                return Ok(None);
+            }
+            Ok(Some(data[8]))
+        }
+
+        /// Returns a mode page without the parameter header nor the block
+        /// descriptors, its first byte holds the PS bit and the page code.
+        ///
+        /// # Arguments
+        ///
+        /// * `page` - the mode page code, example: 0x08
+        /// * `control` - the page control, 0 the current values, 1 the
+        ///   changeable ones, 2 the defaults and 3 the saved ones
+        ///
+        pub fn mode_sense(&self, page: u8, control: u8) -> io::Result<Vec<u8>> {
+            let mut data = vec![0u8; 252];
+            // DBD, the block descriptors aren't needed.
+            let cdb = [
+                MODE_SENSE_10,
+                0x08,
+                (control << 6) | (page & 0x3f),
+                0,
+                0,
+                0,
+                0,
+                0,
+                data.len() as u8,
+                0,
+            ];
+            let len = self.read_command(&cdb, &mut data)?;
+            if len < MODE_HEADER_LEN {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "short MODE SENSE response"));
+            }
+            let descriptors = u16::from_be_bytes([data[6], data[7]]) as usize;
+            let start = MODE_HEADER_LEN + descriptors;
+            if len < start + 2 || data[start] & 0x3f != page & 0x3f {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("mode page 0x{:02x} not returned", page),
+                ));
+            }
+            let end = (start + 2 + data[start + 1] as usize).min(len);
+            Ok(data[start..end].to_vec())
+        }
+
+        /// Returns an empty Result once a mode page was sent with MODE
+        /// SELECT(10).
+        ///
+        /// # Arguments
+        ///
+        /// * `page` - the mode page as returned by `mode_sense`
+        /// * `save` - the device also saves it, it survives a power cycle
+        ///
+        pub fn mode_select(&self, page: &[u8], save: bool) -> io::Result<()> {
+            // The mode data length, the medium type and the device specific
+            // parameter are reserved in MODE SELECT, no block descriptor.
+            let mut data = vec![0u8; MODE_HEADER_LEN];
+            data.extend_from_slice(page);
+            // The PS bit is reserved too.
+            data[MODE_HEADER_LEN] &= 0x7f;
+            let len = (data.len() as u16).to_be_bytes();
+            let cdb = [
+                MODE_SELECT_10,
+                0x10 | save as u8,
+                0,
+                0,
+                0,
+                0,
+                0,
+                len[0],
+                len[1],
+                0,
+            ];
+            self.write_command(&cdb, &data, Util::command_timeout())
+        }
+
+        /// Returns true when the write cache of the disk is enabled, from
+        /// the WCE bit of the caching mode page.
+        pub fn write_cache(&self) -> io::Result<bool> {
+            let page = self.mode_sense(PAGE_CACHING, 0)?;
+            match page.get(2) {
+                Some(flags) => Ok(flags & 0x04 != 0),
+                None => Err(io::Error::new(io::ErrorKind::InvalidData, "short caching page")),
+            }
+        }
+
+        /// Returns an empty Result once the write cache of the disk is
+        /// enabled or disabled, saved when the disk can save the caching
+        /// page.
+        ///
+        /// # Arguments
+        ///
+        /// * `enable` - the WCE bit to set
+        ///
+        pub fn set_write_cache(&self, enable: bool) -> io::Result<()> {
+            let mut page = self.mode_sense(PAGE_CACHING, 0)?;
+            if page.len() < 3 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "short caching page"));
+            }
+            let saveable = page[0] & 0x80 != 0;
+            if enable {
+                page[2] |= 0x04;
+            } else {
+                page[2] &= !0x04;
+            }
+            self.mode_select(&page, saveable)
+        }
+
+        /// Returns the data of a SECURITY PROTOCOL IN command.
+        ///
+        /// # Arguments
+        ///
+        /// * `protocol` - the security protocol, 0x00 lists the supported
+        ///   ones and 0x01 is TCG
+        /// * `specific` - the protocol specific field, the ComID for TCG
+        /// * `len` - the allocation length
+        ///
+        pub fn security_protocol_in(
+            &self,
+            protocol: u8,
+            specific: u16,
+            len: u32,
+        ) -> io::Result<Vec<u8>> {
+            let mut data = vec![0u8; len as usize];
+            let specific = specific.to_be_bytes();
+            let len = len.to_be_bytes();
+            let cdb = [
+                SECURITY_PROTOCOL_IN,
+                protocol,
+                specific[0],
+                specific[1],
+                0,
+                0,
+                len[0],
+                len[1],
+                len[2],
+                len[3],
+                0,
+                0,
+            ];
+            let received = self.read_command(&cdb, &mut data)?;
+            data.truncate(received);
+            Ok(data)
+        }
+    }
+}
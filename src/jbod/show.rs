@@ -0,0 +1,220 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Show {
+    use clap::ArgMatches;
+    use serde::Serialize;
+    use std::path::Path;
+
+    use crate::jbod::context::Context;
+    use crate::jbod::disks::DiskShelf;
+    use crate::jbod::disks::DiskShelf::{Disk, Temperature};
+    use crate::jbod::element::Element::LedState;
+    use crate::jbod::health::Health;
+    use crate::jbod::resolver::Resolver;
+    use crate::jbod::resolver::Resolver::Identifier;
+    use crate::jbod::standby::Standby;
+    use crate::jbod::wear::Wear;
+    use crate::jbod::write_cache::WriteCache;
+    use crate::utils::i18n::I18n;
+    use crate::utils::i18n::I18n::Message;
+    use crate::utils::output::Output;
+    use crate::utils::output::Output::ErrorCode;
+
+    use prettytable::{color, format, Attr, Cell, Row, Table};
+
+    /// Everything known about a disk.
+    #[derive(Debug, Serialize)]
+    pub struct Details {
+        #[serde(flatten)]
+        pub disk: Disk,
+        /// State of the locate led, None when the slot has no such led
+        pub locate: Option<String>,
+        /// State of the fault led, None when the slot has no such led
+        pub fault: Option<String>,
+        /// What is wrong with the disk, see `Health::disk_findings()`
+        pub problems: Vec<Health::Finding>,
+    }
+
+    /// Returns the state of a led file, "unreadable" when it can't be read.
+    fn led_state(path: &Option<String>) -> Option<String> {
+        path.as_ref().map(|p| match LedState::read(Path::new(p)) {
+            Ok(state) => state.to_string(),
+            Err(_) => "unreadable".to_string(),
+        })
+    }
+
+    /// Returns the temperature written like the disk tree of `jbod list`.
+    fn temperature(temperature: &Temperature) -> String {
+        match temperature {
+            Temperature::Celsius(t) => format!("{}c", t),
+            Temperature::Unsupported => "N/A".to_string(),
+            Temperature::Unreadable => "ERR".to_string(),
+            Temperature::NotCollected => "-".to_string(),
+        }
+    }
+
+    /// Returns a led written as its state and its file.
+    fn led(state: &Option<String>, path: &Option<String>) -> String {
+        match (state, path) {
+            (Some(state), Some(path)) => format!("{} ({})", state, path),
+            _ => "not exposed".to_string(),
+        }
+    }
+
+    /// Returns the disk designated by the identifier with the fields
+    /// collected on request filled, or None.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `show` subcommand
+    /// * `identifier` - a device node, a serial, a WWN or `<enclosure>/<slot>`
+    ///
+    fn find_disk(options: &ArgMatches, identifier: &str) -> Option<Disk> {
+        let mut scan = DiskShelf::ScanOptions::from_matches(options);
+        // Only the enclosure of a slot is scanned.
+        if let Identifier::Slot { enclosure, .. } = Resolver::parse(identifier) {
+            scan.enclosure = Some(enclosure);
+        }
+        let jobs = scan.jobs;
+        let run = Context::Run::new(scan);
+        let disks = DiskShelf::jbod_disk_map(&run);
+        let mut disk = vec![Resolver::resolve(identifier, &disks)?.clone()];
+
+        Standby::annotate(&mut disk, jobs);
+        WriteCache::annotate(&mut disk, jobs);
+        Wear::annotate(&mut disk, jobs, true);
+        Health::annotate_predicted_failures(&mut disk, jobs);
+        disk.pop()
+    }
+
+    /// Creates the pretty table of the details, a row per field.
+    fn create_show_table(details: &Details) -> Table {
+        let disk = &details.disk;
+        let mut rows = vec![
+            ("Enclosure", disk.enclosure.clone()),
+            (
+                "Slot",
+                format!("{}{}", disk.slot, DiskShelf::slot_label_suffix(&disk.label)),
+            ),
+            ("Device", disk.device_path.clone()),
+            ("Map", disk.device_map.clone()),
+        ];
+        if !disk.alternate_paths.is_empty() {
+            rows.push(("Paths", disk.alternate_paths.join(",")));
+        }
+        if let Some(role) = &disk.role {
+            rows.push(("Role", role.to_string()));
+        }
+        rows.extend([
+            ("Vendor", disk.vendor.clone()),
+            ("Model", disk.model.clone()),
+            ("Serial", disk.serial.clone()),
+        ]);
+        if !disk.wwn.is_empty() {
+            rows.push(("WWN", disk.wwn.clone()));
+        }
+        if !disk.sas_address.is_empty() {
+            rows.push(("SAS address", disk.sas_address.clone()));
+        }
+        rows.extend([
+            ("Firmware", disk.fw_revision.clone()),
+            ("Temperature", temperature(&disk.temperature)),
+            ("Status", disk.status.to_string()),
+            ("Locate led", led(&details.locate, &disk.led_locate_path)),
+            ("Fault led", led(&details.fault, &disk.led_fault_path)),
+        ]);
+        if let Some(power) = &disk.power {
+            rows.push(("Power", power.to_string()));
+        }
+        if let Some(write_cache) = disk.write_cache {
+            let state = if write_cache { "enabled" } else { "disabled" };
+            rows.push(("Write cache", state.to_string()));
+        }
+        if let Some(wear) = disk.wear {
+            rows.push(("Wear", format!("{}%", wear)));
+        }
+        if let Some(failure) = &disk.predicted_failure {
+            rows.push(("Predicted failure", failure.clone()));
+        }
+        if !details.problems.is_empty() {
+            let kinds: Vec<&str> = details.problems.iter().map(|f| f.kind).collect();
+            rows.push(("Problems", kinds.join(",")));
+        }
+
+        let mut show_table = Table::new();
+        show_table.set_format(*format::consts::FORMAT_NO_BORDER);
+        for (name, value) in rows {
+            show_table.add_row(Row::new(vec![
+                Cell::new(name)
+                    .with_style(Attr::Bold)
+                    .with_style(Attr::ForegroundColor(color::BLUE)),
+                Cell::new(&value),
+            ]));
+        }
+
+        show_table
+    }
+
+    /// Returns an empty Result or an error when the disk isn't found.
+    ///
+    /// This function is used in the `show` menu option, it prints
+    /// everything known about a single disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `show` subcommand
+    ///
+    pub fn jbod_show(options: &ArgMatches) -> Result<(), ()> {
+        let identifier = options.value_of("device").unwrap_or_default();
+        let disk = find_disk(options, identifier).ok_or_else(|| {
+            Output::print_error_code(
+                ErrorCode::NotFound,
+                &I18n::tr(Message::NotADisk, &[&identifier]),
+            )
+        })?;
+        let details = Details {
+            locate: led_state(&disk.led_locate_path),
+            fault: led_state(&disk.led_fault_path),
+            problems: Health::disk_findings(&disk),
+            disk,
+        };
+
+        let format = Output::get_format(options);
+        if format != Output::Format::Table {
+            Output::render(format, std::slice::from_ref(&details))
+                .map_err(|e| Output::print_error(&e))?;
+        } else {
+            Output::print_table(&create_show_table(&details));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,244 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Spares {
+    use serde::Serialize;
+    use std::fmt;
+    use std::fs;
+    use std::path::Path;
+
+    use crate::jbod::context::Context;
+    use crate::jbod::disks::DiskShelf;
+    use crate::jbod::disks::DiskShelf::Disk;
+    use crate::utils::config::Config;
+    use crate::utils::config::Config::Role;
+    use crate::utils::helper::Util;
+
+    /// What became of a designated spare.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum SpareState {
+        /// The disk is there and nothing uses it
+        Standby,
+        /// The disk is held by md or dm, mounted or in use by a ZFS pool
+        InUse,
+        /// No disk has the serial number, or the slot is empty
+        Missing,
+    }
+
+    impl fmt::Display for SpareState {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                SpareState::Standby => write!(f, "in standby"),
+                SpareState::InUse => write!(f, "in use"),
+                SpareState::Missing => write!(f, "missing"),
+            }
+        }
+    }
+
+    /// A spare designated in the configuration, by slot or by serial number.
+    #[derive(Debug, Clone, Serialize)]
+    pub struct Spare {
+        /// How the configuration names it, example: "shelf-a slot 23"
+        pub designation: String,
+        /// Enclosure number identification, empty when the disk is missing
+        pub enclosure: String,
+        pub slot: String,
+        pub serial: String,
+        /// Disk map, example: /dev/sdcz
+        pub device_map: String,
+        pub state: SpareState,
+        /// Why the spare is in use or missing
+        pub reason: String,
+    }
+
+    impl Spare {
+        /// Returns the Spare of a designation given the disk found for it.
+        fn new(designation: String, disk: Option<&Disk>, zfs_in_use: &[String]) -> Self {
+            let disk = match disk {
+                Some(disk) => disk,
+                None => {
+                    return Spare {
+                        designation,
+                        enclosure: String::new(),
+                        slot: String::new(),
+                        serial: String::new(),
+                        device_map: String::new(),
+                        state: SpareState::Missing,
+                        reason: "no disk found".to_string(),
+                    }
+                }
+            };
+            let reason = used_by(disk, zfs_in_use);
+            Spare {
+                designation,
+                enclosure: disk.enclosure.clone(),
+                slot: disk.slot.clone(),
+                serial: disk.serial.clone(),
+                device_map: disk.device_map.clone(),
+                state: if reason.is_some() {
+                    SpareState::InUse
+                } else {
+                    SpareState::Standby
+                },
+                reason: reason.unwrap_or_default(),
+            }
+        }
+    }
+
+    /// Returns true when the configuration designates at least one spare.
+    pub fn configured() -> bool {
+        let settings = Config::get();
+        settings.roles.values().any(|r| *r == Role::Spare)
+            || settings
+                .chassis
+                .iter()
+                .any(|c| c.roles.values().any(|r| *r == Role::Spare))
+    }
+
+    /// Returns what uses a disk, None when it is idle.
+    ///
+    /// A spare may carry partitions or a ZFS label while waiting so only
+    /// holders, mounts and the ZFS spares marked INUSE count.
+    ///
+    /// # Arguments
+    ///
+    /// * `disk` - the disk
+    /// * `zfs_in_use` - the devices of the ZFS spares in use, see `zfs_spares_in_use`
+    ///
+    fn used_by(disk: &Disk, zfs_in_use: &[String]) -> Option<String> {
+        let name = disk.device_map.strip_prefix("/dev/")?;
+        let sysfs_block = Util::sysfs_path("block").join(name);
+
+        // The disk itself then each of its partitions.
+        let mut devices = vec![name.to_string()];
+        if let Ok(entries) = fs::read_dir(&sysfs_block) {
+            devices.extend(
+                entries
+                    .flatten()
+                    .map(|e| e.file_name().to_string_lossy().to_string())
+                    .filter(|n| n.starts_with(name)),
+            );
+        }
+
+        let mounts = fs::read_to_string("/proc/mounts").unwrap_or_default();
+        for device in &devices {
+            let dir = if device == name {
+                sysfs_block.clone()
+            } else {
+                sysfs_block.join(device)
+            };
+            if let Some(holder) = fs::read_dir(dir.join("holders"))
+                .ok()
+                .and_then(|mut d| d.next())
+                .and_then(|e| e.ok())
+            {
+                return Some(format!(
+                    "/dev/{} is held by {}",
+                    device,
+                    holder.file_name().to_string_lossy()
+                ));
+            }
+            let node = format!("/dev/{}", device);
+            if mounts
+                .lines()
+                .any(|l| l.split_whitespace().next() == Some(node.as_str()))
+            {
+                return Some(format!("{} is mounted", node));
+            }
+            if zfs_in_use.contains(&node) {
+                return Some(format!("{} replaces a disk of a ZFS pool", node));
+            }
+        }
+        None
+    }
+
+    /// Returns the devices of the ZFS spares in use, resolved to their
+    /// /dev node, empty when ZFS isn't installed.
+    ///
+    /// `zpool status -P` lists the spares with their full path:
+    ///
+    /// ```text
+    ///         spares
+    ///           /dev/disk/by-id/wwn-0x5000cca26b6a1234-part1    INUSE     currently in use
+    /// ```
+    ///
+    fn zfs_spares_in_use() -> Vec<String> {
+        if !Util::path_exists(Util::ZPOOL) {
+            return Vec::new();
+        }
+        Util::command_stdout(Util::ZPOOL, &["status", "-P"])
+            .lines()
+            .filter(|l| l.contains("INUSE"))
+            .filter_map(|l| l.split_whitespace().next())
+            .filter_map(|path| fs::canonicalize(Path::new(path)).ok())
+            .map(|path| path.to_string_lossy().to_string())
+            .collect()
+    }
+
+    /// Returns the state of every spare designated in the configuration,
+    /// the ones designated by slot first in enclosure order.
+    ///
+    /// # Arguments
+    ///
+    /// * `run` - the run Context, the disks need their serial number
+    ///
+    pub fn spares(run: &Context::Run) -> Vec<Spare> {
+        let settings = Config::get();
+        let mut disks = DiskShelf::jbod_disk_map(run);
+        DiskShelf::sort_disks(&mut disks);
+        let zfs_in_use = zfs_spares_in_use();
+
+        let mut spares = Vec::new();
+        for enclosure in DiskShelf::jbod_enclosures(run) {
+            let name = settings
+                .enclosure_alias(&enclosure.serial, &enclosure.model)
+                .unwrap_or(&enclosure.slot)
+                .to_string();
+            for slot in settings.slots_with_role(&enclosure.serial, &enclosure.model, Role::Spare) {
+                let disk = disks.iter().find(|d| {
+                    d.enclosure == enclosure.slot
+                        && Util::slot_number(&d.slot).map(|n| n.to_string()) == Some(slot.clone())
+                });
+                spares.push(Spare::new(
+                    format!("{} slot {}", name, slot),
+                    disk,
+                    &zfs_in_use,
+                ));
+            }
+        }
+        for serial in settings.serials_with_role(Role::Spare) {
+            let disk = disks.iter().find(|d| d.serial == serial);
+            spares.push(Spare::new(format!("serial {}", serial), disk, &zfs_in_use));
+        }
+        spares
+    }
+}
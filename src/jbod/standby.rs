@@ -0,0 +1,292 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Standby {
+    use clap::ArgMatches;
+    use colored::Colorize;
+    use serde::{Deserialize, Serialize};
+    use std::fmt;
+    use std::time::Duration;
+
+    use crate::jbod::context::Context;
+    use crate::jbod::disks::DiskShelf;
+    use crate::jbod::disks::DiskShelf::Disk;
+    use crate::jbod::resolver::Resolver;
+    use crate::jbod::sgio::SgIo;
+    use crate::utils::audit::Audit;
+    use crate::utils::helper::Util;
+    use crate::utils::i18n::I18n;
+    use crate::utils::i18n::I18n::Message;
+    use crate::utils::output::Output;
+    use crate::utils::output::Output::ErrorCode;
+
+    /// How long a disk may take to spin down.
+    const SPIN_DOWN_TIMEOUT: Duration = Duration::from_secs(60);
+    /// The power condition timers count tenths of a second.
+    const TIMER_UNIT_PER_SECOND: u64 = 10;
+    const SENSE_NOT_READY: u8 = 0x02;
+    const ASC_NOT_READY: u8 = 0x04;
+    const ASC_LOW_POWER: u8 = 0x5e;
+
+    /// The power condition of a disk.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Power {
+        /// Spinning and serving
+        Active,
+        /// Heads unloaded or slower, still spinning
+        Idle,
+        /// Spun down, the next access spins it up
+        Standby,
+        /// Stopped, it needs a START UNIT
+        Stopped,
+    }
+
+    impl fmt::Display for Power {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let power = match self {
+                Power::Active => "active",
+                Power::Idle => "idle",
+                Power::Standby => "standby",
+                Power::Stopped => "stopped",
+            };
+            write!(f, "{}", power)
+        }
+    }
+
+    /// Returns the Power from the sense data of REQUEST SENSE.
+    ///
+    /// A disk in a low power condition reports it with the additional
+    /// sense code 0x5e, a stopped disk is not ready and needs a START UNIT.
+    ///
+    /// # Arguments
+    ///
+    /// * `sense` - the fixed or descriptor sense data
+    ///
+    pub fn parse_sense(sense: &[u8]) -> Power {
+        match SgIo::sense_codes(sense) {
+            Some((SENSE_NOT_READY, ASC_NOT_READY, 0x02)) => Power::Stopped,
+            Some((_, ASC_LOW_POWER, ascq)) => match ascq {
+                0x02 | 0x04 | 0x09 | 0x0a | 0x43 => Power::Standby,
+                0x41 => Power::Active,
+                _ => Power::Idle,
+            },
+            _ => Power::Active,
+        }
+    }
+
+    /// Returns the Power of a disk, asking for it doesn't spin it up.
+    ///
+    /// # Arguments
+    ///
+    /// * `device_path` - the sg node of the disk, example: /dev/sg12
+    ///
+    pub fn status(device_path: &str) -> Result<Power, String> {
+        let device = SgIo::Device::open(device_path)
+            .map_err(|e| I18n::tr(Message::CannotOpen, &[&device_path, &e]))?;
+        device
+            .request_sense()
+            .map(|sense| parse_sense(&sense))
+            .map_err(|e| format!("REQUEST SENSE failed: {}", e))
+    }
+
+    /// Fills the `power` field of the disks, the ones that couldn't be
+    /// queried keep None.
+    ///
+    /// # Arguments
+    ///
+    /// * `disks` - the disks to query
+    /// * `jobs` - the disks queried at the same time
+    ///
+    pub fn annotate(disks: &mut [Disk], jobs: usize) {
+        let statuses = Util::parallel_map(disks, jobs, |d| status(&d.device_path));
+        for (disk, status) in disks.iter_mut().zip(statuses) {
+            match status {
+                Ok(power) => disk.power = Some(power),
+                Err(e) => Output::print_warning(&format!("{}: {}", disk.device_path, e)),
+            }
+        }
+    }
+
+    /// Returns an empty Result once the disk is spun down.
+    ///
+    /// The SAT layer only translates a plain stop, into STANDBY IMMEDIATE,
+    /// a SAS disk is moved to the standby power condition instead since a
+    /// stopped one would fail the I/O until it gets a START UNIT.
+    fn spin_down(disk: &Disk) -> Result<(), String> {
+        let device = SgIo::Device::open_rw(&disk.device_path)
+            .map_err(|e| I18n::tr(Message::CannotOpen, &[&disk.device_path, &e]))?;
+        let result = if disk.vendor.trim() == "ATA" {
+            device.start_stop_unit(0, false, SPIN_DOWN_TIMEOUT)
+        } else {
+            device.start_stop_unit(SgIo::POWER_STANDBY, false, SPIN_DOWN_TIMEOUT)
+        };
+        result.map_err(|e| format!("START STOP UNIT failed: {}", e))
+    }
+
+    /// Returns the previous standby timer in seconds, 0 when it was
+    /// disabled, once the standby_z timer of the power condition mode
+    /// page is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `disk` - the disk to set
+    /// * `seconds` - the idle time before the disk spins down, 0 disables
+    ///   the timer
+    ///
+    fn set_timer(disk: &Disk, seconds: u64) -> Result<u64, String> {
+        let device = SgIo::Device::open_rw(&disk.device_path)
+            .map_err(|e| I18n::tr(Message::CannotOpen, &[&disk.device_path, &e]))?;
+        let mut page = device
+            .mode_sense(SgIo::PAGE_POWER_CONDITION, 0)
+            .map_err(|e| format!("cannot read the power condition page: {}", e))?;
+        if page.len() < 12 {
+            return Err("short power condition page".to_string());
+        }
+        let before = if page[3] & 0x01 != 0 {
+            u32::from_be_bytes([page[8], page[9], page[10], page[11]]) as u64
+                / TIMER_UNIT_PER_SECOND
+        } else {
+            0
+        };
+
+        let timer = (seconds * TIMER_UNIT_PER_SECOND).min(u32::MAX as u64) as u32;
+        if seconds == 0 {
+            page[3] &= !0x01;
+        } else {
+            page[3] |= 0x01;
+        }
+        page[8..12].copy_from_slice(&timer.to_be_bytes());
+        let saveable = page[0] & 0x80 != 0;
+        device
+            .mode_select(&page, saveable)
+            .map_err(|e| format!("MODE SELECT failed: {}", e))?;
+        Ok(before)
+    }
+
+    /// Returns the timer shown to a human.
+    fn timer(seconds: u64) -> String {
+        if seconds == 0 {
+            "disabled".to_string()
+        } else {
+            format!("{}s", seconds)
+        }
+    }
+
+    /// Returns an empty Result or an error when a disk wasn't spun down or
+    /// its timer wasn't set.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `standby` subcommand
+    ///
+    pub fn jbod_standby(options: &ArgMatches) -> Result<(), ()> {
+        let mut scan = DiskShelf::ScanOptions::from_matches(options);
+        scan.fields.temperature = false;
+        scan.fields.firmware = false;
+        let run = Context::Run::new(scan);
+        let mut disks = DiskShelf::jbod_disk_map(&run);
+        DiskShelf::sort_disks(&mut disks);
+        for error in run.errors() {
+            Output::print_warning(&error.to_string());
+        }
+
+        let targets: Vec<Disk> = match options.value_of("device") {
+            Some(device) => match Resolver::resolve(device, &disks) {
+                Some(disk) => vec![disk.clone()],
+                None => {
+                    Output::print_error_code(
+                        ErrorCode::NotFound,
+                        &I18n::tr(Message::NotADisk, &[&device]),
+                    );
+                    return Err(());
+                }
+            },
+            None => disks,
+        };
+        if targets.is_empty() {
+            Output::print_error_code(ErrorCode::NotFound, "no disk to spin down");
+            return Err(());
+        }
+
+        let seconds: Option<u64> = options.value_of("timer").and_then(|t| t.parse().ok());
+        let results: Vec<Result<String, String>> =
+            Util::parallel_map(&targets, run.options.jobs, |disk| {
+                let target = format!("disk {} {}", disk.device_path, disk.serial);
+                match seconds {
+                    Some(seconds) => {
+                        let result = set_timer(disk, seconds);
+                        Audit::record(
+                            "standby_timer",
+                            &target,
+                            result.as_ref().ok().map(|before| timer(*before)).as_deref(),
+                            &timer(seconds),
+                            result.as_ref().map(|_| ()).map_err(|e| e.as_str()),
+                        );
+                        result.map(|before| {
+                            format!(
+                                "timer {} -> {}",
+                                timer(before).yellow().bold(),
+                                timer(seconds).green().bold()
+                            )
+                        })
+                    }
+                    None => {
+                        let result = spin_down(disk);
+                        Audit::record(
+                            "standby",
+                            &target,
+                            None,
+                            "standby",
+                            result.as_ref().map(|_| ()).map_err(|e| e.as_str()),
+                        );
+                        result.map(|_| Power::Standby.to_string().green().bold().to_string())
+                    }
+                }
+            });
+
+        let mut failed = 0;
+        for (disk, result) in targets.iter().zip(results) {
+            match result {
+                Ok(done) => println!("{} {}", disk.device_path.bold(), done),
+                Err(e) => {
+                    failed += 1;
+                    Output::print_error(&format!("{}: {}", disk.device_path, e));
+                }
+            }
+        }
+        if failed > 0 {
+            Output::print_error(&format!("{} of {} disks failed", failed, targets.len()));
+            return Err(());
+        }
+        Ok(())
+    }
+}
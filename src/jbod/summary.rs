@@ -0,0 +1,294 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Summary {
+    use clap::ArgMatches;
+    use colored::*;
+    use serde::Serialize;
+
+    use crate::jbod::context::Context;
+    use crate::jbod::disks::DiskShelf;
+    use crate::jbod::element::Element::SlotStatus;
+    use crate::jbod::enclosure::BackPlane;
+    use crate::jbod::health::Health;
+    use crate::jbod::map::ChassisMap;
+    use crate::jbod::map::ChassisMap::BayState;
+    use crate::utils::config::Config;
+    use crate::utils::output::Output;
+
+    extern crate prettytable;
+    use prettytable::{color, format, Attr, Cell, Row, Table};
+
+    /// The elements of a kind, fans or power supplies, and how many work.
+    #[derive(Debug, Default, Clone, Copy, Serialize)]
+    pub struct Elements {
+        pub total: usize,
+        pub ok: usize,
+        pub failed: usize,
+    }
+
+    impl Elements {
+        fn add(&mut self, status: SlotStatus) {
+            self.total += 1;
+            if status == SlotStatus::Ok {
+                self.ok += 1;
+            } else if status.is_failed() {
+                self.failed += 1;
+            }
+        }
+    }
+
+    /// One line of the summary.
+    #[derive(Debug, Serialize)]
+    pub struct EnclosureSummary {
+        pub enclosure: String,
+        pub serial: String,
+        pub model: String,
+        pub location: Option<String>,
+        /// The SES summary status of the enclosure.
+        pub status: SlotStatus,
+        pub slots: usize,
+        pub disks: usize,
+        /// The lowest and highest disk temperatures in Celsius.
+        pub temperature_min: Option<i32>,
+        pub temperature_max: Option<i32>,
+        pub fans: Elements,
+        pub power_supplies: Elements,
+        /// The slots with the locate led on.
+        pub locate: Vec<String>,
+        /// The slots with the fault led on or failed.
+        pub fault: Vec<String>,
+    }
+
+    /// The summary of the JBOD, the enclosures and the findings of Health.
+    #[derive(Debug, Serialize)]
+    pub struct Dashboard {
+        pub health: String,
+        pub enclosures: Vec<EnclosureSummary>,
+        pub alerts: Vec<Health::Finding>,
+    }
+
+    /// Returns the summary of the enclosures and disks of a run.
+    ///
+    /// # Arguments
+    ///
+    /// * `run` - the run Context, the disks need their temperature and serial
+    ///
+    pub fn summarize(run: &Context::Run) -> Dashboard {
+        let jobs = run.options.jobs;
+        let enclosures = DiskShelf::jbod_enclosures(run);
+        let mut disks = DiskShelf::jbod_disk_map(run);
+        DiskShelf::sort_disks(&mut disks);
+        let report = Health::evaluate_scanned(run, &enclosures, &mut disks);
+        let elements = BackPlane::get_enclosure_elements(&enclosures, jobs);
+        let statuses = BackPlane::get_enclosure_health(&enclosures, jobs);
+
+        let summaries = enclosures
+            .iter()
+            .map(|enclosure| {
+                let bays = ChassisMap::bays(enclosure);
+                let slots_in = |state: BayState| -> Vec<String> {
+                    bays.iter()
+                        .filter(|b| b.state == state)
+                        .map(|b| b.slot.clone())
+                        .collect()
+                };
+                let temperatures: Vec<i32> = disks
+                    .iter()
+                    .filter(|d| d.enclosure == enclosure.slot)
+                    .filter_map(|d| match d.temperature {
+                        DiskShelf::Temperature::Celsius(t) => Some(t),
+                        _ => None,
+                    })
+                    .collect();
+
+                let mut fans = Elements::default();
+                let mut power_supplies = Elements::default();
+                // The overall element of each type, index -1, is skipped.
+                for element in elements
+                    .iter()
+                    .filter(|e| e.slot == enclosure.slot && !e.index.ends_with(",-1"))
+                {
+                    match element.element_type.as_str() {
+                        "Cooling" => fans.add(element.status),
+                        "Power supply" => power_supplies.add(element.status),
+                        _ => {}
+                    }
+                }
+
+                EnclosureSummary {
+                    enclosure: enclosure.slot.clone(),
+                    serial: enclosure.serial.clone(),
+                    model: enclosure.model.clone(),
+                    location: enclosure.location.as_ref().map(|l| l.to_string()),
+                    status: statuses
+                        .iter()
+                        .find(|h| h.slot == enclosure.slot)
+                        .map(|h| h.status)
+                        .unwrap_or(SlotStatus::Unknown),
+                    slots: bays.len(),
+                    disks: disks
+                        .iter()
+                        .filter(|d| d.enclosure == enclosure.slot)
+                        .count(),
+                    temperature_min: temperatures.iter().min().copied(),
+                    temperature_max: temperatures.iter().max().copied(),
+                    fans,
+                    power_supplies,
+                    locate: slots_in(BayState::Locate),
+                    fault: slots_in(BayState::Fault),
+                }
+            })
+            .collect();
+
+        Dashboard {
+            health: report.oneline(),
+            enclosures: summaries,
+            alerts: report.findings,
+        }
+    }
+
+    /// Returns a cell with the working elements out of all, red when one
+    /// failed.
+    fn elements_cell(elements: &Elements) -> Cell {
+        let text = format!("{}/{}", elements.ok, elements.total);
+        if elements.failed > 0 {
+            Cell::new(&text).with_style(Attr::ForegroundColor(color::RED))
+        } else {
+            Cell::new(&text)
+        }
+    }
+
+    /// Returns the slots of a led, `-` when there is none.
+    fn slots_cell(slots: &[String]) -> Cell {
+        if slots.is_empty() {
+            Cell::new("-")
+        } else {
+            Cell::new(&slots.join(",")).with_style(Attr::ForegroundColor(color::YELLOW))
+        }
+    }
+
+    /// Creates the pretty table for the enclosures.
+    fn create_summary_table() -> Table {
+        let mut summary_table = Table::new();
+        summary_table.set_format(*format::consts::FORMAT_NO_BORDER);
+        let header = [
+            "ENCLOSURE",
+            "SERIAL",
+            "MODEL",
+            "LOCATION",
+            "STATUS",
+            "DISKS",
+            "TEMP",
+            "FANS",
+            "PSUS",
+            "LOCATE",
+            "FAULT",
+        ];
+        summary_table.add_row(Row::new(
+            header
+                .iter()
+                .map(|h| {
+                    Cell::new(h)
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(color::BLUE))
+                })
+                .collect(),
+        ));
+
+        summary_table
+    }
+
+    /// Returns an empty Result once the summary is printed.
+    ///
+    /// This function is used in the `summary` menu option, one line per
+    /// enclosure and the outstanding alerts of `jbod status`.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches
+    ///
+    pub fn jbod_summary(options: &ArgMatches) -> Result<(), ()> {
+        let mut scan = DiskShelf::ScanOptions::from_matches(options);
+        scan.fields.firmware = false;
+        let run = Context::Run::new(scan);
+        let dashboard = summarize(&run);
+
+        let format = Output::get_format(options);
+        if format != Output::Format::Table {
+            return Output::render_document(format, &dashboard)
+                .map_err(|e| Output::print_error(&e));
+        }
+
+        let unit = Config::get().temperature_unit;
+        println!("{}", dashboard.health.bold());
+        println!();
+        let mut summary_table = create_summary_table();
+        for enclosure in &dashboard.enclosures {
+            let status = match enclosure.status {
+                SlotStatus::Ok => Cell::new("OK").with_style(Attr::ForegroundColor(color::GREEN)),
+                status if status.is_failed() => {
+                    Cell::new(&status.to_string()).with_style(Attr::ForegroundColor(color::RED))
+                }
+                status => Cell::new(&status.to_string()),
+            };
+            let temperature = match (enclosure.temperature_min, enclosure.temperature_max) {
+                (Some(min), Some(max)) => format!(
+                    "{}-{}{}",
+                    unit.convert(min),
+                    unit.convert(max),
+                    unit.symbol()
+                ),
+                _ => "-".to_string(),
+            };
+            summary_table.add_row(Row::new(vec![
+                Cell::new(&enclosure.enclosure),
+                Cell::new(&enclosure.serial),
+                Cell::new(&enclosure.model),
+                Cell::new(enclosure.location.as_deref().unwrap_or("-")),
+                status,
+                Cell::new(&format!("{}/{}", enclosure.disks, enclosure.slots)),
+                Cell::new(&temperature),
+                elements_cell(&enclosure.fans),
+                elements_cell(&enclosure.power_supplies),
+                slots_cell(&enclosure.locate),
+                slots_cell(&enclosure.fault),
+            ]));
+        }
+        Output::print_table(&summary_table);
+
+        if !dashboard.alerts.is_empty() {
+            println!();
+            Health::print_findings(&dashboard.alerts);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,235 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+//! Discovery against the sysfs trees of tests/fixtures/sysfs, each one
+//! reproduces the layout the kernel exposes for a shelf model.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::jbod::cache::Cache;
+use crate::jbod::context::Context;
+use crate::jbod::disks::DiskShelf::{self, Fields, ScanOptions};
+use crate::jbod::element::Element::SlotStatus;
+use crate::jbod::enclosure::BackPlane::Enclosure;
+use crate::jbod::resolver::Resolver;
+use crate::utils::helper::Util;
+use crate::utils::output::Output::ErrorCode;
+
+// The sysfs root is global, the tests using it must not overlap.
+static SYSFS: Mutex<()> = Mutex::new(());
+
+/// Returns the guard of the fixture tree now used as the sysfs root.
+fn use_fixture(name: &str) -> MutexGuard<'static, ()> {
+    let guard = SYSFS.lock().unwrap_or_else(|e| e.into_inner());
+    Util::set_sysfs_root(&fixture(name));
+    guard
+}
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/sysfs")
+        .join(name)
+}
+
+fn enclosure(slot: &str, vendor: &str, model: &str, serial: &str) -> Enclosure {
+    Enclosure {
+        slot: slot.to_string(),
+        device_path: String::new(),
+        vendor: vendor.to_string(),
+        model: model.to_string(),
+        revision: String::new(),
+        serial: serial.to_string(),
+        sas_address: String::new(),
+        expander_sas_address: String::new(),
+        location: None,
+        usb: false,
+        controllers: Vec::new(),
+    }
+}
+
+/// Returns a run collecting only what sysfs provides, the fixture devices
+/// can't be queried.
+fn sysfs_only_run() -> Context::Run {
+    Context::Run::new(ScanOptions {
+        jobs: 2,
+        fields: Fields {
+            temperature: false,
+            firmware: false,
+            serial: false,
+        },
+        ..ScanOptions::default()
+    })
+}
+
+#[test]
+fn hgst_slots_are_listed_in_natural_order() {
+    let _sysfs = use_fixture("hgst-h4060-j");
+    let shelf = enclosure("0:0:60:0", "HGST", "H4060-J", "THCL1234500ABC");
+
+    let disks = DiskShelf::jbod_enclosure_disks(&sysfs_only_run(), &shelf);
+
+    let slots: Vec<&str> = disks.iter().map(|d| d.slot.as_str()).collect();
+    assert_eq!(slots, ["Slot 00", "Slot 01", "Slot 10"]);
+    let paths: Vec<&str> = disks.iter().map(|d| d.device_path.as_str()).collect();
+    assert_eq!(paths, ["/dev/sg1", "/dev/sg2", "/dev/sg3"]);
+    assert!(disks.iter().all(|d| d.enclosure == "0:0:60:0"));
+}
+
+#[test]
+fn hgst_disk_attributes_come_from_sysfs() {
+    let _sysfs = use_fixture("hgst-h4060-j");
+    let shelf = enclosure("0:0:60:0", "HGST", "H4060-J", "THCL1234500ABC");
+
+    let disks = DiskShelf::jbod_enclosure_disks(&sysfs_only_run(), &shelf);
+
+    let disk = &disks[0];
+    assert_eq!(disk.vendor, "HGST");
+    assert_eq!(disk.model, "HUH721212AL5200");
+    assert_eq!(disk.wwn, "naa.5000cca2510a1b2c");
+    assert_eq!(disk.status, SlotStatus::Ok);
+    let locate = fixture("hgst-h4060-j").join("class/enclosure/0:0:60:0/Slot 00/locate");
+    assert_eq!(
+        disk.led_locate_path.as_deref(),
+        Some(locate.to_string_lossy().as_ref())
+    );
+    assert_eq!(disks[2].status, SlotStatus::Critical);
+}
+
+#[test]
+fn supermicro_backplanes_are_scanned_separately() {
+    let _sysfs = use_fixture("supermicro-sc847");
+    let run = sysfs_only_run();
+    let front = enclosure("2:0:12:0", "LSI", "SAS3x40", "500304801f2a3b3f");
+    let rear = enclosure("2:0:25:0", "LSI", "SAS3x28", "500304801f2a3c7f");
+
+    let front_disks = DiskShelf::jbod_enclosure_disks(&run, &front);
+    let rear_disks = DiskShelf::jbod_enclosure_disks(&run, &rear);
+
+    let slots: Vec<&str> = front_disks.iter().map(|d| d.slot.as_str()).collect();
+    assert_eq!(slots, ["Slot01", "Slot02", "Slot10"]);
+    assert_eq!(rear_disks.len(), 1);
+    assert_eq!(rear_disks[0].device_path, "/dev/sg21");
+    assert_eq!(rear_disks[0].status, SlotStatus::Noncritical);
+    assert!(rear_disks[0].wwn.starts_with("t10.ATA"));
+}
+
+#[test]
+fn locate_by_device_follows_the_enclosure_link() {
+    let _sysfs = use_fixture("hgst-h4060-j");
+
+    let by_sg = Resolver::locate("/dev/sg2").expect("sg2 is in Slot 01");
+    let by_sd = Resolver::locate("/dev/sdc").expect("sdc is in Slot 01");
+
+    assert_eq!(by_sg.enclosure, "0:0:60:0");
+    assert_eq!(by_sg.slot, "Slot 01");
+    assert_eq!(by_sd.slot, by_sg.slot);
+    assert_eq!(by_sd.device_path, "/dev/sg2");
+}
+
+#[test]
+fn locate_by_slot_serial_and_wwn() {
+    let _sysfs = use_fixture("hgst-h4060-j");
+
+    let by_slot = Resolver::locate("0:0:60:0/10").expect("Slot 10 exists");
+    let by_serial = Resolver::locate("8HG6ABCF").expect("8HG6ABCF is in Slot 10");
+    let by_wwn = Resolver::locate("0x5000CCA2510A1B4C").expect("the wwid of Slot 10 matches");
+    let empty = Resolver::locate("0:0:60:0/Slot 02").expect("empty slots are located");
+
+    assert_eq!(by_slot.slot, "Slot 10");
+    assert_eq!(by_serial.device_path, "/dev/sg3");
+    assert_eq!(by_wwn.slot, "Slot 10");
+    assert!(empty.device_path.is_empty());
+    assert!(Resolver::locate("0:0:60:0/Slot 42").is_none());
+}
+
+#[test]
+fn locate_sata_serial_from_the_t10_wwid() {
+    let _sysfs = use_fixture("supermicro-sc847");
+
+    // The SATA disks behind the expander have no vpd_pg80, `list` shows the
+    // serial ending their wwid.
+    let front = Resolver::locate("ZA1ABC10").expect("ZA1ABC10 is in Slot10");
+    let rear = Resolver::locate("za1abc99").expect("ZA1ABC99 is in the rear backplane");
+
+    assert_eq!(front.enclosure, "2:0:12:0");
+    assert_eq!(front.slot, "Slot10");
+    assert_eq!(rear.enclosure, "2:0:25:0");
+    assert_eq!(rear.device_path, "/dev/sg21");
+    assert!(Resolver::locate("ZA1ABC42").is_none());
+}
+
+#[test]
+fn led_of_a_disk_found_by_wwn_in_sysfs() {
+    let _sysfs = use_fixture("hgst-h4060-j");
+
+    // No udev link in the fixture, the WWN is matched against the wwid of
+    // the slots. Slot 10 has a locate led but no fault led.
+    let (location, locate) = DiskShelf::disk_led("0x5000CCA2510A1B4C", "locate").expect("Slot 10 has a locate led");
+    let missing = DiskShelf::disk_led("0x5000CCA2510A1B4C", "fault").map(|_| ());
+    let unknown = DiskShelf::disk_led("0x5000CCA2510AFFFF", "locate").map(|_| ());
+
+    assert_eq!(location.slot, "Slot 10");
+    assert_eq!(location.device_path, "/dev/sg3");
+    assert!(locate.ends_with("Slot 10/locate"));
+    assert!(matches!(missing, Err((ErrorCode::Unsupported, _))));
+    assert!(matches!(unknown, Err((ErrorCode::NotFound, _))));
+}
+
+#[test]
+fn hotplug_fingerprint_depends_on_the_tree() {
+    let hgst = {
+        let _sysfs = use_fixture("hgst-h4060-j");
+        Cache::hotplug_fingerprint()
+    };
+    let supermicro = {
+        let _sysfs = use_fixture("supermicro-sc847");
+        Cache::hotplug_fingerprint()
+    };
+
+    assert_ne!(hgst, supermicro);
+}
+
+#[test]
+fn missing_enclosure_is_reported_and_skipped() {
+    let _sysfs = use_fixture("supermicro-sc847");
+    let run = sysfs_only_run();
+    let gone = enclosure("2:0:99:0", "LSI", "SAS3x40", "500304801f2a3dff");
+    let rear = enclosure("2:0:25:0", "LSI", "SAS3x28", "500304801f2a3c7f");
+
+    let disks = DiskShelf::jbod_enclosure_disks(&run, &gone);
+    let rear_disks = DiskShelf::jbod_enclosure_disks(&run, &rear);
+
+    assert!(disks.is_empty());
+    assert_eq!(rear_disks.len(), 1);
+    let errors = run.errors();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].enclosure, "2:0:99:0");
+}
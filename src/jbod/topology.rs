@@ -0,0 +1,682 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Topology {
+    use clap::ArgMatches;
+    use regex::Regex;
+    use serde::Serialize;
+    use std::fmt::Write;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use crate::jbod::context::Context;
+    use crate::jbod::disks::DiskShelf;
+    use crate::jbod::enclosure::BackPlane;
+    use crate::utils::helper::Util;
+    use crate::utils::output::Output;
+
+    use prettytable::{color, format, Attr, Cell, Row, Table};
+
+    /// Where a SCSI device sits in the SAS fabric, from its sysfs path.
+    ///
+    /// The path of a disk behind an expander looks like
+    /// `0000:03:00.0/host0/port-0:0/expander-0:0/port-0:0:4/end_device-0:0:4/target0:0:4/0:0:4:0`.
+    #[derive(Debug, Default, Clone, PartialEq, Eq)]
+    pub struct Chain {
+        /// PCI address of the HBA, example: 0000:03:00.0
+        pub pci: Option<String>,
+        /// SCSI host of the HBA, example: host0
+        pub host: Option<String>,
+        /// Expanders from the HBA to the device, with the port leading to
+        /// each one
+        pub expanders: Vec<(String, PathBuf)>,
+        /// The SAS end device and the port leading to it
+        pub end_device: Option<(String, PathBuf)>,
+    }
+
+    /// Returns the sysfs directory of a SCSI device with symlinks resolved.
+    ///
+    /// # Arguments
+    ///
+    /// * `device_path` - the sg node of the device, example: /dev/sg12
+    ///
+    pub fn device_dir(device_path: &str) -> Option<PathBuf> {
+        let sg = device_path.rsplit('/').next()?;
+        fs::canonicalize(Util::sys_class_scsi_generic().join(sg).join("device")).ok()
+    }
+
+    /// Returns the Chain of a device from its sysfs directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - the canonical sysfs directory of the device
+    ///
+    pub fn chain(dir: &Path) -> Chain {
+        let pci_re = Regex::new(r"^[0-9a-f]{4}:[0-9a-f]{2}:[0-9a-f]{2}\.[0-7]$").unwrap();
+        let host_re = Regex::new(r"^host\d+$").unwrap();
+        let mut chain = Chain::default();
+        let mut current = PathBuf::new();
+        let mut port: Option<PathBuf> = None;
+
+        for component in dir.components() {
+            current.push(component);
+            let name = component.as_os_str().to_string_lossy();
+            if pci_re.is_match(&name) {
+                chain.pci = Some(name.to_string());
+            } else if host_re.is_match(&name) {
+                chain.host = Some(name.to_string());
+            } else if name.starts_with("port-") {
+                port = Some(current.clone());
+            } else if name.starts_with("expander-") {
+                let leading = port.take().unwrap_or_default();
+                chain.expanders.push((name.to_string(), leading));
+            } else if name.starts_with("end_device-") {
+                chain.end_device = Some((name.to_string(), port.take().unwrap_or_default()));
+            }
+        }
+
+        chain
+    }
+
+    /// Returns the SAS address of an expander or an end device, None off a
+    /// SAS fabric.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - the transport device, example: expander-0:0 or end_device-0:0:4
+    ///
+    pub fn sas_address(name: &str) -> Option<String> {
+        let path = Util::sysfs_path("class/sas_device")
+            .join(name)
+            .join("sas_address");
+        let address = fs::read_to_string(path).ok()?.trim().to_string();
+        (!address.is_empty()).then_some(address)
+    }
+
+    /// Returns the SAS address of a SCSI device, None off a SAS fabric.
+    ///
+    /// Drivers like mpt3sas expose it on the device, libsas only on the
+    /// end device of the transport class.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - the canonical sysfs directory of the device
+    ///
+    pub fn device_sas_address(dir: &Path) -> Option<String> {
+        let address = fs::read_to_string(dir.join("sas_address"))
+            .map(|a| a.trim().to_string())
+            .unwrap_or_default();
+        if !address.is_empty() {
+            return Some(address);
+        }
+        chain(dir).end_device.and_then(|(end_device, _)| sas_address(&end_device))
+    }
+
+    /// Returns the negotiated rate of a SAS port with its width, example:
+    /// 4x12.0 Gbit, None when it isn't known.
+    ///
+    /// # Arguments
+    ///
+    /// * `port` - the sysfs directory of the port, it holds its phys
+    ///
+    pub fn link_rate(port: &Path) -> Option<String> {
+        let mut phys: Vec<String> = fs::read_dir(port)
+            .ok()?
+            .flatten()
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .filter(|n| n.starts_with("phy-"))
+            .collect();
+        phys.sort_by(|a, b| Util::natural_cmp(a, b));
+        let phy = phys.first()?;
+        let rate = fs::read_to_string(
+            Util::sysfs_path("class/sas_phy")
+                .join(phy)
+                .join("negotiated_linkrate"),
+        )
+        .ok()?
+        .trim()
+        .to_string();
+        if phys.len() > 1 {
+            Some(format!("{}x{}", phys.len(), rate))
+        } else {
+            Some(rate)
+        }
+    }
+
+    /// A device of the graph.
+    #[derive(Debug, Clone, Serialize)]
+    pub struct Node {
+        pub id: String,
+        /// hba, expander, enclosure, slot or disk
+        pub kind: &'static str,
+        pub label: String,
+        pub sas_address: Option<String>,
+    }
+
+    /// A link of the graph, a cable or a containment.
+    #[derive(Debug, Clone, Serialize)]
+    pub struct Edge {
+        pub from: String,
+        pub to: String,
+        /// Negotiated rate of the SAS link, None for a slot of an enclosure
+        pub link_rate: Option<String>,
+    }
+
+    /// The HBAs, expanders, enclosures, slots and disks and how they connect.
+    #[derive(Debug, Default, Serialize)]
+    pub struct Graph {
+        pub nodes: Vec<Node>,
+        pub edges: Vec<Edge>,
+    }
+
+    impl Graph {
+        /// Adds a node unless one has the same id.
+        fn node(
+            &mut self,
+            id: &str,
+            kind: &'static str,
+            label: String,
+            sas_address: Option<String>,
+        ) {
+            if !self.nodes.iter().any(|n| n.id == id) {
+                self.nodes.push(Node {
+                    id: id.to_string(),
+                    kind,
+                    label,
+                    sas_address,
+                });
+            }
+        }
+
+        /// Adds an edge unless it already exists.
+        fn edge(&mut self, from: &str, to: &str, link_rate: Option<String>) {
+            if !self.edges.iter().any(|e| e.from == from && e.to == to) {
+                self.edges.push(Edge {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                    link_rate,
+                });
+            }
+        }
+
+        /// Adds the HBA and the expanders of a chain, returns the id of the
+        /// last one and the rate of the link to the device.
+        fn fabric(&mut self, chain: &Chain) -> (Option<String>, Option<String>) {
+            let mut parent = chain.host.as_ref().map(|host| {
+                let label = match &chain.pci {
+                    Some(pci) => format!("{} {}", host, pci),
+                    None => host.clone(),
+                };
+                self.node(host, "hba", label, None);
+                host.clone()
+            });
+            for (expander, port) in &chain.expanders {
+                let address = sas_address(expander);
+                let label = match &address {
+                    Some(address) => format!("{}\n{}", expander, address),
+                    None => expander.clone(),
+                };
+                self.node(expander, "expander", label, address);
+                if let Some(from) = &parent {
+                    self.edge(from, expander, link_rate(port));
+                }
+                parent = Some(expander.clone());
+            }
+            let rate = chain
+                .end_device
+                .as_ref()
+                .and_then(|(_, port)| link_rate(port));
+            (parent, rate)
+        }
+    }
+
+    /// Returns the Graph of the JBODs from sysfs.
+    ///
+    /// Each path of a dual ported disk is a link of its own, the disk is a
+    /// single node keyed by its WWN.
+    ///
+    /// # Arguments
+    ///
+    /// * `run` - a reference of the run Context
+    ///
+    pub fn graph(run: &Context::Run) -> Graph {
+        let mut graph = Graph::default();
+
+        for enclosure in DiskShelf::jbod_enclosures(run) {
+            let id = format!("enclosure {}", enclosure.slot);
+            let label = format!(
+                "{} {} {}\n{}",
+                enclosure.vendor, enclosure.model, enclosure.serial, enclosure.slot
+            );
+            let dir = fs::canonicalize(
+                Util::sys_class_enclosure()
+                    .join(&enclosure.slot)
+                    .join("device"),
+            );
+            let chain = dir.map(|d| chain(&d)).unwrap_or_default();
+            let address = chain.end_device.as_ref().and_then(|(e, _)| sas_address(e));
+            graph.node(&id, "enclosure", label, address);
+            if let (Some(parent), rate) = graph.fabric(&chain) {
+                graph.edge(&parent, &id, rate);
+            }
+        }
+
+        let mut disks = DiskShelf::jbod_disk_map(run);
+        DiskShelf::sort_disks(&mut disks);
+        for disk in &disks {
+            let enclosure = format!("enclosure {}", disk.enclosure);
+            let slot = format!("{}/{}", disk.enclosure, disk.slot);
+            graph.node(
+                &slot,
+                "slot",
+                disk.label.clone().unwrap_or_else(|| disk.slot.clone()),
+                None,
+            );
+            graph.edge(&enclosure, &slot, None);
+
+            let id = if disk.wwn.is_empty() {
+                disk.device_path.clone()
+            } else {
+                disk.wwn.clone()
+            };
+            let chain = device_dir(&disk.device_path)
+                .map(|d| chain(&d))
+                .unwrap_or_default();
+            let address = chain.end_device.as_ref().and_then(|(e, _)| sas_address(e));
+            let label = format!("{} {}\n{}", disk.model, disk.serial, disk.device_path);
+            graph.node(&id, "disk", label, address);
+            let (_, rate) = graph.fabric(&chain);
+            graph.edge(&slot, &id, rate);
+        }
+
+        graph
+    }
+
+    /// Returns the Graph in the graphviz language.
+    pub fn to_dot(graph: &Graph) -> String {
+        let quote = |s: &str| {
+            s.replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n")
+        };
+        let mut dot =
+            String::from("digraph jbod {\n    rankdir=LR;\n    node [fontname=\"monospace\"];\n");
+        for node in &graph.nodes {
+            let shape = match node.kind {
+                "hba" => "box3d",
+                "expander" => "diamond",
+                "enclosure" => "box",
+                "slot" => "plaintext",
+                _ => "cylinder",
+            };
+            let _ = writeln!(
+                dot,
+                "    \"{}\" [label=\"{}\", shape={}];",
+                quote(&node.id),
+                quote(&node.label),
+                shape
+            );
+        }
+        for edge in &graph.edges {
+            let attributes = match &edge.link_rate {
+                Some(rate) => format!(" [label=\"{}\"]", quote(rate)),
+                None => String::new(),
+            };
+            let _ = writeln!(
+                dot,
+                "    \"{}\" -> \"{}\"{};",
+                quote(&edge.from),
+                quote(&edge.to),
+                attributes
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// A SAS or Fibre Channel controller.
+    #[derive(Debug, Clone, Serialize)]
+    pub struct Hba {
+        /// SCSI host, example: host0
+        pub host: String,
+        /// sas or fc
+        pub transport: &'static str,
+        /// Kernel driver, example: mpt3sas
+        pub driver: String,
+        pub model: Option<String>,
+        pub firmware: Option<String>,
+        pub bios: Option<String>,
+        /// PCI address, example: 0000:03:00.0
+        pub pci: Option<String>,
+        /// Enclosures reached through this controller
+        pub enclosures: usize,
+    }
+
+    /// Returns the first attribute of a SCSI host the driver provides, each
+    /// driver names them its own way.
+    fn host_attribute(host_dir: &Path, names: &[&str]) -> Option<String> {
+        names.iter().find_map(|name| {
+            let value = fs::read_to_string(host_dir.join(name)).ok()?;
+            let value = value.trim();
+            (!value.is_empty()).then(|| value.to_string())
+        })
+    }
+
+    /// Returns the SAS and Fibre Channel controllers, other SCSI hosts like
+    /// the AHCI ports or the USB bridges are left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `run` - a reference of the run Context
+    ///
+    pub fn hbas(run: &Context::Run) -> Vec<Hba> {
+        let enclosure_hosts: Vec<Option<String>> = DiskShelf::jbod_enclosures(run)
+            .iter()
+            .map(|e| {
+                fs::canonicalize(Util::sys_class_enclosure().join(&e.slot).join("device"))
+                    .ok()
+                    .and_then(|d| chain(&d).host)
+            })
+            .collect();
+
+        let scsi_host = Util::sysfs_path("class/scsi_host");
+        let mut hosts: Vec<String> = fs::read_dir(&scsi_host)
+            .map(|d| {
+                d.flatten()
+                    .map(|e| e.file_name().to_string_lossy().to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        hosts.sort_by(|a, b| Util::natural_cmp(a, b));
+
+        let mut hbas = Vec::new();
+        for host in hosts {
+            let transport = if Util::sysfs_path("class/sas_host").join(&host).exists() {
+                "sas"
+            } else if Util::sysfs_path("class/fc_host").join(&host).exists() {
+                "fc"
+            } else {
+                continue;
+            };
+            let dir = scsi_host.join(&host);
+            let pci = fs::canonicalize(dir.join("device"))
+                .ok()
+                .and_then(|d| chain(&d).pci);
+            hbas.push(Hba {
+                driver: host_attribute(&dir, &["proc_name"]).unwrap_or_default(),
+                model: host_attribute(&dir, &["board_name", "model_name", "modelname", "model"]),
+                firmware: host_attribute(
+                    &dir,
+                    &["version_fw", "fw_version", "firmware_revision", "fwrev"],
+                ),
+                bios: host_attribute(
+                    &dir,
+                    &["version_bios", "optrom_bios_version", "option_rom_version"],
+                ),
+                pci,
+                enclosures: enclosure_hosts
+                    .iter()
+                    .filter(|h| h.as_deref() == Some(host.as_str()))
+                    .count(),
+                host,
+                transport,
+            });
+        }
+
+        hbas
+    }
+
+    /// Creates the pretty table for the HBAs.
+    pub fn create_hba_table() -> Table {
+        let mut hba_table = Table::new();
+        hba_table.set_format(*format::consts::FORMAT_NO_BORDER);
+        let header = [
+            "HOST", "TRANSPORT", "DRIVER", "MODEL", "FIRMWARE", "BIOS", "PCI", "ENCLOSURES",
+        ];
+        hba_table.add_row(Row::new(
+            header
+                .iter()
+                .map(|h| {
+                    Cell::new(h)
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(color::BLUE))
+                })
+                .collect(),
+        ));
+
+        hba_table
+    }
+
+    /// An enclosure of a daisy chain, the shelves cabled one after the
+    /// other from a port of an HBA.
+    #[derive(Debug, Clone, Serialize)]
+    pub struct Shelf {
+        /// SCSI host of the HBA, example: host0
+        pub host: String,
+        /// Port of the HBA the chain starts from, example: port-0:0
+        pub port: String,
+        /// Place in the chain, 1 for the shelf cabled to the HBA
+        pub position: usize,
+        /// Enclosure number identification, example: 0:0:4:0
+        pub enclosure: String,
+        pub serial: String,
+        pub model: String,
+        /// The first expander of the shelf, example: expander-0:1
+        pub expander: Option<String>,
+        /// The port the shelf is cabled to, on the HBA or on the previous
+        /// shelf, example: expander-0:0 phys 12-15
+        pub upstream: Option<String>,
+        /// The serial of the previous shelf, None for the first one
+        pub upstream_enclosure: Option<String>,
+        /// The port of this shelf cabled to the next one
+        pub downstream: Option<String>,
+        /// The serial of the next shelf, None for the last one
+        pub downstream_enclosure: Option<String>,
+    }
+
+    /// Returns the phy identifiers of a SAS port, example: 12-15
+    fn port_phys(port: &Path) -> Option<String> {
+        let mut ids: Vec<u32> = fs::read_dir(port)
+            .ok()?
+            .flatten()
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .filter(|n| n.starts_with("phy-"))
+            .filter_map(|phy| {
+                let identifier = Util::sysfs_path("class/sas_phy")
+                    .join(&phy)
+                    .join("phy_identifier");
+                fs::read_to_string(identifier)
+                    .ok()
+                    .and_then(|i| i.trim().parse().ok())
+                    .or_else(|| phy.rsplit(':').next()?.parse().ok())
+            })
+            .collect();
+        ids.sort_unstable();
+        let (first, last) = (*ids.first()?, *ids.last()?);
+        if last - first + 1 == ids.len() as u32 && ids.len() > 1 {
+            Some(format!("{}-{}", first, last))
+        } else {
+            Some(ids.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(","))
+        }
+    }
+
+    /// Returns the port shown to a human, the device holding it and its
+    /// phys, example: expander-0:0 phys 12-15
+    fn describe_port(port: &Path) -> String {
+        let owner = port
+            .parent()
+            .and_then(|p| p.file_name())
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        match port_phys(port) {
+            Some(phys) => format!("{} phys {}", owner, phys),
+            None => owner,
+        }
+    }
+
+    /// Returns the shelves of the daisy chains, ordered by HBA, port and
+    /// place in the chain.
+    ///
+    /// Every shelf adds its expanders to the SAS path of the next one, the
+    /// previous shelf of an enclosure is the one whose expanders are the
+    /// longest prefix of its own.
+    ///
+    /// # Arguments
+    ///
+    /// * `run` - a reference of the run Context
+    ///
+    pub fn daisy_chains(run: &Context::Run) -> Vec<Shelf> {
+        let enclosures: Vec<(BackPlane::Enclosure, Chain)> = DiskShelf::jbod_enclosures(run)
+            .into_iter()
+            .filter_map(|e| {
+                let dir =
+                    fs::canonicalize(Util::sys_class_enclosure().join(&e.slot).join("device")).ok()?;
+                let chain = chain(&dir);
+                (chain.host.is_some() && !chain.expanders.is_empty()).then_some((e, chain))
+            })
+            .collect();
+        let names = |chain: &Chain| {
+            chain
+                .expanders
+                .iter()
+                .map(|(e, _)| e.clone())
+                .collect::<Vec<String>>()
+        };
+        let hba_port = |chain: &Chain| {
+            chain.expanders[0]
+                .1
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default()
+        };
+
+        // The previous shelf of each enclosure, an index into enclosures.
+        let upstream: Vec<Option<usize>> = enclosures
+            .iter()
+            .map(|(_, chain)| {
+                let own = names(chain);
+                enclosures
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (_, other))| {
+                        let other = names(other);
+                        other.len() < own.len() && own.starts_with(&other)
+                    })
+                    .max_by_key(|(_, (_, other))| other.expanders.len())
+                    .map(|(i, _)| i)
+            })
+            .collect();
+        // The first expander of a shelf is the one after those of the
+        // previous shelf, the port leading to it is the upstream cable.
+        let entry = |i: usize| -> &(String, PathBuf) {
+            let skip = upstream[i].map_or(0, |u| enclosures[u].1.expanders.len());
+            &enclosures[i].1.expanders[skip]
+        };
+
+        let mut shelves: Vec<Shelf> = enclosures
+            .iter()
+            .enumerate()
+            .map(|(i, (enclosure, chain))| {
+                let mut position = 1;
+                let mut previous = upstream[i];
+                while let Some(p) = previous {
+                    position += 1;
+                    previous = upstream[p];
+                }
+                let next = (0..enclosures.len()).find(|n| upstream[*n] == Some(i));
+                let (expander, port) = entry(i);
+                Shelf {
+                    host: chain.host.clone().unwrap_or_default(),
+                    port: hba_port(chain),
+                    position,
+                    enclosure: enclosure.slot.clone(),
+                    serial: enclosure.serial.clone(),
+                    model: enclosure.model.clone(),
+                    expander: Some(expander.clone()),
+                    upstream: Some(describe_port(port)),
+                    upstream_enclosure: upstream[i].map(|u| enclosures[u].0.serial.clone()),
+                    downstream: next.map(|n| describe_port(&entry(n).1)),
+                    downstream_enclosure: next.map(|n| enclosures[n].0.serial.clone()),
+                }
+            })
+            .collect();
+        shelves.sort_by(|a, b| {
+            Util::natural_cmp(&a.host, &b.host)
+                .then_with(|| Util::natural_cmp(&a.port, &b.port))
+                .then(a.position.cmp(&b.position))
+        });
+        shelves
+    }
+
+    /// Creates the pretty table for the daisy chains.
+    pub fn create_chain_table() -> Table {
+        let mut chain_table = Table::new();
+        chain_table.set_format(*format::consts::FORMAT_NO_BORDER);
+        let header = [
+            "HOST", "PORT", "POSITION", "ENCLOSURE", "SERIAL", "MODEL", "UPSTREAM", "DOWNSTREAM",
+        ];
+        chain_table.add_row(Row::new(
+            header
+                .iter()
+                .map(|h| {
+                    Cell::new(h)
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(color::BLUE))
+                })
+                .collect(),
+        ));
+
+        chain_table
+    }
+
+    /// Returns an empty Result or an error when the graph can't be written.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `topology` subcommand
+    ///
+    pub fn jbod_topology(options: &ArgMatches) -> Result<(), ()> {
+        let mut scan = DiskShelf::ScanOptions::from_matches(options);
+        scan.fields.temperature = false;
+        scan.fields.firmware = false;
+        scan.raw_paths = true;
+        let run = Context::Run::new(scan);
+        let graph = graph(&run);
+
+        match options.value_of("format") {
+            Some("json") => Output::render_document(Output::Format::Json, &graph)
+                .map_err(|e| Output::print_error(&e))?,
+            _ => print!("{}", to_dot(&graph)),
+        }
+        Output::render_errors(Output::Format::Table, &run.errors());
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Wear {
+    use std::fs;
+
+    use crate::jbod::disks::DiskShelf::Disk;
+    use crate::jbod::sgio::SgIo;
+    use crate::utils::config::Config;
+    use crate::utils::helper::Util;
+    use crate::utils::i18n::I18n;
+    use crate::utils::i18n::I18n::Message;
+    use crate::utils::output::Output;
+
+    /// Returns true when the block device of a disk isn't rotational, an
+    /// unmapped disk is assumed rotational.
+    ///
+    /// # Arguments
+    ///
+    /// * `device_path` - the sg node of the disk, example: /dev/sg12
+    ///
+    pub fn is_flash(device_path: &str) -> bool {
+        let sg = device_path.rsplit('/').next().unwrap_or(device_path);
+        let block = Util::sys_class_scsi_generic().join(sg).join("device/block");
+        let device = match fs::read_dir(block).ok().and_then(|mut d| d.next()) {
+            Some(Ok(device)) => device,
+            _ => return false,
+        };
+        fs::read_to_string(device.path().join("queue/rotational"))
+            .map(|r| r.trim() == "0")
+            .unwrap_or(false)
+    }
+
+    /// Returns the percentage of the rated endurance used by a disk, None
+    /// when it doesn't report it. SATA drives behind the SAT layer have no
+    /// solid state media page, their device statistics are read instead.
+    pub fn percentage_used(disk: &Disk) -> Result<Option<u8>, String> {
+        let device = SgIo::Device::open(&disk.device_path)
+            .map_err(|e| I18n::tr(Message::CannotOpen, &[&disk.device_path, &e]))?;
+        let used = if disk.vendor.trim() == "ATA" {
+            device.ata_percentage_used()
+        } else {
+            device.percentage_used()
+        };
+        used.map_err(|e| format!("cannot read the endurance indicator: {}", e))
+    }
+
+    /// Returns true when a percentage used reached the `wear_threshold`
+    /// of the configuration.
+    pub fn worn(percentage_used: u8) -> bool {
+        percentage_used >= Config::get().wear_threshold
+    }
+
+    /// Fills the `wear` field of the flash disks, the rotational ones and
+    /// the ones that couldn't be queried keep None, like every disk without
+    /// the `smart` feature.
+    ///
+    /// # Arguments
+    ///
+    /// * `disks` - the disks to query
+    /// * `jobs` - the disks queried at the same time
+    /// * `quiet` - the failures aren't warned about
+    ///
+    pub fn annotate(disks: &mut [Disk], jobs: usize, quiet: bool) {
+        if !cfg!(feature = "smart") {
+            return;
+        }
+        let readings = Util::parallel_map(disks, jobs, |d| {
+            if is_flash(&d.device_path) {
+                percentage_used(d)
+            } else {
+                Ok(None)
+            }
+        });
+        for (disk, reading) in disks.iter_mut().zip(readings) {
+            match reading {
+                Ok(used) => disk.wear = used,
+                Err(e) if !quiet => Output::print_warning(&format!("{}: {}", disk.device_path, e)),
+                Err(_) => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,301 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+
+#[allow(non_snake_case)]
+pub mod WriteCache {
+    use clap::ArgMatches;
+    use colored::Colorize;
+    use std::fs;
+
+    use crate::jbod::cache::Cache;
+    use crate::jbod::context::Context;
+    use crate::jbod::disks::DiskShelf;
+    use crate::jbod::disks::DiskShelf::Disk;
+    use crate::jbod::resolver::Resolver;
+    use crate::jbod::sgio::SgIo;
+    use crate::utils::audit::Audit;
+    use crate::utils::helper::Util;
+    use crate::utils::i18n::I18n;
+    use crate::utils::i18n::I18n::Message;
+    use crate::utils::output::Output;
+    use crate::utils::output::Output::ErrorCode;
+
+    use prettytable::{color, format, Attr, Cell, Row, Table};
+
+    /// Returns enabled or disabled.
+    fn state(enabled: bool) -> &'static str {
+        if enabled {
+            "enabled"
+        } else {
+            "disabled"
+        }
+    }
+
+    /// Returns true when the write cache of a disk is enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `device_path` - the sg node of the disk, example: /dev/sg12
+    ///
+    pub fn status(device_path: &str) -> Result<bool, String> {
+        let device = SgIo::Device::open(device_path)
+            .map_err(|e| I18n::tr(Message::CannotOpen, &[&device_path, &e]))?;
+        device
+            .write_cache()
+            .map_err(|e| format!("cannot read the caching page: {}", e))
+    }
+
+    /// Fills the `write_cache` field of the disks, the ones that couldn't
+    /// be queried keep None.
+    ///
+    /// # Arguments
+    ///
+    /// * `disks` - the disks to query
+    /// * `jobs` - the disks queried at the same time
+    ///
+    pub fn annotate(disks: &mut [Disk], jobs: usize) {
+        let statuses = Util::parallel_map(disks, jobs, |d| status(&d.device_path));
+        for (disk, status) in disks.iter_mut().zip(statuses) {
+            match status {
+                Ok(enabled) => disk.write_cache = Some(enabled),
+                Err(e) => Output::print_warning(&format!("{}: {}", disk.device_path, e)),
+            }
+        }
+    }
+
+    /// Returns the previous state of the write cache once it is switched.
+    ///
+    /// The sd driver only reads the caching page when it probes the disk,
+    /// the disk is rescanned so it flushes the cache if it is now enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `disk` - the disk to switch
+    /// * `enable` - the state asked for
+    ///
+    fn switch(disk: &Disk, enable: bool) -> Result<bool, String> {
+        let device = SgIo::Device::open_rw(&disk.device_path)
+            .map_err(|e| I18n::tr(Message::CannotOpen, &[&disk.device_path, &e]))?;
+        let before = device
+            .write_cache()
+            .map_err(|e| format!("cannot read the caching page: {}", e))?;
+        if before == enable {
+            return Ok(before);
+        }
+        device
+            .set_write_cache(enable)
+            .map_err(|e| format!("MODE SELECT failed: {}", e))?;
+        match device.write_cache() {
+            Ok(after) if after == enable => {}
+            Ok(after) => return Err(format!("the write cache is still {}", state(after))),
+            Err(e) => return Err(format!("cannot read the caching page: {}", e)),
+        }
+
+        if let Some(block) = disk.device_map.strip_prefix("/dev/") {
+            let rescan = Util::sysfs_path(&format!("block/{}/device/rescan", block));
+            if let Err(e) = fs::write(&rescan, "1") {
+                Output::print_warning(&format!("cannot rescan {}: {}", disk.device_map, e));
+            }
+        }
+        Ok(before)
+    }
+
+    /// Returns the disks of the scan, optionally only those of one enclosure.
+    fn scan_disks(options: &ArgMatches) -> (Context::Run, Vec<Disk>) {
+        let mut scan = DiskShelf::ScanOptions::from_matches(options);
+        scan.fields.temperature = false;
+        scan.fields.firmware = false;
+        let run = Context::Run::new(scan);
+        let mut disks = DiskShelf::jbod_disk_map(&run);
+        DiskShelf::sort_disks(&mut disks);
+        (run, disks)
+    }
+
+    /// Creates the pretty table for the write cache status.
+    fn create_cache_table() -> Table {
+        let mut cache_table = Table::new();
+        cache_table.set_format(*format::consts::FORMAT_NO_BORDER);
+        let header = ["DEVICE", "MAP", "ENCLOSURE", "SLOT", "MODEL", "SERIAL", "WRITE CACHE"];
+        cache_table.add_row(Row::new(
+            header
+                .iter()
+                .map(|h| {
+                    Cell::new(h)
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(color::BLUE))
+                })
+                .collect(),
+        ));
+
+        cache_table
+    }
+
+    /// Returns an empty Result or an error when a disk couldn't be queried.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `cache status` subcommand
+    ///
+    fn cache_status(options: &ArgMatches) -> Result<(), ()> {
+        let (run, mut disks) = scan_disks(options);
+        annotate(&mut disks, run.options.jobs);
+        let failed = disks.iter().any(|d| d.write_cache.is_none()) || !run.errors().is_empty();
+
+        let format = Output::get_format(options);
+        if format != Output::Format::Table {
+            Output::render(format, &disks).map_err(|e| Output::print_error(&e))?;
+        } else {
+            let mut cache_table = create_cache_table();
+            for disk in &disks {
+                let write_cache = match disk.write_cache {
+                    Some(enabled) => Cell::new(state(enabled)),
+                    None => Cell::new("ERR")
+                        .with_style(Attr::Bold)
+                        .with_style(Attr::ForegroundColor(color::RED)),
+                };
+                cache_table.add_row(Row::new(vec![
+                    Cell::new(&disk.device_path),
+                    Cell::new(&disk.device_map),
+                    Cell::new(&disk.enclosure),
+                    Cell::new(&disk.slot),
+                    Cell::new(&disk.model),
+                    Cell::new(&disk.serial),
+                    write_cache,
+                ]));
+            }
+            Output::print_table(&cache_table);
+        }
+
+        let scanned = run.errors();
+        Output::render_errors(format, &scanned);
+        if failed {
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns an empty Result or an error when a disk wasn't switched.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `cache enable` or `cache
+    ///   disable` subcommand
+    /// * `enable` - the state asked for
+    ///
+    fn cache_switch(options: &ArgMatches, enable: bool) -> Result<(), ()> {
+        let (run, disks) = scan_disks(options);
+        for error in run.errors() {
+            Output::print_warning(&error.to_string());
+        }
+
+        let targets: Vec<Disk> = if options.is_present("all") {
+            disks
+        } else {
+            let mut targets = Vec::new();
+            for device in options.values_of("device").into_iter().flatten() {
+                match Resolver::resolve(device, &disks) {
+                    Some(disk) => targets.push(disk.clone()),
+                    None => {
+                        Output::print_error_code(
+                            ErrorCode::NotFound,
+                            &I18n::tr(Message::NotADisk, &[&device]),
+                        );
+                        return Err(());
+                    }
+                }
+            }
+            targets
+        };
+        if targets.is_empty() {
+            Output::print_error_code(ErrorCode::NotFound, "no disk to switch");
+            return Err(());
+        }
+
+        let results = Util::parallel_map(&targets, run.options.jobs, |disk| switch(disk, enable));
+        let mut failed = 0;
+        for (disk, result) in targets.iter().zip(results) {
+            Audit::record(
+                "write_cache",
+                &format!("disk {} {}", disk.device_path, disk.serial),
+                result.as_ref().ok().map(|before| state(*before)),
+                state(enable),
+                result.as_ref().map(|_| ()).map_err(|e| e.as_str()),
+            );
+            match result {
+                Ok(before) if before == enable => {
+                    println!("{} already {}", disk.device_path.bold(), state(enable))
+                }
+                Ok(before) => println!(
+                    "{} {} -> {}",
+                    disk.device_path.bold(),
+                    state(before).yellow().bold(),
+                    state(enable).green().bold()
+                ),
+                Err(e) => {
+                    failed += 1;
+                    Output::print_error(&format!("{}: {}", disk.device_path, e));
+                }
+            }
+        }
+
+        if failed > 0 {
+            Output::print_error(&format!("{} of {} disks were not switched", failed, targets.len()));
+            return Err(());
+        }
+        Ok(())
+    }
+
+    /// Returns an empty Result or an error, it dispatches the `cache`
+    /// subcommands.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `cache` subcommand
+    ///
+    pub fn jbod_cache(options: &ArgMatches) -> Result<(), ()> {
+        match options.subcommand() {
+            Some(("status", m)) => cache_status(m),
+            Some(("enable", m)) => cache_switch(m, true),
+            Some(("disable", m)) => cache_switch(m, false),
+            Some(("clear", _)) => Cache::clear().map_err(|e| {
+                Output::print_error_code(ErrorCode::from_io(&e), &format!("{}: {}", Cache::CACHE_FILE, e))
+            }),
+            _ => {
+                Output::print_error_code(
+                    ErrorCode::InvalidArgument,
+                    "missing the action, see jbod cache --help",
+                );
+                Err(())
+            }
+        }
+    }
+}
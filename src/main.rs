@@ -28,31 +28,121 @@
  * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
  */
 
-#[forbid(unsafe_code)]
+#![forbid(unsafe_code)]
+
 use clap::{App, Arg, ArgMatches, SubCommand};
 use colored::*;
 use nix::{
-    sys::wait::waitpid,
-    unistd::{fork, ForkResult},
+    sys::signal::{kill, Signal},
+    unistd::{geteuid, Pid},
 };
-use std::process::{exit, Command};
+use std::fs::OpenOptions;
+use std::io::{self, IsTerminal};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{exit, Command, Stdio};
 
 extern crate prettytable;
-use prettytable::{Cell, Row};
+use prettytable::{Attr, Cell, Row};
 
-mod jbod;
-mod utils;
+// The modules come from the library, shared with the other binary and
+// the programs registering their own collectors.
+use ::jbod::{jbod, utils};
+use crate::jbod::apply::Apply;
+use crate::jbod::bench::Bench;
+use crate::jbod::burnin::Burnin;
+use crate::jbod::cabling::Cabling;
+use crate::jbod::catalog::Catalog;
+use crate::jbod::context::Context;
 use crate::jbod::disks::DiskShelf;
+use crate::jbod::diskstats::DiskStats;
 use crate::jbod::enclosure::BackPlane;
+use crate::jbod::events::Events;
+use crate::jbod::exec::Exec;
+use crate::jbod::find::Find;
+use crate::jbod::firmware::Firmware;
+use crate::jbod::genmap::GenMap;
+use crate::jbod::health::Health;
+use crate::jbod::inventory::Inventory;
+use crate::jbod::io_errors::IoErrors;
+use crate::jbod::map::ChassisMap;
+use crate::jbod::modepage::ModePage;
+use crate::jbod::plugin::Plugin;
+use crate::jbod::sed::Sed;
+use crate::jbod::show::Show;
+use crate::jbod::standby::Standby;
+use crate::jbod::summary::Summary;
+use crate::jbod::topology::Topology;
+use crate::jbod::wear::Wear;
+use crate::jbod::write_cache::WriteCache;
+use crate::utils::config::Config;
+use crate::utils::config::Config::TemperatureUnit;
+use crate::utils::daemon::Daemon;
+use crate::utils::doctor::Doctor;
 use crate::utils::helper::Util;
+use crate::utils::i18n::I18n;
+use crate::utils::i18n::I18n::Message;
+use crate::utils::output::Output;
+use crate::utils::output::Output::ErrorCode;
+
+/// Where `prometheus --daemonize` records the pid of the exporter.
+const DEFAULT_PIDFILE: &str = "/run/jbod-exporter.pid";
+
+/// The subcommands taking the `--lock` alone, they write to the enclosures
+/// or the disks, the other ones share it.
+const CONTROL_COMMANDS: &[&str] = &[
+    "led", "locate", "firmware", "apply", "verify-cabling", "cache", "standby", "burnin", "exec",
+];
+
+/// The subcommands not collecting the temperature of the disks, they only
+/// need to find the enclosures and their disks.
+const NO_TEMPERATURE_COMMANDS: &[&str] = &[
+    "led", "locate", "find", "inventory", "burnin", "bench", "genmap", "sed", "cache", "modepage", "standby",
+    "topology", "map", "verify-cabling",
+];
+
+/// Returns the helper binaries run by a subcommand.
+///
+/// The enclosures are found with lsscsi and sg_inq, scsi_temperature is only
+/// needed when the temperature of the disks is collected. `doctor` reports
+/// what is missing and `daemon` runs each request in a process of its own.
+///
+/// # Arguments
+///
+/// * `name` - the name of the subcommand
+/// * `option` - clappy's ArgMatches of the subcommand
+///
+fn required_binaries(name: &str, option: &ArgMatches) -> Vec<&'static str> {
+    if ["doctor", "daemon"].contains(&name) || (name == "cache" && matches!(option.subcommand(), Some(("clear", _)))) {
+        return Vec::new();
+    }
+    let temperature = match name {
+        "list" => option.is_present("disks") || option.is_present("elements"),
+        "exec" => ["filter", "command"]
+            .iter()
+            .filter_map(|arg| option.values_of(arg))
+            .flatten()
+            .any(|value| value.contains("temp")),
+        _ => !NO_TEMPERATURE_COMMANDS.contains(&name),
+    };
+    let fields_temperature = match option.try_get_many::<String>("fields") {
+        Ok(Some(mut fields)) => fields.any(|f| f == "temperature"),
+        _ => true,
+    };
+    let mut binaries = vec![Util::LSSCSI, Util::SG_INQ];
+    if temperature && fields_temperature {
+        binaries.push(Util::SCSI_TEMP);
+    }
+    binaries
+}
 
 /// Fallback help function, we should never fall here
 fn help() {
     println!("Use command with help option");
 }
 
-/// Given a string representing a temperature like: [0-9]+ it will
-/// return colored string first for the temperature second for the unit.
+/// Given the temperature of a disk it will return colored string first
+/// for the temperature second for the unit.
 ///
 /// Coloration:
 ///
@@ -60,22 +150,285 @@ fn help() {
 /// - Between 45 excluded and below 50 included it's yellow bold
 /// - Above it's blinking red you must act maybe :)
 ///
-/// If temperature is not readable it return `None` it's caller responsibility
-/// to report it properly.
+/// The thresholds are in Celsius, the temperature is converted to the
+/// requested `unit` only for display.
+///
+/// If there is no temperature it return `None` it's caller responsibility
+/// to report the state properly.
 ///
-fn color_temp(temperature: &str) -> Option<(ColoredString, ColoredString)> {
-    let temp_conv = temperature.parse::<i32>().ok()?;
-    let coloreds = if temp_conv > 45 && temp_conv <= 50 {
-        (temperature.yellow().bold(),
-        "c".yellow().bold())
-    } else if temp_conv > 50 {
-        (temperature.red().bold().blink(), "c".red().bold().blink())
+fn color_temp(
+    temperature: &DiskShelf::Temperature,
+    unit: TemperatureUnit,
+) -> Option<(ColoredString, ColoredString)> {
+    let temp_conv = match temperature {
+        DiskShelf::Temperature::Celsius(t) => *t,
+        _ => return None,
+    };
+    let displayed = unit.convert(temp_conv).to_string();
+    let symbol = unit.symbol();
+    let coloreds = if temp_conv > Health::TEMPERATURE_WARM && temp_conv <= Health::TEMPERATURE_HOT {
+        (displayed.yellow().bold(), symbol.yellow().bold())
+    } else if temp_conv > Health::TEMPERATURE_HOT {
+        (displayed.red().bold().blink(), symbol.red().bold().blink())
     } else {
-        (temperature.green(), "c".green())
+        (displayed.green(), symbol.green())
     };
     Some(coloreds)
 }
 
+/// Returns an error if any disk has partial data.
+///
+/// This function reports on stderr every field that could not be collected,
+/// it is used by `--strict`.
+///
+/// # Arguments
+///
+/// * `disks` - the disks that were listed
+/// * `scan` - the options the disks were scanned with
+///
+fn verify_strict(disks: &[DiskShelf::Disk], scan: &DiskShelf::ScanOptions) -> Result<(), ()> {
+    let mut failed = false;
+    for disk in disks {
+        for error in DiskShelf::collection_errors(disk, &scan.fields) {
+            Output::print_error(&format!("{} slot {}: {}", disk.device_path, disk.slot, error));
+            failed = true;
+        }
+    }
+
+    if failed {
+        Err(())
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns an error if an enclosure could not be scanned.
+///
+/// The disks of the healthy enclosures are listed first, then this function
+/// reports the failures on stderr in the selected format.
+///
+/// # Arguments
+///
+/// * `run` - the run Context holding the failures
+/// * `format` - the selected output format
+///
+fn verify_scan(run: &Context::Run, format: Output::Format) -> Result<(), ()> {
+    let errors = run.errors();
+    Output::render_errors(format, &errors);
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(())
+    }
+}
+
+/// The columns of the disk tree, in the order they are printed.
+const DISK_COLUMNS: &[&str] = &[
+    "device", "map", "slot", "label", "role", "vendor", "model", "serial", "wwn", "sas", "temp",
+    "fw", "sed", "power", "io-errors", "io", "wear", "paths", "problems",
+];
+
+/// Returns the columns of the disk tree selected by `--columns`, all of
+/// them by default, the problems only with `--unhealthy`.
+///
+/// # Arguments
+///
+/// * `option` - clappy's ArgMatches of the `list` subcommand
+///
+fn disk_columns(option: &ArgMatches) -> Vec<&str> {
+    match option.values_of("columns") {
+        Some(columns) => columns.collect(),
+        None => DISK_COLUMNS
+            .iter()
+            .copied()
+            .filter(|c| *c != "problems" || option.is_present("unhealthy"))
+            .collect(),
+    }
+}
+
+/// Prints the disks of an enclosure as a tree below the enclosure table.
+///
+/// # Arguments
+///
+/// * `enc` - the enclosure
+/// * `disks` - the disks, the ones of other enclosures are skipped
+/// * `unit` - the unit of the temperatures
+/// * `columns` - the columns shown, see `disk_columns()`
+///
+fn print_disk_tree(
+    enc: &BackPlane::Enclosure,
+    disks: &[DiskShelf::Disk],
+    unit: TemperatureUnit,
+    columns: &[&str],
+) {
+    let shown = |column: &str| columns.contains(&column);
+    println!("     '");
+    for disk in disks {
+        if enc.slot == disk.enclosure {
+            print!("     `+-");
+            if shown("device") {
+                print!(" Disk: {:<10}", disk.device_path.green(),);
+            }
+            if !shown("map") {
+            } else if disk.device_map == "NONE" {
+                print!(" Map: {:<10}", disk.device_map.yellow());
+            } else {
+                print!(" Map: {:<10}", disk.device_map.green());
+            }
+            if shown("slot") {
+                print!(" Slot: {:<10}", disk.slot.green());
+            }
+            if let Some(label) = disk.label.as_ref().filter(|_| shown("label")) {
+                print!(" Label: {:<6}", label.green());
+            }
+            if let Some(role) = disk.role.as_ref().filter(|_| shown("role")) {
+                print!(" Role: {:<8}", role.to_string().cyan());
+            }
+            if shown("vendor") {
+                print!(" Vendor: {:<10}", disk.vendor.blue());
+            }
+            if shown("model") {
+                print!(" Model: {:<10}", disk.model.blue());
+            }
+            if shown("serial") {
+                print!(" Serial: {:<10}", disk.serial.blue());
+            }
+            if !disk.wwn.is_empty() && shown("wwn") {
+                print!(" WWN: {}", disk.wwn.blue());
+            }
+            if !disk.sas_address.is_empty() && shown("sas") {
+                print!(" SAS: {}", disk.sas_address.blue());
+            }
+            if shown("temp") {
+                match color_temp(&disk.temperature, unit) {
+                    Some((temp_colored, unit_colored)) => print!(" Temp: {}{:<2}", temp_colored, unit_colored),
+                    None => match disk.temperature {
+                        DiskShelf::Temperature::Unsupported => print!(" Temp: {:<4}", "N/A".yellow()),
+                        DiskShelf::Temperature::NotCollected => print!(" Temp: {:<4}", "-"),
+                        _ => print!(" Temp: {:<4}", "ERR".red().bold().blink()),
+                    },
+                }
+            }
+            if shown("fw") {
+                print!(" Fw: {}", disk.fw_revision.blue());
+            }
+            if let Some(sed) = disk.sed.as_ref().filter(|_| shown("sed")) {
+                if sed.locked {
+                    print!(" SED: {}", sed.to_string().red().bold());
+                } else {
+                    print!(" SED: {}", sed.to_string().blue());
+                }
+            }
+            if let Some(power) = disk.power.as_ref().filter(|_| shown("power")) {
+                if *power == Standby::Power::Active {
+                    print!(" Power: {}", power.to_string().green());
+                } else {
+                    print!(" Power: {}", power.to_string().yellow());
+                }
+            }
+            if let Some(counters) = disk.io_errors.as_ref().filter(|_| shown("io-errors")) {
+                if counters.ioerr_delta.unwrap_or(0) > 0 {
+                    print!(" IO errors: {}", counters.to_string().red().bold());
+                } else if counters.ioerr > 0 {
+                    print!(" IO errors: {}", counters.to_string().yellow());
+                } else {
+                    print!(" IO errors: {}", counters.to_string().green());
+                }
+            }
+            if let Some(io) = disk.io.as_ref().filter(|_| shown("io")) {
+                if io.busy_percent >= 90.0 {
+                    print!(" IO: {}", io.to_string().red().bold());
+                } else if io.busy_percent >= 50.0 {
+                    print!(" IO: {}", io.to_string().yellow());
+                } else {
+                    print!(" IO: {}", io.to_string().green());
+                }
+            }
+            if let Some(wear) = disk.wear.filter(|_| shown("wear")) {
+                if Wear::worn(wear) {
+                    print!(" Wear: {}", format!("{}%", wear).red().bold());
+                } else {
+                    print!(" Wear: {}", format!("{}%", wear).green());
+                }
+            }
+            if !disk.alternate_paths.is_empty() && shown("paths") {
+                print!(" Paths: {}", disk.alternate_paths.join(",").green());
+            }
+            if shown("problems") {
+                let kinds: Vec<&str> = Health::disk_findings(disk).iter().map(|f| f.kind).collect();
+                if !kinds.is_empty() {
+                    print!(" Problems: {}", kinds.join(",").red().bold());
+                }
+            }
+            println!();
+        }
+    }
+}
+
+/// Adds the fields only queried on request to the disks, the SED state
+/// with `--sed`, the power condition with `--power-condition`, the I/O
+/// error counters with `--io-errors`, the activity with `--io` and the
+/// SSD endurance with `--wear`.
+///
+/// With `--unhealthy` only the disks with a problem are kept, before the
+/// other fields are queried.
+///
+/// # Arguments
+///
+/// * `option` - clappy's ArgMatches of the `list` subcommand
+/// * `disks` - the disks to query
+/// * `jobs` - the disks queried at the same time
+///
+fn annotate_disks(option: &ArgMatches, disks: &mut Vec<DiskShelf::Disk>, jobs: usize) {
+    let unhealthy = option.is_present("unhealthy");
+    if unhealthy {
+        Wear::annotate(disks, jobs, true);
+        Health::annotate_predicted_failures(disks, jobs);
+        disks.retain(|d| !Health::disk_findings(d).is_empty());
+    }
+    if option.is_present("sed") {
+        Sed::annotate(disks, jobs);
+    }
+    if option.is_present("power-condition") {
+        Standby::annotate(disks, jobs);
+    }
+    if option.is_present("io-errors") {
+        IoErrors::annotate(disks, jobs);
+    }
+    if option.is_present("io") {
+        let interval = option
+            .value_of("io-interval")
+            .and_then(Util::parse_duration)
+            .unwrap_or(DiskStats::DEFAULT_INTERVAL);
+        DiskStats::annotate(disks, interval);
+    }
+    if option.is_present("wear") && !unhealthy {
+        Wear::annotate(disks, jobs, false);
+    }
+}
+
+/// Returns an empty Result or the rendering error, the records are
+/// written with the `--format` template when there is one, in the output
+/// format otherwise.
+///
+/// # Arguments
+///
+/// * `format` - the selected output format
+/// * `template` - the `--format` template of the `list` subcommand
+/// * `records` - the records to write
+///
+fn render_records<T: serde::Serialize>(
+    format: Output::Format,
+    template: Option<&str>,
+    records: &[T],
+) -> Result<(), String> {
+    match template {
+        Some(template) => Output::render_template(template, records),
+        None => Output::render(format, records),
+    }
+}
+
 /// TODO: Rework error handling, perhaps we don't need return Result
 ///
 /// Returns an empty Result for now.
@@ -90,127 +443,530 @@ fn color_temp(temperature: &str) -> Option<(ColoredString, ColoredString)> {
 ///
 fn enclosure_overview(option: &ArgMatches) -> Result<(), ()> {
     let disks_option = option.is_present("disks");
-    let enclosure_option = option.is_present("enclosure");
+    let enclosure_option = option.is_present("list-enclosure") || option.is_present("enclosure");
     let fan_option = option.is_present("fan");
+    let elements_option = option.is_present("elements");
+    let hba_option = option.is_present("hba");
+    let chain_option = option.is_present("chain");
+    let collectors_option = option.is_present("collectors");
+    let power_option = option.is_present("power");
+    let format = Output::get_format(option);
+    let template = option.value_of("format");
+    let sort = option.value_of("sort").unwrap_or("slot");
+    let strict = option.is_present("strict");
+    let run = Context::Run::new(DiskShelf::ScanOptions::from_matches(option));
+    let scan = &run.options;
+    let unit = if option.is_present("fahrenheit") {
+        TemperatureUnit::Fahrenheit
+    } else {
+        Config::get().temperature_unit
+    };
+
+    if let Some(id) = &scan.enclosure {
+        if DiskShelf::jbod_enclosures(&run).is_empty() {
+            Output::print_error_code(ErrorCode::NotFound, &I18n::tr(Message::NoEnclosure, &[&id]));
+            return Err(());
+        }
+    }
+
+    // JSON Lines are streamed, the records of each enclosure are written
+    // once it is scanned. A cached topology is already complete.
+    if format == Output::Format::Jsonl && scan.cache.is_none() && (disks_option || elements_option) {
+        let mut disks: Vec<DiskShelf::Disk> = Vec::new();
+        for enc in DiskShelf::jbod_enclosures(&run) {
+            if !disks_option {
+                let elements = BackPlane::get_enclosure_elements(std::slice::from_ref(&enc), scan.jobs);
+                Output::render(format, &elements).map_err(|e| Output::print_error(&e))?;
+                continue;
+            }
+            let mut enclosure_disks = DiskShelf::jbod_enclosure_disks(&run, &enc);
+            if !scan.raw_paths {
+                enclosure_disks = DiskShelf::merge_paths(&mut disks, enclosure_disks);
+            } else {
+                disks.extend(enclosure_disks.clone());
+            }
+            DiskShelf::sort_disks_by(&mut enclosure_disks, sort);
+            annotate_disks(option, &mut enclosure_disks, scan.jobs);
+            Output::render(format, &enclosure_disks).map_err(|e| Output::print_error(&e))?;
+        }
+        let scanned = verify_scan(&run, format);
+        return if strict { scanned.and(verify_strict(&disks, scan)) } else { scanned };
+    }
+
+    // Machine readable outputs are flat lists of records, the disks
+    // carry their enclosure so `-ed` and `-d` are the same here. A
+    // `--format` template writes a line per record the same way.
+    if format != Output::Format::Table || template.is_some() {
+        let rendered = if disks_option {
+            let mut disks = DiskShelf::jbod_disk_map(&run);
+            DiskShelf::sort_disks_by(&mut disks, sort);
+            annotate_disks(option, &mut disks, scan.jobs);
+            render_records(format, template, &disks).map_err(|e| Output::print_error(&e))?;
+            let scanned = verify_scan(&run, format);
+            return if strict { scanned.and(verify_strict(&disks, scan)) } else { scanned };
+        } else if enclosure_option {
+            let mut enclosures = DiskShelf::jbod_enclosures(&run);
+            BackPlane::read_controllers(&mut enclosures, scan.jobs);
+            render_records(format, template, &enclosures)
+        } else if fan_option {
+            let enclosures = DiskShelf::jbod_enclosures(&run);
+            render_records(format, template, &BackPlane::get_enclosure_fan(&enclosures, scan.jobs))
+        } else if elements_option {
+            let enclosures = DiskShelf::jbod_enclosures(&run);
+            render_records(format, template, &BackPlane::get_enclosure_elements(&enclosures, scan.jobs))
+        } else if hba_option {
+            render_records(format, template, &Topology::hbas(&run))
+        } else if chain_option {
+            render_records(format, template, &Topology::daisy_chains(&run))
+        } else if collectors_option {
+            let readings = Plugin::collect(&run);
+            render_records(format, template, &readings).map_err(|e| Output::print_error(&e))?;
+            return verify_scan(&run, format);
+        } else if power_option {
+            let enclosures = DiskShelf::jbod_enclosures(&run);
+            render_records(format, template, &BackPlane::get_enclosure_power(&enclosures, scan.jobs))
+        } else {
+            Ok(())
+        };
+        return rendered.map_err(|e| Output::print_error(&e));
+    }
 
     // If the options `-ed` or `-d` are used, it shows
     // the enclosure and disks altogether.
-    if enclosure_option && disks_option || disks_option {
-        let enclosure = BackPlane::get_enclosure();
-        let mut disks = DiskShelf::jbod_disk_map();
-        disks.sort_by_key(|d| d.slot.clone());
-        for enc in enclosure {
-            print!("{}", enc);
-            println!("     '");
-            for disk in &disks {
-                if enc.slot == disk.enclosure {
-                    print!("     `+-");
-                    print!(" Disk: {:<10}", disk.device_path.green(),);
-                    if disk.device_map == "NONE" {
-                        print!(" Map: {:<10}", disk.device_map.yellow());
-                    } else {
-                        print!(" Map: {:<10}", disk.device_map.green());
-                    }
-                    print!(" Slot: {:<10}", disk.slot.green());
-                    print!(" Vendor: {:<10}", disk.vendor.blue());
-                    print!(" Model: {:<10}", disk.model.blue());
-                    print!(" Serial: {:<10} ", disk.serial.blue());
-                    match color_temp(&disk.temperature) {
-                        Some((temp_colored, unit_colored)) => print!("Temp: {}{:<2}", temp_colored, unit_colored),
-                        None => print!("Temp: {:<4}", "ERR".red().bold().blink()),
-                    }
-                    println!(" Fw: {}", disk.fw_revision.blue());
+    if disks_option {
+        let enclosure = DiskShelf::jbod_enclosures(&run);
+        let columns = disk_columns(option);
+        let mut disks: Vec<DiskShelf::Disk> = Vec::new();
+        // On a terminal each enclosure is printed once scanned, a cached
+        // topology is already complete so it is printed at once.
+        if io::stdout().is_terminal() && scan.cache.is_none() {
+            for enc in enclosure {
+                print!("{}", enc);
+                let mut enclosure_disks = DiskShelf::jbod_enclosure_disks(&run, &enc);
+                // The disks already printed through another path are
+                // only recorded, their extra path isn't shown.
+                if !scan.raw_paths {
+                    enclosure_disks = DiskShelf::merge_paths(&mut disks, enclosure_disks);
+                } else {
+                    disks.extend(enclosure_disks.clone());
                 }
+                DiskShelf::sort_disks_by(&mut enclosure_disks, sort);
+                annotate_disks(option, &mut enclosure_disks, scan.jobs);
+                print_disk_tree(&enc, &enclosure_disks, unit, &columns);
+            }
+        } else {
+            disks = DiskShelf::jbod_disk_map(&run);
+            DiskShelf::sort_disks_by(&mut disks, sort);
+            annotate_disks(option, &mut disks, scan.jobs);
+            for enc in enclosure {
+                print!("{}", enc);
+                print_disk_tree(&enc, &disks, unit, &columns);
             }
         }
+
+        let scanned = verify_scan(&run, format);
+        return if strict { scanned.and(verify_strict(&disks, scan)) } else { scanned };
     // Here it shows only the enclosures.
-    } else if enclosure_option && !disks_option {
-        let enclosure = BackPlane::get_enclosure();
+    } else if enclosure_option {
+        let mut enclosure = DiskShelf::jbod_enclosures(&run);
+        BackPlane::read_controllers(&mut enclosure, scan.jobs);
         for enc in enclosure {
             print!("{}", enc);
         }
     // Here it shows the FAN.
     } else if fan_option {
-        let enclosure_fan = BackPlane::get_enclosure_fan();
+        let enclosures = DiskShelf::jbod_enclosures(&run);
+        let enclosure_fan = BackPlane::get_enclosure_fan(&enclosures, scan.jobs);
         let mut fan_table = BackPlane::create_fan_table();
         for fan in enclosure_fan {
             fan_table.add_row(Row::new(vec![
                 Cell::new(&fan.slot),
                 Cell::new(&fan.index),
                 Cell::new(&fan.description),
+                BackPlane::status_cell(fan.status),
                 Cell::new(&fan.comment),
                 Cell::new(&fan.speed.to_string()),
             ]));
         }
-        fan_table.printstd();
+        Output::print_table(&fan_table);
+    // Here it shows every SES element.
+    } else if elements_option {
+        let enclosures = DiskShelf::jbod_enclosures(&run);
+        let mut element_table = BackPlane::create_element_table();
+        for element in BackPlane::get_enclosure_elements(&enclosures, scan.jobs) {
+            element_table.add_row(Row::new(vec![
+                Cell::new(&element.slot),
+                Cell::new(&element.index),
+                Cell::new(&element.element_type),
+                Cell::new(&element.description),
+                BackPlane::status_cell(element.status),
+                Cell::new(&element.reading),
+            ]));
+        }
+        Output::print_table(&element_table);
+    // Here it shows the controllers.
+    } else if hba_option {
+        let mut hba_table = Topology::create_hba_table();
+        let unknown = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+        for hba in Topology::hbas(&run) {
+            hba_table.add_row(Row::new(vec![
+                Cell::new(&hba.host),
+                Cell::new(hba.transport),
+                Cell::new(&hba.driver),
+                Cell::new(&unknown(&hba.model)),
+                Cell::new(&unknown(&hba.firmware)),
+                Cell::new(&unknown(&hba.bios)),
+                Cell::new(&unknown(&hba.pci)),
+                Cell::new(&hba.enclosures.to_string()),
+            ]));
+        }
+        Output::print_table(&hba_table);
+    // Here it shows the order of the daisy chained shelves.
+    } else if chain_option {
+        let mut chain_table = Topology::create_chain_table();
+        let cable = |port: &Option<String>, serial: &Option<String>| match (port, serial) {
+            (Some(port), Some(serial)) => format!("{} ({})", port, serial),
+            (Some(port), None) => port.clone(),
+            _ => "-".to_string(),
+        };
+        for shelf in Topology::daisy_chains(&run) {
+            chain_table.add_row(Row::new(vec![
+                Cell::new(&shelf.host),
+                Cell::new(&shelf.port),
+                Cell::new(&shelf.position.to_string()).with_style(Attr::Bold),
+                Cell::new(&shelf.enclosure),
+                Cell::new(&shelf.serial),
+                Cell::new(&shelf.model),
+                Cell::new(&cable(&shelf.upstream, &shelf.upstream_enclosure)),
+                Cell::new(&cable(&shelf.downstream, &shelf.downstream_enclosure)),
+            ]));
+        }
+        Output::print_table(&chain_table);
+    // Here it shows what the collectors read on each enclosure.
+    } else if collectors_option {
+        if Plugin::collectors().is_empty() {
+            Output::print_warning("no collector is configured");
+            return Ok(());
+        }
+        let mut collector_table = Plugin::create_collector_table();
+        for reading in Plugin::collect(&run) {
+            collector_table.add_row(Plugin::collector_row(&reading));
+        }
+        Output::print_table(&collector_table);
+        return verify_scan(&run, format);
+    // Here it shows the power drawn by each enclosure.
+    } else if power_option {
+        let enclosures = DiskShelf::jbod_enclosures(&run);
+        let readings = BackPlane::get_enclosure_power(&enclosures, scan.jobs);
+        if readings.is_empty() {
+            Output::print_warning("no enclosure reports current sensors");
+            return Ok(());
+        }
+        let mut power_table = BackPlane::create_power_table();
+        let decimal = |value: Option<f64>| value.map_or("-".to_string(), |v| format!("{:.2}", v));
+        for enclosure in &enclosures {
+            let enclosure_readings: Vec<_> =
+                readings.iter().filter(|r| r.slot == enclosure.slot).collect();
+            for reading in &enclosure_readings {
+                power_table.add_row(Row::new(vec![
+                    Cell::new(&reading.slot),
+                    Cell::new(&reading.serial),
+                    Cell::new(&reading.sensor),
+                    Cell::new(&decimal(reading.volts)),
+                    Cell::new(&decimal(Some(reading.amps))),
+                    Cell::new(&decimal(reading.watts)),
+                ]));
+            }
+            if enclosure_readings.iter().any(|r| r.watts.is_some()) {
+                let total: f64 = enclosure_readings.iter().filter_map(|r| r.watts).sum();
+                power_table.add_row(Row::new(vec![
+                    Cell::new(&enclosure.slot).with_style(Attr::Bold),
+                    Cell::new(&enclosure.serial).with_style(Attr::Bold),
+                    Cell::new("Total").with_style(Attr::Bold),
+                    Cell::new(""),
+                    Cell::new(""),
+                    Cell::new(&format!("{:.2}", total)).with_style(Attr::Bold),
+                ]));
+            }
+        }
+        Output::print_table(&power_table);
     }
 
     Ok(())
 }
 
-/// TODO: Rework error handling, perhaps we don't need return Result 
-///
-/// Returns an empty Result for now.
+/// Returns the pid recorded in a pidfile when that process is still alive.
+fn running_pid(pidfile: &str) -> Option<i32> {
+    let pid: i32 = std::fs::read_to_string(pidfile).ok()?.trim().parse().ok()?;
+    kill(Pid::from_raw(pid), None).ok().map(|_| pid)
+}
+
+/// Prints why the prometheus-exporter can't run, a missing binary is a
+/// missing package.
+fn print_exporter_error(e: &io::Error) {
+    let code = match e.kind() {
+        io::ErrorKind::NotFound => ErrorCode::ToolMissing,
+        _ => ErrorCode::from_io(e),
+    };
+    Output::print_error_code(code, &format!("cannot run {}: {}", Util::JBOD_EXPORTER, e));
+}
+
+/// Returns an error if the prometheus-exporter can't be started.
 ///
-/// This function forks another binary for the prometheus-exporter. 
+/// By default the exporter replaces this process and stays in the
+/// foreground, which is what systemd expects. With `--daemonize` it is
+/// started in a new session detached from the terminal, its pid is written
+/// in the `--pidfile` and this process returns at once.
 ///
 /// # Arguments
 ///
-/// * `option` - clappy's ArgMatches
+/// * `option` - clappy's ArgMatches of the `prometheus` subcommand
+/// * `config` - the configuration file given on the command line
 ///
-fn fork_prometheus(option: &ArgMatches) -> Result<(), ()> {
-    let mut default_port = "9945";
-    let mut default_address = "0.0.0.0";
+fn start_prometheus(option: &ArgMatches, config: Option<&str>) -> Result<(), ()> {
+    let port = option.value_of("port").unwrap_or("9945");
+    let address = option.value_of("ip-address").unwrap_or("0.0.0.0");
 
-    if let Some(port) = option.value_of("port") {
-        default_port = port;
+    let mut command = Command::new(Util::JBOD_EXPORTER);
+    command
+        .args([address, port])
+        .env(Util::SYSFS_ROOT_ENV, Util::sysfs_root());
+    if let Some(config) = config {
+        command.env(Config::CONFIG_ENV, config);
     }
 
-    if let Some(ip) = option.value_of("ip-address") {
-        default_address = ip;
+    if !option.is_present("daemonize") {
+        print_exporter_error(&command.exec());
+        return Err(());
     }
 
-    match unsafe { fork() } {
-        Ok(ForkResult::Parent { child }) => {
-            println!("prometheus-exporter pid: {:?}", child);
-            waitpid(Some(child), None).unwrap();
-            exit(0);
-        }
+    let pidfile = option.value_of("pidfile").unwrap_or(DEFAULT_PIDFILE);
+    if let Some(pid) = running_pid(pidfile) {
+        Output::print_error(&format!("the prometheus-exporter already runs with pid {}", pid));
+        Output::print_hint(&format!("stop it or remove {}", pidfile));
+        return Err(());
+    }
 
-        Ok(ForkResult::Child) => {
-            Command::new(Util::JBOD_EXPORTER)
-                .args(&[default_address, default_port])
-                .spawn()
-                .expect("Failed to spawn the target process");
-            exit(0);
+    // The exporter logs every scrape on stderr, they go to the log file
+    // when one is given.
+    let log = match option.value_of("log") {
+        Some(path) => match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => Stdio::from(file),
+            Err(e) => {
+                Output::print_error_code(
+                    ErrorCode::from_io(&e),
+                    &I18n::tr(Message::CannotOpen, &[&path, &e]),
+                );
+                return Err(());
+            }
+        },
+        None => Stdio::null(),
+    };
+    command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(log);
+    // A process group of its own so the exporter survives Ctrl-C and the
+    // hangup of the jobs of the shell.
+    command.process_group(0);
+
+    let child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            print_exporter_error(&e);
+            return Err(());
         }
-        Err(_) => println!("Fork Failed"),
+    };
+    if let Err(e) = std::fs::write(pidfile, format!("{}\n", child.id())) {
+        Output::print_error_code(ErrorCode::from_io(&e), &format!("cannot write {}: {}", pidfile, e));
+        let _ = kill(Pid::from_raw(child.id() as i32), Signal::SIGTERM);
+        return Err(());
     }
+    println!("prometheus-exporter pid: {}", child.id());
 
     Ok(())
 }
 
 /// The main function that creates the menu.
 fn main() {
-    Util::verify_binary_needed();
 
     let matches = App::new("jbod")
-        .version("0.0.1")
+        .version(Util::VERSION)
+        .long_version(Util::LONG_VERSION)
         .author("\nAuthor: Marcelo Araujo <marcelo.araujo@gandi.net>")
         .about("About: A generic storage enclosure tool")
+        .arg(
+            Arg::with_name("output")
+                .short('o')
+                .long("output")
+                .global(true)
+                .required(false)
+                .takes_value(true)
+                .value_name("FORMAT")
+                .possible_values(Output::FORMATS)
+                .default_value("table")
+                .help("Output format"),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .global(true)
+                .required(false)
+                .takes_value(false)
+                .help("Shorthand for --output json"),
+        )
+        .arg(
+            Arg::with_name("csv")
+                .long("csv")
+                .global(true)
+                .required(false)
+                .takes_value(false)
+                .help("Shorthand for --output csv"),
+        )
+        .arg(
+            Arg::with_name("tsv")
+                .long("tsv")
+                .global(true)
+                .required(false)
+                .takes_value(false)
+                .help("Shorthand for --output tsv"),
+        )
+        .arg(
+            Arg::with_name("no-color")
+                .long("no-color")
+                .global(true)
+                .required(false)
+                .takes_value(false)
+                .help("Turn the colors off, like a NO_COLOR environment variable or TERM=dumb"),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .global(true)
+                .required(false)
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Configuration file, default /etc/jbod/jbod.toml"),
+        )
+        .arg(
+            Arg::with_name("fahrenheit")
+                .long("fahrenheit")
+                .global(true)
+                .required(false)
+                .takes_value(false)
+                .help("Display temperatures in Fahrenheit"),
+        )
+        .arg(
+            Arg::with_name("strict")
+                .long("strict")
+                .global(true)
+                .required(false)
+                .takes_value(false)
+                .help("Exit with an error when any disk field cannot be collected"),
+        )
+        .arg(
+            Arg::with_name("jobs")
+                .short('j')
+                .long("jobs")
+                .global(true)
+                .required(false)
+                .takes_value(true)
+                .value_name("N")
+                .validator(|v| v.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+                .help("Number of devices probed at the same time, default 8"),
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .long("timeout")
+                .global(true)
+                .required(false)
+                .takes_value(true)
+                .value_name("DURATION")
+                .validator(|v| {
+                    Util::parse_duration(v)
+                        .map(|_| ())
+                        .ok_or("invalid duration, example: 5s")
+                })
+                .help("Timeout of each external command, default 5s"),
+        )
+        .arg(
+            Arg::with_name("sysfs-root")
+                .long("sysfs-root")
+                .global(true)
+                .required(false)
+                .takes_value(true)
+                .value_name("DIR")
+                .validator(|v| {
+                    if Path::new(v).is_dir() {
+                        Ok(())
+                    } else {
+                        Err(format!("{} is not a directory", v))
+                    }
+                })
+                .help("Where sysfs is mounted, default $JBOD_SYSFS_ROOT or /sys"),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short('q')
+                .long("quiet")
+                .global(true)
+                .required(false)
+                .takes_value(false)
+                .help("Don't draw the progress bars and the spinners"),
+        )
+        .arg(
+            Arg::with_name("cache")
+                .long("cache")
+                .global(true)
+                .required(false)
+                .takes_value(true)
+                .value_name("TTL")
+                .validator(|v| {
+                    Util::parse_duration(v)
+                        .map(|_| ())
+                        .ok_or("invalid duration, example: 30s")
+                })
+                .help("Reuse the topology discovered less than TTL ago, example: 30s"),
+        )
+        .arg(
+            Arg::with_name("no-cache")
+                .long("no-cache")
+                .global(true)
+                .required(false)
+                .takes_value(false)
+                .conflicts_with("cache")
+                .help("Scan again without reading the topology cache"),
+        )
+        .arg(
+            Arg::with_name("lock")
+                .long("lock")
+                .global(true)
+                .required(false)
+                .takes_value(false)
+                .help("Wait for the other jbod, scans share the lock and control operations take it alone"),
+        )
         .subcommand(
             SubCommand::with_name("list")
                 .about("list")
                 .arg_required_else_help(true)
                 .arg(
-                    Arg::with_name("enclosure")
+                    Arg::with_name("list-enclosure")
                         .short('e')
-                        .long("enclosure")
                         .multiple(false)
                         .required(false)
                         .takes_value(false)
                         .help("List enclosure"),
                 )
+                // Apart from -e so -ed still lists the enclosures and their
+                // disks.
+                .arg(
+                    Arg::with_name("enclosure")
+                        .long("enclosure")
+                        .multiple(false)
+                        .required(false)
+                        .takes_value(true)
+                        .min_values(0)
+                        .max_values(1)
+                        .value_name("SLOT|SERIAL|ALIAS")
+                        .help("List enclosure, or only scan the given enclosure"),
+                )
                 .arg(
                     Arg::with_name("disks")
                         .short('d')
@@ -229,6 +985,142 @@ fn main() {
                         .takes_value(false)
                         .exclusive(false)
                         .help("List fan"),
+                )
+                .arg(
+                    Arg::with_name("elements")
+                        .long("elements")
+                        .required(false)
+                        .takes_value(false)
+                        .help("List every SES element, like power supplies and sensors"),
+                )
+                .arg(
+                    Arg::with_name("hba")
+                        .long("hba")
+                        .required(false)
+                        .takes_value(false)
+                        .help("List the SAS and Fibre Channel controllers"),
+                )
+                .arg(
+                    Arg::with_name("chain")
+                        .long("chain")
+                        .required(false)
+                        .takes_value(false)
+                        .help("List the daisy chained enclosures in their cabling order"),
+                )
+                .arg(
+                    Arg::with_name("collectors")
+                        .long("collectors")
+                        .required(false)
+                        .takes_value(false)
+                        .help("List the readings of the collectors, see [[collectors]] in the configuration"),
+                )
+                .arg(
+                    Arg::with_name("power")
+                        .long("power")
+                        .required(false)
+                        .takes_value(false)
+                        .help("List the power drawn from the current sensors of the enclosures"),
+                )
+                .arg(
+                    Arg::with_name("raw-paths")
+                        .long("raw-paths")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Show dual-ported disks once per path instead of merged"),
+                )
+                .arg(
+                    Arg::with_name("fields")
+                        .long("fields")
+                        .required(false)
+                        .takes_value(true)
+                        .use_value_delimiter(true)
+                        .value_name("FIELDS")
+                        .possible_values(["temperature", "firmware", "serial", "none"])
+                        .help("Expensive disk fields to collect, all by default"),
+                )
+                .arg(
+                    Arg::with_name("sed")
+                        .long("sed")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Query the self-encrypting drive state of the disks"),
+                )
+                .arg(
+                    Arg::with_name("power-condition")
+                        .long("power-condition")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Query whether the disks are active, idle or spun down"),
+                )
+                .arg(
+                    Arg::with_name("io-errors")
+                        .long("io-errors")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Show the I/O error counters and the errors since the last listing"),
+                )
+                .arg(
+                    Arg::with_name("io")
+                        .long("io")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Show the IOPS, throughput, requests in flight and busy time of the disks"),
+                )
+                .arg(
+                    Arg::with_name("io-interval")
+                        .long("io-interval")
+                        .required(false)
+                        .takes_value(true)
+                        .value_name("DURATION")
+                        .requires("io")
+                        .validator(|v| {
+                            Util::parse_duration(v)
+                                .filter(|d| !d.is_zero())
+                                .map(|_| ())
+                                .ok_or("invalid duration, example: 5s")
+                        })
+                        .help("How long the activity is measured, default 1s"),
+                )
+                .arg(
+                    Arg::with_name("wear")
+                        .long("wear")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Query the percentage of the rated endurance used by the SSDs"),
+                )
+                .arg(
+                    Arg::with_name("unhealthy")
+                        .long("unhealthy")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Only show the disks with a problem, failed, overheating, worn or unmapped"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .required(false)
+                        .takes_value(true)
+                        .value_name("TEMPLATE")
+                        .help("Write a line per record from a template of {field}, example: '{slot}\\t{serial}'"),
+                )
+                .arg(
+                    Arg::with_name("columns")
+                        .long("columns")
+                        .required(false)
+                        .takes_value(true)
+                        .use_value_delimiter(true)
+                        .value_name("COLUMNS")
+                        .possible_values(DISK_COLUMNS)
+                        .help("Columns of the disks shown below the enclosures, all by default"),
+                )
+                .arg(
+                    Arg::with_name("sort")
+                        .long("sort")
+                        .required(false)
+                        .takes_value(true)
+                        .value_name("KEY")
+                        .possible_values(DiskShelf::SORT_KEYS)
+                        .help("Sort the disks by slot, temp, serial or device in natural order, slot by default"),
                 ),
         )
         .subcommand(
@@ -240,8 +1132,10 @@ fn main() {
                         .long("locate")
                         .required(false)
                         .multiple(true)
-                        .value_name("DEVICE")
-                        .takes_value(true),
+                        .min_values(0)
+                        .value_name("SERIAL|WWN|DEVICE")
+                        .takes_value(true)
+                        .help("The locate led of the disk, or of the --slot bays without a disk"),
                 )
                 .arg(
                     Arg::with_name("fault")
@@ -249,12 +1143,626 @@ fn main() {
                         .long("fault")
                         .required(false)
                         .multiple(true)
-                        .value_name("DEVICE")
-                        .takes_value(true),
+                        .min_values(0)
+                        .value_name("SERIAL|WWN|DEVICE")
+                        .takes_value(true)
+                        .help("The fault led of the disk, or of the --slot bays without a disk"),
+                )
+                .arg(
+                    Arg::with_name("slot")
+                        .long("slot")
+                        .required(false)
+                        .multiple(true)
+                        .takes_value(true)
+                        .value_name("SLOT")
+                        .help("A bay, <enclosure>/<slot> or a slot of --enclosure, even when its disk is dead"),
+                )
+                .arg(
+                    Arg::with_name("enclosure")
+                        .long("enclosure")
+                        .required(false)
+                        .takes_value(true)
+                        .requires("slot")
+                        .value_name("SLOT|SERIAL|ALIAS")
+                        .help("The enclosure of the --slot bays"),
                 )
                 .arg(Arg::with_name("on").long("on").required(false))
                 .arg(Arg::with_name("off").long("off").required(false)),
         )
+        .subcommand(
+            SubCommand::with_name("locate")
+                .about("Find a disk and turn its locate led on")
+                .arg(
+                    Arg::with_name("disk")
+                        .required(true)
+                        .value_name("SERIAL|WWN|DEVICE")
+                        .help("The disk, also <enclosure>/<slot>"),
+                )
+                .arg(
+                    Arg::with_name("off")
+                        .long("off")
+                        .help("Turn the locate led off instead"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("find")
+                .about("Search the disks for a text in any of their identifiers")
+                .arg(
+                    Arg::with_name("text")
+                        .required(true)
+                        .value_name("TEXT")
+                        .help("Matched against serial, model, devices, slot, wwn..., case insensitive"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("show")
+                .about("Print everything known about a disk")
+                .arg(
+                    Arg::with_name("device")
+                        .required(true)
+                        .value_name("DEVICE")
+                        .help("A device node, or a serial, a WWN, <enclosure>/<slot> of a disk"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("firmware")
+                .about("Update the firmware of a device")
+                .subcommand(
+                    SubCommand::with_name("enclosure")
+                        .about("Download a microcode image to an enclosure with WRITE BUFFER")
+                        .arg(
+                            Arg::with_name("enclosure")
+                                .required(true)
+                                .value_name("SLOT|SERIAL|ALIAS|SG")
+                                .help("The enclosure to update"),
+                        )
+                        .arg(
+                            Arg::with_name("file")
+                                .long("file")
+                                .required(true)
+                                .takes_value(true)
+                                .value_name("FILE")
+                                .help("The microcode image"),
+                        )
+                        .arg(
+                            Arg::with_name("sha256")
+                                .long("sha256")
+                                .required(false)
+                                .takes_value(true)
+                                .value_name("HEX")
+                                .help("Refuse the image unless it has this checksum"),
+                        )
+                        .arg(
+                            Arg::with_name("chunk")
+                                .long("chunk")
+                                .required(false)
+                                .takes_value(true)
+                                .value_name("BYTES")
+                                .validator(|v| match v.parse::<usize>() {
+                                    Ok(c) if c > 0 && c <= 0xff_ffff => Ok(()),
+                                    _ => Err("a size between 1 and 16777215 bytes"),
+                                })
+                                .help("Size of each WRITE BUFFER, default 4096"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("disk")
+                        .about("Download a firmware image to the disks of a model with WRITE BUFFER")
+                        .arg(
+                            Arg::with_name("model")
+                                .long("model")
+                                .required(true)
+                                .takes_value(true)
+                                .value_name("MODEL")
+                                .help("The model of the disks, a prefix like ST16000NM"),
+                        )
+                        .arg(
+                            Arg::with_name("file")
+                                .long("file")
+                                .required(true)
+                                .takes_value(true)
+                                .value_name("FILE")
+                                .help("The firmware image"),
+                        )
+                        .arg(
+                            Arg::with_name("all-matching")
+                                .long("all-matching")
+                                .required(false)
+                                .takes_value(false)
+                                .help("Update every disk of the model, not only a single one"),
+                        )
+                        .arg(
+                            Arg::with_name("dry-run")
+                                .long("dry-run")
+                                .required(false)
+                                .takes_value(false)
+                                .help("Only list the disks that would be updated"),
+                        )
+                        .arg(
+                            Arg::with_name("mode")
+                                .long("mode")
+                                .required(false)
+                                .takes_value(true)
+                                .value_name("MODE")
+                                .possible_values(["7", "e"])
+                                .help("WRITE BUFFER mode, 7 activates after the last piece, e then activates with mode f, default 7"),
+                        )
+                        .arg(
+                            Arg::with_name("force")
+                                .long("force")
+                                .required(false)
+                                .takes_value(false)
+                                .help("Update the disks with partitions, holders or mounts too"),
+                        )
+                        .arg(
+                            Arg::with_name("sha256")
+                                .long("sha256")
+                                .required(false)
+                                .takes_value(true)
+                                .value_name("HEX")
+                                .help("Refuse the image unless it has this checksum"),
+                        )
+                        .arg(
+                            Arg::with_name("chunk")
+                                .long("chunk")
+                                .required(false)
+                                .takes_value(true)
+                                .value_name("BYTES")
+                                .validator(|v| match v.parse::<usize>() {
+                                    Ok(c) if c > 0 && c <= 0xff_ffff => Ok(()),
+                                    _ => Err("a size between 1 and 16777215 bytes"),
+                                })
+                                .help("Size of each WRITE BUFFER, default 4096"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("inventory")
+                .about("Report every slot with its enclosure and disk identification")
+                .arg(
+                    Arg::with_name("fields")
+                        .long("fields")
+                        .required(false)
+                        .takes_value(true)
+                        .use_value_delimiter(true)
+                        .value_name("FIELDS")
+                        .possible_values(["firmware", "serial", "none"])
+                        .help("Expensive disk fields to collect, all by default"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("burnin")
+                .about("Test the surface of the disks of an enclosure")
+                .arg(
+                    Arg::with_name("enclosure")
+                        .short('e')
+                        .long("enclosure")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("SLOT|SERIAL|ALIAS")
+                        .help("The enclosure holding the disks"),
+                )
+                .arg(
+                    Arg::with_name("slots")
+                        .long("slots")
+                        .required(false)
+                        .takes_value(true)
+                        .value_name("SLOTS")
+                        .validator(|s| Util::parse_slots(s).map(|_| ()))
+                        .help("Only test these slots, like 0,1,10-13"),
+                )
+                .arg(
+                    Arg::with_name("hours")
+                        .long("hours")
+                        .required(false)
+                        .takes_value(true)
+                        .value_name("HOURS")
+                        .validator(|h| match h.parse::<f64>() {
+                            Ok(h) if h >= 0.0 => Ok(()),
+                            _ => Err("a positive number of hours"),
+                        })
+                        .help("Repeat the passes for this long, default a single pass"),
+                )
+                .arg(
+                    Arg::with_name("mode")
+                        .long("mode")
+                        .required(false)
+                        .takes_value(true)
+                        .value_name("MODE")
+                        .possible_values(["verify", "read", "write"])
+                        .help("SCSI VERIFY, READ, or write patterns and read them back, default verify"),
+                )
+                .arg(
+                    Arg::with_name("destructive")
+                        .long("destructive")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Confirm the write mode, which destroys the data of the disks"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("bench")
+                .about("Measure the read throughput of every slot and flag the slow ones")
+                .arg(
+                    Arg::with_name("device")
+                        .required_unless_present("enclosure")
+                        .takes_value(true)
+                        .value_name("DEVICE|SLOT|SERIAL")
+                        .help("The disk to measure"),
+                )
+                .arg(
+                    Arg::with_name("enclosure")
+                        .short('e')
+                        .long("enclosure")
+                        .required(false)
+                        .takes_value(true)
+                        .value_name("SLOT|SERIAL|ALIAS")
+                        .help("Measure every disk of the enclosure"),
+                )
+                .arg(
+                    Arg::with_name("test")
+                        .long("test")
+                        .required(false)
+                        .takes_value(true)
+                        .value_name("TEST")
+                        .possible_values(["sequential", "random", "both"])
+                        .help("Sequential 1 MiB reads, random 4 KiB reads, or both, default both"),
+                )
+                .arg(
+                    Arg::with_name("seconds")
+                        .long("seconds")
+                        .required(false)
+                        .takes_value(true)
+                        .value_name("SECONDS")
+                        .validator(|s| match s.parse::<u64>() {
+                            Ok(s) if s > 0 => Ok(()),
+                            _ => Err("a positive number of seconds"),
+                        })
+                        .help("Duration of each test on each disk, default 10"),
+                )
+                .arg(
+                    Arg::with_name("parallel")
+                        .long("parallel")
+                        .required(false)
+                        .takes_value(true)
+                        .value_name("DISKS")
+                        .validator(|p| match p.parse::<usize>() {
+                            Ok(p) if p > 0 => Ok(()),
+                            _ => Err("a positive number of disks"),
+                        })
+                        .help("Disks measured at the same time, default 1"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("genmap")
+                .about("Generate the aliases of the disks from their enclosure and slot")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .required(false)
+                        .takes_value(true)
+                        .value_name("FORMAT")
+                        .possible_values(["vdev_id", "udev"])
+                        .help("A ZFS vdev_id.conf or udev rules, default vdev_id"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("sed")
+                .about("Self-encrypting drives")
+                .subcommand(
+                    SubCommand::with_name("status")
+                        .about("Report the TCG class, ownership and locking of every disk")
+                        .arg(
+                            Arg::with_name("enclosure")
+                                .short('e')
+                                .long("enclosure")
+                                .required(false)
+                                .takes_value(true)
+                                .value_name("SLOT|SERIAL|ALIAS")
+                                .help("Only query the disks of the given enclosure"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("cache")
+                .about("Write cache of the disks and the topology cache of --cache")
+                .subcommand(
+                    SubCommand::with_name("status")
+                        .about("Report whether the write cache (WCE) of every disk is enabled")
+                        .arg(
+                            Arg::with_name("enclosure")
+                                .short('e')
+                                .long("enclosure")
+                                .required(false)
+                                .takes_value(true)
+                                .value_name("SLOT|SERIAL|ALIAS")
+                                .help("Only query the disks of the given enclosure"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("enable")
+                        .about("Enable the write cache with MODE SELECT")
+                        .arg(
+                            Arg::with_name("device")
+                                .required_unless_present("all")
+                                .multiple(true)
+                                .value_name("DEVICE")
+                                .help("The disks, a serial, a WWN, a device or <enclosure>/<slot>"),
+                        )
+                        .arg(
+                            Arg::with_name("all")
+                                .long("all")
+                                .conflicts_with("device")
+                                .help("Every disk, or every disk of --enclosure"),
+                        )
+                        .arg(
+                            Arg::with_name("enclosure")
+                                .short('e')
+                                .long("enclosure")
+                                .required(false)
+                                .takes_value(true)
+                                .value_name("SLOT|SERIAL|ALIAS")
+                                .help("Only switch the disks of the given enclosure"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("disable")
+                        .about("Disable the write cache with MODE SELECT")
+                        .arg(
+                            Arg::with_name("device")
+                                .required_unless_present("all")
+                                .multiple(true)
+                                .value_name("DEVICE")
+                                .help("The disks, a serial, a WWN, a device or <enclosure>/<slot>"),
+                        )
+                        .arg(
+                            Arg::with_name("all")
+                                .long("all")
+                                .conflicts_with("device")
+                                .help("Every disk, or every disk of --enclosure"),
+                        )
+                        .arg(
+                            Arg::with_name("enclosure")
+                                .short('e')
+                                .long("enclosure")
+                                .required(false)
+                                .takes_value(true)
+                                .value_name("SLOT|SERIAL|ALIAS")
+                                .help("Only switch the disks of the given enclosure"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("clear").about("Remove the topology cache of --cache"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("modepage")
+                .about("Decode the caching, control and informational exceptions mode pages of a disk")
+                .arg(
+                    Arg::with_name("device")
+                        .required(true)
+                        .value_name("DEVICE")
+                        .help("A device node, or a serial, a WWN, <enclosure>/<slot> of a disk"),
+                )
+                .arg(
+                    Arg::with_name("page")
+                        .long("page")
+                        .required(false)
+                        .takes_value(true)
+                        .value_name("PAGE")
+                        .validator(|p| ModePage::parse_page(p).map(|_| ()))
+                        .help("Only this mode page, in hexadecimal, example: 0x08"),
+                )
+                .arg(
+                    Arg::with_name("values")
+                        .long("values")
+                        .required(false)
+                        .takes_value(true)
+                        .value_name("VALUES")
+                        .possible_values(["current", "changeable", "default", "saved"])
+                        .help("Which values of the pages, default current"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("standby")
+                .about("Spin disks down, now or after an idle time")
+                .arg(
+                    Arg::with_name("device")
+                        .required_unless_present("enclosure")
+                        .conflicts_with("enclosure")
+                        .value_name("DEVICE")
+                        .help("The disk, a serial, a WWN, a device or <enclosure>/<slot>"),
+                )
+                .arg(
+                    Arg::with_name("enclosure")
+                        .short('e')
+                        .long("enclosure")
+                        .required(false)
+                        .takes_value(true)
+                        .value_name("SLOT|SERIAL|ALIAS")
+                        .help("Every disk of the given enclosure"),
+                )
+                .arg(
+                    Arg::with_name("now")
+                        .long("now")
+                        .required_unless_present("timer")
+                        .conflicts_with("timer")
+                        .help("Spin the disks down now with START STOP UNIT"),
+                )
+                .arg(
+                    Arg::with_name("timer")
+                        .long("timer")
+                        .required(false)
+                        .takes_value(true)
+                        .value_name("SECONDS")
+                        .validator(|t| t.parse::<u64>().map(|_| ()).map_err(|_| "a number of seconds"))
+                        .help("Spin the disks down after SECONDS without access, 0 disables it"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("events")
+                .about("Print the state of the slots and elements, and stream their changes")
+                .arg(
+                    Arg::with_name("follow")
+                        .short('f')
+                        .long("follow")
+                        .help("Keep printing the disks added or removed, the status changes and the uevents"),
+                )
+                .arg(
+                    Arg::with_name("enclosure")
+                        .short('e')
+                        .long("enclosure")
+                        .required(false)
+                        .takes_value(true)
+                        .value_name("SLOT|SERIAL|ALIAS")
+                        .help("Only watch the given enclosure"),
+                )
+                .arg(
+                    Arg::with_name("interval")
+                        .long("interval")
+                        .required(false)
+                        .takes_value(true)
+                        .value_name("DURATION")
+                        .requires("follow")
+                        .validator(|v| {
+                            Util::parse_duration(v)
+                                .filter(|d| !d.is_zero())
+                                .map(|_| ())
+                                .ok_or("invalid duration, example: 10s")
+                        })
+                        .help("How often the slots and the SES elements are read, default 10s"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("topology")
+                .about("Print the graph of the HBAs, expanders, enclosures, slots and disks")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .required(false)
+                        .takes_value(true)
+                        .value_name("FORMAT")
+                        .possible_values(["dot", "json"])
+                        .help("Graphviz or JSON, default dot"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("map")
+                .about("Draw the bays of a chassis colored by their state")
+                .arg(
+                    Arg::with_name("enclosure")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("SLOT|SERIAL|ALIAS")
+                        .help("The enclosure to draw"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("exec")
+                .about("Run a command for each disk matching the filters")
+                .arg(
+                    Arg::with_name("filter")
+                        .long("filter")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .value_name("FIELD<OP>VALUE")
+                        .help("Keep the disks where it holds, like temp>50 or model~HUH72, all of them must hold"),
+                )
+                .arg(
+                    Arg::with_name("command")
+                        .required(true)
+                        .multiple_values(true)
+                        .last(true)
+                        .value_name("COMMAND")
+                        .help("Run after --, {device}, {sg}, {serial}, {slot}... are replaced by the disk values"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("status")
+                .about("Report the failed, overheating and missing disks, the fans and the enclosures")
+                .arg(
+                    Arg::with_name("oneline")
+                        .long("oneline")
+                        .help("Sum it up in one line, for the motd"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("health")
+                .about("Report like status and exit with 1 on warnings, 2 on critical problems, 3 when an enclosure can't be read")
+                .arg(
+                    Arg::with_name("oneline")
+                        .long("oneline")
+                        .help("Sum it up in one line, for the monitoring plugins"),
+                )
+                .arg(
+                    Arg::with_name("silent")
+                        .long("silent")
+                        .conflicts_with("oneline")
+                        .help("Print nothing, only exit with the status"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("summary")
+                .about("Show the disks, temperatures, fans, power supplies, leds and alerts of each enclosure"),
+        )
+        .subcommand(
+            SubCommand::with_name("upgrade-check")
+                .about("Compare the disk and enclosure firmware with a catalog of approved revisions")
+                .arg(
+                    Arg::with_name("catalog")
+                        .long("catalog")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .help("TOML catalog of the approved firmware revisions per model"),
+                )
+                .arg(
+                    Arg::with_name("all")
+                        .long("all")
+                        .help("List every component, not only the out of date ones"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("verify-cabling")
+                .about("Blink each slot in order and check with the operator it is the expected bay")
+                .arg(
+                    Arg::with_name("enclosure")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("SLOT|SERIAL|ALIAS")
+                        .help("The enclosure to check"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("apply")
+                .about("Reconcile the leds, aliases and slot roles with a YAML manifest")
+                .arg(
+                    Arg::with_name("manifest")
+                        .required(true)
+                        .value_name("FILE")
+                        .help("The desired state"),
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("List the changes without applying them"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("daemon")
+                .about("Serve the queries and the leds to the users without root on a unix socket")
+                .arg(
+                    Arg::with_name("socket")
+                        .long("socket")
+                        .value_name("PATH")
+                        .takes_value(true)
+                        .help("Socket to listen on, default the daemon socket of the configuration or /run/jbod.sock"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("doctor")
+                .about("Report helper binaries, kernel support and usable features"),
+        )
         .subcommand(
             SubCommand::with_name("prometheus")
                 .about("Prometheus")
@@ -264,7 +1772,9 @@ fn main() {
                         .long("port")
                         .required(false)
                         .value_name("PORT")
-                        .takes_value(true),
+                        .takes_value(true)
+                        .validator(|p| Util::listen_address("0.0.0.0", p).map(|_| ()))
+                        .help("Port to listen on, default 9945"),
                 )
                 .arg(
                     Arg::with_name("ip-address")
@@ -272,16 +1782,132 @@ fn main() {
                         .long("ip-address")
                         .required(false)
                         .value_name("IPADDRESS")
-                        .takes_value(true),
+                        .takes_value(true)
+                        .validator(|a| Util::listen_address(a, "9945").map(|_| ()))
+                        .help("IPv4, IPv6 or hostname to listen on, default 0.0.0.0"),
+                )
+                .arg(
+                    Arg::with_name("daemonize")
+                        .long("daemonize")
+                        .help("Detach from the terminal, the exporter runs in the foreground otherwise"),
+                )
+                .arg(
+                    Arg::with_name("pidfile")
+                        .long("pidfile")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .requires("daemonize")
+                        .help("Where to write the pid of the daemon, default /run/jbod-exporter.pid"),
+                )
+                .arg(
+                    Arg::with_name("log")
+                        .long("log")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .requires("daemonize")
+                        .help("Append the output of the daemon to FILE, it is discarded otherwise"),
                 ),
         )
         .get_matches();
 
+    if let Some((_, m)) = matches.subcommand() {
+        Output::set_format(Output::get_format(m));
+    }
+    Config::init(matches.value_of("config"));
+    if let Some(timeout) = matches.value_of("timeout").and_then(Util::parse_duration) {
+        Util::set_command_timeout(timeout);
+    }
+    if let Some(root) = matches.value_of("sysfs-root") {
+        Util::set_sysfs_root(Path::new(root));
+    }
+    Util::set_quiet(matches.is_present("quiet"));
+    Output::set_color(Output::color_wanted(matches.is_present("no-color")));
+
+    // Without root the queries and the leds go through `jbod daemon` when
+    // one listens, unless the user asked for another configuration or sysfs.
+    if let Some((name, _)) = matches.subcommand() {
+        if !geteuid().is_root()
+            && Daemon::COMMANDS.contains(&name)
+            && !matches.is_present("config")
+            && !matches.is_present("sysfs-root")
+        {
+            if let Some(code) = Daemon::forward() {
+                exit(code);
+            }
+        }
+    }
+
+    if let Some((name, m)) = matches.subcommand() {
+        Util::verify_binary_needed(&required_binaries(name, m));
+    }
+
+    if matches!(matches.subcommand(), Some(("list", _))) {
+        if let Some(node) = Util::unreadable_sg_node() {
+            Output::print_warning(&I18n::tr(Message::UnreadableNode, &[&node]));
+            Output::print_hint(&Util::permission_guidance(&node));
+        }
+    }
+
+    // The exporter, the daemon and events would hold the lock for good,
+    // doctor touches nothing, the commands of the daemon take it themselves.
+    let _lock = match matches.subcommand() {
+        Some((name, m))
+            if m.is_present("lock") && !["prometheus", "daemon", "doctor", "events"].contains(&name) =>
+        {
+            let exclusive = CONTROL_COMMANDS.contains(&name);
+            match Util::lock_runtime(exclusive) {
+                Ok(lock) => Some(lock),
+                Err(e) => {
+                    Output::print_error_code(
+                        ErrorCode::from_io(&e),
+                        &I18n::tr(Message::CannotTakeLock, &[&e]),
+                    );
+                    exit(1);
+                }
+            }
+        }
+        _ => None,
+    };
+
     // Here it matches the menu options with its respective functions.
-    match matches.subcommand() {
+    let result = match matches.subcommand() {
         Some(("list", m)) => enclosure_overview(m),
         Some(("led", m)) => DiskShelf::jbod_led_switch(m),
-        Some(("prometheus", m)) => fork_prometheus(m),
-        _ => Ok(help()),
+        Some(("locate", m)) => DiskShelf::jbod_locate(m),
+        Some(("find", m)) => Find::jbod_find(m),
+        Some(("show", m)) => Show::jbod_show(m),
+        Some(("prometheus", m)) => start_prometheus(m, matches.value_of("config")),
+        Some(("firmware", m)) => Firmware::jbod_firmware(m),
+        Some(("inventory", m)) => Inventory::jbod_inventory(m),
+        Some(("burnin", m)) => Burnin::jbod_burnin(m),
+        Some(("bench", m)) => Bench::jbod_bench(m),
+        Some(("genmap", m)) => GenMap::jbod_genmap(m),
+        Some(("sed", m)) => Sed::jbod_sed(m),
+        Some(("cache", m)) => WriteCache::jbod_cache(m),
+        Some(("modepage", m)) => ModePage::jbod_modepage(m),
+        Some(("standby", m)) => Standby::jbod_standby(m),
+        Some(("events", m)) => Events::jbod_events(m),
+        Some(("topology", m)) => Topology::jbod_topology(m),
+        Some(("map", m)) => ChassisMap::jbod_map(m),
+        Some(("apply", m)) => Apply::jbod_apply(m),
+        Some(("verify-cabling", m)) => Cabling::jbod_verify_cabling(m),
+        Some(("status", m)) => Health::jbod_status(m),
+        Some(("health", m)) => match Health::jbod_health(m) {
+            0 => Ok(()),
+            code => exit(code),
+        },
+        Some(("summary", m)) => Summary::jbod_summary(m),
+        Some(("upgrade-check", m)) => Catalog::jbod_upgrade_check(m),
+        Some(("exec", m)) => Exec::jbod_exec(m),
+        Some(("daemon", m)) => Daemon::jbod_daemon(m),
+        Some(("doctor", m)) => Doctor::jbod_doctor(m),
+        _ => {
+            help();
+            Ok(())
+        }
     };
+
+    if result.is_err() {
+        exit(1);
+    }
 }
@@ -28,22 +28,40 @@
  * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
  */
 
+#![forbid(unsafe_code)]
+
 #[macro_use]
 extern crate lazy_static;
 
 use std::net::SocketAddr;
 
 use std::env;
+use std::process::exit;
 use std::result::Result;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use warp::http::StatusCode;
 use warp::{Filter, Rejection, Reply};
 use prometheus::{
-    IntGauge, IntGaugeVec, Opts, Registry,
+    GaugeVec, IntGauge, IntGaugeVec, Opts, Registry,
 };
 
-mod jbod;
-mod utils;
+// The modules come from the library, shared with the other binary and
+// the programs registering their own collectors.
+use ::jbod::{jbod, utils};
+use crate::jbod::catalog::Catalog;
+use crate::jbod::context::Context;
 use crate::jbod::disks::DiskShelf;
+use crate::jbod::element::Element::SlotStatus;
 use crate::jbod::enclosure::BackPlane;
+use crate::jbod::health::Health::Severity;
+use crate::jbod::io_errors::IoErrors;
+use crate::jbod::map::ChassisMap;
+use crate::jbod::plugin::Plugin;
+use crate::jbod::spares::Spares;
+use crate::jbod::wear::Wear;
+use crate::utils::config::Config;
 use crate::utils::helper::Util;
 
 // Declare code to be executed at runtime, this includes anything requiring
@@ -52,32 +70,254 @@ use crate::utils::helper::Util;
 // Every exporter metrics are declared here first.
 //
 lazy_static! {
-    pub static ref REGISTRY: Registry = Registry::new();
+    pub static ref REGISTRY: Registry = new_registry();
 
     pub static ref NUMBER_OF_ENCLOSURES: IntGauge =
         IntGauge::new("number_of_enclosures", "Number of enclosures").expect("metric can be created");
 
+    pub static ref JBOD_BUILD_INFO: IntGaugeVec =
+        IntGaugeVec::new(
+        Opts::new("jbod_build_info", "Always 1, the labels tell what the exporter is built from"),
+        &["version", "revision", "rustc"]
+    ).expect("metric can be created");
+
+    pub static ref JBOD_ENCLOSURE_STATUS: IntGaugeVec =
+        IntGaugeVec::new(
+        Opts::new("jbod_enclosure_status", "Overall SES status of an enclosure: 0 ok, 1 non-critical, 2 critical, 3 unrecoverable, 4 unknown"),
+        &["enclosure", "serial"]
+    ).expect("metric can be created");
+
     pub static ref JBOD_SLOT_TEMPERATURE: IntGaugeVec =
         IntGaugeVec::new(
         Opts::new("jbod_slot_temperature", "Enclosure number, slot position and temperature"),
         &["slot", "enclosure"]
     ).expect("metric can be created");
 
+    pub static ref JBOD_ENCLOSURE_TEMPERATURE_MIN: IntGaugeVec =
+        IntGaugeVec::new(
+        Opts::new("jbod_enclosure_temperature_min", "Lowest disk temperature of an enclosure"),
+        &["enclosure"]
+    ).expect("metric can be created");
+
+    pub static ref JBOD_ENCLOSURE_TEMPERATURE_MAX: IntGaugeVec =
+        IntGaugeVec::new(
+        Opts::new("jbod_enclosure_temperature_max", "Highest disk temperature of an enclosure"),
+        &["enclosure"]
+    ).expect("metric can be created");
+
+    pub static ref JBOD_ENCLOSURE_TEMPERATURE_MEAN: GaugeVec =
+        GaugeVec::new(
+        Opts::new("jbod_enclosure_temperature_mean", "Mean disk temperature of an enclosure"),
+        &["enclosure"]
+    ).expect("metric can be created");
+
+    pub static ref JBOD_SLOT_TEMP_DELTA: GaugeVec =
+        GaugeVec::new(
+        Opts::new("jbod_slot_temp_delta", "Degrees between a disk and the median disk temperature of its enclosure"),
+        &["slot", "enclosure"]
+    ).expect("metric can be created");
+
+    pub static ref JBOD_SLOT_HOTSPOT: IntGaugeVec =
+        IntGaugeVec::new(
+        Opts::new("jbod_slot_hotspot", "1 when a slot stayed hotter than its enclosure median by hotspot_delta degrees"),
+        &["slot", "enclosure"]
+    ).expect("metric can be created");
+
+    pub static ref JBOD_SLOT_PRESENT: IntGaugeVec =
+        IntGaugeVec::new(
+        Opts::new("jbod_slot_present", "1 when a disk is in the slot"),
+        &["slot", "enclosure"]
+    ).expect("metric can be created");
+
+    pub static ref JBOD_SLOT_EXPECTED: IntGaugeVec =
+        IntGaugeVec::new(
+        Opts::new("jbod_slot_expected", "1 when the configuration expects a disk in the slot"),
+        &["slot", "enclosure"]
+    ).expect("metric can be created");
+
     pub static ref JBOD_FAN_RPM: IntGaugeVec =
         IntGaugeVec::new(
         Opts::new("jbod_fan_rpm", "The RPM speed of FAN components, device and slot"),
         &["device", "slot"]
     ).expect("metric can be created");
+
+    pub static ref JBOD_FAN_STATUS: IntGaugeVec =
+        IntGaugeVec::new(
+        Opts::new("jbod_fan_status", "State of a FAN: 0 ok, 1 warning, 2 critical, 3 failed, 4 off, 5 unknown"),
+        &["enclosure", "index"]
+    ).expect("metric can be created");
+
+    pub static ref JBOD_ENCLOSURE_POWER_WATTS: GaugeVec =
+        GaugeVec::new(
+        Opts::new("jbod_enclosure_power_watts", "Power drawn through a current sensor of an enclosure"),
+        &["enclosure", "serial", "sensor"]
+    ).expect("metric can be created");
+
+    pub static ref JBOD_DISK_FIRMWARE_INFO: IntGaugeVec =
+        IntGaugeVec::new(
+        Opts::new("jbod_disk_firmware_info", "Number of disks of a model running a firmware revision in an enclosure"),
+        &["enclosure", "model", "firmware"]
+    ).expect("metric can be created");
+
+    pub static ref JBOD_DISK_FIRMWARE_MISMATCH: IntGaugeVec =
+        IntGaugeVec::new(
+        Opts::new("jbod_disk_firmware_mismatch", "1 when the disks of a model run mixed firmware revisions in an enclosure"),
+        &["enclosure", "model"]
+    ).expect("metric can be created");
+
+    pub static ref JBOD_FIRMWARE_OUTDATED: IntGaugeVec =
+        IntGaugeVec::new(
+        Opts::new("jbod_firmware_outdated", "1 when a disk or an enclosure controller runs a firmware the catalog doesn't approve"),
+        &["enclosure", "kind", "location", "model", "firmware"]
+    ).expect("metric can be created");
+
+    pub static ref JBOD_DISK_IO_ERRORS: IntGaugeVec =
+        IntGaugeVec::new(
+        Opts::new("jbod_disk_io_errors", "The commands of a disk completed with an error since it was attached"),
+        &["slot", "enclosure", "serial"]
+    ).expect("metric can be created");
+
+    pub static ref JBOD_DISK_IO_DONE: IntGaugeVec =
+        IntGaugeVec::new(
+        Opts::new("jbod_disk_io_done", "The commands of a disk completed since it was attached"),
+        &["slot", "enclosure", "serial"]
+    ).expect("metric can be created");
+
+    pub static ref JBOD_DISK_IO_REQUESTS: IntGaugeVec =
+        IntGaugeVec::new(
+        Opts::new("jbod_disk_io_requests", "The commands sent to a disk since it was attached"),
+        &["slot", "enclosure", "serial"]
+    ).expect("metric can be created");
+
+    pub static ref JBOD_DISK_IO_ERRORS_NEW: IntGaugeVec =
+        IntGaugeVec::new(
+        Opts::new("jbod_disk_io_errors_new", "The I/O errors of a disk since the previous collection"),
+        &["slot", "enclosure", "serial"]
+    ).expect("metric can be created");
+
+    pub static ref JBOD_DISK_WEAR_PERCENT: IntGaugeVec =
+        IntGaugeVec::new(
+        Opts::new("jbod_disk_wear_percent", "The percentage of the rated endurance used by an SSD"),
+        &["slot", "enclosure", "serial"]
+    ).expect("metric can be created");
+
+    pub static ref JBOD_DISK_WORN: IntGaugeVec =
+        IntGaugeVec::new(
+        Opts::new("jbod_disk_worn", "1 when an SSD used the wear_threshold percentage of its rated endurance"),
+        &["slot", "enclosure", "serial"]
+    ).expect("metric can be created");
+
+    pub static ref JBOD_SPARE_MISSING: IntGaugeVec =
+        IntGaugeVec::new(
+        Opts::new("jbod_spare_missing", "1 when a spare designated in the configuration is not found"),
+        &["spare"]
+    ).expect("metric can be created");
+
+    pub static ref JBOD_SPARE_IN_USE: IntGaugeVec =
+        IntGaugeVec::new(
+        Opts::new("jbod_spare_in_use", "1 when a spare designated in the configuration is used"),
+        &["spare"]
+    ).expect("metric can be created");
+
+    pub static ref JBOD_PLUGIN_VALUE: GaugeVec =
+        GaugeVec::new(
+        Opts::new("jbod_plugin_value", "The value measured by a collector of the configuration or a plugin"),
+        &["slot", "serial", "plugin", "name"]
+    ).expect("metric can be created");
+
+    pub static ref JBOD_PLUGIN_STATUS: IntGaugeVec =
+        IntGaugeVec::new(
+        Opts::new("jbod_plugin_status", "The check of a collector: 0 ok, 1 warning, 2 critical"),
+        &["slot", "serial", "plugin", "name"]
+    ).expect("metric can be created");
+
+    pub static ref JBOD_COLLECTOR_STALE: IntGaugeVec =
+        IntGaugeVec::new(
+        Opts::new("jbod_collector_stale", "1 when the collector metrics are not from the last scrape"),
+        &["collector"]
+    ).expect("metric can be created");
+
+    // Held by the collection, concurrent scrapes wait for each other
+    // instead of querying the expanders twice.
+    static ref COLLECTION: Mutex<()> = Mutex::new(());
+
+    // When each collector last ran.
+    static ref LAST_COLLECTION: Mutex<HashMap<&'static str, Instant>> = Mutex::new(HashMap::new());
+
+    // The last deltas of each (enclosure, slot), newest last.
+    static ref SLOT_DELTAS: Mutex<HashMap<(String, String), VecDeque<f64>>> = Mutex::new(HashMap::new());
+
+    // The I/O errors of each disk at the previous collection.
+    static ref IO_ERRORS: Mutex<HashMap<String, u64>> = Mutex::new(HashMap::new());
+
+    // The last known state of each spare, a change is logged.
+    static ref SPARE_STATES: Mutex<HashMap<String, Spares::SpareState>> = Mutex::new(HashMap::new());
+}
+
+/// Returns the registry of the exporter metrics, every series gets the
+/// constant labels of the `[exporter.labels]` configuration.
+fn new_registry() -> Registry {
+    let labels = &Config::get().exporter.labels;
+    let labels = if labels.is_empty() { None } else { Some(labels.clone()) };
+    match Registry::new_custom(None, labels) {
+        Ok(registry) => registry,
+        Err(e) => {
+            eprintln!("Error: invalid exporter labels: {}", e);
+            exit(1);
+        }
+    }
+}
+
+/// Registers a metric, a label of the configuration clashing with the
+/// labels of the metric is fatal.
+fn register(metric: Box<dyn prometheus::core::Collector>) {
+    let labels = &Config::get().exporter.labels;
+    for desc in metric.desc() {
+        if let Some(label) = desc.variable_labels.iter().find(|l| labels.contains_key(*l)) {
+            eprintln!("Error: the exporter label {} is already a label of {}", label, desc.fq_name);
+            exit(1);
+        }
+    }
+    if let Err(e) = REGISTRY.register(metric) {
+        eprintln!("Error: cannot register the metrics: {}", e);
+        exit(1);
+    }
 }
 
 /// Here we register the metrics, this function is called in the `main()`.
+///
+/// The process metrics are registered here as well, instead of the default
+/// registry, so they get the constant labels too.
 fn register_metrics() {
-    REGISTRY.register(Box::new(NUMBER_OF_ENCLOSURES.clone()))
-        .expect("collector can be registered");
-    REGISTRY.register(Box::new(JBOD_SLOT_TEMPERATURE.clone()))
-        .expect("collector can be registered");
-    REGISTRY.register(Box::new(JBOD_FAN_RPM.clone()))
-        .expect("collector can be registered");
+    register(Box::new(prometheus::process_collector::ProcessCollector::for_self()));
+    register(Box::new(JBOD_BUILD_INFO.clone()));
+    register(Box::new(NUMBER_OF_ENCLOSURES.clone()));
+    register(Box::new(JBOD_ENCLOSURE_STATUS.clone()));
+    register(Box::new(JBOD_SLOT_TEMPERATURE.clone()));
+    register(Box::new(JBOD_ENCLOSURE_TEMPERATURE_MIN.clone()));
+    register(Box::new(JBOD_ENCLOSURE_TEMPERATURE_MAX.clone()));
+    register(Box::new(JBOD_ENCLOSURE_TEMPERATURE_MEAN.clone()));
+    register(Box::new(JBOD_SLOT_TEMP_DELTA.clone()));
+    register(Box::new(JBOD_SLOT_HOTSPOT.clone()));
+    register(Box::new(JBOD_SLOT_PRESENT.clone()));
+    register(Box::new(JBOD_SLOT_EXPECTED.clone()));
+    register(Box::new(JBOD_FAN_RPM.clone()));
+    register(Box::new(JBOD_FAN_STATUS.clone()));
+    register(Box::new(JBOD_ENCLOSURE_POWER_WATTS.clone()));
+    register(Box::new(JBOD_DISK_FIRMWARE_INFO.clone()));
+    register(Box::new(JBOD_DISK_FIRMWARE_MISMATCH.clone()));
+    register(Box::new(JBOD_FIRMWARE_OUTDATED.clone()));
+    register(Box::new(JBOD_DISK_IO_ERRORS.clone()));
+    register(Box::new(JBOD_DISK_IO_DONE.clone()));
+    register(Box::new(JBOD_DISK_IO_REQUESTS.clone()));
+    register(Box::new(JBOD_DISK_IO_ERRORS_NEW.clone()));
+    register(Box::new(JBOD_DISK_WEAR_PERCENT.clone()));
+    register(Box::new(JBOD_DISK_WORN.clone()));
+    register(Box::new(JBOD_SPARE_MISSING.clone()));
+    register(Box::new(JBOD_SPARE_IN_USE.clone()));
+    register(Box::new(JBOD_PLUGIN_VALUE.clone()));
+    register(Box::new(JBOD_PLUGIN_STATUS.clone()));
+    register(Box::new(JBOD_COLLECTOR_STALE.clone()));
 }
 
 // Index handler.
@@ -85,113 +325,674 @@ async fn index_handler() -> Result<impl Reply, Rejection> {
     Ok("")
 }
 
-/// Returns an `i64` with the total number of enclosures.
-async fn number_of_enclosure_metrics() -> i64 {
-    let enclosure = BackPlane::get_enclosure();
-    return(enclosure.len() as i64)
+// Health handler, it never waits on the hardware.
+async fn health_handler() -> Result<impl Reply, Rejection> {
+    Ok("OK")
 }
 
-/// Returns Result with Reply and Rejection.
-///
-/// This function updates the prometheus-exporter metrics.
-/// Also here we can find the logic behind each metric.
-async fn metrics_handler() -> Result<impl Reply, Rejection> {
-    use prometheus::Encoder;
-    let encoder = prometheus::TextEncoder::new();
+/// Returns an `i64` with the total number of enclosures.
+fn number_of_enclosure_metrics(run: &Context::Run) -> i64 {
+    run.enclosures().len() as i64
+}
 
-    // Enclosure FAN rpm
-    let mut enclosure_fan = BackPlane::get_enclosure_fan();
-    enclosure_fan.sort_by_key(|f| f.index.clone());
+/// Updates the FAN rpm and status metrics.
+fn collect_fans(run: &Context::Run) {
+    let mut enclosure_fan = BackPlane::get_enclosure_fan(run.enclosures(), run.options.jobs);
+    enclosure_fan.sort_by(|a, b| {
+        Util::natural_cmp(&a.slot, &b.slot).then_with(|| Util::natural_cmp(&a.index, &b.index))
+    });
     for fan in enclosure_fan.iter() {
         JBOD_FAN_RPM.with_label_values(&[&fan.description, &fan.index])
             .set(fan.speed);
+        JBOD_FAN_STATUS.with_label_values(&[&fan.slot, &fan.index])
+            .set(fan.state.code());
+    }
+}
+
+/// Updates the power drawn by the enclosures, the sensors without a
+/// voltage to compute it from are left out.
+fn collect_power(run: &Context::Run) {
+    for reading in BackPlane::get_enclosure_power(run.enclosures(), run.options.jobs) {
+        if let Some(watts) = reading.watts {
+            JBOD_ENCLOSURE_POWER_WATTS
+                .with_label_values(&[&reading.slot, &reading.serial, &reading.sensor])
+                .set(watts);
+        }
+    }
+}
+
+/// Returns where an enclosure is racked, appended to the alerts, or an
+/// empty string when its location isn't configured.
+fn location_of(run: &Context::Run, enclosure: &str) -> String {
+    run.enclosures()
+        .iter()
+        .find(|e| e.slot == enclosure)
+        .and_then(|e| e.location.as_ref())
+        .map(|l| format!(" (rack {})", l))
+        .unwrap_or_default()
+}
+
+/// Returns the median of some temperatures, 0 when there is none.
+fn median(temperatures: &[i32]) -> f64 {
+    let mut sorted = temperatures.to_vec();
+    sorted.sort_unstable();
+    match sorted.len() {
+        0 => 0.0,
+        n if n.is_multiple_of(2) => (sorted[n / 2 - 1] + sorted[n / 2]) as f64 / 2.0,
+        n => sorted[n / 2] as f64,
+    }
+}
+
+/// Updates how much hotter than the median of its enclosure each slot is,
+/// a slot hot on every one of the last `hotspot_samples` runs is a hotspot,
+/// usually a blocked airflow.
+fn collect_hotspots(run: &Context::Run) {
+    let settings = &Config::get().exporter;
+    let disks = DiskShelf::jbod_disk_map(run);
+    let mut per_enclosure: HashMap<&str, Vec<(&str, i32)>> = HashMap::new();
+    for disk in &disks {
+        if let DiskShelf::Temperature::Celsius(temperature) = disk.temperature {
+            per_enclosure.entry(&disk.enclosure).or_default().push((&disk.slot, temperature));
+        }
+    }
+
+    let mut history = SLOT_DELTAS.lock().unwrap();
+    for (enclosure, slots) in per_enclosure {
+        let temperatures: Vec<i32> = slots.iter().map(|(_, t)| *t).collect();
+        let median = median(&temperatures);
+        for (slot, temperature) in slots {
+            let delta = temperature as f64 - median;
+            JBOD_SLOT_TEMP_DELTA.with_label_values(&[slot, enclosure]).set(delta);
+
+            let deltas = history.entry((enclosure.to_string(), slot.to_string())).or_default();
+            deltas.push_back(delta);
+            while deltas.len() > settings.hotspot_samples {
+                deltas.pop_front();
+            }
+            let hotspot = deltas.len() >= settings.hotspot_samples
+                && deltas.iter().all(|d| *d >= settings.hotspot_delta);
+            let gauge = JBOD_SLOT_HOTSPOT.with_label_values(&[slot, enclosure]);
+            if hotspot && gauge.get() == 0 {
+                eprintln!(
+                    "Alert: enclosure {}{} {} is {:.1} degrees above the median, check its airflow",
+                    enclosure,
+                    location_of(run, enclosure),
+                    slot,
+                    delta
+                );
+            }
+            gauge.set(hotspot as i64);
+        }
+    }
+}
+
+/// Updates the firmware revisions run by each disk model, and with the
+/// `catalog` of the configuration whether each disk and enclosure
+/// controller runs an approved one.
+///
+/// The revisions aren't read by the shared scan, so the disks are scanned
+/// again with them. The series are reset first so an updated revision
+/// doesn't linger.
+fn collect_firmware(run: &Context::Run) {
+    let mut scan = run.options.clone();
+    scan.fields.firmware = true;
+    let firmware_run = Context::Run::new(scan);
+    let disks = DiskShelf::jbod_disk_map(&firmware_run);
+
+    // (enclosure, model) to the number of disks per revision.
+    let mut revisions: HashMap<(String, String), HashMap<String, i64>> = HashMap::new();
+    for disk in &disks {
+        *revisions
+            .entry((disk.enclosure.clone(), disk.model.clone()))
+            .or_default()
+            .entry(disk.fw_revision.clone())
+            .or_default() += 1;
+    }
+
+    // Only the components the catalog can judge get a series.
+    JBOD_FIRMWARE_OUTDATED.reset();
+    if let Some(path) = &Config::get().exporter.catalog {
+        match Catalog::load(path) {
+            Ok(catalog) => {
+                let mut enclosures = firmware_run.enclosures().to_vec();
+                BackPlane::read_controllers(&mut enclosures, firmware_run.options.jobs);
+                for component in Catalog::check(&catalog, &enclosures, &disks) {
+                    let outdated = match component.compliance {
+                        Catalog::Compliance::Approved => 0,
+                        Catalog::Compliance::Outdated => 1,
+                        _ => continue,
+                    };
+                    JBOD_FIRMWARE_OUTDATED
+                        .with_label_values(&[
+                            &component.enclosure,
+                            &component.kind,
+                            &component.location,
+                            &component.model,
+                            &component.firmware,
+                        ])
+                        .set(outdated);
+                }
+            }
+            Err(e) => eprintln!("Error: invalid catalog {}", e),
+        }
+    }
+
+    JBOD_DISK_FIRMWARE_INFO.reset();
+    JBOD_DISK_FIRMWARE_MISMATCH.reset();
+    for ((enclosure, model), counts) in &revisions {
+        JBOD_DISK_FIRMWARE_MISMATCH
+            .with_label_values(&[enclosure, model])
+            .set((counts.len() > 1) as i64);
+        for (firmware, count) in counts {
+            JBOD_DISK_FIRMWARE_INFO
+                .with_label_values(&[enclosure, model, firmware])
+                .set(*count);
+        }
+    }
+}
+
+/// Updates whether each slot holds a disk and whether it should, the
+/// expected population comes from the `populated` slots of the chassis,
+/// so an intentionally empty slot isn't taken for a disk that fell out.
+fn collect_slots(run: &Context::Run) {
+    let settings = Config::get();
+    for enclosure in run.enclosures() {
+        for bay in ChassisMap::bays(enclosure) {
+            JBOD_SLOT_PRESENT
+                .with_label_values(&[&bay.slot, &enclosure.slot])
+                .set(bay.device_path.is_some() as i64);
+            if let Some(expected) = settings.slot_expected(&enclosure.serial, &enclosure.model, &bay.slot) {
+                JBOD_SLOT_EXPECTED
+                    .with_label_values(&[&bay.slot, &enclosure.slot])
+                    .set(expected as i64);
+            }
+        }
+    }
+}
+
+/// Updates the state of the designated spares and logs when one goes
+/// missing or gets used.
+///
+/// The spares designated by serial number need the serials, so the disks
+/// are scanned again with them, only when the configuration has spares.
+fn collect_spares(run: &Context::Run) {
+    if !Spares::configured() {
+        return;
+    }
+    let mut scan = run.options.clone();
+    scan.fields.serial = true;
+    let spare_run = Context::Run::new(scan);
+
+    let mut states = SPARE_STATES.lock().unwrap();
+    for spare in Spares::spares(&spare_run) {
+        let missing = spare.state == Spares::SpareState::Missing;
+        let in_use = spare.state == Spares::SpareState::InUse;
+        JBOD_SPARE_MISSING.with_label_values(&[&spare.designation]).set(missing as i64);
+        JBOD_SPARE_IN_USE.with_label_values(&[&spare.designation]).set(in_use as i64);
+
+        let previous = states.insert(spare.designation.clone(), spare.state);
+        if previous != Some(spare.state) && (missing || in_use) {
+            eprintln!(
+                "Alert: spare {}{} is {}: {}",
+                spare.designation,
+                location_of(run, &spare.enclosure),
+                spare.state,
+                spare.reason
+            );
+        } else if previous.is_some() && previous != Some(spare.state) {
+            eprintln!("spare {} is back in standby", spare.designation);
+        }
+    }
+}
+
+/// Updates the I/O counters of the disks and logs the ones with new
+/// errors, the transport errors often show there before SMART tells.
+///
+/// The series are labelled with the serials, so the disks are scanned
+/// again with them.
+fn collect_io_errors(run: &Context::Run) {
+    let mut scan = run.options.clone();
+    scan.fields.serial = true;
+    let serial_run = Context::Run::new(scan);
+
+    let mut previous = IO_ERRORS.lock().unwrap();
+    for disk in DiskShelf::jbod_disk_map(&serial_run) {
+        let counters = match IoErrors::read(&disk.device_path) {
+            Ok(counters) => counters,
+            Err(e) => {
+                eprintln!("Failed to read the I/O counters of {}: {}", disk.device_path, e);
+                continue;
+            }
+        };
+        let labels = [disk.slot.as_str(), &disk.enclosure, &disk.serial];
+        JBOD_DISK_IO_ERRORS.with_label_values(&labels).set(counters.ioerr as i64);
+        JBOD_DISK_IO_DONE.with_label_values(&labels).set(counters.iodone as i64);
+        JBOD_DISK_IO_REQUESTS.with_label_values(&labels).set(counters.iorequest as i64);
+
+        let key = IoErrors::key(&disk);
+        let new = IoErrors::delta(previous.insert(key, counters.ioerr), counters.ioerr);
+        JBOD_DISK_IO_ERRORS_NEW.with_label_values(&labels).set(new.unwrap_or(0) as i64);
+        if let Some(new) = new.filter(|n| *n > 0) {
+            eprintln!(
+                "Alert: disk {} ({}){} got {} new I/O errors",
+                disk.device_path,
+                disk.serial,
+                location_of(run, &disk.enclosure),
+                new
+            );
+        }
     }
-    drop(enclosure_fan);
+}
+
+/// Updates the endurance used by the SSDs, the series are labelled with
+/// the serials like the I/O counters.
+fn collect_wear(run: &Context::Run) {
+    let mut scan = run.options.clone();
+    scan.fields.serial = true;
+    let serial_run = Context::Run::new(scan);
 
-    // Enclosures
-    let enclosures = number_of_enclosure_metrics();
-    NUMBER_OF_ENCLOSURES.set(enclosures.await);
+    let mut disks = DiskShelf::jbod_disk_map(&serial_run);
+    Wear::annotate(&mut disks, run.options.jobs, true);
+    for disk in disks {
+        if let Some(wear) = disk.wear {
+            let labels = [disk.slot.as_str(), &disk.enclosure, &disk.serial];
+            JBOD_DISK_WEAR_PERCENT.with_label_values(&labels).set(wear as i64);
+            JBOD_DISK_WORN.with_label_values(&labels).set(Wear::worn(wear) as i64);
+        }
+    }
+}
 
-    // Disks slot temperature
-    let mut disks_temperature = DiskShelf::jbod_disk_map();
-    disks_temperature.sort_by_key(|d| d.slot.clone());
+/// Updates the number of enclosures.
+fn collect_enclosures(run: &Context::Run) {
+    NUMBER_OF_ENCLOSURES.set(number_of_enclosure_metrics(run));
+}
+
+/// Updates the overall status of each enclosure, one series per shelf
+/// for the simple alert rules.
+fn collect_health(run: &Context::Run) {
+    for health in BackPlane::get_enclosure_health(run.enclosures(), run.options.jobs) {
+        let code = match health.status {
+            SlotStatus::Ok => 0,
+            SlotStatus::Noncritical => 1,
+            SlotStatus::Critical => 2,
+            SlotStatus::Unrecoverable => 3,
+            _ => 4,
+        };
+        JBOD_ENCLOSURE_STATUS
+            .with_label_values(&[&health.slot, &health.serial])
+            .set(code);
+    }
+}
+
+/// Updates the values and the checks of the `[[collectors]]` of the
+/// configuration and of the registered plugins.
+fn collect_plugins(run: &Context::Run) {
+    for reading in Plugin::collect(run) {
+        let labels = [
+            reading.enclosure.as_str(),
+            &reading.serial,
+            &reading.collector,
+            &reading.sample.name,
+        ];
+        if let Some(value) = reading.sample.value {
+            JBOD_PLUGIN_VALUE.with_label_values(&labels).set(value);
+        }
+        let code = match reading.sample.severity {
+            None => 0,
+            Some(Severity::Warning) => 1,
+            Some(Severity::Critical) => 2,
+        };
+        JBOD_PLUGIN_STATUS.with_label_values(&labels).set(code);
+    }
+}
+
+/// Updates the disks slot temperature and the lowest, highest and mean
+/// temperature of each enclosure, so a dashboard doesn't have to aggregate
+/// every slot of a shelf.
+fn collect_temperature(run: &Context::Run) {
+    let mut disks_temperature = DiskShelf::jbod_disk_map(run);
+    DiskShelf::sort_disks(&mut disks_temperature);
+    let mut per_enclosure: HashMap<&str, Vec<i32>> = HashMap::new();
     for disk in disks_temperature.iter() {
-        match disk.temperature.parse() {
-            Ok(temperature) => {
+        match disk.temperature {
+            DiskShelf::Temperature::Celsius(temperature) => {
                 JBOD_SLOT_TEMPERATURE
                 .with_label_values(&[&disk.slot, &disk.enclosure])
-                .set(temperature)},
-            Err(e) => eprintln!("Failed to read temperature: {:?} of disk: {:?}", e, disk),
+                .set(temperature as i64);
+                per_enclosure.entry(&disk.enclosure).or_default().push(temperature);
+            },
+            DiskShelf::Temperature::Unreadable => eprintln!("Failed to read temperature of disk: {:?}", disk),
+            _ => {}
         }
     }
-    drop(disks_temperature);
 
-    let mut buffer = Vec::new();
-    if let Err(e) = encoder.encode(&REGISTRY.gather(), &mut buffer) {
-        eprintln!("could not encode custom metrics: {}", e);
+    for (enclosure, temperatures) in per_enclosure {
+        let min = temperatures.iter().min().copied().unwrap_or_default();
+        let max = temperatures.iter().max().copied().unwrap_or_default();
+        let mean = temperatures.iter().sum::<i32>() as f64 / temperatures.len() as f64;
+        JBOD_ENCLOSURE_TEMPERATURE_MIN.with_label_values(&[enclosure]).set(min as i64);
+        JBOD_ENCLOSURE_TEMPERATURE_MAX.with_label_values(&[enclosure]).set(max as i64);
+        JBOD_ENCLOSURE_TEMPERATURE_MEAN.with_label_values(&[enclosure]).set(mean);
+    }
+}
+
+/// A function updating some metrics.
+type Collector = fn(&Context::Run);
+
+/// Every collector with the function updating its metrics and the name of
+/// these metrics, the name is the one used in the `[exporter.intervals]`
+/// configuration and the `collect[]` parameter.
+const COLLECTORS: &[(&str, Collector, &[&str])] = &[
+    ("enclosures", collect_enclosures, &["number_of_enclosures"]),
+    ("fans", collect_fans, &["jbod_fan_rpm", "jbod_fan_status"]),
+    ("firmware", collect_firmware, &[
+        "jbod_disk_firmware_info",
+        "jbod_disk_firmware_mismatch",
+        "jbod_firmware_outdated",
+    ]),
+    ("health", collect_health, &["jbod_enclosure_status"]),
+    ("io_errors", collect_io_errors, &[
+        "jbod_disk_io_errors",
+        "jbod_disk_io_done",
+        "jbod_disk_io_requests",
+        "jbod_disk_io_errors_new",
+    ]),
+    ("hotspots", collect_hotspots, &["jbod_slot_temp_delta", "jbod_slot_hotspot"]),
+    ("plugins", collect_plugins, &["jbod_plugin_value", "jbod_plugin_status"]),
+    ("power", collect_power, &["jbod_enclosure_power_watts"]),
+    ("slots", collect_slots, &["jbod_slot_present", "jbod_slot_expected"]),
+    ("spares", collect_spares, &["jbod_spare_missing", "jbod_spare_in_use"]),
+    ("temperature", collect_temperature, &[
+        "jbod_slot_temperature",
+        "jbod_enclosure_temperature_min",
+        "jbod_enclosure_temperature_max",
+        "jbod_enclosure_temperature_mean",
+    ]),
+    ("wear", collect_wear, &["jbod_disk_wear_percent", "jbod_disk_worn"]),
+];
+
+/// Returns the collectors asked for by the `collect[]` parameters of a
+/// scrape, every collector when there is none.
+///
+/// # Arguments
+///
+/// * `params` - the query parameters of the scrape
+///
+fn selected_collectors(params: &[(String, String)]) -> Result<Vec<&'static str>, String> {
+    let asked: Vec<&str> = params
+        .iter()
+        .filter(|(key, _)| key == "collect[]" || key == "collect")
+        .map(|(_, value)| value.as_str())
+        .collect();
+    if asked.is_empty() {
+        return Ok(COLLECTORS.iter().map(|(name, _, _)| *name).collect());
+    }
+
+    let mut selected = Vec::new();
+    for name in asked {
+        match COLLECTORS.iter().find(|(n, _, _)| *n == name) {
+            Some((n, _, _)) => selected.push(*n),
+            None => {
+                let names: Vec<&str> = COLLECTORS.iter().map(|(n, _, _)| *n).collect();
+                return Err(format!(
+                    "unknown collector {}, expected one of: {}",
+                    name,
+                    names.join(", ")
+                ));
+            }
+        }
+    }
+    Ok(selected)
+}
+
+/// What a collection did, logged with the scrape.
+#[derive(Debug, Default)]
+struct Collection {
+    /// The collectors that ran.
+    ran: Vec<&'static str>,
+    /// The due collectors skipped on the deadline.
+    skipped: Vec<&'static str>,
+    /// The number of enclosures that failed to scan.
+    errors: usize,
+}
+
+/// This function updates the prometheus-exporter metrics.
+///
+/// Each collector runs only when its interval elapsed, so cheap metrics are
+/// refreshed on every scrape and expensive ones on a slower cadence, the
+/// metrics keep their previous values in between.
+///
+/// It runs subprocesses and reads sysfs, so it must not run on the
+/// async runtime threads.
+///
+/// A due collector is marked stale until it finishes, the ones that would
+/// start after the `deadline` are skipped and stay stale so a scrape
+/// served on timeout tells which metrics are old.
+///
+/// One collection runs at a time, a scrape arriving meanwhile waits for it
+/// and then finds the collectors it just ran not due, see `min_interval`.
+///
+/// # Arguments
+///
+/// * `deadline` - the instant after which no collector is started
+/// * `selected` - the names of the collectors to run
+///
+/// Returns what ran, so the scrape can log it.
+///
+fn collect_metrics(deadline: Instant, selected: &[&str]) -> Collection {
+    let _collection = COLLECTION.lock().unwrap_or_else(|e| e.into_inner());
+    let settings = &Config::get().exporter;
+    // The collectors share the enclosure discovery and the sg map, only
+    // the temperature is read from the disks.
+    let mut scan = DiskShelf::ScanOptions {
+        fields: DiskShelf::Fields {
+            temperature: true,
+            ..DiskShelf::Fields::minimal()
+        },
+        ..DiskShelf::ScanOptions::default()
     };
+    if let Some(jobs) = settings.jobs {
+        scan.jobs = jobs;
+    }
+    let run = Context::Run::new(scan);
 
-    let mut res = match String::from_utf8(buffer.clone()) {
-        Ok(v) => v,
+    let due: Vec<&(&str, Collector, &[&str])> = COLLECTORS
+        .iter()
+        .filter(|(name, _, _)| selected.contains(name))
+        .filter(|(name, _, _)| {
+            let interval = Duration::from_secs(settings.interval(name));
+            LAST_COLLECTION
+                .lock()
+                .unwrap()
+                .get(name)
+                .map(|t| t.elapsed() >= interval)
+                .unwrap_or(true)
+        })
+        .collect();
+    for (name, _, _) in &due {
+        JBOD_COLLECTOR_STALE.with_label_values(&[name]).set(1);
+    }
+
+    let mut collection = Collection::default();
+    for (name, collector, _) in due {
+        if Instant::now() >= deadline {
+            eprintln!("collection deadline reached, skipping the {} collector", name);
+            collection.skipped.push(name);
+            continue;
+        }
+        let started = Instant::now();
+        let known_errors = run.errors().len();
+        collector(&run);
+        LAST_COLLECTION.lock().unwrap().insert(name, Instant::now());
+        JBOD_COLLECTOR_STALE.with_label_values(&[name]).set(0);
+        collection.ran.push(name);
+
+        if settings.trace {
+            let errors = run.errors();
+            eprintln!(
+                "collector={} duration_ms={} errors={}",
+                name,
+                started.elapsed().as_millis(),
+                errors.len() - known_errors
+            );
+            for error in &errors[known_errors..] {
+                eprintln!(
+                    "collector={} enclosure={} error={:?}",
+                    name, error.enclosure, error.error
+                );
+            }
+        }
+    }
+
+    let errors = run.errors();
+    for error in &errors {
+        eprintln!("Failed to scan {}", error);
+    }
+    collection.errors = errors.len();
+    collection
+}
+
+/// Returns Result with Reply and Rejection.
+///
+/// This function collects the metrics on a blocking thread, if the collection
+/// doesn't finish in time the previous values are served.
+///
+/// A scrape with `collect[]` parameters only runs and serves the metrics of
+/// these collectors, example: `/metrics?collect[]=fans&collect[]=temperature`,
+/// so cheap metrics can be scraped often and expensive ones rarely.
+async fn metrics_handler(
+    client: Option<SocketAddr>,
+    params: Vec<(String, String)>,
+) -> Result<impl Reply, Rejection> {
+    use prometheus::Encoder;
+    let encoder = prometheus::TextEncoder::new();
+    let started = Instant::now();
+    let client = client.map(|c| c.to_string()).unwrap_or_else(|| "-".to_string());
+
+    let selected = match selected_collectors(&params) {
+        Ok(selected) => selected,
         Err(e) => {
-            eprintln!("custom metrics could not be from_utf8: {}", e);
-            String::default()
+            log_scrape(&client, StatusCode::BAD_REQUEST, started, "rejected");
+            return Ok(warp::reply::with_status(e, StatusCode::BAD_REQUEST));
         }
     };
-    buffer.clear();
+    let filtered = selected.len() < COLLECTORS.len();
+
+    let timeout = Duration::from_secs(Config::get().exporter.collection_timeout);
+    let deadline = Instant::now() + timeout;
+    let collected = selected.clone();
+    let collection = tokio::task::spawn_blocking(move || collect_metrics(deadline, &collected));
+    let outcome = match tokio::time::timeout(timeout, collection).await {
+        Ok(Ok(collection)) if collection.skipped.is_empty() && collection.errors == 0 => format!(
+            "complete collectors={}",
+            collection.ran.join(",")
+        ),
+        Ok(Ok(collection)) => format!(
+            "partial collectors={} skipped={} errors={}",
+            collection.ran.join(","),
+            collection.skipped.join(","),
+            collection.errors
+        ),
+        Ok(Err(e)) => {
+            eprintln!("metrics collection failed: {}", e);
+            "failed".to_string()
+        }
+        Err(_) => {
+            eprintln!(
+                "metrics collection did not finish in {}s, serving previous values",
+                timeout.as_secs()
+            );
+            "timeout".to_string()
+        }
+    };
+
+    // A filtered scrape serves the metrics of its collectors and their
+    // staleness only.
+    let mut families = REGISTRY.gather();
+    if filtered {
+        let metrics: Vec<&str> = COLLECTORS
+            .iter()
+            .filter(|(name, _, _)| selected.contains(name))
+            .flat_map(|(_, _, metrics)| metrics.iter().copied())
+            .chain(["jbod_collector_stale"])
+            .collect();
+        families.retain(|f| metrics.contains(&f.get_name()));
+    }
 
     let mut buffer = Vec::new();
-    if let Err(e) = encoder.encode(&prometheus::gather(), &mut buffer) {
-        eprintln!("could not encode prometheus metrics: {}", e);
+    if let Err(e) = encoder.encode(&families, &mut buffer) {
+        eprintln!("could not encode custom metrics: {}", e);
     };
-    let res_custom = match String::from_utf8(buffer.clone()) {
+
+    let res = match String::from_utf8(buffer) {
         Ok(v) => v,
         Err(e) => {
-            eprintln!("prometheus metrics could not be from_utf8'd: {}", e);
+            eprintln!("custom metrics could not be from_utf8: {}", e);
             String::default()
         }
     };
-    buffer.clear();
 
-    res.push_str(&res_custom);
-    Ok(res)
+    log_scrape(&client, StatusCode::OK, started, &outcome);
+    Ok(warp::reply::with_status(res, StatusCode::OK))
+}
+
+/// Logs a scrape of `/metrics` as one line of key=value pairs.
+///
+/// # Arguments
+///
+/// * `client` - the address of the scraper
+/// * `status` - the HTTP status of the reply
+/// * `started` - when the request was received
+/// * `outcome` - how the collection went, example: "complete collectors=fans"
+///
+fn log_scrape(client: &str, status: StatusCode, started: Instant, outcome: &str) {
+    eprintln!(
+        "client={} path=/metrics status={} duration_ms={} outcome={}",
+        client,
+        status.as_u16(),
+        started.elapsed().as_millis(),
+        outcome
+    );
 }
 
 /// `main()` function that starts the webserver.
 #[tokio::main]
 async fn main() {
     let args: Vec<String> = env::args().collect();
-    let mut port: String = "9945".to_string();
-    let mut ipv4: String = "0.0.0.0".to_string();
+    let address = args.get(1).map(String::as_str).unwrap_or("0.0.0.0");
+    let port = args.get(2).map(String::as_str).unwrap_or("9945");
 
-    if args.len() > 2 {
-        if Util::is_string_numeric(&args[2]) {
-            port = args[2].to_string();
-        } else {
-            println!("Port is not decimal, using default {}", port);
-        }
-
-        let t = args[1]
-            .split(".")
-            .map(Util::is_string_numeric)
-            .any(|i| i == false);
-        if !t {
-            ipv4 = args[1].to_string();
-        } else {
-            println!("Using default ipv4: {}", ipv4);
+    let adr_convert: SocketAddr = match Util::listen_address(address, port) {
+        Ok(adr) => adr,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            exit(1);
         }
-    }
-
-    let adr: String = ipv4 + ":" + &port;
-    let adr_convert: SocketAddr = adr.parse().expect("Could not parse SocketAddr");
+    };
 
+    // The scans of a scrape never draw a progress bar, even in a terminal.
+    Util::set_quiet(true);
     register_metrics();
+    JBOD_BUILD_INFO
+        .with_label_values(&[Util::VERSION, Util::GIT_REVISION, Util::RUSTC_VERSION])
+        .set(1);
 
-    let metrics_route = warp::path!("metrics").and_then(metrics_handler);
+    let metrics_route = warp::path!("metrics")
+        .and(warp::addr::remote())
+        .and(warp::query::<Vec<(String, String)>>())
+        .and_then(metrics_handler);
+    let health_route = warp::path!("health").and_then(health_handler);
     let route = warp::path::end().and_then(index_handler);
 
-    println!("==> Started on {}", adr);
-    warp::serve(metrics_route.or(route))
-        .run(adr_convert)
-        .await;
+    let server = warp::serve(metrics_route.or(health_route).or(route));
+    match server.try_bind_ephemeral(adr_convert) {
+        Ok((adr, serving)) => {
+            println!("==> Started on {}", adr);
+            serving.await;
+        }
+        Err(e) => {
+            eprintln!("Error: cannot listen on {}: {}", adr_convert, e);
+            exit(1);
+        }
+    }
 }
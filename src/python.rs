@@ -0,0 +1,151 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+
+//! The `jbod` Python module of the `python` feature, for the automation
+//! scripts calling jbod-rs instead of parsing the output of the CLI. It is
+//! built and installed as the `jbod-py` package with `maturin build`, see
+//! `pyproject.toml`.
+//!
+//! The listings return the records of `jbod list -e -o json` and
+//! `jbod list -d -o json` as dicts, a failure raises `jbod.JbodError` whose
+//! `code` is the one of the JSON errors of the CLI, example: not_found.
+
+// The pyo3 macros expand to the unsafe calls of the CPython API.
+#[allow(non_snake_case)]
+#[allow(unsafe_code)]
+pub mod Python {
+    use pyo3::create_exception;
+    use pyo3::exceptions::PyException;
+    use pyo3::prelude::*;
+    use serde::Serialize;
+
+    use crate::jbod::context::Context;
+    use crate::jbod::disks::DiskShelf;
+    use crate::jbod::element::Element::LedState;
+    use crate::utils::config::Config;
+    use crate::utils::helper::Util;
+    use crate::utils::output::Output::ErrorCode;
+
+    create_exception!(jbod, JbodError, PyException, "A failure of jbod, `code` tells which.");
+
+    /// A failure of jbod, the ErrorCode and the reason.
+    type Failure = (ErrorCode, String);
+
+    /// Returns the JbodError of a failure, with its code as an attribute.
+    fn error(py: Python<'_>, (code, message): Failure) -> PyErr {
+        let err = JbodError::new_err(message);
+        let code = serde_json::to_value(code)
+            .ok()
+            .and_then(|c| c.as_str().map(str::to_string))
+            .unwrap_or_default();
+        match err.value(py).setattr("code", code) {
+            Ok(()) => err,
+            Err(e) => e,
+        }
+    }
+
+    /// Returns the records as Python objects, through the json module so
+    /// they are the ones of the CLI.
+    fn records<T: Serialize>(py: Python<'_>, records: &[T]) -> PyResult<Py<PyAny>> {
+        let json = serde_json::to_string(records)
+            .map_err(|e| error(py, (ErrorCode::Failed, e.to_string())))?;
+        Ok(py.import("json")?.call_method1("loads", (json,))?.unbind())
+    }
+
+    /// Returns the scan options of an enclosure, every enclosure for None,
+    /// once the configuration is loaded. The scans of a script never draw
+    /// a progress bar.
+    fn scan_options(enclosure: Option<String>) -> Result<DiskShelf::ScanOptions, Failure> {
+        Config::try_init(None).map_err(|e| (ErrorCode::InvalidArgument, e))?;
+        Util::set_quiet(true);
+        Ok(DiskShelf::ScanOptions {
+            enclosure,
+            ..DiskShelf::ScanOptions::default()
+        })
+    }
+
+    /// Returns the enclosures, the records of `jbod list -e -o json`.
+    #[pyfunction]
+    fn list_enclosures(py: Python<'_>) -> PyResult<Py<PyAny>> {
+        let enclosures = py
+            .detach(|| {
+                let run = Context::Run::new(scan_options(None)?);
+                Ok(DiskShelf::jbod_enclosures(&run))
+            })
+            .map_err(|e| error(py, e))?;
+        records(py, &enclosures)
+    }
+
+    /// Returns the disks of an enclosure, given by its slot, serial number
+    /// or alias, or of every enclosure, the records of
+    /// `jbod list -d -o json`.
+    #[pyfunction]
+    #[pyo3(signature = (enclosure=None))]
+    fn list_disks(py: Python<'_>, enclosure: Option<String>) -> PyResult<Py<PyAny>> {
+        let disks = py
+            .detach(|| {
+                let run = Context::Run::new(scan_options(enclosure.clone())?);
+                if let Some(id) = &enclosure {
+                    if DiskShelf::jbod_enclosures(&run).is_empty() {
+                        return Err((ErrorCode::NotFound, format!("enclosure {} not found", id)));
+                    }
+                }
+                let mut disks = DiskShelf::jbod_disk_map(&run);
+                DiskShelf::sort_disks(&mut disks);
+                Ok(disks)
+            })
+            .map_err(|e| error(py, e))?;
+        records(py, &disks)
+    }
+
+    /// Turns the `locate` or the `fault` led of a disk on or off, the
+    /// device is a serial number, a WWN, a device or `<enclosure>/<slot>`.
+    #[pyfunction]
+    fn set_led(py: Python<'_>, device: String, led: String, on: bool) -> PyResult<()> {
+        py.detach(|| {
+            Config::try_init(None).map_err(|e| (ErrorCode::InvalidArgument, e))?;
+            let (location, path) = DiskShelf::disk_led(&device, &led)?;
+            let state = if on { LedState::On } else { LedState::Off };
+            DiskShelf::try_write_led(&location.enclosure, &path, state)
+        })
+        .map_err(|e| error(py, e))
+    }
+
+    /// The disks and the enclosures found by jbod-rs and their leds.
+    #[pymodule]
+    fn jbod(m: &Bound<'_, PyModule>) -> PyResult<()> {
+        m.add("JbodError", m.py().get_type::<JbodError>())?;
+        m.add_function(wrap_pyfunction!(list_enclosures, m)?)?;
+        m.add_function(wrap_pyfunction!(list_disks, m)?)?;
+        m.add_function(wrap_pyfunction!(set_led, m)?)?;
+        Ok(())
+    }
+}
@@ -1 +1,7 @@
+pub mod audit;
+pub mod config;
+pub mod daemon;
+pub mod doctor;
 pub mod helper;
+pub mod i18n;
+pub mod output;
@@ -0,0 +1,216 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Audit {
+    use nix::unistd::{getuid, User};
+    use serde::Serialize;
+    use std::env;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::os::unix::net::UnixDatagram;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use crate::utils::config::Config;
+    use crate::utils::daemon::Daemon;
+    use crate::utils::output::Output;
+
+    /// The socket of the native journald protocol.
+    const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+    /// The identifier of the audit entries in the journal.
+    pub const IDENTIFIER: &str = "jbod-audit";
+
+    static WARNED: AtomicBool = AtomicBool::new(false);
+
+    /// A control operation, written as one JSON line in the audit log.
+    #[derive(Debug, Serialize)]
+    pub struct Entry {
+        /// UTC, example: 2023-04-02T10:31:07Z
+        pub timestamp: String,
+        pub user: String,
+        /// The user who ran sudo, None without sudo
+        pub sudo_user: Option<String>,
+        /// example: led_locate, led_fault, firmware, alias, role
+        pub action: String,
+        /// The enclosure, the slot or the device acted on
+        pub target: String,
+        /// The value before, None when it couldn't be read
+        pub old: Option<String>,
+        pub new: String,
+        /// ok or the error
+        pub result: String,
+    }
+
+    /// Returns the RFC 3339 UTC representation of a time.
+    pub fn rfc3339(time: SystemTime) -> String {
+        let seconds = time
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let (days, rest) = ((seconds / 86400) as i64, seconds % 86400);
+        // Days to civil date, from Howard Hinnant's algorithm.
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + (month <= 2) as i64;
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            year,
+            month,
+            day,
+            rest / 3600,
+            rest % 3600 / 60,
+            rest % 60
+        )
+    }
+
+    /// Returns the name of the user running jbod, the client of the daemon
+    /// for the commands it runs.
+    fn user() -> String {
+        let uid = getuid();
+        if uid.is_root() {
+            if let Ok(client) = env::var(Daemon::CLIENT_ENV) {
+                return client;
+            }
+        }
+        User::from_uid(uid)
+            .ok()
+            .flatten()
+            .map(|u| u.name)
+            .unwrap_or_else(|| uid.to_string())
+    }
+
+    /// Prints why the audit entry couldn't be written, once per run.
+    fn warn(error: String) {
+        if !WARNED.swap(true, Ordering::SeqCst) {
+            Output::print_warning(&format!("the audit entry was not recorded: {}", error));
+        }
+    }
+
+    /// Sends an entry to journald with the `jbod-audit` identifier, each
+    /// field of the entry is a `JBOD_` field of the journal.
+    fn send_journald(entry: &Entry) -> Result<(), String> {
+        let clean = |value: &str| value.replace('\n', " ");
+        let mut message = format!(
+            "MESSAGE={} {} {} -> {} by {}: {}\nSYSLOG_IDENTIFIER={}\nPRIORITY=5\n",
+            clean(&entry.action),
+            clean(&entry.target),
+            clean(entry.old.as_deref().unwrap_or("unknown")),
+            clean(&entry.new),
+            clean(&entry.user),
+            clean(&entry.result),
+            IDENTIFIER
+        );
+        let fields = [
+            ("JBOD_ACTION", Some(&entry.action)),
+            ("JBOD_TARGET", Some(&entry.target)),
+            ("JBOD_OLD", entry.old.as_ref()),
+            ("JBOD_NEW", Some(&entry.new)),
+            ("JBOD_RESULT", Some(&entry.result)),
+            ("JBOD_USER", Some(&entry.user)),
+            ("JBOD_SUDO_USER", entry.sudo_user.as_ref()),
+        ];
+        for (name, value) in fields {
+            if let Some(value) = value {
+                message.push_str(&format!("{}={}\n", name, clean(value)));
+            }
+        }
+        let socket = UnixDatagram::unbound().map_err(|e| e.to_string())?;
+        socket
+            .send_to(message.as_bytes(), JOURNALD_SOCKET)
+            .map_err(|e| format!("{}: {}", JOURNALD_SOCKET, e))?;
+        Ok(())
+    }
+
+    /// Appends an entry to the audit log file.
+    fn append(path: &str, entry: &Entry) -> Result<(), String> {
+        let line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        writeln!(file, "{}", line).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    /// Records a control operation in the audit log and, when enabled, in
+    /// the journal. A failure to record it is a warning, the operation is
+    /// already done.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - what was done, example: led_locate
+    /// * `target` - what it was done to, example: 15:0:1:0/Slot 05
+    /// * `old` - the value before, None when unknown
+    /// * `new` - the value asked for
+    /// * `result` - the outcome of the operation
+    ///
+    pub fn record(
+        action: &str,
+        target: &str,
+        old: Option<&str>,
+        new: &str,
+        result: Result<(), &str>,
+    ) {
+        let settings = &Config::get().audit;
+        if settings.log.is_empty() && !settings.journald {
+            return;
+        }
+        let entry = Entry {
+            timestamp: rfc3339(SystemTime::now()),
+            user: user(),
+            sudo_user: env::var("SUDO_USER").ok(),
+            action: action.to_string(),
+            target: target.to_string(),
+            old: old.map(str::to_string),
+            new: new.to_string(),
+            result: match result {
+                Ok(()) => "ok".to_string(),
+                Err(e) => e.to_string(),
+            },
+        };
+        if !settings.log.is_empty() {
+            if let Err(e) = append(&settings.log, &entry) {
+                warn(e);
+            }
+        }
+        if settings.journald {
+            if let Err(e) = send_journald(&entry) {
+                warn(e);
+            }
+        }
+    }
+}
@@ -0,0 +1,646 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Config {
+    use colored::*;
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+    use std::env;
+    use std::fmt;
+    use std::fs;
+    use std::io;
+    use std::process::exit;
+    use std::sync::OnceLock;
+
+    use crate::utils::helper::Util;
+    use crate::utils::i18n::I18n;
+
+    pub const DEFAULT_CONFIG: &str = "/etc/jbod/jbod.toml";
+    pub const CONFIG_ENV: &str = "JBOD_CONFIG";
+    pub const DEFAULT_AUDIT_LOG: &str = "/var/log/jbod-audit.log";
+    pub const DEFAULT_DAEMON_SOCKET: &str = "/run/jbod.sock";
+
+    static SETTINGS: OnceLock<Settings> = OnceLock::new();
+    static PATH: OnceLock<String> = OnceLock::new();
+
+    /// The content of the configuration file, every section is optional.
+    #[derive(Debug, Deserialize)]
+    #[serde(default)]
+    pub struct Settings {
+        /// Unit used to display temperatures, machine readable outputs and
+        /// metrics are always in Celsius.
+        pub temperature_unit: TemperatureUnit,
+        /// Maximum number of devices probed at the same time.
+        pub jobs: Option<usize>,
+        /// Seconds an external command or SCSI query may take.
+        pub command_timeout: u64,
+        /// Per chassis slot label mapping.
+        pub chassis: Vec<Chassis>,
+        /// Disk serial number to role, it wins over the role of the slot.
+        pub roles: HashMap<String, Role>,
+        /// Enclosure serial number to its position in the datacenter.
+        pub locations: HashMap<String, RackLocation>,
+        /// prometheus-exporter settings.
+        pub exporter: Exporter,
+        /// Where the control operations are recorded.
+        pub audit: AuditLog,
+        /// The socket of `jbod daemon`.
+        pub daemon: Daemon,
+        /// Language of the messages, the POSIX locale when not set.
+        pub locale: Option<I18n::Locale>,
+        /// Site-specific checks run on every enclosure.
+        pub collectors: Vec<CommandCollector>,
+        /// Percentage of the rated endurance used from which an SSD is
+        /// flagged as worn.
+        pub wear_threshold: u8,
+    }
+
+    /// The `[audit]` section, every led, firmware, alias and role change is
+    /// recorded with its user and its outcome.
+    #[derive(Debug, Deserialize)]
+    #[serde(default)]
+    pub struct AuditLog {
+        /// File the entries are appended to as JSON lines, empty to disable.
+        pub log: String,
+        /// Also send the entries to journald, with the `jbod-audit` identifier.
+        pub journald: bool,
+    }
+
+    impl Default for AuditLog {
+        fn default() -> Self {
+            AuditLog {
+                log: DEFAULT_AUDIT_LOG.to_string(),
+                journald: false,
+            }
+        }
+    }
+
+    /// The `[daemon]` section, the socket `jbod daemon` listens on and the
+    /// CLI of the users without root goes through.
+    #[derive(Debug, Deserialize)]
+    #[serde(default)]
+    pub struct Daemon {
+        pub socket: String,
+        /// Group owning the socket, the one of the daemon when not set.
+        pub group: Option<String>,
+        /// Octal permissions of the socket, only the owner and the group
+        /// can connect by default.
+        pub mode: String,
+    }
+
+    impl Default for Daemon {
+        fn default() -> Self {
+            Daemon {
+                socket: DEFAULT_DAEMON_SOCKET.to_string(),
+                group: None,
+                mode: "0660".to_string(),
+            }
+        }
+    }
+
+    /// A `[[collectors]]` entry, a command run for every enclosure whose
+    /// readings show in `list --collectors`, `status` and the exporter,
+    /// see `Plugin::CommandCollector`.
+    ///
+    /// ```toml
+    /// [[collectors]]
+    /// name = "psu_vendor"
+    /// command = "/usr/local/lib/jbod/psu-vendor"
+    /// args = ["{device}", "{serial}"]
+    /// ```
+    #[derive(Debug, Clone, Default, Deserialize)]
+    #[serde(default)]
+    pub struct CommandCollector {
+        pub name: String,
+        pub command: String,
+        /// `{device}`, `{slot}`, `{serial}`, `{vendor}` and `{model}` are
+        /// replaced by the ones of the enclosure.
+        pub args: Vec<String>,
+    }
+
+    /// The `[exporter]` section.
+    #[derive(Debug, Deserialize)]
+    #[serde(default)]
+    pub struct Exporter {
+        /// Seconds a scrape waits for the collection before serving the
+        /// previous values, the collectors not started by then are skipped.
+        pub collection_timeout: u64,
+        /// Maximum number of devices probed at the same time, it falls back
+        /// on the global `jobs`.
+        pub jobs: Option<usize>,
+        /// Minimum seconds between two runs of a collector, 0 runs it on
+        /// every scrape.
+        pub intervals: HashMap<String, u64>,
+        /// Minimum seconds between two runs of any collector, so scrapers
+        /// polling at the same time don't repeat the SES queries.
+        pub min_interval: u64,
+        /// Degrees above the median of its enclosure that make a slot hot.
+        pub hotspot_delta: f64,
+        /// Consecutive runs of the `hotspots` collector a slot must be hot
+        /// in to be reported as a hotspot.
+        pub hotspot_samples: usize,
+        /// Constant labels added to every exported series, example: the
+        /// datacenter and the rack of the host.
+        pub labels: HashMap<String, String>,
+        /// Logs the duration and the failures of each collector run.
+        pub trace: bool,
+        /// The catalog of approved firmware revisions checked by the
+        /// `firmware` collector, see `jbod upgrade-check`.
+        pub catalog: Option<String>,
+    }
+
+    impl Default for Exporter {
+        fn default() -> Self {
+            Exporter {
+                collection_timeout: 30,
+                jobs: None,
+                intervals: HashMap::new(),
+                min_interval: 10,
+                hotspot_delta: 5.0,
+                hotspot_samples: 10,
+                labels: HashMap::new(),
+                trace: false,
+                catalog: None,
+            }
+        }
+    }
+
+    impl Exporter {
+        /// Returns the refresh interval in seconds of a collector.
+        ///
+        /// The enclosure discovery and the firmware revisions barely change
+        /// and cost an inquiry per device so they are refreshed every 5
+        /// minutes by default, the hotspots are sampled every minute and the
+        /// others on every scrape.
+        ///
+        /// An interval is never shorter than `min_interval`.
+        pub fn interval(&self, collector: &str) -> u64 {
+            let interval = match self.intervals.get(collector) {
+                Some(i) => *i,
+                None if matches!(collector, "enclosures" | "firmware" | "wear") => 300,
+                None if collector == "hotspots" => 60,
+                None => 0,
+            };
+            interval.max(self.min_interval)
+        }
+    }
+
+    impl Default for Settings {
+        fn default() -> Self {
+            Settings {
+                temperature_unit: TemperatureUnit::default(),
+                jobs: None,
+                command_timeout: 5,
+                chassis: Vec::new(),
+                roles: HashMap::new(),
+                locations: HashMap::new(),
+                exporter: Exporter::default(),
+                audit: AuditLog::default(),
+                daemon: Daemon::default(),
+                locale: None,
+                collectors: Vec::new(),
+                wear_threshold: 80,
+            }
+        }
+    }
+
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum TemperatureUnit {
+        #[default]
+        Celsius,
+        Fahrenheit,
+    }
+
+    impl TemperatureUnit {
+        /// Returns the temperature converted from Celsius.
+        pub fn convert(&self, celsius: i32) -> i32 {
+            match self {
+                TemperatureUnit::Celsius => celsius,
+                TemperatureUnit::Fahrenheit => (celsius as f64 * 1.8 + 32.0).round() as i32,
+            }
+        }
+
+        /// Returns the unit symbol as displayed after a temperature.
+        pub fn symbol(&self) -> &'static str {
+            match self {
+                TemperatureUnit::Celsius => "c",
+                TemperatureUnit::Fahrenheit => "f",
+            }
+        }
+    }
+
+    /// What a disk is set aside for, a disk without a role is in service.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Role {
+        /// A hot-spare, the exporter reports it when it goes missing or
+        /// gets used.
+        Spare,
+        /// Kept out of service, for an upcoming pool or a replacement.
+        Reserved,
+    }
+
+    impl fmt::Display for Role {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                Role::Spare => write!(f, "spare"),
+                Role::Reserved => write!(f, "reserved"),
+            }
+        }
+    }
+
+    /// Where an enclosure is racked, printed in the listings and the alerts
+    /// so remote hands know where to walk to.
+    ///
+    /// ```toml
+    /// [locations]
+    /// USWSJ03918EZ0069 = { rack = "R12", unit = 30 }
+    /// ```
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct RackLocation {
+        pub rack: String,
+        /// The lowest rack unit of the enclosure.
+        pub unit: Option<u32>,
+    }
+
+    impl fmt::Display for RackLocation {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self.unit {
+                Some(unit) => write!(f, "{} U{}", self.rack, unit),
+                None => write!(f, "{}", self.rack),
+            }
+        }
+    }
+
+    /// Translates the SES slot of a chassis into the label printed on its bezel.
+    ///
+    /// A chassis is identified by the enclosure serial or model, the serial wins
+    /// when both match different entries.
+    ///
+    /// ```toml
+    /// [[chassis]]
+    /// model = "H4102-J"
+    /// alias = "shelf-a"
+    /// offset = 1
+    /// slots = { "7" = "A8" }
+    /// roles = { "23" = "spare" }
+    /// populated = "0-21"
+    /// ```
+    #[derive(Debug, Default, Deserialize)]
+    #[serde(default)]
+    pub struct Chassis {
+        /// Enclosure serial number this mapping applies to.
+        pub serial: Option<String>,
+        /// Enclosure model (product identification) this mapping applies to.
+        pub model: Option<String>,
+        /// Name of the chassis, usable with `list --enclosure=<alias>`.
+        pub alias: Option<String>,
+        /// Added to the slot number when no explicit label exists.
+        pub offset: Option<i64>,
+        /// SES slot number to bezel label.
+        pub slots: HashMap<String, String>,
+        /// Position of the bays, drawn by `jbod map`.
+        pub layout: Option<Layout>,
+        /// SES slot number to the role of the disk it holds.
+        pub roles: HashMap<String, Role>,
+        /// SES slot numbers expected to hold a disk, example: "0-21,23",
+        /// the other slots are intentionally empty.
+        pub populated: Option<String>,
+    }
+
+    /// The bays of a chassis as seen from the front and the rear, each row
+    /// from left to right lists SES slot numbers, -1 marks a position
+    /// without a bay.
+    ///
+    /// ```toml
+    /// [chassis.layout]
+    /// front = [[0, 1, 2, 3], [4, 5, 6, 7]]
+    /// rear = [[8, 9, -1, -1]]
+    /// ```
+    #[derive(Debug, Default, Clone, Deserialize)]
+    #[serde(default)]
+    pub struct Layout {
+        pub front: Vec<Vec<i64>>,
+        pub rear: Vec<Vec<i64>>,
+    }
+
+    impl Chassis {
+        /// Returns the label for a given slot name or None.
+        fn label(&self, slot: &str) -> Option<String> {
+            if let Some(label) = self.slots.get(slot.trim()) {
+                return Some(label.clone());
+            }
+            let number = Util::slot_number(slot)?;
+            if let Some(label) = self.slots.get(&number.to_string()) {
+                return Some(label.clone());
+            }
+            self.offset.map(|o| (number as i64 + o).to_string())
+        }
+
+        /// Returns whether a given slot name should hold a disk, None when
+        /// the population isn't declared.
+        fn expected(&self, slot: &str) -> Option<bool> {
+            let populated = Util::parse_slots(self.populated.as_deref()?).ok()?;
+            Some(populated.contains(&Util::slot_number(slot)?))
+        }
+
+        /// Returns the role of a given slot name or None.
+        fn role(&self, slot: &str) -> Option<Role> {
+            let number = Util::slot_number(slot)?;
+            self.roles.get(&number.to_string()).copied()
+        }
+    }
+
+    impl Settings {
+        /// Returns the chassis entry for an enclosure or None.
+        fn chassis_for(&self, serial: &str, model: &str) -> Option<&Chassis> {
+            self.chassis
+                .iter()
+                .find(|c| c.serial.as_deref() == Some(serial))
+                .or_else(|| self.chassis.iter().find(|c| c.model.as_deref() == Some(model)))
+        }
+
+        /// Returns the physical label of a slot or None if it isn't mapped.
+        ///
+        /// # Arguments
+        ///
+        /// * `serial` - the enclosure serial number
+        /// * `model` - the enclosure model
+        /// * `slot` - the logical slot name reported by SES
+        ///
+        pub fn slot_label(&self, serial: &str, model: &str, slot: &str) -> Option<String> {
+            self.chassis_for(serial, model)?.label(slot)
+        }
+
+        /// Returns the alias of an enclosure or None.
+        ///
+        /// # Arguments
+        ///
+        /// * `serial` - the enclosure serial number
+        /// * `model` - the enclosure model
+        ///
+        pub fn enclosure_alias(&self, serial: &str, model: &str) -> Option<&str> {
+            self.chassis_for(serial, model)?.alias.as_deref()
+        }
+
+        /// Returns the rack location of an enclosure or None.
+        ///
+        /// # Arguments
+        ///
+        /// * `serial` - the enclosure serial number
+        ///
+        pub fn enclosure_location(&self, serial: &str) -> Option<&RackLocation> {
+            self.locations.get(serial)
+        }
+
+        /// Returns the role of a disk, the one of its serial number first
+        /// then the one of its slot, or None when it is in service.
+        ///
+        /// # Arguments
+        ///
+        /// * `serial` - the enclosure serial number
+        /// * `model` - the enclosure model
+        /// * `slot` - the logical slot name reported by SES
+        /// * `disk_serial` - the disk serial number, empty when not collected
+        ///
+        pub fn disk_role(
+            &self,
+            serial: &str,
+            model: &str,
+            slot: &str,
+            disk_serial: &str,
+        ) -> Option<Role> {
+            if let Some(role) = self.roles.get(disk_serial) {
+                return Some(*role);
+            }
+            self.chassis_for(serial, model)?.role(slot)
+        }
+
+        /// Returns the SES slot numbers holding a disk of the given role in
+        /// an enclosure.
+        ///
+        /// # Arguments
+        ///
+        /// * `serial` - the enclosure serial number
+        /// * `model` - the enclosure model
+        /// * `role` - the role looked for
+        ///
+        pub fn slots_with_role(&self, serial: &str, model: &str, role: Role) -> Vec<String> {
+            let mut slots: Vec<String> = match self.chassis_for(serial, model) {
+                Some(chassis) => chassis
+                    .roles
+                    .iter()
+                    .filter(|(_, r)| **r == role)
+                    .map(|(slot, _)| slot.clone())
+                    .collect(),
+                None => Vec::new(),
+            };
+            slots.sort_by(|a, b| Util::natural_cmp(a, b));
+            slots
+        }
+
+        /// Returns the disk serial numbers given the role.
+        pub fn serials_with_role(&self, role: Role) -> Vec<String> {
+            let mut serials: Vec<String> = self
+                .roles
+                .iter()
+                .filter(|(_, r)| **r == role)
+                .map(|(serial, _)| serial.clone())
+                .collect();
+            serials.sort();
+            serials
+        }
+
+        /// Returns whether a slot should hold a disk, None when the
+        /// population of the chassis isn't declared.
+        ///
+        /// # Arguments
+        ///
+        /// * `serial` - the enclosure serial number
+        /// * `model` - the enclosure model
+        /// * `slot` - the logical slot name reported by SES
+        ///
+        pub fn slot_expected(&self, serial: &str, model: &str, slot: &str) -> Option<bool> {
+            self.chassis_for(serial, model)?.expected(slot)
+        }
+
+        /// Returns the bay layout of an enclosure or None.
+        ///
+        /// # Arguments
+        ///
+        /// * `serial` - the enclosure serial number
+        /// * `model` - the enclosure model
+        ///
+        pub fn chassis_layout(&self, serial: &str, model: &str) -> Option<&Layout> {
+            self.chassis_for(serial, model)?.layout.as_ref()
+        }
+    }
+
+    /// Returns why a value of the configuration can't be used.
+    fn validate(settings: &Settings, path: &str) -> Result<(), String> {
+        for spec in settings.chassis.iter().filter_map(|c| c.populated.as_deref()) {
+            Util::parse_slots(spec).map_err(|e| format!("{}: populated: {}", path, e))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the Settings read from a file, or why they can't be used.
+    ///
+    /// A missing default file is not an error, the configuration is optional,
+    /// but an unreadable or malformed file is.
+    fn read(path: &str, explicit: bool) -> Result<Settings, String> {
+        match fs::read_to_string(path) {
+            Ok(content) => {
+                let settings = toml::from_str(&content).map_err(|e| format!("{}: {}", path, e))?;
+                validate(&settings, path)?;
+                Ok(settings)
+            }
+            Err(e) if explicit => Err(format!("{}: {}", path, e)),
+            Err(_) => Ok(Settings::default()),
+        }
+    }
+
+    /// Loads the configuration, or returns why it can't be used. The
+    /// library entry points call it instead of exiting like `init()`, once
+    /// loaded the configuration is kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - an optional path, else `JBOD_CONFIG` or the default path
+    ///
+    pub fn try_init(path: Option<&str>) -> Result<&'static Settings, String> {
+        if let Some(settings) = SETTINGS.get() {
+            return Ok(settings);
+        }
+        let explicit = path.map(str::to_string).or_else(|| env::var(CONFIG_ENV).ok());
+        let settings = match &explicit {
+            Some(p) => read(p, true)?,
+            None => read(DEFAULT_CONFIG, false)?,
+        };
+        let _ = PATH.set(explicit.unwrap_or_else(|| DEFAULT_CONFIG.to_string()));
+        Ok(SETTINGS.get_or_init(|| settings))
+    }
+
+    /// Loads the configuration, it must be called before the first `get()`
+    /// to take effect. Exits when the configuration can't be used.
+    ///
+    /// The path comes from the argument, `JBOD_CONFIG` or the default path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - an optional path given on the command line
+    ///
+    pub fn init(path: Option<&str>) -> &'static Settings {
+        match try_init(path) {
+            Ok(settings) => settings,
+            Err(e) => {
+                eprintln!("{} {}", "Error:".red().bold(), e);
+                exit(1);
+            }
+        }
+    }
+
+    /// Returns the configuration when it is already loaded, None before
+    /// `init()` or the first `get()`.
+    pub fn loaded() -> Option<&'static Settings> {
+        SETTINGS.get()
+    }
+
+    /// Returns the path of the configuration file in use, it may not exist.
+    pub fn path() -> &'static str {
+        PATH.get_or_init(|| env::var(CONFIG_ENV).unwrap_or_else(|_| DEFAULT_CONFIG.to_string()))
+    }
+
+    /// Rewrites the `[[chassis]]` entry of an enclosure in the configuration
+    /// file, the file and the entry are created when missing. An enclosure
+    /// only matched by its model gets its own entry copied from the model
+    /// one, so its labels and layout still apply.
+    ///
+    /// The comments of the file are not preserved.
+    ///
+    /// # Arguments
+    ///
+    /// * `serial` - the enclosure serial number
+    /// * `model` - the enclosure model
+    /// * `update` - changes the entry
+    ///
+    pub fn update_chassis<F>(serial: &str, model: &str, update: F) -> Result<(), String>
+    where
+        F: FnOnce(&mut toml::value::Table),
+    {
+        let path = path();
+        let mut document: toml::value::Table = match fs::read_to_string(path) {
+            Ok(content) => toml::from_str(&content).map_err(|e| format!("{}: {}", path, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => toml::value::Table::new(),
+            Err(e) => return Err(format!("{}: {}", path, e)),
+        };
+        let chassis = match document
+            .entry("chassis")
+            .or_insert_with(|| toml::Value::Array(Vec::new()))
+        {
+            toml::Value::Array(chassis) => chassis,
+            _ => return Err(format!("{}: chassis is not an array of tables", path)),
+        };
+        let field = |entry: &toml::Value, key: &str, value: &str| {
+            entry.get(key).and_then(|v| v.as_str()) == Some(value)
+        };
+        let index = match chassis.iter().position(|c| field(c, "serial", serial)) {
+            Some(index) => index,
+            None => {
+                let mut entry = chassis
+                    .iter()
+                    .find(|c| field(c, "model", model))
+                    .and_then(|c| c.as_table().cloned())
+                    .unwrap_or_default();
+                entry.remove("model");
+                entry.insert("serial".to_string(), toml::Value::String(serial.to_string()));
+                chassis.push(toml::Value::Table(entry));
+                chassis.len() - 1
+            }
+        };
+        match chassis[index].as_table_mut() {
+            Some(entry) => update(entry),
+            None => return Err(format!("{}: chassis is not an array of tables", path)),
+        }
+        let content = toml::to_string(&document).map_err(|e| format!("{}: {}", path, e))?;
+        fs::write(path, content).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    /// Returns the loaded configuration, loading the default one if needed
+    /// like `init()`.
+    pub fn get() -> &'static Settings {
+        match SETTINGS.get() {
+            Some(settings) => settings,
+            None => init(None),
+        }
+    }
+}
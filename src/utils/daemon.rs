@@ -0,0 +1,468 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Daemon {
+    use clap::ArgMatches;
+    use nix::sys::socket::{getsockopt, sockopt::PeerCredentials};
+    use nix::unistd::{chown, geteuid, Group, Uid, User};
+    use serde::{Deserialize, Serialize};
+    use std::env;
+    use std::fs;
+    use std::io::{self, BufRead, BufReader, IsTerminal, Read, Write};
+    use std::net::Shutdown;
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::Path;
+    use std::process::Command;
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::utils::config::Config;
+    use crate::utils::config::Config::CONFIG_ENV;
+    use crate::utils::helper::Util;
+    use crate::utils::i18n::I18n;
+    use crate::utils::output::Output;
+    use crate::utils::output::Output::ErrorCode;
+
+    /// The subcommands the daemon runs for its clients, the queries and
+    /// the leds.
+    pub const COMMANDS: &[&str] = &[
+        "list",
+        "find",
+        "show",
+        "status",
+        "health",
+        "summary",
+        "topology",
+        "map",
+        "inventory",
+        "led",
+        "locate",
+    ];
+
+    /// The global options a client can't give, they would make the daemon
+    /// read another configuration or another sysfs as root.
+    const REFUSED_OPTIONS: &[&str] = &["--config", "--sysfs-root"];
+
+    /// The global options followed by their value.
+    const VALUE_OPTIONS: &[&str] = &["-o", "--output", "-j", "--jobs", "--timeout", "--cache"];
+
+    /// Set on the commands the daemon runs, the audit log records this
+    /// user instead of root.
+    pub const CLIENT_ENV: &str = "JBOD_DAEMON_CLIENT";
+
+    /// Seconds a client has to send its request.
+    const REQUEST_TIMEOUT: u64 = 5;
+
+    /// Bytes a request may take, its newline included.
+    const MAX_REQUEST: u64 = 64 * 1024;
+
+    /// What a client asks, the arguments of its command line.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Request {
+        args: Vec<String>,
+        /// The client writes on a terminal, the output is colored.
+        color: bool,
+        /// The language of the client, the messages are translated.
+        #[serde(default)]
+        locale: I18n::Locale,
+    }
+
+    /// What the daemon answers, the output of the command or why it was
+    /// refused.
+    #[derive(Debug, Default, Serialize, Deserialize)]
+    struct Response {
+        stdout: String,
+        stderr: String,
+        code: i32,
+        refused: Option<String>,
+    }
+
+    /// Returns the subcommand of a command line, the first argument that
+    /// is neither a global option nor its value.
+    fn subcommand(args: &[String]) -> Option<&str> {
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let short_without_value = split_short(arg, "oj").is_some_and(|(_, _, value)| value.is_empty());
+            if VALUE_OPTIONS.contains(&arg.as_str()) || short_without_value {
+                args.next();
+            } else if !arg.starts_with('-') {
+                return Some(arg);
+            }
+        }
+        None
+    }
+
+    /// Returns the short options taking a value, `-f` only takes one with
+    /// `led` and `-e` takes none with `list`.
+    fn value_shorts(command: Option<&str>) -> &'static str {
+        match command {
+            Some("led") => "ojelpf",
+            Some("list") => "ojlp",
+            _ => "ojelp",
+        }
+    }
+
+    /// Splits a cluster of short options at its first option taking a
+    /// value, example: "-qj500" is ("-q", 'j', "500") and "-j=500" is
+    /// ("-", 'j', "500"), None when none of them takes a value.
+    fn split_short(arg: &str, shorts: &str) -> Option<(String, char, String)> {
+        let flags = arg.strip_prefix('-').filter(|a| !a.starts_with('-'))?;
+        let (i, option) = flags.char_indices().find(|(_, c)| shorts.contains(*c))?;
+        let value = &flags[i + option.len_utf8()..];
+        Some((
+            format!("-{}", &flags[..i]),
+            option,
+            value.strip_prefix('=').unwrap_or(value).to_string(),
+        ))
+    }
+
+    /// Returns why a command line is refused, None when the daemon runs it.
+    fn refusal(args: &[String]) -> Option<String> {
+        if let Some(option) = args.iter().find(|a| {
+            REFUSED_OPTIONS
+                .iter()
+                .any(|o| a.as_str() == *o || a.starts_with(&format!("{}=", o)))
+        }) {
+            return Some(format!("{} is not accepted through the daemon", option));
+        }
+        match subcommand(args) {
+            Some(command) if COMMANDS.contains(&command) => None,
+            Some(command) => Some(format!(
+                "{} is not run by the daemon, only {}",
+                command,
+                COMMANDS.join(", ")
+            )),
+            None => Some("no subcommand given".to_string()),
+        }
+    }
+
+    /// Returns the command line with `--jobs` and `--timeout` lowered to the
+    /// limits of the daemon, a client can't make it probe more devices at
+    /// once or wait longer on them.
+    fn clamp_limits(args: &[String]) -> Vec<String> {
+        let max_jobs = Config::get().jobs.unwrap_or(Util::DEFAULT_JOBS);
+        let max_timeout = Util::command_timeout();
+        let limit = |option: &str, value: &str| match option {
+            "-j" | "--jobs" => value.parse::<usize>().ok().map(|j| j.clamp(1, max_jobs).to_string()),
+            "--timeout" => Util::parse_duration(value).map(|t| format!("{}s", t.min(max_timeout).as_secs())),
+            _ => None,
+        };
+
+        let shorts = value_shorts(subcommand(args));
+        let mut clamped = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if let Some((option, value)) = arg.split_once('=').filter(|(o, _)| o.starts_with("--")) {
+                match limit(option, value) {
+                    Some(value) => clamped.push(format!("{}={}", option, value)),
+                    None => clamped.push(arg.clone()),
+                }
+            } else if ["--jobs", "--timeout"].contains(&arg.as_str()) {
+                clamped.push(arg.clone());
+                if let Some(value) = args.next() {
+                    clamped.push(limit(arg, value).unwrap_or_else(|| value.clone()));
+                }
+            } else if let Some((flags, 'j', value)) = split_short(arg, shorts) {
+                // The flags before -j, example: -q of -qj500.
+                if flags != "-" {
+                    clamped.push(flags);
+                }
+                if value.is_empty() {
+                    clamped.push("-j".to_string());
+                    if let Some(value) = args.next() {
+                        clamped.push(limit("-j", value).unwrap_or_else(|| value.clone()));
+                    }
+                } else {
+                    clamped.push(format!("-j{}", limit("-j", &value).unwrap_or(value)));
+                }
+            } else {
+                clamped.push(arg.clone());
+            }
+        }
+        clamped
+    }
+
+    /// Returns the name of a user, its uid when it has none.
+    fn user_name(uid: Uid) -> String {
+        User::from_uid(uid)
+            .ok()
+            .flatten()
+            .map(|u| u.name)
+            .unwrap_or_else(|| uid.to_string())
+    }
+
+    /// Runs the command of a client with this jbod, as root.
+    fn run(request: &Request, client: &str) -> Response {
+        let exe = match env::current_exe() {
+            Ok(exe) => exe,
+            Err(e) => {
+                return Response {
+                    stderr: format!("cannot find the jbod executable: {}\n", e),
+                    code: 1,
+                    ..Default::default()
+                }
+            }
+        };
+        let mut command = Command::new(exe);
+        command
+            .args(clamp_limits(&request.args))
+            .env(CLIENT_ENV, client)
+            .env(Util::SYSFS_ROOT_ENV, Util::sysfs_root())
+            .env(I18n::LANG_ENV, request.locale.code());
+        // A missing default configuration is not an error, a missing
+        // explicit one is.
+        if Path::new(Config::path()).exists() {
+            command.env(CONFIG_ENV, Config::path());
+        } else {
+            command.env_remove(CONFIG_ENV);
+        }
+        if request.color {
+            command.env("CLICOLOR_FORCE", "1");
+        } else {
+            command.env_remove("CLICOLOR_FORCE");
+        }
+        match command.output() {
+            Ok(output) => Response {
+                stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+                code: output.status.code().unwrap_or(1),
+                refused: None,
+            },
+            Err(e) => Response {
+                stderr: format!("cannot run jbod: {}\n", e),
+                code: 1,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Answers one client, its user is the one of the socket peer.
+    fn serve(mut stream: UnixStream) -> Result<(), String> {
+        let credentials =
+            getsockopt(stream.as_raw_fd(), PeerCredentials).map_err(|e| e.to_string())?;
+        let client = user_name(Uid::from_raw(credentials.uid()));
+        stream
+            .set_read_timeout(Some(Duration::from_secs(REQUEST_TIMEOUT)))
+            .map_err(|e| e.to_string())?;
+        let mut line = String::new();
+        BufReader::new((&stream).take(MAX_REQUEST))
+            .read_line(&mut line)
+            .map_err(|e| format!("{}: {}", client, e))?;
+        if !line.ends_with('\n') && line.len() as u64 >= MAX_REQUEST {
+            let reason = format!("request larger than {} bytes", MAX_REQUEST);
+            let refused = Response {
+                code: 1,
+                refused: Some(reason.clone()),
+                ..Default::default()
+            };
+            if let Ok(answer) = serde_json::to_string(&refused) {
+                let _ = stream.write_all(format!("{}\n", answer).as_bytes());
+            }
+            return Err(format!("{}: {}", client, reason));
+        }
+        let request: Request = serde_json::from_str(&line)
+            .map_err(|e| format!("{}: invalid request: {}", client, e))?;
+        let response = match refusal(&request.args) {
+            Some(reason) => Response {
+                code: 1,
+                refused: Some(reason),
+                ..Default::default()
+            },
+            None => run(&request, &client),
+        };
+        eprintln!(
+            "{} jbod {} -> {}",
+            client,
+            request.args.join(" "),
+            response
+                .refused
+                .as_deref()
+                .map_or(response.code.to_string(), |r| format!("refused, {}", r))
+        );
+        let mut answer = serde_json::to_string(&response).map_err(|e| e.to_string())?;
+        answer.push('\n');
+        stream
+            .write_all(answer.as_bytes())
+            .map_err(|e| format!("{}: {}", client, e))
+    }
+
+    /// Binds the socket with the permissions and the group of the
+    /// configuration, a socket left by a previous daemon is replaced.
+    fn bind(path: &str) -> Result<UnixListener, String> {
+        let settings = &Config::get().daemon;
+        let mode = u32::from_str_radix(settings.mode.trim_start_matches("0o"), 8)
+            .map_err(|_| format!("invalid socket mode {}, example: 0660", settings.mode))?;
+        let group = match &settings.group {
+            Some(name) => match Group::from_name(name) {
+                Ok(Some(group)) => Some(group.gid),
+                _ => return Err(format!("unknown group {}", name)),
+            },
+            None => None,
+        };
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                return Err(format!("{} exists and is not a socket", path));
+            }
+            if UnixStream::connect(path).is_ok() {
+                return Err(format!("another daemon listens on {}", path));
+            }
+            fs::remove_file(path).map_err(|e| format!("{}: {}", path, e))?;
+        }
+        let listener = UnixListener::bind(path).map_err(|e| format!("{}: {}", path, e))?;
+        chown(Path::new(path), None, group).map_err(|e| format!("{}: {}", path, e))?;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))
+            .map_err(|e| format!("{}: {}", path, e))?;
+        Ok(listener)
+    }
+
+    /// Serves the queries and the led operations of the users without root
+    /// on a unix socket, each request runs the same jbod as root.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the `daemon` subcommand
+    ///
+    pub fn jbod_daemon(options: &ArgMatches) -> Result<(), ()> {
+        if !geteuid().is_root() {
+            Output::print_error_code(ErrorCode::PermissionDenied, "the daemon must run as root");
+            return Err(());
+        }
+        let path = options
+            .value_of("socket")
+            .unwrap_or(&Config::get().daemon.socket)
+            .to_string();
+        let listener = match bind(&path) {
+            Ok(listener) => listener,
+            Err(e) => {
+                Output::print_error(&format!("cannot listen: {}", e));
+                return Err(());
+            }
+        };
+        eprintln!("jbod daemon listening on {}", path);
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    thread::spawn(move || {
+                        if let Err(e) = serve(stream) {
+                            Output::print_error(&e);
+                        }
+                    });
+                }
+                Err(e) => Output::print_error(&e.to_string()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends a request to the daemon and returns its answer.
+    fn exchange(stream: &mut UnixStream, request: &Request) -> Result<Response, String> {
+        let mut line = serde_json::to_string(request).map_err(|e| e.to_string())?;
+        line.push('\n');
+        stream
+            .write_all(line.as_bytes())
+            .map_err(|e| e.to_string())?;
+        stream
+            .shutdown(Shutdown::Write)
+            .map_err(|e| e.to_string())?;
+        let mut answer = String::new();
+        stream
+            .read_to_string(&mut answer)
+            .map_err(|e| e.to_string())?;
+        serde_json::from_str(&answer).map_err(|e| format!("invalid answer: {}", e))
+    }
+
+    /// Runs the command line through the daemon, for a user without root.
+    /// Returns the exit code of the command, or None when no daemon
+    /// answers on the socket and the command runs locally.
+    pub fn forward() -> Option<i32> {
+        let path = &Config::get().daemon.socket;
+        let mut stream = UnixStream::connect(path).ok()?;
+        let request = Request {
+            args: env::args().skip(1).collect(),
+            color: io::stdout().is_terminal() && Output::color(),
+            locale: I18n::locale(),
+        };
+        match exchange(&mut stream, &request) {
+            Ok(response) => {
+                if let Some(reason) = response.refused {
+                    Output::print_error_code(
+                        ErrorCode::PermissionDenied,
+                        &format!("the daemon refused: {}", reason),
+                    );
+                    return Some(1);
+                }
+                print!("{}", response.stdout);
+                eprint!("{}", response.stderr);
+                let _ = io::stdout().flush();
+                Some(response.code)
+            }
+            Err(e) => {
+                Output::print_error(&format!("the daemon on {} failed: {}", path, e));
+                Some(1)
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn args(line: &str) -> Vec<String> {
+            line.split_whitespace().map(str::to_string).collect()
+        }
+
+        #[test]
+        fn limits_are_clamped() {
+            assert_eq!(
+                clamp_limits(&args("--jobs 500 --timeout=1h -j0 list --jobs=2 --timeout 1s")),
+                args("--jobs 8 --timeout=5s -j1 list --jobs=2 --timeout 1s")
+            );
+            assert_eq!(clamp_limits(&args("-j=500 list")), args("-j8 list"));
+            assert_eq!(clamp_limits(&args("-qj500 list -dj=500")), args("-q -j8 list -d -j8"));
+            assert_eq!(clamp_limits(&args("-qj 500 list -d")), args("-q -j 8 list -d"));
+            // The value of an option isn't an option.
+            assert_eq!(clamp_limits(&args("led -ej500")), args("led -ej500"));
+            assert_eq!(clamp_limits(&args("list -ej500")), args("list -e -j8"));
+            assert_eq!(clamp_limits(&args("led -fj500")), args("led -fj500"));
+            assert_eq!(clamp_limits(&args("list -fj500")), args("list -f -j8"));
+        }
+
+        #[test]
+        fn subcommand_skips_the_values() {
+            assert_eq!(subcommand(&args("-j 4 --output json show sdb")), Some("show"));
+            assert_eq!(subcommand(&args("-qj 4 -o json show sdb")), Some("show"));
+            assert!(refusal(&args("--sysfs-root=/tmp summary")).is_some());
+            assert!(refusal(&args("summary")).is_none());
+        }
+    }
+}
@@ -0,0 +1,244 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Doctor {
+    use clap::ArgMatches;
+    use colored::*;
+    use nix::unistd::{access, geteuid, AccessFlags};
+    use serde::Serialize;
+    use std::fs;
+
+    use crate::jbod::enclosure::BackPlane;
+    use crate::jbod::quirks::Quirks::{self, Quirk};
+    use crate::utils::config::Config;
+    use crate::utils::helper::Util;
+    use crate::utils::helper::Util::{
+        HELPER_BINARIES, JBOD_EXPORTER, LSSCSI, SCSI_TEMP, SGINFO, SG_INQ, SG_MAP, SG_SES,
+    };
+    use crate::utils::output::Output;
+
+    #[derive(Debug, Serialize)]
+    pub struct Binary {
+        /// Full path of the helper binary
+        pub path: String,
+        /// Package that provides the binary
+        pub package: String,
+        pub found: bool,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct Feature {
+        /// The jbod-rs functionality, example: `list -d`
+        pub name: String,
+        pub usable: bool,
+        /// Why the feature is not usable, empty when it is
+        pub reason: String,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct EnclosureQuirk {
+        /// The SCSI address of the enclosure, example: 15:0:1:0
+        pub enclosure: String,
+        pub vendor: String,
+        pub model: String,
+        /// The quirk selected from the vendor and the model
+        pub quirk: &'static Quirk,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct Report {
+        pub binaries: Vec<Binary>,
+        /// The kernel exposes enclosures in class/enclosure of sysfs
+        pub kernel_enclosure_support: bool,
+        /// Number of enclosures found in class/enclosure of sysfs
+        pub enclosures: usize,
+        pub root: bool,
+        /// The led files can be written by the current user
+        pub led_writable: bool,
+        pub features: Vec<Feature>,
+        /// The quirk used for each enclosure, empty without lsscsi
+        pub quirks: Vec<EnclosureQuirk>,
+    }
+
+    /// Returns the first led control file found in sysfs or None.
+    fn find_led_file() -> Option<String> {
+        for enclosure in fs::read_dir(Util::sys_class_enclosure()).ok()?.flatten() {
+            for slot in fs::read_dir(enclosure.path()).ok()?.flatten() {
+                let locate = slot.path().join("locate");
+                if locate.exists() {
+                    return Some(locate.to_string_lossy().to_string());
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns a Feature usable when all binaries are present and the extra
+    /// requirement is met.
+    fn feature(name: &str, binaries: &[&str], requirement: Option<&str>) -> Feature {
+        let missing: Vec<&str> = binaries
+            .iter()
+            .filter(|b| !Util::path_exists(b))
+            .copied()
+            .collect();
+        let mut reasons: Vec<String> = Vec::new();
+        if !missing.is_empty() {
+            reasons.push(format!("missing {}", missing.join(", ")));
+        }
+        if let Some(r) = requirement {
+            reasons.push(r.to_string());
+        }
+
+        Feature {
+            name: name.to_string(),
+            usable: reasons.is_empty(),
+            reason: reasons.join("; "),
+        }
+    }
+
+    /// Returns the Report describing what jbod-rs can do on this host.
+    pub fn diagnose() -> Report {
+        let binaries = HELPER_BINARIES
+            .iter()
+            .map(|(path, package)| Binary {
+                path: path.to_string(),
+                package: package.to_string(),
+                found: Util::path_exists(path),
+            })
+            .collect();
+
+        let enclosures = fs::read_dir(Util::sys_class_enclosure())
+            .map(|d| d.count())
+            .unwrap_or(0);
+        let kernel_enclosure_support = enclosures > 0;
+        let root = geteuid().is_root();
+        let led_writable = find_led_file()
+            .map(|f| access(f.as_str(), AccessFlags::W_OK).is_ok())
+            .unwrap_or(false);
+
+        let no_enclosure = format!("no enclosure in {}", Util::sys_class_enclosure().display());
+        let no_sysfs = (!kernel_enclosure_support).then_some(no_enclosure.as_str());
+        let no_led = if !kernel_enclosure_support {
+            no_sysfs
+        } else if !led_writable {
+            Some("led files are not writable by this user")
+        } else {
+            None
+        };
+
+        let features = vec![
+            feature("list -e", &[LSSCSI, SG_INQ], None),
+            feature("list -d", &[LSSCSI, SG_INQ, SG_MAP, SCSI_TEMP, SGINFO], no_sysfs),
+            feature("list -f", &[LSSCSI, SG_INQ, SG_SES], None),
+            feature("led", &[LSSCSI, SG_INQ, SG_MAP, SCSI_TEMP, SGINFO], no_led),
+            feature("prometheus", &[JBOD_EXPORTER, LSSCSI, SG_INQ, SG_SES, SCSI_TEMP], None),
+        ];
+
+        let quirks = if Util::path_exists(LSSCSI) {
+            BackPlane::get_enclosure(Config::get().jobs.unwrap_or(Util::DEFAULT_JOBS))
+                .into_iter()
+                .map(|e| EnclosureQuirk {
+                    quirk: Quirks::for_enclosure(&e.vendor, &e.model),
+                    enclosure: e.slot,
+                    vendor: e.vendor,
+                    model: e.model,
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        Report {
+            binaries,
+            kernel_enclosure_support,
+            enclosures,
+            root,
+            led_writable,
+            features,
+            quirks,
+        }
+    }
+
+    /// Returns a colored yes/no.
+    fn yes_no(value: bool) -> ColoredString {
+        if value {
+            "yes".green().bold()
+        } else {
+            "no".red().bold()
+        }
+    }
+
+    /// Prints the report for humans.
+    fn print_report(report: &Report) {
+        println!("{} {} {}", "==> ".blue().bold(), "Helper binaries".bold(), " <==".blue().bold());
+        for binary in &report.binaries {
+            println!(" {:<40} {:<12} {}", binary.path, binary.package, yes_no(binary.found));
+        }
+
+        println!("{} {} {}", "==> ".blue().bold(), "Host".bold(), " <==".blue().bold());
+        println!(" {:<40} {}", "Kernel enclosure support", yes_no(report.kernel_enclosure_support));
+        println!(" {:<40} {}", "Enclosures", report.enclosures);
+        println!(" {:<40} {}", "Running as root", yes_no(report.root));
+        println!(" {:<40} {}", "Led files writable", yes_no(report.led_writable));
+
+        println!("{} {} {}", "==> ".blue().bold(), "Features".bold(), " <==".blue().bold());
+        for feature in &report.features {
+            println!(" {:<40} {} {}", feature.name, yes_no(feature.usable), feature.reason.yellow());
+        }
+
+        if !report.quirks.is_empty() {
+            println!("{} {} {}", "==> ".blue().bold(), "Enclosure quirks".bold(), " <==".blue().bold());
+            for e in &report.quirks {
+                let name = format!("{} {} {}", e.enclosure, e.vendor, e.model);
+                println!(" {:<40} {:<16} {}", name, e.quirk.name.bold(), e.quirk.description);
+            }
+        }
+    }
+
+    /// TODO: Rework error handling, perhaps we don't need return Result
+    ///
+    /// This function is used by the `doctor` menu option.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - a reference of ArgMatches
+    ///
+    pub fn jbod_doctor(options: &ArgMatches) -> Result<(), ()> {
+        let report = diagnose();
+        match Output::get_format(options) {
+            Output::Format::Table => {
+                print_report(&report);
+                Ok(())
+            }
+            format => Output::render_document(format, &report).map_err(|e| Output::print_error(&e)),
+        }
+    }
+}
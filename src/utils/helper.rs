@@ -31,10 +31,26 @@
 #[allow(non_snake_case)]
 pub mod Util {
     use colored::*;
+    use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+    use nix::fcntl::{flock, FlockArg};
+    use nix::unistd::{access, geteuid, AccessFlags, Group};
+    use std::env;
     use std::fs;
-    use std::io;
-    use std::path::Path;
-    use std::process::exit;
+    use std::io::{self, IsTerminal, Read};
+    use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+    use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+    use std::os::unix::io::AsRawFd;
+    use std::path::{Path, PathBuf};
+    use std::process::{exit, Command, Output, Stdio};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex, OnceLock, RwLock};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use crate::utils::config::Config;
+    use crate::utils::i18n::I18n;
+    use crate::utils::i18n::I18n::Message;
+    use crate::utils::output::Output::{self as Out, ErrorCode};
 
     pub const LSSCSI: &str = "/usr/bin/lsscsi";
     pub const SG_INQ: &str = "/usr/bin/sg_inq";
@@ -42,7 +58,213 @@ pub mod Util {
     pub const SG_MAP: &str = "/usr/bin/sg_map";
     pub const SG_SES: &str = "/usr/bin/sg_ses";
     pub const SGINFO: &str = "/usr/bin/sginfo";
+    pub const SG_VPD: &str = "/usr/bin/sg_vpd";
+    pub const ZPOOL: &str = "/usr/sbin/zpool";
     pub const JBOD_EXPORTER: &str = "/usr/bin/prometheus-jbod-exporter";
+    /// What the binaries are built from, recorded by build.rs.
+    pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+    pub const GIT_REVISION: &str = env!("JBOD_GIT_REVISION");
+    pub const RUSTC_VERSION: &str = env!("JBOD_RUSTC_VERSION");
+    pub const FEATURES: &str = env!("JBOD_FEATURES");
+    /// The output of `jbod --version`.
+    pub const LONG_VERSION: &str = concat!(
+        env!("CARGO_PKG_VERSION"),
+        "\nrevision: ",
+        env!("JBOD_GIT_REVISION"),
+        "\nrustc: ",
+        env!("JBOD_RUSTC_VERSION"),
+        "\nfeatures: ",
+        env!("JBOD_FEATURES"),
+    );
+    pub const DEFAULT_SYSFS_ROOT: &str = "/sys";
+    /// Where the control locks are taken, writable by every user.
+    pub const LOCK_DIR: &str = "/run/lock";
+    /// The lock of the `--lock` mode, in LOCK_DIR.
+    pub const RUNTIME_LOCK: &str = "jbod.lock";
+    /// Environment variable read when no sysfs root is given, example: /host/sys
+    pub const SYSFS_ROOT_ENV: &str = "JBOD_SYSFS_ROOT";
+
+    /// Default number of devices probed at the same time.
+    pub const DEFAULT_JOBS: usize = 8;
+
+    static COMMAND_TIMEOUT: OnceLock<Duration> = OnceLock::new();
+
+    /// Sets the timeout of the external commands, it overrides the
+    /// configuration and must be called before the first command.
+    pub fn set_command_timeout(timeout: Duration) {
+        let _ = COMMAND_TIMEOUT.set(timeout);
+    }
+
+    /// Returns the timeout of the external commands and SCSI queries.
+    pub fn command_timeout() -> Duration {
+        *COMMAND_TIMEOUT.get_or_init(|| Duration::from_secs(Config::get().command_timeout))
+    }
+
+    static SYSFS_ROOT: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+    /// Sets where sysfs is read from, a fixture tree or the sysfs of the
+    /// host mounted in a container.
+    pub fn set_sysfs_root(root: &Path) {
+        *SYSFS_ROOT.write().unwrap_or_else(|e| e.into_inner()) = Some(root.to_path_buf());
+    }
+
+    /// Returns where sysfs is read from, the root set with `set_sysfs_root`,
+    /// or else `SYSFS_ROOT_ENV`, or else /sys.
+    pub fn sysfs_root() -> PathBuf {
+        if let Some(root) = SYSFS_ROOT.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+            return root.clone();
+        }
+        match env::var_os(SYSFS_ROOT_ENV).filter(|r| !r.is_empty()) {
+            Some(root) => PathBuf::from(root),
+            None => PathBuf::from(DEFAULT_SYSFS_ROOT),
+        }
+    }
+
+    /// Returns the path of an entry of sysfs under `sysfs_root()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `relative` - the path relative to the sysfs mount point
+    ///
+    /// # Example:
+    /// ```
+    /// let enclosures = sysfs_path("class/enclosure");
+    /// ```
+    pub fn sysfs_path(relative: &str) -> PathBuf {
+        sysfs_root().join(relative)
+    }
+
+    /// Returns the folder where the kernel exposes the enclosures.
+    pub fn sys_class_enclosure() -> PathBuf {
+        sysfs_path("class/enclosure")
+    }
+
+    /// Returns the folder where the kernel exposes the scsi generic devices.
+    pub fn sys_class_scsi_generic() -> PathBuf {
+        sysfs_path("class/scsi_generic")
+    }
+
+    /// Returns the Output of a command or an error if it fails to start or
+    /// doesn't finish within `command_timeout()`.
+    ///
+    /// A hung helper, like sg_ses against a dead expander, is killed so the
+    /// caller can report the device and carry on.
+    ///
+    /// # Arguments
+    ///
+    /// * `program` - the path of the binary
+    /// * `args` - the arguments
+    ///
+    /// # Example:
+    /// ```
+    /// let output = run_command(SG_INQ, &["/dev/sg9"])?;
+    /// ```
+    pub fn run_command(program: &str, args: &[&str]) -> io::Result<Output> {
+        run_command_timeout(program, args, command_timeout())
+    }
+
+    /// Returns the Output of a command like `run_command` with its own
+    /// timeout, for the devices answering slower than the others.
+    ///
+    /// # Arguments
+    ///
+    /// * `program` - the path of the binary
+    /// * `args` - the arguments
+    /// * `timeout` - how long the command may take
+    ///
+    pub fn run_command_timeout(program: &str, args: &[&str], timeout: Duration) -> io::Result<Output> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // Pipes are drained while waiting so a verbose command can't fill them.
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let mut stderr = child.stderr.take().expect("stderr is piped");
+        let stdout_reader = thread::spawn(move || {
+            let mut buffer = Vec::new();
+            let _ = stdout.read_to_end(&mut buffer);
+            buffer
+        });
+        let stderr_reader = thread::spawn(move || {
+            let mut buffer = Vec::new();
+            let _ = stderr.read_to_end(&mut buffer);
+            buffer
+        });
+
+        let deadline = Instant::now() + timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("timed out after {}s", timeout.as_secs_f32()),
+                ));
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+
+        Ok(Output {
+            status,
+            stdout: stdout_reader.join().unwrap_or_default(),
+            stderr: stderr_reader.join().unwrap_or_default(),
+        })
+    }
+
+    /// Returns the stdout of a command, or an empty string after reporting
+    /// the failure on stderr.
+    ///
+    /// # Arguments
+    ///
+    /// * `program` - the path of the binary
+    /// * `args` - the arguments
+    ///
+    pub fn command_stdout(program: &str, args: &[&str]) -> String {
+        command_stdout_timeout(program, args, command_timeout())
+    }
+
+    /// Returns the stdout of a command like `command_stdout` with its own
+    /// timeout.
+    ///
+    /// # Arguments
+    ///
+    /// * `program` - the path of the binary
+    /// * `args` - the arguments
+    /// * `timeout` - how long the command may take
+    ///
+    pub fn command_stdout_timeout(program: &str, args: &[&str], timeout: Duration) -> String {
+        match run_command_timeout(program, args, timeout) {
+            Ok(output) => String::from_utf8_lossy(&output.stdout).to_string(),
+            Err(e) => {
+                eprintln!(
+                    "{} {} {}: {}",
+                    "Error:".red().bold(),
+                    program,
+                    args.join(" "),
+                    e
+                );
+                String::new()
+            }
+        }
+    }
+
+    /// Every helper binary used by jbod-rs with the package providing it.
+    pub const HELPER_BINARIES: &[(&str, &str)] = &[
+        (LSSCSI, "lsscsi"),
+        (SG_INQ, "sg3-utils"),
+        (SCSI_TEMP, "sg3-utils"),
+        (SG_MAP, "sg3-utils"),
+        (SG_SES, "sg3-utils"),
+        (SGINFO, "sg3-utils"),
+        (SG_VPD, "sg3-utils"),
+        (JBOD_EXPORTER, "gandi-jbod"),
+    ];
 
     /// Returns an enum with true or false if a directory is empty
     ///
@@ -51,13 +273,13 @@ pub mod Util {
     /// # Arguments
     ///
     /// * `path` - a string reference
-    pub fn is_folder_empty(path: &str) -> io::Result<bool> {
+    pub fn is_folder_empty(path: &Path) -> io::Result<bool> {
         Ok(fs::read_dir(path)?.next().is_none())
     }
 
     /// Verify if sysclass folder exists or otherwise exit and
     /// provide software alternatives to jbod-rs.
-    pub fn verify_sysclass_folder(path: &str) {
+    pub fn verify_sysclass_folder(path: &Path) {
         if is_folder_empty(path).unwrap_or(false) {
             println!(
                 "{} {} {}",
@@ -75,6 +297,120 @@ pub mod Util {
         }
     }
 
+    /// An exclusive lock on the control of an enclosure, released when
+    /// dropped.
+    pub struct ControlLock {
+        _file: fs::File,
+    }
+
+    /// Returns the ControlLock of an enclosure, waiting for the jbod holding it.
+    ///
+    /// The lock is advisory, it keeps concurrent jbod invocations from
+    /// interleaving their writes to the SES control page of one enclosure.
+    ///
+    /// # Arguments
+    ///
+    /// * `enclosure` - the enclosure identification, example: 15:0:1:0
+    ///
+    pub fn lock_enclosure(enclosure: &str) -> io::Result<ControlLock> {
+        let file = open_lock(&Path::new(LOCK_DIR).join(format!("jbod-{}.lock", enclosure)))?;
+        flock(file.as_raw_fd(), FlockArg::LockExclusive)?;
+        Ok(ControlLock { _file: file })
+    }
+
+    /// Returns the lock file, created if needed.
+    fn open_lock(path: &Path) -> io::Result<fs::File> {
+        // Whoever creates the file owns it, the others can only read it,
+        // which is enough for flock.
+        match fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o644)
+            .open(path)
+        {
+            Ok(file) => Ok(file),
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => fs::File::open(path),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns the runtime lock of the `--lock` mode, waiting for the jbod
+    /// holding it and telling so.
+    ///
+    /// The scans share it and the control operations take it exclusively,
+    /// so an inventory run by cron never reads the enclosures while a
+    /// firmware update runs.
+    ///
+    /// # Arguments
+    ///
+    /// * `exclusive` - true for a control operation
+    ///
+    pub fn lock_runtime(exclusive: bool) -> io::Result<ControlLock> {
+        let path = Path::new(LOCK_DIR).join(RUNTIME_LOCK);
+        let file = open_lock(&path)?;
+        let (wait, try_only) = if exclusive {
+            (FlockArg::LockExclusive, FlockArg::LockExclusiveNonblock)
+        } else {
+            (FlockArg::LockShared, FlockArg::LockSharedNonblock)
+        };
+        match flock(file.as_raw_fd(), try_only) {
+            Ok(()) => {}
+            Err(nix::errno::Errno::EWOULDBLOCK) => {
+                Out::print_warning(&I18n::tr(Message::WaitingLock, &[&path.display()]));
+                flock(file.as_raw_fd(), wait)?;
+            }
+            Err(e) => return Err(e.into()),
+        }
+        Ok(ControlLock { _file: file })
+    }
+
+    /// Returns the first scsi generic node the current user can't open, or
+    /// None when every node can be queried.
+    ///
+    /// The nodes are usually `root:disk 0660`, an unprivileged user outside
+    /// of that group only gets what sysfs exposes.
+    pub fn unreadable_sg_node() -> Option<String> {
+        if geteuid().is_root() {
+            return None;
+        }
+        let mut nodes: Vec<String> = fs::read_dir(sys_class_scsi_generic())
+            .ok()?
+            .flatten()
+            .map(|e| format!("/dev/{}", e.file_name().to_string_lossy()))
+            .filter(|n| Path::new(n).exists())
+            .collect();
+        nodes.sort_by(|a, b| natural_cmp(a, b));
+        nodes
+            .into_iter()
+            .find(|n| access(n.as_str(), AccessFlags::R_OK).is_err())
+    }
+
+    /// Returns how to give the current user access to a device node or to
+    /// a sysfs control file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the node or the file that can't be used
+    ///
+    pub fn permission_guidance(path: &str) -> String {
+        if path.starts_with("/dev/") {
+            let group = fs::metadata(path)
+                .ok()
+                .and_then(|m| Group::from_gid(m.gid().into()).ok().flatten())
+                .map(|g| g.name)
+                .unwrap_or_else(|| "disk".to_string());
+            format!(
+                "run as root or add the user to the {} group: usermod -aG {} $USER",
+                group, group
+            )
+        } else {
+            "run as root or install a udev rule giving the locate and fault files to an \
+             operators group, see the README"
+                .to_string()
+        }
+    }
+
     /// Returns true or false for a given path
     ///
     /// This function verify if a path exist.
@@ -87,19 +423,35 @@ pub mod Util {
         Path::new(path).exists()
     }
 
-    /// Verify if all needed binaries are installed
-    pub fn verify_binary_needed() {
+    /// Verify if the binaries used by a subcommand are installed
+    ///
+    /// Exits when one of them is missing, naming the package to install.
+    ///
+    /// # Arguments
+    ///
+    /// * `binaries` - the paths of the binaries, see `HELPER_BINARIES`
+    ///
+    pub fn verify_binary_needed(binaries: &[&str]) {
         let mut binaries_not_found = Vec::new();
-        if !path_exists(LSSCSI) {
-            binaries_not_found.push("lsscsi");
-        }
-        if !path_exists(SG_INQ) {
-            binaries_not_found.push("sg3-utils");
-        }
-        if !path_exists(SCSI_TEMP) {
-            binaries_not_found.push("sg3-utils: scsi_temperature");
+        for binary in binaries.iter().filter(|b| !path_exists(b)) {
+            let name = binary.rsplit('/').next().unwrap_or(binary);
+            let package = HELPER_BINARIES
+                .iter()
+                .find(|(path, _)| path == binary)
+                .map_or(name, |(_, package)| package);
+            if package == name {
+                binaries_not_found.push(package.to_string());
+            } else {
+                binaries_not_found.push(format!("{}: {}", package, name));
+            }
         }
 
+        if !binaries_not_found.is_empty() && Out::machine_readable() {
+            for package in binaries_not_found {
+                Out::print_error_code(ErrorCode::ToolMissing, &format!("install package {}", package));
+            }
+            exit(1);
+        }
         if !binaries_not_found.is_empty() {
             println!(
                 "{} {} {}",
@@ -112,10 +464,408 @@ pub mod Util {
                 print!("Install package ");
                 println!("{}", err.red().bold().blink());
             }
+            println!("Run {} for details", "jbod doctor".bold());
             exit(1);
         }
     }
 
+    /// Returns the number contained in a slot name or None.
+    ///
+    /// Enclosures name their slots differently, "Slot 05", "SLOT  5 " or "5",
+    /// this function extracts the number so slots can be compared.
+    ///
+    /// # Arguments
+    ///
+    /// * `slot` - a string reference with the slot name
+    ///
+    /// # Example:
+    /// ```
+    /// assert_eq!(slot_number("Slot 05"), Some(5));
+    /// ```
+    pub fn slot_number(slot: &str) -> Option<u32> {
+        let digits: String = slot.chars().filter(|c| c.is_ascii_digit()).collect();
+        digits.parse().ok()
+    }
+
+    /// Returns the slot numbers of a list like `0,1,10-13`.
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - the list, example: the `--slots` of `jbod burnin`
+    ///
+    pub fn parse_slots(spec: &str) -> Result<Vec<u32>, String> {
+        let mut slots = Vec::new();
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let number = |n: &str| {
+                n.trim()
+                    .parse::<u32>()
+                    .map_err(|_| format!("invalid slot number: {}", n))
+            };
+            match part.split_once('-') {
+                Some((first, last)) => {
+                    let (first, last) = (number(first)?, number(last)?);
+                    if first > last {
+                        return Err(format!("invalid slot range: {}", part));
+                    }
+                    slots.extend(first..=last);
+                }
+                None => slots.push(number(part)?),
+            }
+        }
+        Ok(slots)
+    }
+
+    /// Returns true if an entry of an enclosure in sysfs is a slot component.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - the entry name, example: "Slot 05" or "ArrayDevice05"
+    ///
+    pub fn is_slot_component(name: &str) -> bool {
+        let cmp_slot = name.to_lowercase();
+        cmp_slot.contains("slot")
+            || cmp_slot.contains("disk")
+            || cmp_slot.contains("array device")
+            || cmp_slot.bytes().all(|c| c.is_ascii_digit())
+    }
+
+    /// Returns the serial number contained in a raw VPD page 0x80.
+    ///
+    /// The page starts with a 4 bytes header, the length is in the 4th byte.
+    /// Anything that isn't a page is returned cleaned from non printable bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `page` - the raw content of `vpd_pg80`
+    ///
+    pub fn vpd_serial(page: &[u8]) -> String {
+        let content = if page.len() > 4 && page[1] == 0x80 {
+            let end = (4 + page[3] as usize).min(page.len());
+            &page[4..end]
+        } else {
+            page
+        };
+
+        String::from_utf8_lossy(content)
+            .chars()
+            .filter(|c| c.is_ascii_graphic() || *c == ' ')
+            .collect::<String>()
+            .trim()
+            .to_string()
+    }
+
+    /// Returns the serial number ending a t10 `wwid`, the one of the SATA
+    /// drives, example: ZA1ABC01 for "t10.ATA     ST8000NM0055-1RM112     ZA1ABC01".
+    ///
+    /// # Arguments
+    ///
+    /// * `wwid` - the content of the sysfs `wwid`
+    ///
+    pub fn t10_serial(wwid: &str) -> Option<&str> {
+        if !wwid.starts_with("t10.") {
+            return None;
+        }
+        wwid.split_whitespace().last().filter(|s| s.len() > 1)
+    }
+
+    /// Returns the Duration for a string like "30s", "5m", "1h" or "30", or None.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - a string reference with the duration, seconds by default
+    ///
+    /// # Example:
+    /// ```
+    /// assert_eq!(parse_duration("5m"), Some(Duration::from_secs(300)));
+    /// ```
+    pub fn parse_duration(value: &str) -> Option<Duration> {
+        let value = value.trim();
+        let (number, multiplier) = match value.chars().last()? {
+            's' => (&value[..value.len() - 1], 1),
+            'm' => (&value[..value.len() - 1], 60),
+            'h' => (&value[..value.len() - 1], 3600),
+            _ => (value, 1),
+        };
+        number
+            .parse::<u64>()
+            .ok()
+            .map(|n| Duration::from_secs(n * multiplier))
+    }
+
+    /// Returns a vector with the result of `f` for every item, in the items order.
+    ///
+    /// This function spreads the items over at most `jobs` threads, it is used
+    /// to probe devices concurrently since each probe waits on subprocesses.
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - the items to process
+    /// * `jobs` - the maximum number of threads
+    /// * `f` - the function applied to every item
+    ///
+    pub fn parallel_map<T, R, F>(items: &[T], jobs: usize, f: F) -> Vec<R>
+    where
+        T: Sync,
+        R: Send,
+        F: Fn(&T) -> R + Sync,
+    {
+        let next = AtomicUsize::new(0);
+        let results: Mutex<Vec<Option<R>>> = Mutex::new(items.iter().map(|_| None).collect());
+
+        thread::scope(|scope| {
+            for _ in 0..jobs.clamp(1, items.len().max(1)) {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    if index >= items.len() {
+                        break;
+                    }
+                    let result = f(&items[index]);
+                    results.lock().unwrap()[index] = Some(result);
+                });
+            }
+        });
+
+        results
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map(|r| r.expect("every item is processed"))
+            .collect()
+    }
+
+    static QUIET: AtomicBool = AtomicBool::new(false);
+
+    /// Hides the progress bars and the spinners, set with `--quiet`.
+    pub fn set_quiet(quiet: bool) {
+        QUIET.store(quiet, Ordering::Relaxed);
+    }
+
+    /// Returns true when the progress bars and the spinners are drawn, that
+    /// is when stdout and stderr are TTYs and `--quiet` isn't set, so they
+    /// never end up in a pipe, a file or the log of a cron job. A dumb
+    /// terminal can't erase them.
+    pub fn show_progress() -> bool {
+        !QUIET.load(Ordering::Relaxed)
+            && io::stdout().is_terminal()
+            && io::stderr().is_terminal()
+            && env::var_os("TERM").is_none_or(|t| t != "dumb")
+    }
+
+    /// Width of the bar drawn by `Progress`, in characters.
+    const PROGRESS_WIDTH: u64 = 30;
+
+    /// A progress bar drawn on stderr for an operation of a known size, it
+    /// is erased when dropped and hidden unless `show_progress()`.
+    pub struct Progress {
+        bar: ProgressBar,
+    }
+
+    impl Progress {
+        /// Returns a running progress bar of `total` steps, preceded by
+        /// `message`.
+        ///
+        /// # Arguments
+        ///
+        /// * `message` - what is in progress, example: Scanning 60 slots
+        /// * `total` - the number of steps, slots or bytes
+        /// * `bytes` - the steps are bytes, they are shown with a unit
+        ///
+        /// # Example:
+        /// ```
+        /// let progress = Progress::start("Scanning 60 slots".to_string(), 60, false);
+        /// for slot in slots {
+        ///     scan(slot);
+        ///     progress.inc(1);
+        /// }
+        /// drop(progress);
+        /// ```
+        pub fn start(message: String, total: u64, bytes: bool) -> Self {
+            if !show_progress() {
+                return Progress {
+                    bar: ProgressBar::hidden(),
+                };
+            }
+
+            let count = match bytes {
+                true => "{decimal_bytes}/{decimal_total_bytes}",
+                false => "{pos}/{len}",
+            };
+            let template = format!("{{msg}} [{{bar:{}}}] {} {{elapsed}}", PROGRESS_WIDTH, count);
+            let style = ProgressStyle::with_template(&template)
+                .map(|style| style.progress_chars("#-"))
+                .unwrap_or_else(|_| ProgressStyle::default_bar());
+            let bar = ProgressBar::with_draw_target(Some(total), ProgressDrawTarget::stderr())
+                .with_style(style)
+                .with_message(message);
+            bar.enable_steady_tick(Duration::from_millis(100));
+            Progress { bar }
+        }
+
+        /// Adds `steps` to the steps done, from any thread.
+        pub fn inc(&self, steps: u64) {
+            self.bar.inc(steps);
+        }
+
+        /// Sets the number of steps done.
+        pub fn set(&self, steps: u64) {
+            self.bar.set_position(steps);
+        }
+
+        /// Runs `f` with the bar erased, so the lines it prints don't end
+        /// up after the bar, it is drawn again after.
+        pub fn suspend<R>(&self, f: impl FnOnce() -> R) -> R {
+            self.bar.suspend(f)
+        }
+    }
+
+    impl Drop for Progress {
+        fn drop(&mut self) {
+            self.bar.finish_and_clear();
+        }
+    }
+
+    /// A spinner drawn on stderr while a slow probe runs, it is erased when
+    /// dropped and does nothing unless `show_progress()`.
+    pub struct Spinner {
+        stop: Arc<AtomicBool>,
+        handle: Option<thread::JoinHandle<()>>,
+    }
+
+    impl Spinner {
+        /// Returns a running spinner followed by `message`.
+        ///
+        /// # Example:
+        /// ```
+        /// let spinner = Spinner::start("Scanning 15:0:1:0".to_string());
+        /// scan();
+        /// drop(spinner);
+        /// ```
+        pub fn start(message: String) -> Self {
+            let stop = Arc::new(AtomicBool::new(false));
+            if !show_progress() {
+                return Spinner { stop, handle: None };
+            }
+
+            let stopped = stop.clone();
+            let handle = thread::spawn(move || {
+                let started = Instant::now();
+                for frame in ['|', '/', '-', '\\'].iter().cycle() {
+                    if stopped.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    eprint!("\r{} {} {}s", frame, message, started.elapsed().as_secs());
+                    thread::sleep(Duration::from_millis(100));
+                }
+                eprint!("\r\x1b[2K");
+            });
+            Spinner {
+                stop,
+                handle: Some(handle),
+            }
+        }
+    }
+
+    impl Drop for Spinner {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::Relaxed);
+            if let Some(handle) = self.handle.take() {
+                let _ = handle.join();
+            }
+        }
+    }
+
+    /// Returns the SocketAddr to listen on or a message explaining why the
+    /// address or the port is invalid.
+    ///
+    /// The address can be an IPv4, an IPv6 or a hostname which is resolved,
+    /// the first address found is used.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - the address, example: 0.0.0.0, ::1, localhost
+    /// * `port` - the port, from 1 to 65535
+    ///
+    /// # Example:
+    /// ```
+    /// let addr = listen_address("localhost", "9945")?;
+    /// ```
+    pub fn listen_address(address: &str, port: &str) -> Result<SocketAddr, String> {
+        let port: u16 = match port.parse() {
+            Ok(p) if p > 0 => p,
+            _ => return Err(format!("invalid port {}, expected a number from 1 to 65535", port)),
+        };
+
+        let host = address.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok(SocketAddr::new(ip, port));
+        }
+        // The resolver would take 1.2.3 as 1.2.0.3.
+        if host.chars().all(|c| c.is_ascii_digit() || c == '.') {
+            return Err(format!("invalid IP address {}", address));
+        }
+
+        match (host, port).to_socket_addrs() {
+            Ok(mut addrs) => addrs
+                .next()
+                .ok_or_else(|| format!("{} does not resolve to any address", address)),
+            Err(e) => Err(format!("invalid address {}: {}", address, e)),
+        }
+    }
+
+    /// Returns the Ordering of two strings where the numbers are compared by
+    /// value, so "Slot 2" comes before "Slot 10".
+    ///
+    /// # Arguments
+    ///
+    /// * `a` - a string
+    /// * `b` - a string
+    ///
+    /// # Example:
+    /// ```
+    /// slots.sort_by(|a, b| natural_cmp(a, b));
+    /// ```
+    pub fn natural_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+        let mut a = a.chars().peekable();
+        let mut b = b.chars().peekable();
+
+        loop {
+            match (a.peek().copied(), b.peek().copied()) {
+                (None, None) => return std::cmp::Ordering::Equal,
+                (None, Some(_)) => return std::cmp::Ordering::Less,
+                (Some(_), None) => return std::cmp::Ordering::Greater,
+                (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                    let mut number_a = String::new();
+                    while let Some(c) = a.next_if(|c| c.is_ascii_digit()) {
+                        number_a.push(c);
+                    }
+                    let mut number_b = String::new();
+                    while let Some(c) = b.next_if(|c| c.is_ascii_digit()) {
+                        number_b.push(c);
+                    }
+                    // Leading zeros don't change the value, a longer number
+                    // is a bigger one.
+                    let trimmed_a = number_a.trim_start_matches('0');
+                    let trimmed_b = number_b.trim_start_matches('0');
+                    let order = trimmed_a
+                        .len()
+                        .cmp(&trimmed_b.len())
+                        .then_with(|| trimmed_a.cmp(trimmed_b))
+                        .then_with(|| number_a.len().cmp(&number_b.len()));
+                    if order != std::cmp::Ordering::Equal {
+                        return order;
+                    }
+                }
+                (Some(x), Some(y)) => {
+                    if x != y {
+                        return x.cmp(&y);
+                    }
+                    a.next();
+                    b.next();
+                }
+            }
+        }
+    }
+
     /// Returns true or false for every each character
     ///
     /// This function verify is a string is numeric.
@@ -134,4 +884,28 @@ pub mod Util {
     {
         s.as_ref().chars().all(char::is_numeric)
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::cmp::Ordering;
+
+        #[test]
+        fn numbers_are_compared_by_value() {
+            assert_eq!(natural_cmp("Slot 2", "Slot 10"), Ordering::Less);
+            assert_eq!(natural_cmp("Slot 10", "Slot 2"), Ordering::Greater);
+            assert_eq!(natural_cmp("Slot 02", "Slot 10"), Ordering::Less);
+            assert_eq!(natural_cmp("Slot 9", "Slot 09"), Ordering::Less);
+            assert_eq!(natural_cmp("Slot", "Slot 1"), Ordering::Less);
+            assert_eq!(natural_cmp("/dev/sg9", "/dev/sg100"), Ordering::Less);
+        }
+
+        #[test]
+        fn slots_sort_in_natural_order() {
+            let mut slots = vec!["Slot 10", "Slot 2", "Slot 1", "Slot 01", "Slot 20"];
+            slots.sort_by(|a, b| natural_cmp(a, b));
+
+            assert_eq!(slots, ["Slot 1", "Slot 01", "Slot 2", "Slot 10", "Slot 20"]);
+        }
+    }
 }
@@ -0,0 +1,228 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod I18n {
+    use serde::{Deserialize, Serialize};
+    use std::env;
+    use std::fmt::Display;
+
+    use crate::utils::config::Config;
+
+    /// Environment variable choosing the language, it wins over the
+    /// configuration and the POSIX locale, example: JBOD_LANG=fr
+    pub const LANG_ENV: &str = "JBOD_LANG";
+
+    /// A language of the messages.
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Locale {
+        #[default]
+        En,
+        Fr,
+    }
+
+    impl Locale {
+        /// Returns the Locale of a language or a POSIX locale, None when
+        /// there is no catalog for it.
+        ///
+        /// # Arguments
+        ///
+        /// * `value` - example: fr, fr_FR.UTF-8 or C
+        ///
+        pub fn parse(value: &str) -> Option<Locale> {
+            let language = value.split(['_', '.', '@', '-']).next()?.to_lowercase();
+            match language.as_str() {
+                "en" | "c" | "posix" => Some(Locale::En),
+                "fr" => Some(Locale::Fr),
+                _ => None,
+            }
+        }
+
+        /// Returns the language code, as accepted by `JBOD_LANG`.
+        pub fn code(&self) -> &'static str {
+            match self {
+                Locale::En => "en",
+                Locale::Fr => "fr",
+            }
+        }
+    }
+
+    /// Returns the Locale of the messages.
+    ///
+    /// `JBOD_LANG` comes first, then the `locale` of the configuration,
+    /// then the first of `LC_ALL`, `LC_MESSAGES` and `LANG` that is set.
+    /// The configuration is only read once loaded, so a message printed
+    /// before doesn't load the default file in place of `--config`.
+    pub fn locale() -> Locale {
+        if let Some(locale) = env::var(LANG_ENV).ok().and_then(|l| Locale::parse(&l)) {
+            return locale;
+        }
+        if let Some(locale) = Config::loaded().and_then(|s| s.locale) {
+            return locale;
+        }
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| Locale::parse(&value))
+            .unwrap_or_default()
+    }
+
+    /// The messages of the catalog, the ones a DC technician reads.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Message {
+        Error,
+        Warning,
+        Hint,
+        Errors,
+        NotADisk,
+        NoEnclosure,
+        CannotOpen,
+        CannotTakeLock,
+        WaitingLock,
+        NoLocateLed,
+        NoFaultLed,
+        UnreadableNode,
+        CablingNoTerminal,
+        CablingIntro,
+        CablingPrompt,
+        CablingWhichBay,
+        CablingMatches,
+        CablingHint,
+    }
+
+    /// Returns the text of a message, `{}` marks where its arguments go.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - the Message
+    /// * `locale` - the language
+    ///
+    pub fn text(message: Message, locale: Locale) -> &'static str {
+        use Message::*;
+        match (message, locale) {
+            (Error, Locale::En) => "Error:",
+            (Error, Locale::Fr) => "Erreur :",
+            (Warning, Locale::En) => "Warning:",
+            (Warning, Locale::Fr) => "Attention :",
+            (Hint, Locale::En) => "Hint:",
+            (Hint, Locale::Fr) => "Conseil :",
+            (Errors, Locale::En) => "Errors",
+            (Errors, Locale::Fr) => "Erreurs",
+            (NotADisk, Locale::En) => "{} is not a disk of an enclosure",
+            (NotADisk, Locale::Fr) => "{} n'est pas un disque d'un boîtier",
+            (NoEnclosure, Locale::En) => "no enclosure matches {}",
+            (NoEnclosure, Locale::Fr) => "aucun boîtier ne correspond à {}",
+            (CannotOpen, Locale::En) => "cannot open {}: {}",
+            (CannotOpen, Locale::Fr) => "impossible d'ouvrir {} : {}",
+            (CannotTakeLock, Locale::En) => "cannot take the lock: {}",
+            (CannotTakeLock, Locale::Fr) => "impossible de prendre le verrou : {}",
+            (WaitingLock, Locale::En) => "waiting for the jbod holding {}",
+            (WaitingLock, Locale::Fr) => "attente du jbod qui détient {}",
+            (NoLocateLed, Locale::En) => "{} does not expose locate led",
+            (NoLocateLed, Locale::Fr) => "{} n'a pas de led de localisation",
+            (NoFaultLed, Locale::En) => "{} does not expose fault led",
+            (NoFaultLed, Locale::Fr) => "{} n'a pas de led de panne",
+            (UnreadableNode, Locale::En) => {
+                "{} is not readable by this user, temperatures, firmwares and enclosure \
+                 serials are not collected"
+            }
+            (UnreadableNode, Locale::Fr) => {
+                "{} n'est pas lisible par cet utilisateur, les températures, les firmwares \
+                 et les numéros de série des boîtiers ne sont pas relevés"
+            }
+            (CablingNoTerminal, Locale::En) => {
+                "verify-cabling needs an operator, stdin is not a terminal"
+            }
+            (CablingNoTerminal, Locale::Fr) => {
+                "verify-cabling a besoin d'un opérateur, stdin n'est pas un terminal"
+            }
+            (CablingIntro, Locale::En) => {
+                "Each slot of {} {} blinks in turn, answer y when the expected bay blinks, \
+                 n otherwise, q to stop.\n"
+            }
+            (CablingIntro, Locale::Fr) => {
+                "Chaque emplacement de {} {} clignote à son tour, répondez o quand la baie \
+                 attendue clignote, n sinon, q pour arrêter.\n"
+            }
+            (CablingPrompt, Locale::En) => "[{}/{}] {} is blinking, is it bay {}? [Y/n/q] ",
+            (CablingPrompt, Locale::Fr) => "[{}/{}] {} clignote, est-ce la baie {} ? [O/n/q] ",
+            (CablingWhichBay, Locale::En) => "  Which bay is blinking? ",
+            (CablingWhichBay, Locale::Fr) => "  Quelle baie clignote ? ",
+            (CablingMatches, Locale::En) => "\n{} of {} slots checked, the cabling matches",
+            (CablingMatches, Locale::Fr) => {
+                "\n{} emplacements vérifiés sur {}, le câblage est conforme"
+            }
+            (CablingHint, Locale::En) => {
+                "a run of consecutive mismatches usually is a swapped backplane cable"
+            }
+            (CablingHint, Locale::Fr) => {
+                "une suite d'écarts consécutifs vient en général de câbles de fond de panier \
+                 inversés"
+            }
+        }
+    }
+
+    /// Returns a message of the catalog in the current locale.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - the Message
+    /// * `args` - its arguments, in the order of its `{}`
+    ///
+    /// # Example
+    /// ```
+    /// let error = I18n::tr(Message::NoEnclosure, &[&"0:0:1:0"]);
+    /// ```
+    ///
+    pub fn tr(message: Message, args: &[&dyn Display]) -> String {
+        let mut parts = text(message, locale()).split("{}");
+        let mut translated = parts.next().unwrap_or_default().to_string();
+        for (index, part) in parts.enumerate() {
+            if let Some(arg) = args.get(index) {
+                translated.push_str(&arg.to_string());
+            }
+            translated.push_str(part);
+        }
+        translated
+    }
+
+    /// Returns true when an answer to a yes or no prompt is a yes, in any
+    /// language of the catalog.
+    pub fn is_yes(answer: &str) -> bool {
+        matches!(answer, "y" | "yes" | "o" | "oui")
+    }
+
+    /// Returns true when an answer to a yes or no prompt is a no.
+    pub fn is_no(answer: &str) -> bool {
+        matches!(answer, "n" | "no" | "non")
+    }
+}
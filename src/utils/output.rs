@@ -0,0 +1,496 @@
+/*-
+ * SPDX-License-Identifier: BSD-2-Clause
+ *
+ * BSD 2-Clause License
+ *
+ * Copyright (c) 2021-2023, Gandi S.A.S.
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#[allow(non_snake_case)]
+pub mod Output {
+    use clap::ArgMatches;
+    use colored::*;
+    use prettytable::Table;
+    use serde::Serialize;
+    use std::env;
+    use std::fmt::Display;
+    use std::io::{self, Write};
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::OnceLock;
+
+    use crate::utils::i18n::I18n;
+    use crate::utils::i18n::I18n::Message;
+
+    static FORMAT: OnceLock<Format> = OnceLock::new();
+    static COLOR: AtomicBool = AtomicBool::new(true);
+
+    /// Values accepted by the global `--output` flag.
+    pub const FORMATS: &[&str] = &["table", "json", "jsonl", "yaml", "csv", "tsv"];
+
+    /// The rendering used by every subcommand.
+    ///
+    /// `Table` is the human readable output, each subcommand keeps its own
+    /// layout for it, the others are generic serializations of the records.
+    /// `Jsonl` writes one JSON object per line, so a long listing can be
+    /// piped before it is complete. `Csv` and `Tsv` write one row per
+    /// record after a header line.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Format {
+        Table,
+        Json,
+        Jsonl,
+        Yaml,
+        Csv,
+        Tsv,
+    }
+
+    impl FromStr for Format {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.to_lowercase().as_str() {
+                "table" => Ok(Format::Table),
+                "json" => Ok(Format::Json),
+                "jsonl" => Ok(Format::Jsonl),
+                "yaml" => Ok(Format::Yaml),
+                "csv" => Ok(Format::Csv),
+                "tsv" => Ok(Format::Tsv),
+                _ => Err(format!("unknown output format: {}", s)),
+            }
+        }
+    }
+
+    /// Returns the format selected with the global `--output` flag or its
+    /// `--json`, `--csv` and `--tsv` shorthands.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - clappy's ArgMatches of the running subcommand
+    ///
+    pub fn get_format(options: &ArgMatches) -> Format {
+        if options.is_present("json") {
+            return Format::Json;
+        }
+        if options.is_present("csv") {
+            return Format::Csv;
+        }
+        if options.is_present("tsv") {
+            return Format::Tsv;
+        }
+        options
+            .value_of("output")
+            .and_then(|f| f.parse().ok())
+            .unwrap_or(Format::Table)
+    }
+
+    /// Returns false when the colors are turned off, with `--no-color`, a
+    /// non empty NO_COLOR or a dumb terminal.
+    ///
+    /// # Arguments
+    ///
+    /// * `no_color` - the `--no-color` flag is given
+    ///
+    pub fn color_wanted(no_color: bool) -> bool {
+        let env_no_color = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        let dumb = env::var_os("TERM").is_some_and(|t| t == "dumb");
+        !(no_color || env_no_color || dumb)
+    }
+
+    /// Turns the colors and the other styles of the output on or off, the
+    /// tables of prettytable included.
+    pub fn set_color(enabled: bool) {
+        COLOR.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            colored::control::set_override(false);
+        }
+    }
+
+    /// Returns true unless the colors were turned off.
+    pub fn color() -> bool {
+        COLOR.load(Ordering::Relaxed)
+    }
+
+    /// Prints a table to stdout, with its styles on a terminal unless the
+    /// colors were turned off.
+    pub fn print_table(table: &Table) {
+        if color() {
+            table.printstd();
+        } else {
+            let _ = table.print(&mut io::stdout());
+        }
+    }
+
+    /// Records the format of the running command, the errors, warnings and
+    /// hints are written as JSON lines on stderr with a machine readable one.
+    pub fn set_format(format: Format) {
+        let _ = FORMAT.set(format);
+    }
+
+    /// Returns true when the running command has a machine readable output.
+    pub fn machine_readable() -> bool {
+        matches!(FORMAT.get(), Some(format) if *format != Format::Table)
+    }
+
+    /// The stable code of an error, for the scripts reading the JSON errors.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum ErrorCode {
+        /// No device, enclosure or disk matches what was asked
+        NotFound,
+        /// The user lacks the rights on a device or a file
+        PermissionDenied,
+        /// A helper binary isn't installed
+        ToolMissing,
+        /// A command line argument or an input file is invalid
+        InvalidArgument,
+        /// The hardware doesn't support the operation
+        Unsupported,
+        /// Any other failure of a read or a write
+        IoError,
+        /// The operation failed, see the message
+        Failed,
+    }
+
+    impl ErrorCode {
+        /// Returns the ErrorCode of an I/O error.
+        pub fn from_io(error: &io::Error) -> Self {
+            match error.kind() {
+                io::ErrorKind::NotFound => ErrorCode::NotFound,
+                io::ErrorKind::PermissionDenied => ErrorCode::PermissionDenied,
+                _ => ErrorCode::IoError,
+            }
+        }
+    }
+
+    /// Writes a message as a JSON line on stderr, example:
+    /// `{"error":{"code":"not_found","message":"device sdz not found"}}`.
+    fn print_structured(kind: &str, code: Option<ErrorCode>, message: &str) {
+        let mut content = serde_json::Map::new();
+        if let Some(code) = code {
+            content.insert("code".to_string(), serde_json::json!(code));
+        }
+        content.insert("message".to_string(), serde_json::json!(message));
+        eprintln!("{}", serde_json::json!({ kind: content }));
+    }
+
+    /// Returns an empty Result or the serialization error.
+    ///
+    /// This function writes a list of records to stdout in a machine readable
+    /// format, it must not be called with `Format::Table`.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - the selected output format
+    /// * `records` - the records to serialize
+    ///
+    pub fn render<T: Serialize>(format: Format, records: &[T]) -> Result<(), String> {
+        let stdout = io::stdout();
+        let mut handle = stdout.lock();
+
+        match format {
+            Format::Json => {
+                serde_json::to_writer_pretty(&mut handle, records).map_err(|e| e.to_string())?;
+                writeln!(handle).map_err(|e| e.to_string())?;
+            }
+            Format::Jsonl => {
+                for record in records {
+                    serde_json::to_writer(&mut handle, record).map_err(|e| e.to_string())?;
+                    writeln!(handle).map_err(|e| e.to_string())?;
+                }
+                handle.flush().map_err(|e| e.to_string())?;
+            }
+            Format::Yaml => {
+                serde_yaml::to_writer(&mut handle, records).map_err(|e| e.to_string())?;
+            }
+            Format::Csv | Format::Tsv => {
+                let delimiter = if format == Format::Tsv { b'\t' } else { b',' };
+                let mut writer = csv::WriterBuilder::new()
+                    .delimiter(delimiter)
+                    .from_writer(handle);
+                for (i, record) in records.iter().enumerate() {
+                    let row = csv_row(record)?;
+                    if i == 0 {
+                        writer
+                            .write_record(row.iter().map(|(k, _)| k))
+                            .map_err(|e| e.to_string())?;
+                    }
+                    writer
+                        .write_record(row.iter().map(|(_, v)| v))
+                        .map_err(|e| e.to_string())?;
+                }
+                writer.flush().map_err(|e| e.to_string())?;
+            }
+            Format::Table => return Err("table output is rendered by the caller".to_string()),
+        }
+
+        Ok(())
+    }
+
+    /// Returns the columns of a record as CSV cells.
+    ///
+    /// The columns are the fields of the record in their declaration order,
+    /// serde_json preserves it, so the order only changes with the struct.
+    /// A CSV cell can't hold a list, lists are joined with `;` and nested
+    /// structures are written as JSON.
+    fn csv_row<T: Serialize>(record: &T) -> Result<Vec<(String, String)>, String> {
+        let cell = |value: &serde_json::Value| match value {
+            serde_json::Value::Null => String::new(),
+            serde_json::Value::String(s) => s.clone(),
+            serde_json::Value::Array(items) => items
+                .iter()
+                .map(|i| match i {
+                    serde_json::Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                })
+                .collect::<Vec<String>>()
+                .join(";"),
+            other => other.to_string(),
+        };
+
+        match serde_json::to_value(record).map_err(|e| e.to_string())? {
+            serde_json::Value::Object(fields) => {
+                Ok(fields.iter().map(|(k, v)| (k.clone(), cell(v))).collect())
+            }
+            other => Ok(vec![("value".to_string(), cell(&other))]),
+        }
+    }
+
+    /// Returns the template with its `\t`, `\n` and `\\` escapes replaced,
+    /// a shell passes them as is between single quotes.
+    fn unescape(template: &str) -> String {
+        let mut unescaped = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                unescaped.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('t') => unescaped.push('\t'),
+                Some('n') => unescaped.push('\n'),
+                Some('\\') => unescaped.push('\\'),
+                Some(other) => {
+                    unescaped.push('\\');
+                    unescaped.push(other);
+                }
+                None => unescaped.push('\\'),
+            }
+        }
+        unescaped
+    }
+
+    /// Returns a record formatted with a template, each `{field}` is
+    /// replaced by the field as written in a CSV cell, `{{` and `}}` are
+    /// literal braces.
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - the template, example: {slot}\t{serial}
+    /// * `record` - the record to format
+    ///
+    pub fn format_template<T: Serialize>(template: &str, record: &T) -> Result<String, String> {
+        let row = csv_row(record)?;
+        let mut formatted = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find(['{', '}']) {
+            formatted.push_str(&rest[..start]);
+            let brace = &rest[start..];
+            if brace.starts_with("{{") || brace.starts_with("}}") {
+                formatted.push_str(&brace[..1]);
+                rest = &brace[2..];
+                continue;
+            }
+            let end = match brace.find('}') {
+                Some(end) if brace.starts_with('{') => end,
+                _ => return Err(format!("unbalanced brace in the template: {}", template)),
+            };
+            let name = &brace[1..end];
+            match row.iter().find(|(k, _)| k == name) {
+                Some((_, value)) => formatted.push_str(value),
+                None => {
+                    let names: Vec<&str> = row.iter().map(|(k, _)| k.as_str()).collect();
+                    return Err(format!(
+                        "unknown field {{{}}}, expected one of: {}",
+                        name,
+                        names.join(", ")
+                    ));
+                }
+            }
+            rest = &brace[end + 1..];
+        }
+        formatted.push_str(rest);
+        Ok(formatted)
+    }
+
+    /// Returns an empty Result or the formatting error.
+    ///
+    /// This function writes a line per record formatted with a template,
+    /// see `format_template()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - the template given with `--format`
+    /// * `records` - the records to write
+    ///
+    pub fn render_template<T: Serialize>(template: &str, records: &[T]) -> Result<(), String> {
+        let template = unescape(template);
+        let stdout = io::stdout();
+        let mut handle = stdout.lock();
+        for record in records {
+            let line = format_template(&template, record)?;
+            writeln!(handle, "{}", line).map_err(|e| e.to_string())?;
+        }
+        handle.flush().map_err(|e| e.to_string())
+    }
+
+    /// Returns an empty Result or the serialization error.
+    ///
+    /// This function writes a single document, like a report, to stdout. Such
+    /// documents are nested so they have no CSV representation.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - the selected output format
+    /// * `document` - the document to serialize
+    ///
+    pub fn render_document<T: Serialize>(format: Format, document: &T) -> Result<(), String> {
+        let stdout = io::stdout();
+        let mut handle = stdout.lock();
+
+        match format {
+            Format::Json => {
+                serde_json::to_writer_pretty(&mut handle, document).map_err(|e| e.to_string())?;
+                writeln!(handle).map_err(|e| e.to_string())?;
+            }
+            Format::Jsonl => {
+                serde_json::to_writer(&mut handle, document).map_err(|e| e.to_string())?;
+                writeln!(handle).map_err(|e| e.to_string())?;
+            }
+            Format::Yaml => {
+                serde_yaml::to_writer(&mut handle, document).map_err(|e| e.to_string())?;
+            }
+            Format::Csv | Format::Tsv => {
+                return Err("csv output is not supported by this command".to_string())
+            }
+            Format::Table => return Err("table output is rendered by the caller".to_string()),
+        }
+
+        Ok(())
+    }
+
+    /// Writes the failures of a command that kept going to stderr.
+    ///
+    /// The machine readable formats get an `errors` array so stdout stays a
+    /// valid list of records, the table gets a summary section.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - the selected output format
+    /// * `errors` - the failures, nothing is written when empty
+    ///
+    pub fn render_errors<T: Serialize + Display>(format: Format, errors: &[T]) {
+        if errors.is_empty() {
+            return;
+        }
+
+        #[derive(Serialize)]
+        struct Errors<'a, T> {
+            errors: &'a [T],
+        }
+
+        let stderr = io::stderr();
+        let mut handle = stderr.lock();
+        let document = Errors { errors };
+        let written = match format {
+            Format::Json => serde_json::to_writer_pretty(&mut handle, &document)
+                .map_err(|e| e.to_string())
+                .and_then(|_| writeln!(handle).map_err(|e| e.to_string())),
+            Format::Jsonl => serde_json::to_writer(&mut handle, &document)
+                .map_err(|e| e.to_string())
+                .and_then(|_| writeln!(handle).map_err(|e| e.to_string())),
+            Format::Yaml => serde_yaml::to_writer(&mut handle, &document).map_err(|e| e.to_string()),
+            Format::Table | Format::Csv | Format::Tsv => {
+                if format == Format::Table {
+                    let _ = writeln!(
+                        handle,
+                        "{} {} {}",
+                        "==> ".red().bold(),
+                        I18n::tr(Message::Errors, &[]).bold(),
+                        " <==".red().bold()
+                    );
+                }
+                for error in errors {
+                    let error_label = I18n::tr(Message::Error, &[]);
+                    let _ = writeln!(handle, "{} {}", error_label.red().bold(), error);
+                }
+                Ok(())
+            }
+        };
+        if let Err(e) = written {
+            drop(handle);
+            print_error(&e);
+        }
+    }
+
+    /// Prints an error message in the same fashion for every subcommand.
+    pub fn print_error(message: &str) {
+        print_error_code(ErrorCode::Failed, message);
+    }
+
+    /// Prints an error message with its code, the code is only shown with
+    /// a machine readable output.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - the stable code of the error
+    /// * `message` - what went wrong
+    ///
+    pub fn print_error_code(code: ErrorCode, message: &str) {
+        if machine_readable() {
+            print_structured("error", Some(code), message);
+        } else {
+            eprintln!("{} {}", I18n::tr(Message::Error, &[]).red().bold(), message);
+        }
+    }
+
+    /// Prints a warning, the command goes on with less information.
+    pub fn print_warning(message: &str) {
+        if machine_readable() {
+            print_structured("warning", None, message);
+        } else {
+            eprintln!("{} {}", I18n::tr(Message::Warning, &[]).yellow().bold(), message);
+        }
+    }
+
+    /// Prints how to solve the previous error or warning.
+    pub fn print_hint(message: &str) {
+        if machine_readable() {
+            print_structured("hint", None, message);
+        } else {
+            eprintln!("{} {}", I18n::tr(Message::Hint, &[]).yellow().bold(), message);
+        }
+    }
+}